@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    config::Config,
+    git_rust::{BASE_DIR, RepoRust},
+    objects::ObjectType,
+    refs, revision,
+};
+
+#[cfg(test)]
+mod test;
+
+pub struct Branch {
+    // Full name: refs/heads/main or refs/remotes/origin/main
+    pub name: String,
+    pub hash: String,
+    // Checked out in the working tree
+    pub current: bool,
+    // For refs/remotes/<remote>/HEAD, the ref it points to
+    pub target: Option<String>,
+}
+
+fn invalid_name(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("'{name}' is not a valid branch name"),
+    )
+}
+
+fn not_found(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("branch '{name}' not found"),
+    )
+}
+
+fn current_branch() -> std::io::Result<String> {
+    refs::head_branch()?
+        .and_then(|name| name.strip_prefix("refs/heads/").map(str::to_string))
+        .ok_or_else(|| std::io::Error::other("HEAD does not point to a branch"))
+}
+
+// Local branches and/or remote-tracking branches, sorted by name
+pub fn list(local: bool, remote: bool) -> std::io::Result<Vec<Branch>> {
+    let head = refs::head_branch()?;
+    let mut branches = Vec::new();
+    if local {
+        for (name, hash) in refs::list_refs("refs/heads")? {
+            let current = head.as_ref() == Some(&name);
+            branches.push(Branch {
+                name,
+                hash,
+                current,
+                target: None,
+            });
+        }
+    }
+    if remote {
+        let remotes_dir = RepoRust::get_root()
+            .absolute_path
+            .join(BASE_DIR)
+            .join("refs/remotes");
+        // <remote>/HEAD is a symbolic ref, not listed by list_refs
+        let tracking = refs::list_refs("refs/remotes")?;
+        // Remotes with a folder, or with packed remote-tracking branches only
+        let mut remotes: BTreeSet<String> = match std::fs::read_dir(&remotes_dir) {
+            Ok(dir) => dir
+                .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().to_string()))
+                .collect(),
+            Err(_) => BTreeSet::new(),
+        };
+        remotes.extend(tracking.iter().filter_map(|(name, _)| {
+            let remote = name.strip_prefix("refs/remotes/")?.split_once('/')?.0;
+            Some(remote.to_string())
+        }));
+        for remote in remotes {
+            let head = format!("refs/remotes/{remote}/HEAD");
+            if let Some(target) = refs::symbolic_ref(&head)?
+                && let Some(hash) = refs::read_ref(&head)?
+            {
+                branches.push(Branch {
+                    name: head,
+                    hash,
+                    current: false,
+                    target: Some(target),
+                });
+            }
+            let prefix = format!("refs/remotes/{remote}/");
+            for (name, hash) in tracking
+                .iter()
+                .filter(|(name, _)| name.starts_with(&prefix))
+            {
+                branches.push(Branch {
+                    name: name.clone(),
+                    hash: hash.clone(),
+                    current: false,
+                    target: None,
+                });
+            }
+        }
+    }
+    Ok(branches)
+}
+
+// Creates refs/heads/<name> at start. A remote-tracking branch given as start
+// becomes the upstream of the new branch. Returns that upstream, short
+pub fn create(name: &str, start: &str, force: bool) -> std::io::Result<Option<String>> {
+    if !refs::check_name(name) || name == "HEAD" {
+        return Err(invalid_name(name));
+    }
+    let full_name = format!("refs/heads/{name}");
+    let old = refs::read_ref(&full_name)?;
+    if old.is_some() {
+        if !force {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("a branch named '{name}' already exists"),
+            ));
+        }
+        if refs::head_branch()?.as_deref() == Some(full_name.as_str()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("cannot force update the branch '{name}' used by the working tree"),
+            ));
+        }
+    }
+    let hash = revision::resolve_as(start, ObjectType::Commit).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("not a valid object name: '{start}'"),
+        )
+    })?;
+    let mut transaction = refs::Transaction::new();
+    let message = match old {
+        Some(_) => format!("branch: Reset to {start}"),
+        None => format!("branch: Created from {start}"),
+    };
+    transaction
+        .compare_and_swap(&full_name, old.as_deref(), &hash)
+        .message(&message);
+    transaction.commit()?;
+
+    let start_ref = revision::resolve_ref_name(start).ok().flatten();
+    match start_ref {
+        Some(start_ref) if start_ref.starts_with("refs/remotes/") => {
+            Ok(Some(set_upstream(Some(name), &start_ref)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Deletes refs/heads/<name>, its reflog and its config. Without force, the
+// branch must be merged in its upstream, or in HEAD. Returns the hash it had
+pub fn delete(name: &str, force: bool) -> std::io::Result<String> {
+    let full_name = format!("refs/heads/{name}");
+    if refs::head_branch()?.as_deref() == Some(full_name.as_str()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Cannot delete branch '{name}' checked out"),
+        ));
+    }
+    let hash = refs::read_ref(&full_name)?.ok_or_else(|| not_found(name))?;
+    if !force {
+        let merged_into = match revision::upstream(&full_name) {
+            Ok(upstream) => refs::read_ref(&upstream)?,
+            Err(_) => refs::read_ref("HEAD")?,
+        };
+        let merged = match merged_into {
+            Some(target) => revision::is_ancestor(&hash, &target)?,
+            None => false,
+        };
+        if !merged {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "the branch '{name}' is not fully merged\n\
+                     hint: If you are sure you want to delete it, run 'branch -D {name}'"
+                ),
+            ));
+        }
+    }
+    refs::delete_ref(&full_name)?;
+    let mut config = Config::read()?;
+    if config.remove_section("branch", Some(name)) {
+        config.write()?;
+    }
+    Ok(hash)
+}
+
+// Renames a branch (the current one if old is None), with its reflog and config
+pub fn rename(old: Option<&str>, new: &str, force: bool) -> std::io::Result<()> {
+    let old = match old {
+        Some(old) => old.to_string(),
+        None => current_branch()?,
+    };
+    if !refs::check_name(new) || new == "HEAD" {
+        return Err(invalid_name(new));
+    }
+    let old_ref = format!("refs/heads/{old}");
+    let new_ref = format!("refs/heads/{new}");
+    let is_current = refs::head_branch()?.as_deref() == Some(old_ref.as_str());
+    if old_ref == new_ref {
+        return Ok(());
+    }
+    if refs::read_ref(&new_ref)?.is_some() && !force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("a branch named '{new}' already exists"),
+        ));
+    }
+    match refs::read_ref(&old_ref)? {
+        Some(_) => {
+            refs::delete_ref(&new_ref)?;
+            refs::rename_ref(&old_ref, &new_ref)?;
+        }
+        // The current branch has no commit yet. Only HEAD changes
+        None if is_current => {}
+        None => return Err(not_found(&old)),
+    }
+    if is_current {
+        refs::set_head_branch(&new_ref)?;
+    }
+    let mut config = Config::read()?;
+    config.remove_section("branch", Some(new));
+    config.rename_section("branch", Some(&old), Some(new));
+    config.write()
+}
+
+// Sets branch.<name>.remote and branch.<name>.merge. upstream is a remote-tracking
+// branch (origin/main) or a local branch. Returns the short name of the upstream
+pub fn set_upstream(branch: Option<&str>, upstream: &str) -> std::io::Result<String> {
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => current_branch()?,
+    };
+    if refs::read_ref(&format!("refs/heads/{branch}"))?.is_none() {
+        return Err(not_found(&branch));
+    }
+    let missing = || {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("the requested upstream branch '{upstream}' does not exist"),
+        )
+    };
+    let upstream_ref = revision::resolve_ref_name(upstream)
+        .ok()
+        .flatten()
+        .ok_or_else(missing)?;
+    let (remote, merge) = if let Some(rest) = upstream_ref.strip_prefix("refs/remotes/") {
+        let (remote, name) = rest.split_once('/').ok_or_else(missing)?;
+        (remote.to_string(), format!("refs/heads/{name}"))
+    } else if upstream_ref.starts_with("refs/heads/") {
+        (".".to_string(), upstream_ref.clone())
+    } else {
+        return Err(missing());
+    };
+    let mut config = Config::read()?;
+    config.set(&format!("branch.{branch}.remote"), &remote)?;
+    config.set(&format!("branch.{branch}.merge"), &merge)?;
+    config.write()?;
+    Ok(revision::shorten_ref(&upstream_ref).to_string())
+}
+
+pub fn unset_upstream(branch: Option<&str>) -> std::io::Result<()> {
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => current_branch()?,
+    };
+    let mut config = Config::read()?;
+    let remote = config.unset(&format!("branch.{branch}.remote"))?;
+    let merge = config.unset(&format!("branch.{branch}.merge"))?;
+    if !remote && !merge {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("branch '{branch}' has no upstream information"),
+        ));
+    }
+    config.write()
+}
@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use crate::{
+    branch,
+    config::Config,
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType},
+    refs, revision,
+    test_common::{run_test, run_test_matches},
+};
+
+fn write_commit(message: &str, parents: &[&str]) -> String {
+    let tree = objects::write_object(ObjectType::Tree, b"").unwrap();
+    let mut commit = format!("tree {tree}\n");
+    for parent in parents {
+        commit.push_str(&format!("parent {parent}\n"));
+    }
+    commit.push_str("author A U Thor <author@example.com> 1700000000 +0000\n");
+    commit.push_str("committer A U Thor <author@example.com> 1700000000 +0000\n\n");
+    commit.push_str(message);
+    objects::write_object(ObjectType::Commit, commit.as_bytes()).unwrap()
+}
+
+fn names(local: bool, remote: bool) -> Vec<String> {
+    branch::list(local, remote)
+        .unwrap()
+        .into_iter()
+        .map(|b| match b.current {
+            true => format!("* {}", b.name),
+            false => b.name,
+        })
+        .collect()
+}
+
+#[test]
+fn test_branch_create_and_delete() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // Nothing to start from yet
+        assert!(branch::create("topic", "HEAD", false).is_err());
+
+        let first = write_commit("first\n", &[]);
+        let second = write_commit("second\n", &[&first]);
+        refs::write_ref("refs/heads/master", &second).unwrap();
+
+        assert_eq!(branch::create("old", "HEAD~1", false).unwrap(), None);
+        assert_eq!(
+            refs::read_ref("refs/heads/old").unwrap(),
+            Some(first.clone())
+        );
+        assert!(branch::create("old", "HEAD", false).is_err());
+        branch::create("old", "HEAD", true).unwrap();
+        assert_eq!(
+            refs::read_ref("refs/heads/old").unwrap(),
+            Some(second.clone())
+        );
+        assert!(branch::create("master", "HEAD~1", true).is_err());
+        assert!(branch::create("bad..name", "HEAD", false).is_err());
+        assert!(branch::create("HEAD", "HEAD", false).is_err());
+        assert!(branch::create("nope", "missing", false).is_err());
+        assert_eq!(
+            names(true, false),
+            ["* refs/heads/master", "refs/heads/old"]
+        );
+
+        // Merged in HEAD
+        let hash = branch::delete("old", false).unwrap();
+        assert_eq!(hash, second);
+        assert!(branch::delete("old", false).is_err());
+        assert!(branch::delete("master", true).is_err());
+
+        // Not merged in HEAD
+        let side = write_commit("side\n", &[&second]);
+        refs::write_ref("refs/heads/side", &side).unwrap();
+        let mut config = Config::read().unwrap();
+        config.set("branch.side.remote", ".").unwrap();
+        config.write().unwrap();
+        let error = branch::delete("side", false).unwrap_err();
+        assert!(error.to_string().contains("not fully merged"));
+        branch::delete("side", true).unwrap();
+        assert_eq!(Config::read().unwrap().get("branch.side.remote"), None);
+        assert_eq!(names(true, false), ["* refs/heads/master"]);
+    });
+}
+
+#[test]
+fn test_branch_rename_and_upstream() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let git_dir = path.join(BASE_DIR);
+
+        // A branch with no commit is renamed in HEAD only
+        branch::rename(None, "main", false).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/main");
+
+        let first = write_commit("first\n", &[]);
+        refs::write_ref("refs/heads/main", &first).unwrap();
+        refs::write_ref("refs/remotes/origin/main", &first).unwrap();
+        refs::write_ref("refs/remotes/origin/dev", &first).unwrap();
+        std::fs::write(
+            git_dir.join("refs/remotes/origin/HEAD"),
+            "ref: refs/remotes/origin/main\n",
+        )
+        .unwrap();
+
+        // Starting from a remote-tracking branch sets the upstream
+        let upstream = branch::create("dev", "origin/dev", false).unwrap();
+        assert_eq!(upstream.as_deref(), Some("origin/dev"));
+        let config = Config::read().unwrap();
+        assert_eq!(config.get("branch.dev.remote"), Some("origin"));
+        assert_eq!(config.get("branch.dev.merge"), Some("refs/heads/dev"));
+        assert_eq!(revision::resolve("dev@{u}").unwrap(), first);
+
+        assert_eq!(
+            branch::set_upstream(None, "origin/main").unwrap(),
+            "origin/main"
+        );
+        assert_eq!(branch::set_upstream(Some("dev"), "main").unwrap(), "main");
+        assert_eq!(Config::read().unwrap().get("branch.dev.remote"), Some("."));
+        assert!(branch::set_upstream(None, "origin/missing").is_err());
+        assert!(branch::set_upstream(Some("missing"), "main").is_err());
+        branch::unset_upstream(Some("dev")).unwrap();
+        assert!(branch::unset_upstream(Some("dev")).is_err());
+
+        // The current branch is renamed with its reflog and config
+        std::fs::create_dir_all(git_dir.join("logs/refs/heads")).unwrap();
+        std::fs::write(git_dir.join("logs/refs/heads/main"), "log\n").unwrap();
+        assert!(branch::rename(None, "dev", false).is_err());
+        branch::rename(None, "trunk", false).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/trunk");
+        assert!(git_dir.join("logs/refs/heads/trunk").is_file());
+        let config = Config::read().unwrap();
+        assert_eq!(config.get("branch.trunk.merge"), Some("refs/heads/main"));
+        assert_eq!(config.get("branch.main.merge"), None);
+        branch::rename(Some("trunk"), "dev", true).unwrap();
+        assert!(branch::rename(Some("missing"), "other", false).is_err());
+
+        assert_eq!(
+            names(true, true),
+            [
+                "* refs/heads/dev",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/dev",
+                "refs/remotes/origin/main"
+            ]
+        );
+        let remote_head = &branch::list(false, true).unwrap()[0];
+        assert_eq!(
+            remote_head.target.as_deref(),
+            Some("refs/remotes/origin/main")
+        );
+    });
+}
+
+#[test]
+fn test_branch_command() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let first = write_commit("first\n", &[]);
+        refs::write_ref("refs/heads/master", &first).unwrap();
+
+        let run = |args: Vec<&str>| RepoRust::branch(&run_test_matches(args));
+        run(vec!["", "branch", "topic"]).unwrap();
+        run(vec!["", "branch", "-v"]).unwrap();
+        run(vec!["", "branch", "-a"]).unwrap();
+        run(vec!["", "branch", "-m", "topic", "feature"]).unwrap();
+        assert!(run(vec!["", "branch", "-d"]).is_err());
+        run(vec!["", "branch", "-u", "feature"]).unwrap();
+        run(vec!["", "branch", "--unset-upstream"]).unwrap();
+        run(vec!["", "branch", "-D", "feature"]).unwrap();
+        assert_eq!(names(true, false), ["* refs/heads/master"]);
+    });
+}
@@ -0,0 +1,181 @@
+use std::fmt::Display;
+
+use crate::git_rust::{BASE_DIR, RepoRust};
+
+// The config file uses the same format as git:
+// [core]
+//         repositoryformatversion = 0
+// [remote "origin"]
+//         url = https://github.com/user/repo
+// Keys are addressed as section.subsection.key. Example: "remote.origin.url"
+#[derive(Default)]
+pub struct Config {
+    pub sections: Vec<ConfigSection>,
+}
+
+pub struct ConfigSection {
+    pub name: String,
+    pub subsection: Option<String>,
+    pub entries: Vec<(String, String)>,
+}
+
+#[allow(dead_code)]
+impl Config {
+    pub fn read() -> std::io::Result<Self> {
+        let config_path = RepoRust::get_root()
+            .absolute_path
+            .join(BASE_DIR)
+            .join("config");
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(config_path)?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> std::io::Result<Self> {
+        let mut sections: Vec<ConfigSection> = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| std::io::Error::other("Invalid config section"))?;
+                let (name, subsection) = match header.split_once(' ') {
+                    Some((name, sub)) => (name, Some(sub.trim().trim_matches('"').to_string())),
+                    None => (header, None),
+                };
+                sections.push(ConfigSection {
+                    name: name.to_lowercase(),
+                    subsection,
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| std::io::Error::other("Config entry outside of a section"))?;
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                // A key with no value is a boolean true
+                None => (line, "true"),
+            };
+            section
+                .entries
+                .push((key.to_lowercase(), value.trim_matches('"').to_string()));
+        }
+        Ok(Self { sections })
+    }
+
+    pub fn write(&self) -> std::io::Result<()> {
+        let config_path = RepoRust::get_root()
+            .absolute_path
+            .join(BASE_DIR)
+            .join("config");
+        std::fs::write(config_path, self.to_string())
+    }
+
+    // Splits "branch.main.merge" into ("branch", Some("main"), "merge")
+    fn split_key(key: &str) -> std::io::Result<(String, Option<String>, String)> {
+        let (section, rest) = key
+            .split_once('.')
+            .ok_or_else(|| std::io::Error::other(format!("Invalid config key: {key}")))?;
+        match rest.rsplit_once('.') {
+            Some((subsection, name)) => Ok((
+                section.to_lowercase(),
+                Some(subsection.to_string()),
+                name.to_lowercase(),
+            )),
+            None => Ok((section.to_lowercase(), None, rest.to_lowercase())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let (section, subsection, name) = Self::split_key(key).ok()?;
+        // Last value wins, same as git
+        self.sections
+            .iter()
+            .filter(|s| s.name == section && s.subsection == subsection)
+            .flat_map(|s| s.entries.iter())
+            .filter(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_str())
+            .next_back()
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> std::io::Result<()> {
+        let (section, subsection, name) = Self::split_key(key)?;
+        let existing = self
+            .sections
+            .iter_mut()
+            .rfind(|s| s.name == section && s.subsection == subsection);
+        let section = match existing {
+            Some(s) => s,
+            None => {
+                self.sections.push(ConfigSection {
+                    name: section,
+                    subsection,
+                    entries: Vec::new(),
+                });
+                self.sections.last_mut().unwrap()
+            }
+        };
+        match section.entries.iter_mut().find(|(k, _)| *k == name) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => section.entries.push((name, value.to_string())),
+        }
+        Ok(())
+    }
+
+    // Removes every value of a key. Returns false if there was none
+    pub fn unset(&mut self, key: &str) -> std::io::Result<bool> {
+        let (section, subsection, name) = Self::split_key(key)?;
+        let mut found = false;
+        for s in self
+            .sections
+            .iter_mut()
+            .filter(|s| s.name == section && s.subsection == subsection)
+        {
+            let before = s.entries.len();
+            s.entries.retain(|(k, _)| *k != name);
+            found |= s.entries.len() != before;
+        }
+        // Same as git, a section left empty is removed
+        self.sections
+            .retain(|s| !(s.name == section && s.subsection == subsection && s.entries.is_empty()));
+        Ok(found)
+    }
+
+    // [branch "main"] is removed with remove_section("branch", Some("main"))
+    pub fn remove_section(&mut self, name: &str, subsection: Option<&str>) -> bool {
+        let before = self.sections.len();
+        self.sections
+            .retain(|s| !(s.name == name && s.subsection.as_deref() == subsection));
+        self.sections.len() != before
+    }
+
+    pub fn rename_section(&mut self, name: &str, old: Option<&str>, new: Option<&str>) {
+        for section in &mut self.sections {
+            if section.name == name && section.subsection.as_deref() == old {
+                section.subsection = new.map(str::to_string);
+            }
+        }
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for section in &self.sections {
+            match &section.subsection {
+                Some(sub) => writeln!(f, "[{} \"{}\"]", section.name, sub)?,
+                None => writeln!(f, "[{}]", section.name)?,
+            }
+            for (key, value) in &section.entries {
+                writeln!(f, "\t{key} = {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1012 @@
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    git_rust::RepoRust,
+    index::{Index, IndexEntry},
+    objects::{
+        self, ObjectType,
+        blob::Blob,
+        tree::{MODE_GITLINK, MODE_SYMLINK, Tree, TreeEntry},
+    },
+    status::{self, Change, WorktreeState},
+    worktree,
+};
+
+#[cfg(test)]
+mod test;
+
+// Same as git diff -U3
+pub const DEFAULT_CONTEXT: usize = 3;
+// Same as git: a NUL in the first 8000 bytes makes a file binary
+const BINARY_CHECK_LEN: usize = 8000;
+const NULL_OID: &str = "0000000000000000000000000000000000000000";
+const ABBREV_LEN: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// One step of the edit script, with the positions of the line in both files
+// An insert has the position in old it is inserted at, a delete the position
+// in new it is removed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub kind: EditKind,
+    pub old: usize,
+    pub new: usize,
+}
+
+// A group of changes with the lines of context around them
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+impl Hunk {
+    // @@ -<start>,<len> +<start>,<len> @@. An empty range starts at the line before it
+    pub fn header(&self) -> String {
+        fn range(start: usize, len: usize) -> String {
+            let start = if len == 0 { start } else { start + 1 };
+            if len == 1 {
+                start.to_string()
+            } else {
+                format!("{start},{len}")
+            }
+        }
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+// One side of a diff: a blob, a file of the working tree, or nothing
+pub struct DiffFile {
+    pub path: String,
+    pub mode: u32,
+    pub oid: String,
+    pub content: Vec<u8>,
+}
+
+impl DiffFile {
+    pub fn from_blob(path: &str, mode: u32, oid: &str) -> std::io::Result<Self> {
+        // A submodule is shown as the commit it points to
+        let content = if mode == MODE_GITLINK {
+            format!("Subproject commit {oid}\n").into_bytes()
+        } else {
+            Blob::decode_object(&objects::read_object(oid)?)?
+        };
+        Ok(Self {
+            path: path.to_string(),
+            mode,
+            oid: oid.to_string(),
+            content,
+        })
+    }
+
+    // The file at path, relative to the root. None if it doesn't exist
+    pub fn from_worktree(path: &str) -> std::io::Result<Option<Self>> {
+        let file_path = RepoRust::get_root().absolute_path.join(path);
+        let Ok(metadata) = file_path.symlink_metadata() else {
+            return Ok(None);
+        };
+        if metadata.is_dir() {
+            return Ok(None);
+        }
+        let content = Index::file_content(&file_path, &metadata)?;
+        Ok(Some(Self {
+            path: path.to_string(),
+            mode: Index::mode_from_metadata(&metadata),
+            oid: objects::hash_object("blob", &content),
+            content,
+        }))
+    }
+
+    fn is_binary(&self) -> bool {
+        self.mode != MODE_SYMLINK && is_binary(&self.content)
+    }
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+// The lines of a file, each with its newline. The last one may have none
+pub fn lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&b| b == b'\n').collect()
+}
+
+// Myers' O(ND) diff: the shortest edit script turning old into new
+// The furthest reaching x of every diagonal k is kept for each number of edits d,
+// then the path is walked back from the end
+pub fn myers<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let i = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(edit(EditKind::Equal, x, y));
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(edit(EditKind::Insert, x, y - 1));
+            } else {
+                edits.push(edit(EditKind::Delete, x - 1, y));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    edits
+}
+
+fn edit(kind: EditKind, old: isize, new: isize) -> Edit {
+    Edit {
+        kind,
+        old: old as usize,
+        new: new as usize,
+    }
+}
+
+// Groups the changes of an edit script with context lines around them
+// Changes closer than two contexts are in the same hunk
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let is_change = |i: usize| edits[i].kind != EditKind::Equal;
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < edits.len() {
+        if !is_change(i) {
+            i += 1;
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let mut end = i;
+        loop {
+            while end < edits.len() && is_change(end) {
+                end += 1;
+            }
+            let mut next = end;
+            while next < edits.len() && !is_change(next) {
+                next += 1;
+            }
+            if next == edits.len() || next - end > 2 * context {
+                break;
+            }
+            end = next;
+        }
+        let stop = (end + context).min(edits.len());
+        let edits = edits[start..stop].to_vec();
+        let count = |kind: EditKind| edits.iter().filter(|e| e.kind != kind).count();
+        hunks.push(Hunk {
+            old_start: edits[0].old,
+            old_len: count(EditKind::Insert),
+            new_start: edits[0].new,
+            new_len: count(EditKind::Delete),
+            edits,
+        });
+        i = stop;
+    }
+    hunks
+}
+
+fn abbrev(oid: &str) -> &str {
+    &oid[..ABBREV_LEN.min(oid.len())]
+}
+
+// git diff patch of a file. old is None for an added file, new for a deleted one
+// source is set when new was renamed or copied from old. Empty if nothing changed
+pub fn patch(
+    old: Option<&DiffFile>,
+    new: Option<&DiffFile>,
+    source: Option<&Source>,
+    context: usize,
+) -> String {
+    let (a_path, b_path) = match (old, new) {
+        (Some(old), Some(new)) => (&old.path, &new.path),
+        (Some(old), None) => (&old.path, &old.path),
+        (None, Some(new)) => (&new.path, &new.path),
+        (None, None) => return String::new(),
+    };
+    let old_oid = old.map_or(NULL_OID, |file| &file.oid);
+    let new_oid = new.map_or(NULL_OID, |file| &file.oid);
+    let old_mode = old.map(|file| file.mode);
+    let new_mode = new.map(|file| file.mode);
+    if old_oid == new_oid && old_mode == new_mode && source.is_none() {
+        return String::new();
+    }
+
+    let mut patch = format!("diff --git a/{a_path} b/{b_path}\n");
+    match (old_mode, new_mode) {
+        (None, Some(mode)) => patch.push_str(&format!("new file mode {mode:06o}\n")),
+        (Some(mode), None) => patch.push_str(&format!("deleted file mode {mode:06o}\n")),
+        (Some(old_mode), Some(new_mode)) if old_mode != new_mode => {
+            patch.push_str(&format!(
+                "old mode {old_mode:06o}\nnew mode {new_mode:06o}\n"
+            ));
+        }
+        _ => {}
+    }
+    if let Some(source) = source {
+        let kind = if source.copy { "copy" } else { "rename" };
+        patch.push_str(&format!(
+            "similarity index {}%\n{kind} from {a_path}\n{kind} to {b_path}\n",
+            source.score
+        ));
+    }
+    // Only the mode or the name changed
+    if old_oid == new_oid {
+        return patch;
+    }
+    patch.push_str(&format!("index {}..{}", abbrev(old_oid), abbrev(new_oid)));
+    if let (Some(old_mode), Some(new_mode)) = (old_mode, new_mode)
+        && old_mode == new_mode
+    {
+        patch.push_str(&format!(" {old_mode:06o}"));
+    }
+    patch.push('\n');
+
+    let a_name = old.map_or("/dev/null".to_string(), |_| format!("a/{a_path}"));
+    let b_name = new.map_or("/dev/null".to_string(), |_| format!("b/{b_path}"));
+    if old.is_some_and(DiffFile::is_binary) || new.is_some_and(DiffFile::is_binary) {
+        patch.push_str(&format!("Binary files {a_name} and {b_name} differ\n"));
+        return patch;
+    }
+    patch.push_str(&format!("--- {a_name}\n+++ {b_name}\n"));
+
+    let old_lines = old.map_or(Vec::new(), |file| lines(&file.content));
+    let new_lines = new.map_or(Vec::new(), |file| lines(&file.content));
+    let edits = myers(&old_lines, &new_lines);
+    for hunk in hunks(&edits, context) {
+        patch.push_str(&hunk.header());
+        patch.push('\n');
+        for edit in &hunk.edits {
+            let (sign, line) = match edit.kind {
+                EditKind::Equal => (' ', old_lines[edit.old]),
+                EditKind::Delete => ('-', old_lines[edit.old]),
+                EditKind::Insert => ('+', new_lines[edit.new]),
+            };
+            patch.push(sign);
+            patch.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                patch.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    patch
+}
+
+// Patch between two blobs of path. None is a missing side
+pub fn diff_blobs(
+    path: &str,
+    old: Option<(u32, &str)>,
+    new: Option<(u32, &str)>,
+    context: usize,
+) -> std::io::Result<String> {
+    let old = old
+        .map(|(mode, oid)| DiffFile::from_blob(path, mode, oid))
+        .transpose()?;
+    let new = new
+        .map(|(mode, oid)| DiffFile::from_blob(path, mode, oid))
+        .transpose()?;
+    Ok(patch(old.as_ref(), new.as_ref(), None, context))
+}
+
+// Patch between a blob of path and the file of the working tree at path
+pub fn diff_blob_file(
+    path: &str,
+    old: Option<(u32, &str)>,
+    context: usize,
+) -> std::io::Result<String> {
+    let old = old
+        .map(|(mode, oid)| DiffFile::from_blob(path, mode, oid))
+        .transpose()?;
+    let new = DiffFile::from_worktree(path)?;
+    Ok(patch(old.as_ref(), new.as_ref(), None, context))
+}
+
+// Mode and hash of one side of a changed path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub mode: u32,
+    pub hash: [u8; 20],
+}
+
+impl Side {
+    pub fn from_tree_entry(entry: &TreeEntry) -> Self {
+        Self {
+            mode: u32::from_str_radix(&entry.mode, 8).unwrap_or_default(),
+            hash: entry.hash,
+        }
+    }
+
+    pub fn from_index_entry(entry: &IndexEntry) -> Self {
+        Self {
+            mode: entry.mode,
+            hash: entry.sha1,
+        }
+    }
+}
+
+// A path that differs between two sides. The new side of a file of the working
+// tree is read from the working tree
+#[derive(Debug)]
+pub struct FileChange {
+    pub path: String,
+    pub old: Option<Side>,
+    pub new: Option<Side>,
+    pub worktree: bool,
+    // The file old comes from, when path was renamed or copied
+    pub source: Option<Source>,
+}
+
+// Where a renamed or copied file comes from, and how similar both are (0-100)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub path: String,
+    pub score: u32,
+    pub copy: bool,
+}
+
+impl FileChange {
+    pub fn change(&self) -> Change {
+        match &self.source {
+            Some(source) if source.copy => return Change::Copied,
+            Some(_) => return Change::Renamed,
+            None => {}
+        }
+        match (&self.old, &self.new) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Deleted,
+            (Some(old), Some(new)) => Change::between(old.mode, &old.hash, new.mode, &new.hash)
+                .unwrap_or(Change::Modified),
+        }
+    }
+
+    // Letter of --name-status and --raw. Renames and copies have their score: R100
+    pub fn status(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{}{:03}", self.change().letter(), source.score),
+            None => self.change().letter().to_string(),
+        }
+    }
+
+    // old -> new, or the path if it was not renamed
+    pub fn display_path(&self, separator: &str) -> String {
+        match &self.source {
+            Some(source) => format!("{}{separator}{}", source.path, self.path),
+            None => self.path.clone(),
+        }
+    }
+
+    // The content of both sides
+    pub fn files(&self) -> std::io::Result<(Option<DiffFile>, Option<DiffFile>)> {
+        let old_path = self
+            .source
+            .as_ref()
+            .map_or(&self.path, |source| &source.path);
+        let old = self
+            .old
+            .as_ref()
+            .map(|side| DiffFile::from_blob(old_path, side.mode, &hex::encode(side.hash)))
+            .transpose()?;
+        let blob =
+            |side: &Side| DiffFile::from_blob(&self.path, side.mode, &hex::encode(side.hash));
+        let new = match &self.new {
+            Some(side) if self.worktree && side.mode != MODE_GITLINK => {
+                DiffFile::from_worktree(&self.path)?
+            }
+            Some(side) => Some(blob(side)?),
+            None => None,
+        };
+        Ok((old, new))
+    }
+}
+
+fn matches(path: &str, pathspecs: &[String]) -> bool {
+    pathspecs.is_empty()
+        || pathspecs
+            .iter()
+            .any(|pathspec| worktree::matches_pathspec(path, pathspec))
+}
+
+fn read_index() -> std::io::Result<Index> {
+    match Index::read_index() {
+        Ok(index) => Ok(index),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Index::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn flatten(tree: Option<&str>) -> std::io::Result<BTreeMap<String, TreeEntry>> {
+    match tree {
+        Some(tree) => Tree::flatten(tree),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+// diff <tree> <tree>. None is the empty tree
+// Subtrees with the same hash on both sides are not read
+pub fn diff_trees(
+    old: Option<&str>,
+    new: Option<&str>,
+    pathspecs: &[String],
+) -> std::io::Result<Vec<FileChange>> {
+    let mut changes = Vec::new();
+    compare_trees("", old, new, &mut changes)?;
+    changes.retain(|change| matches(&change.path, pathspecs));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+fn compare_trees(
+    prefix: &str,
+    old: Option<&str>,
+    new: Option<&str>,
+    changes: &mut Vec<FileChange>,
+) -> std::io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let entries = |tree: Option<&str>| -> std::io::Result<BTreeMap<String, TreeEntry>> {
+        let Some(tree) = tree else {
+            return Ok(BTreeMap::new());
+        };
+        Ok(Tree::decode_object(tree)?
+            .entries
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect())
+    };
+    let old_entries = entries(old)?;
+    let new_entries = entries(new)?;
+    let names: BTreeSet<&String> = old_entries.keys().chain(new_entries.keys()).collect();
+    for name in names {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let old_entry = old_entries.get(name);
+        let new_entry = new_entries.get(name);
+        let subtree = |entry: Option<&TreeEntry>| {
+            entry
+                .filter(|entry| entry.object_type == ObjectType::Tree)
+                .map(|entry| hex::encode(entry.hash))
+        };
+        let file = |entry: Option<&TreeEntry>| {
+            entry
+                .filter(|entry| entry.object_type != ObjectType::Tree)
+                .map(Side::from_tree_entry)
+        };
+        // A folder replaced by a file is a deleted folder and an added file
+        let (old_tree, new_tree) = (subtree(old_entry), subtree(new_entry));
+        if old_tree.is_some() || new_tree.is_some() {
+            compare_trees(&path, old_tree.as_deref(), new_tree.as_deref(), changes)?;
+        }
+        let (old_file, new_file) = (file(old_entry), file(new_entry));
+        if (old_file.is_some() || new_file.is_some()) && old_file != new_file {
+            changes.push(FileChange {
+                path,
+                old: old_file,
+                new: new_file,
+                worktree: false,
+                source: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+// diff --cached [<tree>]. None is the empty tree
+pub fn diff_tree_index(
+    tree: Option<&str>,
+    pathspecs: &[String],
+) -> std::io::Result<Vec<FileChange>> {
+    let tree_files = flatten(tree)?;
+    let index = read_index()?;
+    let paths: BTreeSet<&String> = tree_files.keys().chain(index.entries.keys()).collect();
+    let mut changes = Vec::new();
+    for path in paths {
+        let old = tree_files.get(path).map(Side::from_tree_entry);
+        let new = index.entries.get(path).map(Side::from_index_entry);
+        if old != new && matches(path, pathspecs) {
+            changes.push(FileChange {
+                path: path.clone(),
+                old,
+                new,
+                worktree: false,
+                source: None,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+// diff: the index against the working tree
+// Files whose stat data did not change are not hashed again
+pub fn diff_index_worktree(pathspecs: &[String]) -> std::io::Result<Vec<FileChange>> {
+    let root = &RepoRust::get_root().absolute_path;
+    let index = read_index()?;
+    let index_mtime = status::index_mtime();
+    let mut changes = Vec::new();
+    for (path, entry) in &index.entries {
+        if !matches(path, pathspecs) {
+            continue;
+        }
+        let WorktreeState::Changed(change) =
+            status::worktree_change(&root.join(path), entry, index_mtime)?
+        else {
+            continue;
+        };
+        let new = match change {
+            Change::Deleted => None,
+            _ => worktree_side(path)?,
+        };
+        changes.push(FileChange {
+            path: path.clone(),
+            old: Some(Side::from_index_entry(entry)),
+            new,
+            worktree: true,
+            source: None,
+        });
+    }
+    Ok(changes)
+}
+
+// diff <tree>: a tree against the working tree. Only the files in the index are
+// tracked, so a file not in the index is deleted even if it is on disk
+pub fn diff_tree_worktree(
+    tree: Option<&str>,
+    pathspecs: &[String],
+) -> std::io::Result<Vec<FileChange>> {
+    let tree_files = flatten(tree)?;
+    let index = read_index()?;
+    let paths: BTreeSet<&String> = tree_files.keys().chain(index.entries.keys()).collect();
+    let mut changes = Vec::new();
+    for path in paths {
+        if !matches(path, pathspecs) {
+            continue;
+        }
+        let old = tree_files.get(path).map(Side::from_tree_entry);
+        let new = match index.entries.get(path) {
+            Some(entry) if entry.mode == MODE_GITLINK => Some(Side::from_index_entry(entry)),
+            Some(_) => worktree_side(path)?,
+            None => None,
+        };
+        if old != new {
+            changes.push(FileChange {
+                path: path.clone(),
+                old,
+                new,
+                worktree: true,
+                source: None,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+fn worktree_side(path: &str) -> std::io::Result<Option<Side>> {
+    Ok(DiffFile::from_worktree(path)?.map(|file| Side {
+        mode: file.mode,
+        hash: hex::decode(&file.oid).unwrap().try_into().unwrap(),
+    }))
+}
+
+// Same as git: -M alone finds the files that are at least 50% the same
+pub const DEFAULT_SIMILARITY: u32 = 50;
+
+// -M<n>% and -C<n>%. Without %, the digits are a fraction: -M9 is 90%, -M05 is 5%
+pub fn parse_similarity(value: &str) -> std::io::Result<u32> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid similarity: {value}"),
+        )
+    };
+    if value.is_empty() {
+        return Ok(DEFAULT_SIMILARITY);
+    }
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent
+            .parse::<u32>()
+            .ok()
+            .filter(|percent| *percent <= 100)
+            .ok_or_else(invalid);
+    }
+    if !value.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let fraction: f64 = format!("0.{value}").parse().map_err(|_| invalid())?;
+    Ok((fraction * 100.0) as u32)
+}
+
+// How much of two files is the same, from 0 to 100: the bytes of the lines both
+// have, over the size of the bigger one
+pub fn similarity(old: &[u8], new: &[u8]) -> u32 {
+    let max = old.len().max(new.len());
+    if max == 0 {
+        return 100;
+    }
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let common: usize = myers(&old_lines, &new_lines)
+        .iter()
+        .filter(|edit| edit.kind == EditKind::Equal)
+        .map(|edit| old_lines[edit.old].len())
+        .sum();
+    (common * 100 / max) as u32
+}
+
+// -M and -C: pairs the deleted files with the added files that have a similar content
+// Identical blobs are paired first, then the most similar pairs above threshold
+// With copies, an added file not paired can also come from a modified file, or from
+// the source of a rename
+pub fn detect_renames(
+    changes: Vec<FileChange>,
+    threshold: u32,
+    copies: bool,
+) -> std::io::Result<Vec<FileChange>> {
+    let is_file = |side: &Option<Side>| side.as_ref().is_some_and(|s| s.mode != MODE_GITLINK);
+    let deleted: Vec<usize> = (0..changes.len())
+        .filter(|&i| changes[i].new.is_none() && is_file(&changes[i].old))
+        .collect();
+    let added: Vec<usize> = (0..changes.len())
+        .filter(|&i| changes[i].old.is_none() && is_file(&changes[i].new))
+        .collect();
+    if added.is_empty() || (deleted.is_empty() && !copies) {
+        return Ok(changes);
+    }
+    // added -> (deleted, score)
+    let mut pairs: BTreeMap<usize, (usize, u32)> = BTreeMap::new();
+    let mut used: BTreeSet<usize> = BTreeSet::new();
+
+    // Same blob
+    for &a in &added {
+        let hash = changes[a].new.as_ref().unwrap().hash;
+        if let Some(&d) = deleted
+            .iter()
+            .find(|&&d| !used.contains(&d) && changes[d].old.as_ref().unwrap().hash == hash)
+        {
+            pairs.insert(a, (d, 100));
+            used.insert(d);
+        }
+    }
+
+    // Similar content. Pairs whose sizes are too far apart are not compared
+    let mut contents: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    let mut content = |i: usize| -> std::io::Result<Vec<u8>> {
+        if let Some(content) = contents.get(&i) {
+            return Ok(content.clone());
+        }
+        let (old, new) = changes[i].files()?;
+        let file = if changes[i].new.is_none() { old } else { new };
+        let bytes = file.map(|file| file.content).unwrap_or_default();
+        contents.insert(i, bytes.clone());
+        Ok(bytes)
+    };
+    let mut candidates = Vec::new();
+    let unpaired: Vec<usize> = deleted
+        .iter()
+        .copied()
+        .filter(|d| !used.contains(d))
+        .collect();
+    for &a in added.iter().filter(|a| !pairs.contains_key(a)) {
+        let new = content(a)?;
+        for &d in &unpaired {
+            let old = content(d)?;
+            let (min, max) = (old.len().min(new.len()), old.len().max(new.len()));
+            if max > 0 && min * 100 / max < threshold as usize {
+                continue;
+            }
+            let score = similarity(&old, &new);
+            if score >= threshold {
+                candidates.push((score, a, d));
+            }
+        }
+    }
+    // Best score first. On a tie, the order of the paths
+    candidates.sort_by(|x, y| y.0.cmp(&x.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+    for (score, a, d) in candidates {
+        if pairs.contains_key(&a) || used.contains(&d) {
+            continue;
+        }
+        pairs.insert(a, (d, score));
+        used.insert(d);
+    }
+
+    // Copies: the old side of a modified file, or of a renamed one, is kept
+    let mut copied: BTreeMap<usize, (usize, u32)> = BTreeMap::new();
+    if copies {
+        let sources: Vec<usize> = (0..changes.len())
+            .filter(|&i| {
+                used.contains(&i) || (changes[i].new.is_some() && is_file(&changes[i].old))
+            })
+            .collect();
+        for &a in added.iter().filter(|a| !pairs.contains_key(a)) {
+            let hash = changes[a].new.as_ref().unwrap().hash;
+            let new = content(a)?;
+            let mut best: Option<(usize, u32)> = None;
+            for &s in &sources {
+                let old_side = changes[s].old.as_ref().unwrap();
+                let score = if old_side.hash == hash {
+                    100
+                } else {
+                    let old = DiffFile::from_blob("", old_side.mode, &hex::encode(old_side.hash))?;
+                    similarity(&old.content, &new)
+                };
+                if score >= threshold && best.is_none_or(|(_, best)| score > best) {
+                    best = Some((s, score));
+                }
+            }
+            if let Some(best) = best {
+                copied.insert(a, best);
+            }
+        }
+    }
+
+    let found: BTreeMap<usize, (usize, u32, bool)> = pairs
+        .iter()
+        .map(|(&a, &(s, score))| (a, (s, score, false)))
+        .chain(copied.iter().map(|(&a, &(s, score))| (a, (s, score, true))))
+        .collect();
+    let sources: Vec<(String, Option<Side>)> = changes
+        .iter()
+        .map(|change| (change.path.clone(), change.old.clone()))
+        .collect();
+    let mut result = Vec::new();
+    for (i, mut change) in changes.into_iter().enumerate() {
+        // The deleted side of a rename
+        if used.contains(&i) {
+            continue;
+        }
+        if let Some(&(s, score, copy)) = found.get(&i) {
+            let (path, old) = sources[s].clone();
+            change.old = old;
+            change.source = Some(Source { path, score, copy });
+        }
+        result.push(change);
+    }
+    result.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Patch,
+    Stat,
+    NameOnly,
+    NameStatus,
+    Raw,
+}
+
+// Output of git diff for a list of changes
+pub fn format(changes: &[FileChange], format: Format, context: usize) -> std::io::Result<String> {
+    let mut output = String::new();
+    match format {
+        Format::Patch => {
+            for change in changes {
+                let (old, new) = change.files()?;
+                output.push_str(&patch(
+                    old.as_ref(),
+                    new.as_ref(),
+                    change.source.as_ref(),
+                    context,
+                ));
+            }
+        }
+        Format::Stat => {
+            let mut stats = Vec::new();
+            for change in changes {
+                let (old, new) = change.files()?;
+                let path = change.display_path(" => ");
+                stats.push(FileStat::new(&path, old.as_ref(), new.as_ref()));
+            }
+            output = format_stat(&stats);
+        }
+        Format::NameOnly => {
+            for change in changes {
+                output.push_str(&format!("{}\n", change.path));
+            }
+        }
+        Format::NameStatus => {
+            for change in changes {
+                let path = change.display_path("\t");
+                output.push_str(&format!("{}\t{path}\n", change.status()));
+            }
+        }
+        // :<old mode> <new mode> <old hash> <new hash> <status>\t<path>
+        // The hash of a file of the working tree is not computed, same as git
+        // A rename has both paths: R100\t<old path>\t<new path>
+        Format::Raw => {
+            for change in changes {
+                let side = |side: Option<&Side>, worktree: bool| match side {
+                    Some(side) if !worktree => (side.mode, hex::encode(side.hash)),
+                    Some(side) => (side.mode, NULL_OID.to_string()),
+                    None => (0, NULL_OID.to_string()),
+                };
+                let (old_mode, old_oid) = side(change.old.as_ref(), false);
+                let (new_mode, new_oid) = side(change.new.as_ref(), change.worktree);
+                output.push_str(&format!(
+                    ":{old_mode:06o} {new_mode:06o} {} {} {}\t{}\n",
+                    abbrev(&old_oid),
+                    abbrev(&new_oid),
+                    change.status(),
+                    change.display_path("\t")
+                ));
+            }
+        }
+    }
+    Ok(output)
+}
+
+// Lines added and removed in a file, or its sizes if it is binary
+#[derive(Debug)]
+pub struct FileStat {
+    pub path: String,
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub old_size: usize,
+    pub new_size: usize,
+}
+
+impl FileStat {
+    pub fn new(path: &str, old: Option<&DiffFile>, new: Option<&DiffFile>) -> Self {
+        fn content(file: Option<&DiffFile>) -> &[u8] {
+            file.map_or(&[], |file| &file.content)
+        }
+        let binary = old.is_some_and(DiffFile::is_binary) || new.is_some_and(DiffFile::is_binary);
+        let (mut insertions, mut deletions) = (0, 0);
+        if !binary {
+            let old_lines = lines(content(old));
+            let new_lines = lines(content(new));
+            for edit in myers(&old_lines, &new_lines) {
+                match edit.kind {
+                    EditKind::Insert => insertions += 1,
+                    EditKind::Delete => deletions += 1,
+                    EditKind::Equal => {}
+                }
+            }
+        }
+        Self {
+            path: path.to_string(),
+            binary,
+            insertions,
+            deletions,
+            old_size: content(old).len(),
+            new_size: content(new).len(),
+        }
+    }
+}
+
+// Same as git, the whole line fits in 80 columns
+const STAT_WIDTH: usize = 80;
+
+// diff --stat:
+//  <path> | <changes> <+++-->
+//  1 file changed, 2 insertions(+), 1 deletion(-)
+pub fn format_stat(stats: &[FileStat]) -> String {
+    if stats.is_empty() {
+        return String::new();
+    }
+    let name_width = stats.iter().map(|stat| stat.path.len()).max().unwrap_or(0);
+    let max_changes = stats
+        .iter()
+        .filter(|stat| !stat.binary)
+        .map(|stat| stat.insertions + stat.deletions)
+        .max()
+        .unwrap_or(0);
+    // Room for "Bin" when a file is binary
+    let mut number_width = max_changes.to_string().len();
+    if stats.iter().any(|stat| stat.binary) {
+        number_width = number_width.max(3);
+    }
+    let graph_width = STAT_WIDTH
+        .saturating_sub(name_width + number_width + 5)
+        .max(10);
+    // The longest bar takes the whole graph. Every change gets at least one sign
+    let scale = |n: usize| {
+        if max_changes <= graph_width || n == 0 {
+            n
+        } else {
+            (n * graph_width / max_changes).max(1)
+        }
+    };
+
+    let mut output = String::new();
+    for stat in stats {
+        if stat.binary {
+            output.push_str(&format!(
+                " {:name_width$} | {:>number_width$} {} -> {} bytes\n",
+                stat.path, "Bin", stat.old_size, stat.new_size
+            ));
+            continue;
+        }
+        let total = stat.insertions + stat.deletions;
+        let bar_width = scale(total);
+        let plus = scale(stat.insertions).min(bar_width);
+        let minus = bar_width - plus;
+        let graph = format!("{}{}", "+".repeat(plus), "-".repeat(minus));
+        output.push_str(
+            format!(
+                " {:name_width$} | {total:>number_width$} {graph}",
+                stat.path
+            )
+            .trim_end(),
+        );
+        output.push('\n');
+    }
+    output.push_str(&stat_summary(stats));
+    output
+}
+
+// 1 file changed, 2 insertions(+), 1 deletion(-)
+// Without lines changed, both counts are shown
+pub fn stat_summary(stats: &[FileStat]) -> String {
+    let plural = |n: usize, word: &str| {
+        if n == 1 {
+            format!("{n} {word}")
+        } else {
+            format!("{n} {word}s")
+        }
+    };
+    let insertions: usize = stats.iter().map(|stat| stat.insertions).sum();
+    let deletions: usize = stats.iter().map(|stat| stat.deletions).sum();
+    let mut summary = format!(" {} changed", plural(stats.len(), "file"));
+    if insertions > 0 || deletions == 0 {
+        summary.push_str(&format!(", {}(+)", plural(insertions, "insertion")));
+    }
+    if deletions > 0 || insertions == 0 {
+        summary.push_str(&format!(", {}(-)", plural(deletions, "deletion")));
+    }
+    summary.push('\n');
+    summary
+}
@@ -0,0 +1,426 @@
+use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+use crate::{
+    diff::{self, DiffFile, EditKind},
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType, tree::Tree},
+    refs, revision,
+    status::Change,
+    test_common::{run_test, run_test_matches},
+};
+
+fn blob(content: &str) -> String {
+    objects::write_object(ObjectType::Blob, content.as_bytes()).unwrap()
+}
+
+#[test]
+fn test_myers_shortest_edit_script() {
+    let old: Vec<char> = "ABCABBA".chars().collect();
+    let new: Vec<char> = "CBABAC".chars().collect();
+    let edits = diff::myers(&old, &new);
+    let changes = edits.iter().filter(|e| e.kind != EditKind::Equal).count();
+    assert_eq!(changes, 5);
+    // Applying the script gives new back
+    let result: Vec<char> = edits
+        .iter()
+        .filter_map(|edit| match edit.kind {
+            EditKind::Equal => Some(old[edit.old]),
+            EditKind::Insert => Some(new[edit.new]),
+            EditKind::Delete => None,
+        })
+        .collect();
+    assert_eq!(result, new);
+
+    assert!(diff::myers::<char>(&[], &[]).is_empty());
+    let edits = diff::myers(&[], &['a', 'b']);
+    assert!(edits.iter().all(|e| e.kind == EditKind::Insert));
+    let edits = diff::myers(&['a', 'b'], &[]);
+    assert!(edits.iter().all(|e| e.kind == EditKind::Delete));
+}
+
+#[test]
+fn test_patch_between_blobs() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let old = blob("a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n");
+        let new = blob("a\nB\nc\nd\ne\nf\ng\nh\ni\nJ\n");
+        let patch = diff::diff_blobs(
+            "file.txt",
+            Some((0o100644, &old)),
+            Some((0o100644, &new)),
+            diff::DEFAULT_CONTEXT,
+        )
+        .unwrap();
+        assert_eq!(
+            patch,
+            format!(
+                "diff --git a/file.txt b/file.txt\n\
+                 index {}..{} 100644\n\
+                 --- a/file.txt\n\
+                 +++ b/file.txt\n\
+                 @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+                 @@ -7,4 +7,4 @@\n g\n h\n i\n-j\n+J\n",
+                &old[..7],
+                &new[..7]
+            )
+        );
+        // Closer than two contexts: a single hunk
+        let patch = diff::diff_blobs(
+            "file.txt",
+            Some((0o100644, &old)),
+            Some((0o100644, &new)),
+            4,
+        )
+        .unwrap();
+        assert_eq!(patch.matches("@@ -").count(), 1);
+        assert!(patch.contains("@@ -1,10 +1,10 @@\n"));
+        let patch = diff::diff_blobs(
+            "file.txt",
+            Some((0o100644, &old)),
+            Some((0o100644, &new)),
+            0,
+        )
+        .unwrap();
+        assert!(patch.ends_with("@@ -2 +2 @@\n-b\n+B\n@@ -10 +10 @@\n-j\n+J\n"));
+
+        // Deleted, and unchanged
+        let patch = diff::diff_blobs("file.txt", Some((0o100644, &old)), None, 3).unwrap();
+        assert!(patch.starts_with(&format!(
+            "diff --git a/file.txt b/file.txt\n\
+             deleted file mode 100644\n\
+             index {}..0000000\n\
+             --- a/file.txt\n\
+             +++ /dev/null\n\
+             @@ -1,10 +0,0 @@\n-a\n",
+            &old[..7]
+        )));
+        let patch = diff::diff_blobs(
+            "file.txt",
+            Some((0o100644, &old)),
+            Some((0o100644, &old)),
+            3,
+        );
+        assert!(patch.unwrap().is_empty());
+    });
+}
+
+#[test]
+fn test_patch_between_blob_and_file() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // New file without a newline at the end
+        std::fs::write(path.join("new.txt"), "one\ntwo").unwrap();
+        let new = DiffFile::from_worktree("new.txt").unwrap().unwrap();
+        assert_eq!(
+            diff::diff_blob_file("new.txt", None, 3).unwrap(),
+            format!(
+                "diff --git a/new.txt b/new.txt\n\
+                 new file mode 100644\n\
+                 index 0000000..{}\n\
+                 --- /dev/null\n\
+                 +++ b/new.txt\n\
+                 @@ -0,0 +1,2 @@\n+one\n+two\n\\ No newline at end of file\n",
+                &new.oid[..7]
+            )
+        );
+
+        // Only the mode changed
+        let oid = blob("one\ntwo");
+        let file = path.join("new.txt");
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            diff::diff_blob_file("new.txt", Some((0o100644, &oid)), 3).unwrap(),
+            "diff --git a/new.txt b/new.txt\nold mode 100644\nnew mode 100755\n"
+        );
+
+        // Binary content is not diffed line by line
+        std::fs::write(&file, b"one\0two").unwrap();
+        let patch = diff::diff_blob_file("new.txt", Some((0o100755, &oid)), 3).unwrap();
+        assert!(patch.ends_with("Binary files a/new.txt and b/new.txt differ\n"));
+        assert!(!patch.contains("---"));
+
+        // Deleted from the working tree
+        std::fs::remove_file(&file).unwrap();
+        let patch = diff::diff_blob_file("new.txt", Some((0o100755, &oid)), 3).unwrap();
+        assert!(patch.contains("deleted file mode 100755\n"));
+        assert!(patch.ends_with("-one\n-two\n\\ No newline at end of file\n"));
+        assert!(DiffFile::from_worktree("new.txt").unwrap().is_none());
+    });
+}
+
+fn add(path: &str) {
+    let args = run_test_matches(vec!["", "add", path]);
+    RepoRust::add(&args).unwrap();
+}
+
+fn commit(message: &str) -> String {
+    let args = run_test_matches(vec!["", "commit", "-m", message]);
+    RepoRust::commit(&args).unwrap();
+    refs::read_ref("HEAD").unwrap().unwrap()
+}
+
+fn diff(args: &[&str]) -> String {
+    let args = [&["", "diff"], args].concat();
+    RepoRust::diff_output(&run_test_matches(args)).unwrap()
+}
+
+#[test]
+fn test_diff_command_sources() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::create_dir_all(path.join("dir/sub")).unwrap();
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        std::fs::write(path.join("dir/b.txt"), "b\n").unwrap();
+        std::fs::write(path.join("dir/sub/c.txt"), "c\n").unwrap();
+
+        // Before the first commit, HEAD is the empty tree
+        add(".");
+        assert_eq!(
+            diff(&["--cached", "--name-status"]),
+            "A\ta.txt\nA\tdir/b.txt\nA\tdir/sub/c.txt\n"
+        );
+        let first = commit("first");
+        assert_eq!(diff(&[]), "");
+
+        std::fs::write(path.join("a.txt"), "a\nmore\n").unwrap();
+        std::fs::remove_file(path.join("dir/b.txt")).unwrap();
+        assert_eq!(diff(&["--name-status"]), "M\ta.txt\nD\tdir/b.txt\n");
+        assert!(diff(&[]).contains("@@ -1 +1,2 @@\n a\n+more\n"));
+        // Limited to paths, with and without --
+        assert_eq!(diff(&["--name-only", "--", "dir"]), "dir/b.txt\n");
+        assert_eq!(diff(&["--name-only", "a.txt"]), "a.txt\n");
+
+        add("a.txt");
+        assert_eq!(diff(&["--name-only"]), "dir/b.txt\n");
+        assert_eq!(diff(&["--cached", "--name-only"]), "a.txt\n");
+        assert_eq!(diff(&["HEAD", "--name-only"]), "a.txt\ndir/b.txt\n");
+
+        let args = run_test_matches(vec!["", "add", "-A"]);
+        RepoRust::add(&args).unwrap();
+        std::fs::write(path.join("dir/sub/c.txt"), "c\nc\n").unwrap();
+        add("dir");
+        let second = commit("second");
+        assert_eq!(
+            diff(&[&first, &second, "--name-status"]),
+            "M\ta.txt\nD\tdir/b.txt\nM\tdir/sub/c.txt\n"
+        );
+        assert_eq!(
+            diff(&[&second, &first, "--name-status", "--", "dir/sub"]),
+            "M\tdir/sub/c.txt\n"
+        );
+        let patch = diff(&[&first, &second]);
+        assert!(patch.starts_with("diff --git a/a.txt b/a.txt\n"));
+        assert!(patch.contains("deleted file mode 100644\n"));
+    });
+}
+
+#[test]
+fn test_diff_trees_skips_equal_subtrees() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::create_dir_all(path.join("same")).unwrap();
+        std::fs::write(path.join("same/file.txt"), "same\n").unwrap();
+        std::fs::write(path.join("file.txt"), "old\n").unwrap();
+        add(".");
+        let first = commit("first");
+        let first_tree = revision::resolve_as(&first, ObjectType::Tree).unwrap();
+        let same = Tree::decode_object(&first_tree)
+            .unwrap()
+            .entries
+            .into_iter()
+            .find(|entry| entry.name == "same")
+            .unwrap()
+            .hash;
+
+        // A folder replaced by a file
+        std::fs::write(path.join("file.txt"), "new\n").unwrap();
+        add("file.txt");
+        let second = commit("second");
+        let second_tree = revision::resolve_as(&second, ObjectType::Tree).unwrap();
+
+        // The shared subtree is never read: without it the diff still works
+        let object = path
+            .join(BASE_DIR)
+            .join("objects")
+            .join(hex::encode(&same[..1]))
+            .join(hex::encode(&same[1..]));
+        std::fs::remove_file(object).unwrap();
+        let changes = diff::diff_trees(Some(&first_tree), Some(&second_tree), &[]).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert_eq!(changes[0].change(), Change::Modified);
+        assert!(diff::diff_trees(None, Some(&second_tree), &[]).is_err());
+    });
+}
+
+#[test]
+fn test_diff_raw_and_stat_output() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::write(path.join("a.txt"), "1\n2\n3\n").unwrap();
+        std::fs::write(path.join("bin"), b"\0\0").unwrap();
+        add(".");
+        commit("first");
+        let old = DiffFile::from_worktree("a.txt").unwrap().unwrap().oid;
+
+        std::fs::write(path.join("a.txt"), "1\nb\n3\n4\n").unwrap();
+        std::fs::write(path.join("bin"), b"\0\0\0").unwrap();
+        assert_eq!(
+            diff(&["--raw"]),
+            format!(
+                ":100644 100644 {} 0000000 M\ta.txt\n\
+                 :100644 100644 {} 0000000 M\tbin\n",
+                &old[..7],
+                &DiffFile::from_blob("bin", 0o100644, &objects::hash_object("blob", b"\0\0"))
+                    .unwrap()
+                    .oid[..7]
+            )
+        );
+        assert_eq!(
+            diff(&["--stat"]),
+            " a.txt |   3 ++-\n\
+             \x20bin   | Bin 2 -> 3 bytes\n\
+             \x202 files changed, 2 insertions(+), 1 deletion(-)\n"
+        );
+
+        // Long bars are scaled to fit
+        let lines: String = (0..200).map(|i| format!("x{i}\n")).collect();
+        std::fs::write(path.join("a.txt"), lines).unwrap();
+        let stat = diff(&["--stat", "a.txt"]);
+        let bar = stat.lines().next().unwrap();
+        assert!(bar.len() <= 80);
+        assert!(bar.starts_with(" a.txt | 203 +"));
+        assert!(bar.ends_with('-'));
+        assert!(stat.ends_with(" 1 file changed, 200 insertions(+), 3 deletions(-)\n"));
+        // Only the mode changed
+        std::fs::write(path.join("a.txt"), "1\n2\n3\n").unwrap();
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(path.join("a.txt"), permissions).unwrap();
+        assert_eq!(
+            diff(&["--stat", "--", "a.txt"]),
+            " a.txt | 0\n 1 file changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+        assert_eq!(
+            diff::stat_summary(&[]),
+            " 0 files changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+    });
+}
+
+#[test]
+fn test_detect_renames_and_copies() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let content: String = (0..10).map(|i| format!("line {i}\n")).collect();
+        std::fs::write(path.join("same.txt"), &content).unwrap();
+        let original: String = (0..10).map(|i| format!("edit {i}\n")).collect();
+        std::fs::write(path.join("edited.txt"), &original).unwrap();
+        std::fs::write(path.join("other.txt"), "other\n").unwrap();
+        let source: String = (0..10).map(|i| format!("source {i}\n")).collect();
+        std::fs::write(path.join("source.txt"), &source).unwrap();
+        add(".");
+        let first = commit("first");
+
+        // Moved as is, moved and edited (8 of 10 lines kept), deleted and replaced by
+        // something else, and copied from a file that is also modified
+        std::fs::rename(path.join("same.txt"), path.join("moved.txt")).unwrap();
+        let edited = original
+            .replace("edit 3\n", "three\n")
+            .replace("edit 7\n", "7\n");
+        std::fs::remove_file(path.join("edited.txt")).unwrap();
+        std::fs::write(path.join("dir_edited.txt"), &edited).unwrap();
+        std::fs::remove_file(path.join("other.txt")).unwrap();
+        std::fs::write(path.join("unrelated.txt"), "unrelated\n").unwrap();
+        std::fs::write(path.join("copy.txt"), &source).unwrap();
+        std::fs::write(path.join("source.txt"), format!("{source}more\n")).unwrap();
+        let args = run_test_matches(vec!["", "add", "-A"]);
+        RepoRust::add(&args).unwrap();
+        let second = commit("second");
+
+        assert_eq!(
+            diff(&[&first, &second, "--name-status", "-M"]),
+            "A\tcopy.txt\n\
+             R080\tedited.txt\tdir_edited.txt\n\
+             R100\tsame.txt\tmoved.txt\n\
+             D\tother.txt\n\
+             M\tsource.txt\n\
+             A\tunrelated.txt\n"
+        );
+        // Above the similarity of the edited file
+        assert_eq!(
+            diff(&[&first, &second, "--name-status", "-M90%"]),
+            "A\tcopy.txt\n\
+             A\tdir_edited.txt\n\
+             D\tedited.txt\n\
+             R100\tsame.txt\tmoved.txt\n\
+             D\tother.txt\n\
+             M\tsource.txt\n\
+             A\tunrelated.txt\n"
+        );
+        let copies = diff(&[&first, &second, "--name-status", "-C"]);
+        assert!(copies.starts_with("C100\tsource.txt\tcopy.txt\n"));
+
+        let patch = diff(&["-M", &first, &second, "--", "moved.txt", "same.txt"]);
+        assert_eq!(
+            patch,
+            "diff --git a/same.txt b/moved.txt\n\
+             similarity index 100%\n\
+             rename from same.txt\n\
+             rename to moved.txt\n"
+        );
+        let stat = diff(&[
+            &first,
+            &second,
+            "-M",
+            "--stat",
+            "--",
+            "moved.txt",
+            "same.txt",
+        ]);
+        assert!(stat.starts_with(" same.txt => moved.txt | 0\n"));
+        let raw = diff(&[
+            &first,
+            &second,
+            "-M",
+            "--raw",
+            "--",
+            "moved.txt",
+            "same.txt",
+        ]);
+        assert!(raw.ends_with(" R100\tsame.txt\tmoved.txt\n"));
+    });
+}
+
+#[test]
+fn test_parse_similarity() {
+    assert_eq!(diff::parse_similarity("").unwrap(), 50);
+    assert_eq!(diff::parse_similarity("90%").unwrap(), 90);
+    assert_eq!(diff::parse_similarity("9").unwrap(), 90);
+    assert_eq!(diff::parse_similarity("05").unwrap(), 5);
+    assert_eq!(diff::parse_similarity("100%").unwrap(), 100);
+    assert!(diff::parse_similarity("150%").is_err());
+    assert!(diff::parse_similarity("HEAD").is_err());
+    assert_eq!(diff::similarity(b"a\nb\n", b"a\nb\n"), 100);
+    assert_eq!(diff::similarity(b"a\nb\n", b"a\nc\n"), 50);
+    assert_eq!(diff::similarity(b"a\n", b"b\n"), 0);
+}
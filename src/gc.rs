@@ -0,0 +1,322 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    git_rust::RepoRust,
+    index::Index,
+    log,
+    objects::{ObjectType, tree},
+    odb::{self, LooseDatabase, ObjectDatabase, PackedDatabase},
+    pack::{
+        reader, type_code,
+        writer::{self, PackObject, WrittenPack},
+    },
+    reflog, refs,
+};
+
+#[cfg(test)]
+mod test;
+
+// Unreachable objects younger than this are kept. Same as gc.pruneExpire
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+pub struct RepackOptions {
+    pub window: usize,
+    pub depth: usize,
+    // Removes the old packs and the loose objects that are in the new pack
+    pub delete_redundant: bool,
+}
+
+impl Default for RepackOptions {
+    fn default() -> Self {
+        Self {
+            window: writer::DEFAULT_WINDOW,
+            depth: writer::DEFAULT_DEPTH,
+            delete_redundant: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RepackSummary {
+    pub objects: usize,
+    pub deltas: usize,
+    // Checksum of the new pack. None when there was nothing to pack
+    pub pack: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct GcSummary {
+    pub repack: RepackSummary,
+    pub pruned: usize,
+}
+
+// An object and the name of the tree entry it was found under
+// The name is empty for commits and tags
+pub struct ReachableObject {
+    pub hash: String,
+    pub name: String,
+}
+
+// Walks everything that can be reached from:
+// HEAD, all the refs (loose and packed), the reflogs and the index
+// Commits -> tree and parents / Trees -> entries / Tags -> tagged object
+// Submodules (gitlinks) are commits of another repo and are not followed
+pub fn reachable_objects() -> std::io::Result<Vec<ReachableObject>> {
+    let mut stack: Vec<(String, String)> = Vec::new();
+    for hash in ref_tips()? {
+        stack.push((hash, String::new()));
+    }
+    let index = match Index::read_index() {
+        Ok(index) => index,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Index::default(),
+        Err(e) => return Err(e),
+    };
+    for (path, entry) in index.entries {
+        if entry.mode == 0o160000 {
+            continue;
+        }
+        let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        stack.push((hex::encode(entry.sha1), name));
+    }
+    // The tips are walked in the order they were found
+    stack.reverse();
+
+    let database = odb::database();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut reachable = Vec::new();
+    while let Some((hash, name)) = stack.pop() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        let (object_type, content) = database.read(&hash).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("Missing reachable object {hash}: {e}"))
+        })?;
+        match object_type {
+            ObjectType::Commit | ObjectType::Tag => {
+                // Only the header lines before the message hold links
+                for line in content.split(|&b| b == b'\n') {
+                    if line.is_empty() {
+                        break;
+                    }
+                    let line = String::from_utf8_lossy(line);
+                    let linked = line
+                        .strip_prefix("tree ")
+                        .or_else(|| line.strip_prefix("parent "))
+                        .or_else(|| line.strip_prefix("object "));
+                    if let Some(linked) = linked {
+                        stack.push((linked.to_string(), String::new()));
+                    }
+                }
+            }
+            ObjectType::Tree => {
+                for (mode, entry_name, entry_hash) in tree::raw_entries(&content)? {
+                    if mode != "160000" {
+                        stack.push((entry_hash, entry_name));
+                    }
+                }
+            }
+            ObjectType::Blob => {}
+        }
+        reachable.push(ReachableObject { hash, name });
+    }
+    Ok(reachable)
+}
+
+// Hashes pointed to by HEAD, the refs (loose and packed) and the reflogs
+// Symbolic refs are skipped, their target is a ref as well
+fn ref_tips() -> std::io::Result<Vec<String>> {
+    let mut tips: Vec<String> = refs::read_ref("HEAD")?.into_iter().collect();
+    tips.extend(refs::list_refs("refs")?.into_iter().map(|(_, hash)| hash));
+    // A reflog may point to an object that was already pruned
+    let database = odb::database();
+    tips.extend(
+        reflog::hashes()?
+            .into_iter()
+            .filter(|hash| database.exists(hash)),
+    );
+    Ok(tips)
+}
+
+// git repack -a [-d]
+// Writes all the reachable objects in a single new pack
+pub fn repack(options: &RepackOptions) -> std::io::Result<RepackSummary> {
+    let reachable = reachable_objects()?;
+    repack_objects(&reachable, options)
+}
+
+fn repack_objects(
+    reachable: &[ReachableObject],
+    options: &RepackOptions,
+) -> std::io::Result<RepackSummary> {
+    if reachable.is_empty() {
+        return Ok(RepackSummary::default());
+    }
+    let database = odb::database();
+    let mut pack_objects = Vec::with_capacity(reachable.len());
+    for object in reachable {
+        let (object_type, data) = database.read(&object.hash)?;
+        pack_objects.push(PackObject {
+            hash: hash_bytes(&object.hash)?,
+            object_type: type_code(&object_type),
+            data,
+            name_hash: writer::name_hash(&object.name),
+        });
+    }
+    let pack = writer::write_pack(&pack_objects, options.window, options.depth)?;
+    let pack_path = save_pack(&pack)?;
+    let checksum = hex::encode(pack.index.pack_checksum);
+
+    if options.delete_redundant {
+        for old_pack in reader::packs()? {
+            if old_pack.path != pack_path {
+                std::fs::remove_file(old_pack.path.with_extension("idx"))?;
+                std::fs::remove_file(&old_pack.path)?;
+            }
+        }
+        reader::clear_cache();
+        let loose = LooseDatabase::for_repo();
+        for hash in loose.iter()? {
+            if pack.index.find(&hash_bytes(&hash)?).is_some()
+                && let Some(path) = loose.path(&hash)
+            {
+                remove_loose(&path)?;
+            }
+        }
+    }
+
+    Ok(RepackSummary {
+        objects: pack_objects.len(),
+        deltas: pack.deltas,
+        pack: Some(checksum),
+    })
+}
+
+// Deletes a loose object, and its objects/xx folder once it is empty, same as git prune
+fn remove_loose(path: &Path) -> std::io::Result<()> {
+    std::fs::remove_file(path)?;
+    let folder = path.parent().unwrap();
+    if std::fs::read_dir(folder)?.next().is_none() {
+        std::fs::remove_dir(folder)?;
+    }
+    Ok(())
+}
+
+// Writes objects/pack/pack-<checksum>.pack and its .idx
+fn save_pack(pack: &WrittenPack) -> std::io::Result<PathBuf> {
+    let pack_dir = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path).join("pack");
+    std::fs::create_dir_all(&pack_dir)?;
+    let pack_path = pack_dir.join(format!(
+        "pack-{}.pack",
+        hex::encode(pack.index.pack_checksum)
+    ));
+    std::fs::write(&pack_path, &pack.data)?;
+    // The .idx last. A pack without its index is ignored by the readers
+    std::fs::write(pack_path.with_extension("idx"), pack.index.to_bytes())?;
+    Ok(pack_path)
+}
+
+// git gc
+// 0. All the refs are packed in packed-refs (pack-refs --all) and old reflog
+//    entries are expired (reflog expire --all)
+// 1. Unreachable objects in packs younger than the expiry are written as loose objects,
+//    with the time of their pack. Otherwise they would be lost with the old packs
+// 2. All the reachable objects are repacked and the old packs are deleted
+// 3. Loose objects in the new pack are deleted
+// 4. Unreachable loose objects older than the expiry are deleted
+// expiry is None for --prune=never
+pub fn gc(expiry: Option<SystemTime>) -> std::io::Result<GcSummary> {
+    refs::pack_refs(true, true)?;
+    let expire = log::parse_date(reflog::DEFAULT_EXPIRE)?;
+    let expire_unreachable = log::parse_date(reflog::DEFAULT_EXPIRE_UNREACHABLE)?;
+    for name in reflog::list()? {
+        reflog::expire(&name, Some(expire), Some(expire_unreachable))?;
+    }
+    let reachable = reachable_objects()?;
+    let reachable_set: HashSet<&str> = reachable.iter().map(|o| o.hash.as_str()).collect();
+    let loose = LooseDatabase::for_repo();
+
+    for pack in reader::packs()? {
+        let pack_time = std::fs::metadata(&pack.path)?.modified()?;
+        if expiry.is_some_and(|expiry| pack_time <= expiry) {
+            continue;
+        }
+        for hash in &pack.index.hashes {
+            let hash = hex::encode(hash);
+            if reachable_set.contains(hash.as_str()) || loose.exists(&hash) {
+                continue;
+            }
+            let (object_type, content) = PackedDatabase::for_repo().read(&hash)?;
+            loose.write(object_type, &content)?;
+            std::fs::File::options()
+                .write(true)
+                .open(loose.path(&hash).unwrap())?
+                .set_modified(pack_time)?;
+        }
+    }
+
+    let repack = repack_objects(
+        &reachable,
+        &RepackOptions {
+            delete_redundant: true,
+            ..Default::default()
+        },
+    )?;
+
+    let mut pruned = 0;
+    if let Some(expiry) = expiry {
+        for hash in loose.iter()? {
+            if reachable_set.contains(hash.as_str()) {
+                continue;
+            }
+            let Some(path) = loose.path(&hash) else {
+                continue;
+            };
+            if std::fs::metadata(&path)?.modified()? <= expiry {
+                remove_loose(&path)?;
+                pruned += 1;
+            }
+        }
+    }
+    Ok(GcSummary { repack, pruned })
+}
+
+// Parses the value of --prune
+// now / never / <number>.<unit>[.ago]. Example: 2.weeks.ago
+pub fn parse_expiry(value: &str) -> std::io::Result<Option<SystemTime>> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid prune expiry: {value}"),
+        )
+    };
+    match value {
+        "now" => return Ok(Some(SystemTime::now())),
+        "never" => return Ok(None),
+        _ => {}
+    }
+    let value = value.strip_suffix(".ago").unwrap_or(value);
+    let (number, unit) = value.split_once('.').ok_or_else(invalid)?;
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit.trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(SystemTime::now().checked_sub(Duration::from_secs(number * seconds)))
+}
+
+fn hash_bytes(hash: &str) -> std::io::Result<[u8; 20]> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|h| h.try_into().ok())
+        .ok_or_else(|| std::io::Error::other(format!("Invalid object hash {hash}")))
+}
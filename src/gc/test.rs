@@ -0,0 +1,180 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    gc::{self, RepackOptions},
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType},
+    odb::{LooseDatabase, ObjectDatabase},
+    pack::reader,
+    reflog,
+    refs::write_ref,
+    test_common::run_test,
+};
+
+// Writes a commit with a single file as loose objects and moves master to it
+// Returns the hash of the blob and of the commit
+fn commit_file(content: &str, parent: Option<&str>) -> (String, String) {
+    let blob = objects::write_object(ObjectType::Blob, content.as_bytes()).unwrap();
+    let mut tree = b"100644 file.txt\0".to_vec();
+    tree.extend_from_slice(&hex::decode(&blob).unwrap());
+    let tree = objects::write_object(ObjectType::Tree, &tree).unwrap();
+    let mut commit = format!("tree {tree}\n");
+    if let Some(parent) = parent {
+        commit.push_str(&format!("parent {parent}\n"));
+    }
+    commit.push_str("author A U Thor <author@example.com> 1700000000 +0000\n");
+    commit.push_str("committer A U Thor <author@example.com> 1700000000 +0000\n\nmessage\n");
+    let commit = objects::write_object(ObjectType::Commit, commit.as_bytes()).unwrap();
+    write_ref("refs/heads/master", &commit).unwrap();
+    (blob, commit)
+}
+
+fn loose_objects() -> Vec<String> {
+    let mut hashes: Vec<String> = LooseDatabase::for_repo().iter().unwrap().collect();
+    hashes.sort();
+    hashes
+}
+
+fn pack_files(path: &Path) -> Vec<PathBuf> {
+    let pack_dir = path.join(BASE_DIR).join("objects/pack");
+    match std::fs::read_dir(pack_dir) {
+        Ok(dir) => dir.map(|e| e.unwrap().path()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn history(path: &Path) -> Vec<(String, String)> {
+    RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+    RepoRust::init().unwrap();
+    let mut content = String::new();
+    for i in 0..200 {
+        content.push_str(&format!("line {i}\n"));
+    }
+    let mut commits: Vec<(String, String)> = Vec::new();
+    for version in 0..3 {
+        content.push_str(&format!("version {version}\n"));
+        let parent = commits.last().map(|(_, commit)| commit.as_str());
+        commits.push(commit_file(&content, parent));
+    }
+    commits
+}
+
+#[test]
+fn test_gc_packs_and_prunes() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        let commits = history(&path);
+        let unreachable = objects::write_object(ObjectType::Blob, b"nobody points to me").unwrap();
+
+        let summary = gc::gc(gc::parse_expiry("now").unwrap()).unwrap();
+        // 3 commits, 3 trees and 3 blobs
+        assert_eq!(summary.repack.objects, 9);
+        assert!(summary.repack.deltas >= 2);
+        assert_eq!(summary.pruned, 1);
+
+        assert!(loose_objects().is_empty());
+        assert_eq!(pack_files(&path).len(), 2);
+        // The objects/xx folders are removed once empty
+        let objects_dir = path.join(BASE_DIR).join("objects");
+        let folders: Vec<String> = std::fs::read_dir(&objects_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.len() == 2)
+            .collect();
+        assert!(folders.is_empty(), "{folders:?}");
+        for (blob, commit) in &commits {
+            assert!(objects::read_object(blob).is_ok());
+            assert!(objects::read_object(commit).is_ok());
+        }
+        assert!(objects::read_object(&unreachable).is_err());
+
+        // Running it again replaces the pack with an identical one
+        gc::gc(gc::parse_expiry("now").unwrap()).unwrap();
+        assert_eq!(pack_files(&path).len(), 2);
+    });
+}
+
+#[test]
+fn test_gc_keeps_recent_unreachable_objects() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        history(&path);
+        gc::gc(gc::parse_expiry("now").unwrap()).unwrap();
+
+        // Rewind master. The last commit is only in the pack now
+        let (_, commit) = commit_file("rewritten\n", None);
+        // The reflogs keep the old commits reachable until they expire
+        let summary = gc::gc(gc::parse_expiry("now").unwrap()).unwrap();
+        assert_eq!(summary.repack.objects, 12);
+        for name in reflog::list().unwrap() {
+            reflog::expire(&name, Some(i64::MAX), None).unwrap();
+        }
+        let orphan = objects::write_object(ObjectType::Blob, b"recent orphan").unwrap();
+        let old_packs = pack_files(&path);
+
+        let summary = gc::gc(gc::parse_expiry(gc::DEFAULT_PRUNE_EXPIRE).unwrap()).unwrap();
+        assert_eq!(summary.pruned, 0);
+        assert!(objects::read_object(&orphan).is_ok());
+        // Objects of the old pack are kept as loose objects
+        assert!(old_packs.iter().all(|p| !p.exists()));
+        assert!(loose_objects().len() > 1);
+        assert!(objects::read_object(&commit).is_ok());
+
+        // Loose objects older than the expiry are pruned
+        let orphan_path = LooseDatabase::for_repo().path(&orphan).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60);
+        std::fs::File::options()
+            .write(true)
+            .open(&orphan_path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let summary = gc::gc(gc::parse_expiry(gc::DEFAULT_PRUNE_EXPIRE).unwrap()).unwrap();
+        assert_eq!(summary.pruned, 1);
+        assert!(!orphan_path.exists());
+    });
+}
+
+#[test]
+fn test_repack_without_delete_keeps_loose_objects() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        history(&path);
+        let loose = loose_objects();
+
+        let summary = gc::repack(&RepackOptions::default()).unwrap();
+        assert_eq!(summary.objects, 9);
+        assert_eq!(loose_objects(), loose);
+
+        // With -d the loose copies go away. Reads fall back to the pack
+        gc::repack(&RepackOptions {
+            delete_redundant: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(loose_objects().is_empty());
+        assert_eq!(reader::packs().unwrap().len(), 1);
+        for hash in loose {
+            assert!(objects::read_object(&hash).is_ok());
+        }
+    });
+}
+
+#[test]
+fn test_parse_expiry() {
+    let now = SystemTime::now();
+    assert!(gc::parse_expiry("never").unwrap().is_none());
+    assert!(gc::parse_expiry("now").unwrap().unwrap() >= now);
+    let two_weeks = gc::parse_expiry("2.weeks.ago").unwrap().unwrap();
+    let elapsed = now.duration_since(two_weeks).unwrap_or_default();
+    assert!(elapsed >= Duration::from_secs(14 * 24 * 60 * 60 - 5));
+    assert!(gc::parse_expiry("1.day").is_ok());
+    assert!(gc::parse_expiry("yesterday").is_err());
+    assert!(gc::parse_expiry("3.fortnights.ago").is_err());
+}
@@ -9,14 +9,20 @@ use thread_local::ThreadLocal;
 use tracing::{debug, error, info, instrument};
 
 use crate::{
+    branch, diff,
+    gc::{self, RepackOptions},
     index::Index,
+    log, merge,
     objects::{
-        self,
+        self, ObjectType,
         blob::Blob,
         commit::{Commit, CommitSummary},
+        tag::Tag,
         tree::Tree,
     },
-    requests::fetch::fetch,
+    odb, pack, reflog, refs,
+    requests::{clone, fetch::fetch},
+    revision, status, worktree,
 };
 
 pub const BASE_DIR: &str = ".git_rust";
@@ -57,6 +63,7 @@ impl RepoRust {
             let mut guard = mutex.lock().unwrap();
             guard.take();
         }
+        odb::reset_database();
     }
 
     // TODO
@@ -183,8 +190,8 @@ impl RepoRust {
 
     pub fn cat_file(args: &ArgMatches) -> std::io::Result<Vec<u8>> {
         let sub_arg = args.get_flag("pretty");
-        let hash = args.get_one::<String>("hash").unwrap();
-        objects::cat_file(hash, sub_arg)
+        let hash = revision::resolve(args.get_one::<String>("hash").unwrap())?;
+        objects::cat_file(&hash, sub_arg)
     }
 
     pub fn hash_object(args: &ArgMatches) -> std::io::Result<()> {
@@ -198,15 +205,57 @@ impl RepoRust {
             .get_one::<String>("hash")
             .expect("Object is required.")
             .to_owned();
+        // A commit or a tag is listed as its tree
+        let hash_str = revision::resolve_as(&hash_str, ObjectType::Tree)?;
         let tree = Tree::decode_object(&hash_str)?;
         println!("{tree}");
         Ok(())
     }
 
     pub fn add(args: &ArgMatches) -> std::io::Result<()> {
-        let path = args.get_one::<String>("path").unwrap().to_owned();
+        // -u and -A without a path work on the whole tree
+        let path = args
+            .get_one::<String>("path")
+            .map_or(".", |path| path.as_str())
+            .to_owned();
         Self::check_paths(&path)?;
-        Index::build_index(&path)?;
+        if args.get_flag("update") || args.get_flag("all") {
+            Index::update_tracked(&path)?;
+        }
+        if !args.get_flag("update") {
+            Index::build_index(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn rm(args: &ArgMatches) -> std::io::Result<()> {
+        let pathspecs: Vec<String> = args
+            .get_many::<String>("pathspec")
+            .unwrap()
+            .cloned()
+            .collect();
+        for pathspec in &pathspecs {
+            Self::check_paths(pathspec)?;
+        }
+        let removed = worktree::remove_paths(
+            &pathspecs,
+            args.get_flag("cached"),
+            args.get_flag("recursive"),
+            args.get_flag("force"),
+        )?;
+        for path in removed {
+            println!("rm '{path}'");
+        }
+        Ok(())
+    }
+
+    pub fn mv(args: &ArgMatches) -> std::io::Result<()> {
+        let mut sources: Vec<String> = args.get_many::<String>("paths").unwrap().cloned().collect();
+        for path in &sources {
+            Self::check_paths(path)?;
+        }
+        let destination = sources.pop().unwrap();
+        worktree::move_paths(&sources, &destination, args.get_flag("force"))?;
         Ok(())
     }
 
@@ -227,10 +276,19 @@ impl RepoRust {
     }
 
     pub fn commit_tree(args: &ArgMatches) -> std::io::Result<()> {
-        let hash = args.get_one::<String>("hash").unwrap().to_owned();
+        // Revisions are resolved here. Missing objects are reported by Commit::encode
+        let resolve = |rev: &String, object_type| match revision::resolve_as(rev, object_type) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(rev.to_owned()),
+            result => result,
+        };
+        let mut hash = args.get_one::<String>("hash").unwrap().to_owned();
+        if !hash.is_empty() {
+            hash = resolve(&hash, ObjectType::Tree)?;
+        }
         let commit = args
             .get_many::<String>("commit")
-            .map(|vals| vals.cloned().collect::<Vec<_>>())
+            .map(|vals| vals.map(|rev| resolve(rev, ObjectType::Commit)).collect())
+            .transpose()?
             .unwrap_or_default();
         let message = args
             .get_one::<String>("message")
@@ -241,12 +299,131 @@ impl RepoRust {
         Ok(())
     }
 
+    pub fn status(args: &ArgMatches) -> std::io::Result<()> {
+        let status = status::status()?;
+        if args.get_flag("short") || args.contains_id("porcelain") {
+            print!("{}", status.short());
+        } else {
+            print!("{status}");
+        }
+        Ok(())
+    }
+
+    pub fn diff(args: &ArgMatches) -> std::io::Result<()> {
+        print!("{}", Self::diff_output(args)?);
+        Ok(())
+    }
+
+    // diff                     index -> working tree
+    // diff <rev>               <rev> -> working tree
+    // diff --cached [<rev>]    <rev> (HEAD by default) -> index
+    // diff <rev> <rev>         <rev> -> <rev>
+    pub fn diff_output(args: &ArgMatches) -> std::io::Result<String> {
+        let mut revs: Vec<(usize, String)> = match args.get_many::<String>("args") {
+            Some(revs) => args
+                .indices_of("args")
+                .unwrap()
+                .zip(revs.cloned())
+                .collect(),
+            None => Vec::new(),
+        };
+        // -M and -C take their value attached: -M50%. A value that is not a similarity
+        // is the revision or path that came after the flag
+        let mut similarity = |name: &str| match args.get_one::<String>(name) {
+            Some(value) if diff::parse_similarity(value).is_err() => {
+                revs.push((args.index_of(name).unwrap(), value.clone()));
+                Some(diff::DEFAULT_SIMILARITY)
+            }
+            Some(value) => diff::parse_similarity(value).ok(),
+            None => None,
+        };
+        let renames = similarity("find-renames");
+        let copies = similarity("find-copies");
+        revs.sort();
+        let mut revs: Vec<String> = revs.into_iter().map(|(_, rev)| rev).collect();
+        let mut paths: Vec<String> = args
+            .get_many::<String>("paths")
+            .map(|paths| paths.cloned().collect())
+            .unwrap_or_default();
+        // Without --, the arguments after the revisions are paths
+        let split = revs
+            .iter()
+            .position(|rev| rev != "HEAD" && revision::resolve(rev).is_err())
+            .unwrap_or(revs.len());
+        paths.extend(revs.split_off(split));
+        for path in &paths {
+            Self::check_paths(path)?;
+        }
+        if revs.len() > 2 || (args.get_flag("cached") && revs.len() > 1) {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Too many revisions",
+            ));
+        }
+        // HEAD before the first commit is the empty tree
+        let tree = |rev: &str| match (rev, refs::read_ref("HEAD")?) {
+            ("HEAD", None) => Ok(None),
+            _ => revision::resolve_as(rev, ObjectType::Tree).map(Some),
+        };
+
+        let changes = match revs.as_slice() {
+            _ if args.get_flag("cached") => {
+                let rev = revs.first().map_or("HEAD", String::as_str);
+                diff::diff_tree_index(tree(rev)?.as_deref(), &paths)?
+            }
+            [] => diff::diff_index_worktree(&paths)?,
+            [rev] => diff::diff_tree_worktree(tree(rev)?.as_deref(), &paths)?,
+            [old, new] => diff::diff_trees(tree(old)?.as_deref(), tree(new)?.as_deref(), &paths)?,
+            _ => unreachable!(),
+        };
+        // -C finds renames too. With both, the threshold of -C is used
+        let changes = match copies.or(renames) {
+            Some(threshold) => diff::detect_renames(changes, threshold, copies.is_some())?,
+            None => changes,
+        };
+        let format = [
+            ("stat", diff::Format::Stat),
+            ("name-only", diff::Format::NameOnly),
+            ("name-status", diff::Format::NameStatus),
+            ("raw", diff::Format::Raw),
+        ]
+        .into_iter()
+        .find(|(flag, _)| args.get_flag(flag))
+        .map_or(diff::Format::Patch, |(_, format)| format);
+        let context = args
+            .get_one::<usize>("unified")
+            .copied()
+            .unwrap_or(diff::DEFAULT_CONTEXT);
+        diff::format(&changes, format, context)
+    }
+
     pub fn commit(args: &ArgMatches) -> std::io::Result<()> {
-        // TODO Add the -a flag
-        let message = args
-            .get_one::<String>("message")
-            .unwrap_or(&String::new())
-            .to_owned();
+        if args.get_flag("add") {
+            Index::update_tracked(".")?;
+        }
+        let commit_summary = Self::commit_index(args.get_one::<String>("message").cloned(), None)?;
+        print!("{commit_summary}");
+        Ok(())
+    }
+
+    // Commits the index on top of HEAD. While a merge waits to be committed, MERGE_HEAD
+    // is the second parent and MERGE_MSG the default message
+    fn commit_index(
+        message: Option<String>,
+        reflog_message: Option<&str>,
+    ) -> std::io::Result<CommitSummary> {
+        let merge_head = merge::merge_head()?;
+        if merge_head.is_some() && !Index::read_index()?.conflicts.is_empty() {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Committing is not possible because you have unmerged files.",
+            ));
+        }
+        let message = match (message, &merge_head) {
+            (Some(message), _) => message,
+            (None, Some(_)) => merge::merge_message()?,
+            (None, None) => String::new(),
+        };
 
         // Build the current index. Get trees and the hash for the root tree.
         let (trees, new_tree_hash_bytes) = Tree::encode_object().map_err(|_| {
@@ -256,31 +433,24 @@ impl RepoRust {
             )
         })?;
         let new_tree_hash = hex::encode(new_tree_hash_bytes);
-        // Get commit hash from head. Early return if a detached head. TODO
-
-        // Read head and get path.
-        let head_str = Commit::read_head()?;
-        let branch_path = Commit::get_branch_from_head(&head_str)?;
-        // Check if it exists
+        // The parent is the commit of HEAD, through the branch or detached
         let mut parent_commits: Vec<String> = vec![];
-        if !Path::new(&branch_path).exists() {
-            // Create the branch file and leave parents_branches empty
-            std::fs::create_dir_all(branch_path.parent().unwrap())?;
-            std::fs::File::create_new(&branch_path)?;
-        } else {
-            // Read it and push to parent_branches
-            let parent_hash = Commit::get_branch_commit()?;
-            parent_commits.push(parent_hash.clone());
+        let parent = refs::read_ref("HEAD")?;
+        // No parent for the first commit of a branch
+        if let Some(parent_hash) = parent.clone() {
             let last_tree_hash = Commit::get_tree_from_commit(&parent_hash)?;
+            parent_commits.push(parent_hash);
 
             // Use root tree hash to check if there's anything new in staging
-            if new_tree_hash == last_tree_hash {
+            // A merge commit can have the tree of its first parent
+            if new_tree_hash == last_tree_hash && merge_head.is_none() {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::InvalidInput,
                     "Nothing added to commit but untracked files present (use add to track)",
                 ));
             };
         }
+        parent_commits.extend(merge_head.clone());
         // If we can commit, write the trees to file...
         Tree::write_object_to_file(trees)?;
 
@@ -288,45 +458,852 @@ impl RepoRust {
         let commit = Commit::encode(&new_tree_hash, parent_commits, &message)?;
         let new_commit_hash = commit.write_commit_to_file()?;
 
-        // Update the branch to point to the new commit
-        let branch = Commit::update_branch_hash(&new_commit_hash)?;
-        let commit_summary = CommitSummary {
+        // Update the branch (or a detached HEAD) to point to the new commit
+        let subject = message.lines().next().unwrap_or_default();
+        let reflog_message = match (reflog_message, &parent, &merge_head) {
+            (Some(reflog_message), _, _) => reflog_message.to_string(),
+            (None, _, Some(_)) => format!("commit (merge): {subject}"),
+            (None, Some(_), None) => format!("commit: {subject}"),
+            (None, None, None) => format!("commit (initial): {subject}"),
+        };
+        let branch = refs::update_head(&new_commit_hash, parent.as_deref(), &reflog_message)?
+            .unwrap_or_else(|| "detached HEAD".to_string());
+        merge::clear_state()?;
+        CommitSummary::new(
             branch,
-            commit_hash: new_commit_hash,
+            new_commit_hash,
             message,
+            parent.as_deref(),
+            &new_tree_hash,
+        )
+    }
+
+    // merge <rev>: fast-forward when HEAD is an ancestor of rev, otherwise a three-way
+    // merge with the merge base. Conflicts stop before the merge commit, which is made
+    // by merge --continue (or commit) once they are resolved
+    pub fn merge(args: &ArgMatches) -> std::io::Result<()> {
+        if args.get_flag("abort") {
+            return merge::abort();
+        }
+        if args.get_flag("continue") {
+            if merge::merge_head()?.is_none() {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "There is no merge in progress (MERGE_HEAD missing).",
+                ));
+            }
+            print!("{}", Self::commit_index(None, None)?);
+            return Ok(());
+        }
+        let rev = args.get_one::<String>("rev").ok_or_else(|| {
+            Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No commit specified to merge.",
+            )
+        })?;
+        if merge::merge_head()?.is_some() {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+            ));
+        }
+        let theirs = revision::resolve_as(rev, ObjectType::Commit)?;
+        let theirs_tree = revision::resolve_as(&theirs, ObjectType::Tree)?;
+        let fast_forward_message = format!("merge {rev}: Fast-forward");
+        // Nothing to merge into before the first commit. HEAD takes the commit
+        let Some(head) = refs::read_ref("HEAD")? else {
+            worktree::switch_tree(None, &theirs_tree, false)?;
+            refs::update_head(&theirs, None, &fast_forward_message)?;
+            return Ok(());
+        };
+        let head_tree = revision::resolve_as(&head, ObjectType::Tree)?;
+        let base = merge::merge_base(&head, &theirs)?;
+        if base.is_none() && !args.get_flag("allow-unrelated-histories") {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "refusing to merge unrelated histories",
+            ));
+        }
+        if base.as_deref() == Some(theirs.as_str()) {
+            println!("Already up to date.");
+            return Ok(());
+        }
+
+        if base.as_deref() == Some(head.as_str()) && !args.get_flag("no-ff") {
+            println!("Updating {}..{}", &head[..7], &theirs[..7]);
+            worktree::switch_tree(Some(&head_tree), &theirs_tree, false)?;
+            refs::write_ref("ORIG_HEAD", &head)?;
+            refs::update_head(&theirs, Some(&head), &fast_forward_message)?;
+            println!("Fast-forward");
+            print!("{}", Self::merge_stat(&head_tree, &theirs_tree)?);
+            return Ok(());
+        }
+        if args.get_flag("ff-only") {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Not possible to fast-forward, aborting.",
+            ));
+        }
+
+        let base_tree = match &base {
+            Some(base) => Some(revision::resolve_as(base, ObjectType::Tree)?),
+            None => None,
+        };
+        let result = merge::merge_trees(
+            base_tree.as_deref(),
+            &head_tree,
+            &theirs_tree,
+            ("HEAD", rev),
+        )?;
+        let merged_tree = merge::apply(&head_tree, &result)?;
+        for message in &result.messages {
+            println!("{message}");
+        }
+        refs::write_ref("ORIG_HEAD", &head)?;
+        let message = match args.get_one::<String>("message") {
+            Some(message) => message.clone(),
+            None => merge::default_message(rev)?,
+        };
+        let conflicts: Vec<&String> = result.conflicts.keys().collect();
+        merge::write_state(&theirs, &message, &conflicts)?;
+        if !conflicts.is_empty() {
+            return Err(Error::other(
+                "Automatic merge failed; fix conflicts and then commit the result.",
+            ));
+        }
+
+        let reflog_message = format!("merge {rev}: Merge made by the 'resolve' strategy.");
+        Self::commit_index(Some(message), Some(&reflog_message))?;
+        println!("Merge made by the 'resolve' strategy.");
+        print!("{}", Self::merge_stat(&head_tree, &merged_tree)?);
+        Ok(())
+    }
+
+    // Files changed from old_tree to new_tree, as diff --stat shows them
+    fn merge_stat(old_tree: &str, new_tree: &str) -> std::io::Result<String> {
+        let changes = diff::diff_trees(Some(old_tree), Some(new_tree), &[])?;
+        let changes = diff::detect_renames(changes, diff::DEFAULT_SIMILARITY, false)?;
+        diff::format(&changes, diff::Format::Stat, diff::DEFAULT_CONTEXT)
+    }
+
+    pub fn log(args: &ArgMatches) -> std::io::Result<()> {
+        let revs: Vec<String> = args
+            .get_many::<String>("rev")
+            .map(|revs| revs.cloned().collect())
+            .unwrap_or_else(|| vec!["HEAD".to_string()]);
+        if revs == ["HEAD"] && refs::read_ref("HEAD")?.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "your current branch does not have any commits yet",
+            ));
+        }
+        let format = match args.get_one::<String>("format") {
+            Some(format) => log::Format::parse(format)?,
+            None if args.get_flag("oneline") => log::Format::Oneline,
+            None => log::Format::Medium,
+        };
+        let date = |name| {
+            args.get_one::<String>(name)
+                .map(|d| log::parse_date(d))
+                .transpose()
+        };
+        let options = log::LogOptions {
+            max_count: args.get_one::<usize>("max-count").copied(),
+            since: date("since")?,
+            until: date("until")?,
+            author: args.get_one::<String>("author").cloned(),
+            grep: args.get_one::<String>("grep").cloned(),
+            format,
+            graph: args.get_flag("graph"),
+            decorate: args.get_flag("decorate"),
+            paths: args
+                .get_many::<String>("paths")
+                .map(|paths| paths.cloned().collect())
+                .unwrap_or_default(),
+            follow: args.get_flag("follow"),
+        };
+        print!("{}", log::log(&revs, &options)?);
+        Ok(())
+    }
+
+    pub fn branch(args: &ArgMatches) -> std::io::Result<()> {
+        let names: Vec<&str> = args
+            .get_many::<String>("args")
+            .map(|names| names.map(String::as_str).collect())
+            .unwrap_or_default();
+        let force = args.get_flag("force");
+        let missing = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, what);
+
+        if args.get_flag("delete") || args.get_flag("force-delete") {
+            if names.is_empty() {
+                return Err(missing("branch name required"));
+            }
+            let force = force || args.get_flag("force-delete");
+            for name in names {
+                let hash = branch::delete(name, force)?;
+                println!("Deleted branch {name} (was {}).", &hash[..7]);
+            }
+            return Ok(());
+        }
+        if args.get_flag("move") || args.get_flag("force-move") {
+            let force = force || args.get_flag("force-move");
+            return match names.as_slice() {
+                [new] => branch::rename(None, new, force),
+                [old, new] => branch::rename(Some(old), new, force),
+                _ => Err(missing("branch name required")),
+            };
+        }
+        if let Some(upstream) = args.get_one::<String>("set-upstream-to") {
+            let branch = names.first().copied();
+            let upstream = branch::set_upstream(branch, upstream)?;
+            let branch = branch.map_or_else(|| "HEAD".to_string(), str::to_string);
+            println!("branch '{branch}' set up to track '{upstream}'.");
+            return Ok(());
+        }
+        if args.get_flag("unset-upstream") {
+            return branch::unset_upstream(names.first().copied());
+        }
+        if let Some(name) = names.first() {
+            let start = names.get(1).copied().unwrap_or("HEAD");
+            if let Some(upstream) = branch::create(name, start, force)? {
+                println!("branch '{name}' set up to track '{upstream}'.");
+            }
+            return Ok(());
+        }
+
+        let remote = args.get_flag("remotes") || args.get_flag("all");
+        let local = !args.get_flag("remotes");
+        // With -a, remote-tracking branches keep the remotes/ prefix
+        let short = |name: &str| match args.get_flag("all") {
+            true => name.strip_prefix("refs/").unwrap_or(name).to_string(),
+            false => revision::shorten_ref(name).to_string(),
+        };
+        // Same as git, a detached HEAD is listed first
+        if local
+            && refs::head_branch()?.is_none()
+            && let Some(head) = refs::read_ref("HEAD")?
+        {
+            let hash = revision::abbreviate(&head, revision::DEFAULT_ABBREV)?;
+            println!("* (HEAD detached at {hash})");
+        }
+        let branches = branch::list(local, remote)?;
+        let width = branches
+            .iter()
+            .map(|b| short(&b.name).len())
+            .max()
+            .unwrap_or(0);
+        for branch in branches {
+            let name = short(&branch.name);
+            let marker = if branch.current { '*' } else { ' ' };
+            match &branch.target {
+                Some(target) => println!("{marker} {name} -> {}", short(target)),
+                None if args.get_flag("verbose") => {
+                    let commit = Commit::decode(&branch.hash)?;
+                    let message = commit.text(&commit.message);
+                    let subject = message.lines().next().unwrap_or_default();
+                    let hash = revision::abbreviate(&branch.hash, revision::DEFAULT_ABBREV)?;
+                    println!("{marker} {name:<width$} {hash} {subject}");
+                }
+                None => println!("{marker} {name}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn switch(args: &ArgMatches) -> std::io::Result<()> {
+        let target = args.get_one::<String>("target").map(String::as_str);
+        let create = Self::create_arg(args);
+        let detach = args.get_flag("detach");
+        Self::switch_to(target, create, detach, !detach, args.get_flag("force"))
+    }
+
+    pub fn checkout(args: &ArgMatches) -> std::io::Result<()> {
+        let mut revs: Vec<String> = args
+            .get_many::<String>("args")
+            .map(|revs| revs.cloned().collect())
+            .unwrap_or_default();
+        let mut paths: Vec<String> = args
+            .get_many::<String>("paths")
+            .map(|paths| paths.cloned().collect())
+            .unwrap_or_default();
+        // checkout <tree-ish> <paths>... without --
+        if paths.is_empty() && revs.len() > 1 {
+            paths = revs.split_off(1);
+        }
+        let create = Self::create_arg(args);
+        if !paths.is_empty() || args.contains_id("paths") {
+            if create.is_some() || args.get_flag("detach") {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "cannot switch branches while restoring paths",
+                ));
+            }
+            let tree = revs
+                .first()
+                .map(|rev| revision::resolve_as(rev, ObjectType::Tree))
+                .transpose()?;
+            return worktree::checkout_paths(tree.as_deref(), &paths);
+        }
+        let target = revs.first().map(String::as_str);
+        Self::switch_to(
+            target,
+            create,
+            args.get_flag("detach"),
+            false,
+            args.get_flag("force"),
+        )
+    }
+
+    pub fn reset(args: &ArgMatches) -> std::io::Result<()> {
+        let mut revs: Vec<String> = args
+            .get_many::<String>("args")
+            .map(|revs| revs.cloned().collect())
+            .unwrap_or_default();
+        let mut paths: Vec<String> = args
+            .get_many::<String>("paths")
+            .map(|paths| paths.cloned().collect())
+            .unwrap_or_default();
+        // reset [<rev>] <paths>... without --. The first one is a path if it is not a revision
+        if paths.is_empty() && !revs.is_empty() {
+            let split = match revision::resolve(&revs[0]) {
+                Ok(_) => 1,
+                Err(_) => 0,
+            };
+            paths = revs.split_off(split);
+        }
+        let rev = revs.first().map_or("HEAD", String::as_str);
+        let mode = ["soft", "hard"]
+            .into_iter()
+            .find(|mode| args.get_flag(mode))
+            .unwrap_or("mixed");
+        let head = refs::read_ref("HEAD")?;
+        // Before the first commit, HEAD is the empty tree
+        let target = |object_type: ObjectType| match (rev, &head) {
+            ("HEAD", None) => Ok(None),
+            _ => revision::resolve_as(rev, object_type).map(Some),
         };
-        // Update reflog
-        // TODO
 
-        // Show summary w/ git diff - TODO
-        println!("{commit_summary}");
+        if !paths.is_empty() || args.contains_id("paths") {
+            if mode != "mixed" {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Cannot do {mode} reset with paths."),
+                ));
+            }
+            worktree::reset_index(target(ObjectType::Tree)?.as_deref(), &paths)?;
+            return Self::print_unstaged();
+        }
+
+        let commit = target(ObjectType::Commit)?;
+        let tree = match &commit {
+            Some(commit) => Some(revision::resolve_as(commit, ObjectType::Tree)?),
+            None => None,
+        };
+        match mode {
+            "hard" => worktree::reset_hard(tree.as_deref())?,
+            "mixed" => worktree::reset_index(tree.as_deref(), &[])?,
+            _ => {
+                if !Index::read_index().is_ok_and(|index| index.conflicts.is_empty()) {
+                    return Err(Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "Cannot do a soft reset in the middle of a merge.",
+                    ));
+                }
+            }
+        }
+        // The merge in progress, if any, is given up
+        merge::clear_state()?;
+        if let Some(commit) = &commit {
+            // Same as git, ORIG_HEAD keeps the commit before the reset
+            if let Some(head) = &head {
+                refs::write_ref("ORIG_HEAD", head)?;
+            }
+            refs::update_head(commit, head.as_deref(), &format!("reset: moving to {rev}"))?;
+        }
+        match (mode, &commit) {
+            ("hard", Some(commit)) => {
+                println!("HEAD is now at {}", Self::describe_commit(commit)?);
+                Ok(())
+            }
+            ("mixed", _) => Self::print_unstaged(),
+            _ => Ok(()),
+        }
+    }
+
+    fn print_unstaged() -> std::io::Result<()> {
+        let unstaged = status::status()?.unstaged;
+        if !unstaged.is_empty() {
+            println!("Unstaged changes after reset:");
+            for (path, change) in unstaged {
+                println!("{}\t{path}", change.letter());
+            }
+        }
+        Ok(())
+    }
+
+    // -c / -C of switch, -b / -B of checkout: the new branch and if it can be reset
+    fn create_arg(args: &ArgMatches) -> Option<(&str, bool)> {
+        match args.get_one::<String>("create") {
+            Some(name) => Some((name, false)),
+            None => args
+                .get_one::<String>("force-create")
+                .map(|name| (name.as_str(), true)),
+        }
+    }
+
+    // Shared by switch and checkout. Without create, target is a branch, or a
+    // commit that detaches HEAD. With create, target is the start point
+    fn switch_to(
+        target: Option<&str>,
+        create: Option<(&str, bool)>,
+        detach: bool,
+        branch_only: bool,
+        force: bool,
+    ) -> std::io::Result<()> {
+        // Commits made on a detached HEAD are lost when it moves away
+        let detached = match refs::head_branch()? {
+            Some(_) => None,
+            None => refs::read_ref("HEAD")?,
+        };
+        let message = Self::move_head(target, create, detach, branch_only, force)?;
+        if let Some(previous) = detached
+            && refs::read_ref("HEAD")?.as_ref() != Some(&previous)
+        {
+            print!("{}", Self::left_behind(&previous)?);
+        }
+        print!("{message}");
+        Ok(())
+    }
+
+    // <abbreviated hash> <subject>
+    fn describe_commit(hash: &str) -> std::io::Result<String> {
+        let commit = Commit::decode(hash)?;
+        let message = commit.text(&commit.message);
+        let subject = message.lines().next().unwrap_or_default();
+        let hash = revision::abbreviate(hash, revision::DEFAULT_ABBREV)?;
+        Ok(format!("{hash} {subject}"))
+    }
+
+    // What happened to the commit a detached HEAD was on
+    fn left_behind(previous: &str) -> std::io::Result<String> {
+        let orphaned = log::orphaned(previous)?;
+        if orphaned.is_empty() {
+            return Ok(format!(
+                "Previous HEAD position was {}\n",
+                Self::describe_commit(previous)?
+            ));
+        }
+        let (count, them) = match orphaned.len() {
+            1 => ("1 commit".to_string(), "it"),
+            n => (format!("{n} commits"), "them"),
+        };
+        let mut warning = format!(
+            "Warning: you are leaving {count} behind, not connected to\n\
+             any of your branches:\n\n"
+        );
+        // Same as git, at most 5 lines
+        let shown = if orphaned.len() > 5 { 4 } else { 5 };
+        for entry in orphaned.iter().take(shown) {
+            warning.push_str(&format!("  {}\n", Self::describe_commit(&entry.hash)?));
+        }
+        if orphaned.len() > shown {
+            warning.push_str(&format!(" ... and {} more.\n", orphaned.len() - shown));
+        }
+        let hash = revision::abbreviate(previous, revision::DEFAULT_ABBREV)?;
+        warning.push_str(&format!(
+            "\nIf you want to keep {them} by creating a new branch, this may be a good time\n\
+             to do so with:\n\n git_rust branch <new-branch-name> {hash}\n\n"
+        ));
+        Ok(warning)
+    }
+
+    // Moves HEAD, the index and the working tree. Returns the message to show
+    fn move_head(
+        target: Option<&str>,
+        create: Option<(&str, bool)>,
+        detach: bool,
+        branch_only: bool,
+        force: bool,
+    ) -> std::io::Result<String> {
+        if let Some((name, force_create)) = create {
+            return Self::switch_new_branch(name, target, force_create, force);
+        }
+        let target = target.ok_or_else(|| {
+            Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "missing branch or commit argument",
+            )
+        })?;
+        let branch_ref = format!("refs/heads/{target}");
+        if !detach && let Some(hash) = refs::read_ref(&branch_ref)? {
+            let already_on = refs::head_branch()?.as_deref() == Some(branch_ref.as_str());
+            worktree::switch_head(&hash, Some(&branch_ref), force)?;
+            return Ok(match already_on {
+                true => format!("Already on '{target}'\n"),
+                false => format!("Switched to branch '{target}'\n"),
+            });
+        }
+        // A branch of a single remote with that name: create it to track the remote one
+        if !detach && refs::check_name(target) {
+            let remotes: Vec<String> = refs::list_refs("refs/remotes")?
+                .into_iter()
+                .map(|(name, _)| name)
+                .filter(|name| {
+                    name.strip_prefix("refs/remotes/")
+                        .and_then(|name| name.split_once('/'))
+                        .is_some_and(|(_, branch)| branch == target)
+                })
+                .collect();
+            if let [remote] = remotes.as_slice() {
+                return Self::switch_new_branch(target, Some(remote), false, force);
+            }
+        }
+        let commit = revision::resolve_as(target, ObjectType::Commit).map_err(|_| {
+            Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid reference: {target}"),
+            )
+        })?;
+        if branch_only {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "a branch is expected, got '{target}'\n\
+                     hint: If you want to detach HEAD at the commit, try again with the --detach option."
+                ),
+            ));
+        }
+        let was_on_branch = refs::head_branch()?.is_some();
+        worktree::switch_head(&commit, None, force)?;
+        let mut message = String::new();
+        if was_on_branch {
+            message.push_str(&format!(
+                "Note: switching to '{target}'.\n\n\
+                 You are in 'detached HEAD' state. You can look around, make experimental\n\
+                 changes and commit them, and you can discard any commits you make in this\n\
+                 state without impacting any branches by switching back to a branch.\n\n"
+            ));
+        }
+        message.push_str(&format!(
+            "HEAD is now at {}\n",
+            Self::describe_commit(&commit)?
+        ));
+        Ok(message)
+    }
+
+    fn switch_new_branch(
+        name: &str,
+        start: Option<&str>,
+        force_create: bool,
+        force: bool,
+    ) -> std::io::Result<String> {
+        let branch_ref = format!("refs/heads/{name}");
+        // Before the first commit, only HEAD changes
+        if start.is_none() && refs::read_ref("HEAD")?.is_none() {
+            if !refs::check_name(name) {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("'{name}' is not a valid branch name"),
+                ));
+            }
+            refs::set_head_branch(&branch_ref)?;
+            return Ok(format!("Switched to a new branch '{name}'\n"));
+        }
+        // -B on the current branch resets it, with the index and the working tree
+        if force_create && refs::head_branch()?.as_deref() == Some(branch_ref.as_str()) {
+            let start = start.unwrap_or("HEAD");
+            let commit = revision::resolve_as(start, ObjectType::Commit)?;
+            worktree::switch_head(&commit, Some(&branch_ref), force)?;
+            let mut transaction = refs::Transaction::new();
+            transaction
+                .update(&branch_ref, &commit)
+                .message(&format!("branch: Reset to {start}"));
+            transaction.commit()?;
+            return Ok(format!("Reset branch '{name}'\n"));
+        }
+        let old = refs::read_ref(&branch_ref)?;
+        let upstream = branch::create(name, start.unwrap_or("HEAD"), force_create)?;
+        let hash = refs::read_ref(&branch_ref)?.unwrap_or_default();
+        // The branch is put back as it was if the working tree cannot be updated
+        if let Err(e) = worktree::switch_head(&hash, Some(&branch_ref), force) {
+            match old {
+                Some(old) => refs::write_ref(&branch_ref, &old)?,
+                None => {
+                    branch::delete(name, true)?;
+                }
+            }
+            return Err(e);
+        }
+        let mut message = match old {
+            Some(_) => format!("Reset branch '{name}'\n"),
+            None => format!("Switched to a new branch '{name}'\n"),
+        };
+        if let Some(upstream) = upstream {
+            message.push_str(&format!("branch '{name}' set up to track '{upstream}'.\n"));
+        }
+        Ok(message)
+    }
+
+    pub fn reflog(args: &ArgMatches) -> std::io::Result<()> {
+        match args.subcommand() {
+            Some(("expire", args)) => {
+                let expiry = |id: &str, default: &str| -> std::io::Result<Option<i64>> {
+                    match args.get_one::<String>(id).map_or(default, String::as_str) {
+                        "never" => Ok(None),
+                        "all" => Ok(Some(i64::MAX)),
+                        value => log::parse_date(value).map(Some),
+                    }
+                };
+                let expire = expiry("expire", reflog::DEFAULT_EXPIRE)?;
+                let expire_unreachable =
+                    expiry("expire-unreachable", reflog::DEFAULT_EXPIRE_UNREACHABLE)?;
+                let names = match args.get_flag("all") {
+                    true => reflog::list()?,
+                    false => args
+                        .get_many::<String>("refs")
+                        .unwrap_or_default()
+                        .map(|name| reflog::ref_name(name))
+                        .collect::<std::io::Result<_>>()?,
+                };
+                for name in names {
+                    let removed = reflog::expire(&name, expire, expire_unreachable)?;
+                    if removed > 0 {
+                        println!("{name}: removed {removed} entries");
+                    }
+                }
+                Ok(())
+            }
+            Some(("delete", args)) => {
+                let mut entries: Vec<(String, usize)> = args
+                    .get_many::<String>("entries")
+                    .unwrap_or_default()
+                    .map(|rev| reflog::parse_selector(rev))
+                    .collect::<std::io::Result<_>>()?;
+                // The oldest first, so the numbers of the others stay the same
+                entries.sort_by(|a, b| b.cmp(a));
+                for (name, n) in entries {
+                    reflog::delete(&name, n)?;
+                }
+                Ok(())
+            }
+            Some((_, args)) => Self::show_reflog(args),
+            None => Self::show_reflog(args),
+        }
+    }
+
+    // Newest first: <abbrev> <ref>@{n}: <message>
+    fn show_reflog(args: &ArgMatches) -> std::io::Result<()> {
+        let name = args.get_one::<String>("ref").map_or("HEAD", String::as_str);
+        let full_name = reflog::ref_name(name)?;
+        let short = revision::shorten_ref(&full_name);
+        for (n, entry) in reflog::read(&full_name)?.iter().rev().enumerate() {
+            let hash = revision::abbreviate(&entry.new, revision::DEFAULT_ABBREV)?;
+            println!("{hash} {short}@{{{n}}}: {}", entry.message);
+        }
+        Ok(())
+    }
+
+    pub fn tag(args: &ArgMatches) -> std::io::Result<()> {
+        let name = args.get_one::<String>("name");
+        if args.get_flag("delete") {
+            let name = name.ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "tag name required")
+            })?;
+            let hash = Tag::delete_ref(name)?;
+            println!("Deleted tag '{name}' (was {})", &hash[..7.min(hash.len())]);
+            return Ok(());
+        }
+        let Some(name) = name.filter(|_| !args.get_flag("list")) else {
+            for tag in Tag::list()? {
+                println!("{tag}");
+            }
+            return Ok(());
+        };
+
+        let object = args
+            .get_one::<String>("object")
+            .map_or("HEAD", String::as_str);
+        let object = revision::resolve(object)
+            .ok()
+            .filter(|hash| objects::object_exists(hash))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Failed to resolve '{object}' as a valid ref."),
+                )
+            })?;
+        let message = args.get_one::<String>("message");
+        let hash = if args.get_flag("annotate") || message.is_some() {
+            let message = message.ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "no tag message given (use -m)",
+                )
+            })?;
+            Tag::encode(&object, name, message)?.write_tag_to_file()?
+        } else {
+            object
+        };
+        Tag::create_ref(name, &hash, args.get_flag("force"))?;
+        Ok(())
+    }
+
+    pub fn pack_refs(args: &ArgMatches) -> std::io::Result<()> {
+        let summary = refs::pack_refs(args.get_flag("all"), !args.get_flag("no-prune"))?;
+        println!(
+            "Packed {} refs, removed {} loose refs",
+            summary.packed, summary.pruned
+        );
+        Ok(())
+    }
+
+    pub fn rev_parse(args: &ArgMatches) -> std::io::Result<()> {
+        let revs: Vec<&String> = args.get_many::<String>("rev").unwrap_or_default().collect();
+        if args.get_flag("verify") && revs.len() != 1 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Needed a single revision",
+            ));
+        }
+        for rev in revs {
+            if args.get_flag("abbrev-ref")
+                && let Some(name) = revision::resolve_ref_name(rev)?
+            {
+                println!("{}", revision::shorten_ref(&name));
+                continue;
+            }
+            let hash = revision::resolve(rev)?;
+            if args.get_flag("verify") && !objects::object_exists(&hash) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Needed a single revision",
+                ));
+            }
+            match args.get_one::<usize>("short") {
+                Some(len) => println!("{}", revision::abbreviate(&hash, *len)?),
+                None => println!("{hash}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn index_pack(args: &ArgMatches) -> std::io::Result<()> {
+        let pack_file = args.get_one::<String>("pack").unwrap().to_owned();
+        let pack_path = Path::new(&pack_file);
+        if pack_path.extension().is_none_or(|ext| ext != "pack") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Packfile name must end with .pack",
+            ));
+        }
+        let checksum = pack::index_pack(pack_path)?;
+        println!("{checksum}");
+        Ok(())
+    }
+
+    pub fn repack(args: &ArgMatches) -> std::io::Result<()> {
+        let mut options = RepackOptions {
+            delete_redundant: args.get_flag("delete"),
+            ..Default::default()
+        };
+        if let Some(window) = args.get_one::<usize>("window") {
+            options.window = *window;
+        }
+        if let Some(depth) = args.get_one::<usize>("depth") {
+            options.depth = *depth;
+        }
+        let summary = gc::repack(&options)?;
+        match summary.pack {
+            Some(_) => println!("Total {} (delta {})", summary.objects, summary.deltas),
+            None => println!("Nothing new to pack."),
+        }
+        Ok(())
+    }
+
+    pub fn gc(args: &ArgMatches) -> std::io::Result<()> {
+        let prune = args
+            .get_one::<String>("prune")
+            .map(String::as_str)
+            .unwrap_or(gc::DEFAULT_PRUNE_EXPIRE);
+        let expiry = gc::parse_expiry(prune)?;
+        let summary = gc::gc(expiry)?;
+        if summary.repack.pack.is_some() {
+            println!(
+                "Total {} (delta {})",
+                summary.repack.objects, summary.repack.deltas
+            );
+        }
+        if summary.pruned > 0 {
+            println!("Pruned {} unreachable objects", summary.pruned);
+        }
         Ok(())
     }
 
     pub fn fetch(args: &ArgMatches) -> std::io::Result<()> {
         let url = args.get_one::<String>("url").unwrap().to_owned();
-        let _branch = args.get_one::<String>("branch").unwrap().to_owned();
+        let branch = args.get_one::<String>("branch").unwrap().to_owned();
         let dir = args.get_one::<String>("directory").unwrap().to_owned();
 
-        // Create the dir
-        // let dir_path = Path::new(&dir);
-        // if !dir_path.exists() {
-        //     std::fs::create_dir(Path::new(dir_path))?;
-        // }
+        // Fetch into an existing repo
+        if !Path::new(&dir).join(BASE_DIR).is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Not a git_rust repository: {dir}"),
+            ));
+        }
+        Self::new_repo(&dir)?;
 
-        fetch(&url, &dir).unwrap();
-        todo!()
+        let (uploadpack, packfile) = fetch(&url)?;
+        let branch_ref = format!("refs/heads/{branch}");
+        let remote_ref = uploadpack
+            .refs
+            .iter()
+            .find(|r| r.name == branch_ref)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Couldn't find remote ref {branch}"),
+                )
+            })?;
+        clone::store_packfile(&packfile)?;
+        // Fails if another process moved the remote-tracking branch meanwhile
+        let tracking_ref = format!("refs/remotes/{}/{branch}", clone::REMOTE_NAME);
+        let old = refs::read_ref(&tracking_ref)?;
+        let mut transaction = refs::Transaction::new();
+        transaction
+            .compare_and_swap(&tracking_ref, old.as_deref(), &remote_ref.hash)
+            .message(&format!("fetch: {url} {branch}"));
+        transaction.commit()?;
+        std::fs::write(
+            Path::new(&dir).join(BASE_DIR).join("FETCH_HEAD"),
+            format!("{}\t\tbranch '{branch}' of {url}\n", remote_ref.hash),
+        )?;
+        println!("From {url}");
+        println!(" * branch            {branch}     -> FETCH_HEAD");
+        Ok(())
     }
 
     pub fn clone(args: &ArgMatches) -> std::io::Result<()> {
-        let _url = args.get_one::<String>("url").unwrap().to_owned();
+        let url = args.get_one::<String>("url").unwrap().to_owned();
         let dir = args.get_one::<String>("directory").unwrap().to_owned();
 
-        // Create the dir
+        // Create the dir. Same as git, only an empty dir can be cloned into
         let dir_path = Path::new(&dir);
+        if dir_path.exists() && dir_path.read_dir()?.next().is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("destination path '{dir}' already exists and is not an empty directory"),
+            ));
+        }
         if !dir_path.exists() {
-            std::fs::create_dir(Path::new(dir_path))?;
+            std::fs::create_dir_all(dir_path)?;
         }
-        todo!()
+        println!("Cloning into '{dir}'...");
+        Self::new_repo(&dir)?;
+        Self::init()?;
+
+        let (uploadpack, packfile) = fetch(&url)?;
+        clone::clone_from_pack(&url, &uploadpack, &packfile)?;
+        Ok(())
     }
 }
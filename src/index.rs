@@ -2,17 +2,23 @@ use core::fmt;
 use std::{
     collections::BTreeMap,
     fmt::Display,
+    fs::Metadata,
     io::Write,
-    os::unix::fs::MetadataExt,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::{Path, PathBuf},
 };
 
-use ignore::gitignore::GitignoreBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use sha1::{Digest, Sha1};
 
 use crate::{
     git_rust::{BASE_DIR, RepoRust},
-    objects::blob::Blob,
+    objects::{
+        blob::Blob,
+        tree::{MODE_EXECUTABLE, MODE_FILE, MODE_GITLINK, MODE_SYMLINK},
+    },
+    status::{self, Change, WorktreeState},
+    worktree,
 };
 
 #[derive(Default)]
@@ -20,6 +26,9 @@ pub struct Index {
     pub header: IndexHeader,
     // (path, IndexEntry)
     pub entries: BTreeMap<String, IndexEntry>,
+    // Unmerged paths of a conflicted merge: their entries of stages 1 to 3, in order
+    // A path is either here or in entries, never both
+    pub conflicts: BTreeMap<String, Vec<IndexEntry>>,
 }
 
 #[derive(Default)]
@@ -85,6 +94,25 @@ pub struct IndexEntry {
 }
 
 impl IndexEntry {
+    // 0 for a merged entry. 1 is the merge base, 2 ours and 3 theirs
+    pub fn stage(&self) -> u16 {
+        (self.flags >> 12) & 0b11
+    }
+
+    // True if the file looks the same as when it was added. Used to avoid hashing it again
+    // The content can still differ if it changed right after being added (see status)
+    pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+        self.mtime == metadata.mtime() as u32
+            && self.mtime_nanos == metadata.mtime_nsec() as u32
+            && self.ctime == metadata.ctime() as u32
+            && self.ctime_nanos == metadata.ctime_nsec() as u32
+            && self.ino == metadata.ino() as u32
+            && self.dev == metadata.dev() as u32
+            && self.uid == metadata.uid()
+            && self.gid == metadata.gid()
+            && self.file_size == metadata.size() as u32
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
 
@@ -185,9 +213,22 @@ impl Index {
         &self.header
     }
 
-    fn from_entries(entries: BTreeMap<String, IndexEntry>) -> Self {
+    pub fn from_entries(entries: BTreeMap<String, IndexEntry>) -> Self {
         let header = IndexHeader::from(entries.len() as u32);
-        Self { header, entries }
+        Self {
+            header,
+            entries,
+            conflicts: BTreeMap::new(),
+        }
+    }
+
+    // Keeps the unmerged entries of the paths that have no merged entry
+    pub fn with_conflicts(mut self, mut conflicts: BTreeMap<String, Vec<IndexEntry>>) -> Self {
+        conflicts.retain(|path, _| !self.entries.contains_key(path));
+        let total = self.entries.len() + conflicts.values().map(Vec::len).sum::<usize>();
+        self.header = IndexHeader::from(total as u32);
+        self.conflicts = conflicts;
+        self
     }
 
     // TODO: Compare metadata when file already exists in index
@@ -208,33 +249,33 @@ impl Index {
     //      C. Path exists and SHA1 is same              -> Move on
     pub fn build_index(input: &str) -> std::io::Result<()> {
         let abs_root_path = &RepoRust::get_root().absolute_path;
-        let mut entries = if abs_root_path.join(BASE_DIR).join("index").exists() {
-            Self::read_index()?.entries
+        let (mut entries, conflicts) = if abs_root_path.join(BASE_DIR).join("index").exists() {
+            let index = Self::read_index()?;
+            (index.entries, index.conflicts)
         } else {
-            BTreeMap::new()
+            (BTreeMap::new(), BTreeMap::new())
         };
         let path = abs_root_path.join(PathBuf::from(input));
         let mut stack = vec![path];
         while let Some(current_path) = stack.pop() {
-            if current_path.is_file() {
-                if Self::exists_in_git_ignore(&current_path, false) {
+            // Symlinks are added as links, never followed
+            let Ok(metadata) = current_path.symlink_metadata() else {
+                continue;
+            };
+            let is_gitlink = metadata.is_dir()
+                && current_path != *abs_root_path
+                && current_path.join(".git").exists();
+            if metadata.is_file() || metadata.is_symlink() || is_gitlink {
+                if Self::exists_in_git_ignore(&current_path, metadata.is_dir()) {
                     continue;
                 }
-                // 1. Get entry - index the file
-                let entry = Self::index_entry_from_file(&current_path)?;
                 let key: String = current_path
                     .strip_prefix(abs_root_path)
                     .unwrap()
                     .to_string_lossy()
                     .into();
-                // 2. Check if blob already exists on disk
-                let blob_exists = Blob::blob_exists(entry.sha1);
-                if !blob_exists {
-                    // If no - Create the file
-                    let file = std::fs::read(current_path)?;
-                    let blob = Blob::blob_with_sha1(&file);
-                    blob.write_object_to_file(&file)?;
-                } // If yes, move on
+                // 1 and 2. Index the file and write its blob
+                let entry = Self::stage_file(&current_path, &metadata, is_gitlink)?;
 
                 // 3. Check if blob exists in index TODO: Compare metadata
                 match entries.get(&key) {
@@ -242,14 +283,17 @@ impl Index {
                     None => {
                         entries.insert(key, entry);
                     }
-                    // B. Path exists and SHA1 is different -> Update index
-                    Some(existing_entry) if existing_entry.sha1 != entry.sha1 => {
+                    // B. Path exists and SHA1 or mode is different -> Update index
+                    Some(existing_entry)
+                        if existing_entry.sha1 != entry.sha1
+                            || existing_entry.mode != entry.mode =>
+                    {
                         entries.insert(key, entry);
                     }
                     // C. Path exists and SHA1 is same
                     _ => {}
                 }
-            } else if current_path.is_dir() {
+            } else if metadata.is_dir() {
                 if current_path.ends_with(".git_rust") {
                     continue;
                 }
@@ -263,19 +307,96 @@ impl Index {
                 }
             }
         }
-        // Create and update the index
-        let index = Self::from_entries(entries);
+        // Create and update the index. Adding an unmerged path marks it as resolved
+        let index = Self::from_entries(entries).with_conflicts(conflicts);
         index.write_index_to_file()?;
         Ok(())
     }
 
+    // The entry of a file of the working tree. Its blob is written if missing
+    fn stage_file(
+        path: &Path,
+        metadata: &Metadata,
+        is_gitlink: bool,
+    ) -> std::io::Result<IndexEntry> {
+        let entry = if is_gitlink {
+            Self::index_entry_from_gitlink(path)?
+        } else {
+            Self::index_entry_from_file(path)?
+        };
+        // A submodule is a commit of another repo. Nothing to write
+        if !is_gitlink && !Blob::blob_exists(entry.sha1) {
+            let file = Self::file_content(path, metadata)?;
+            let blob = Blob::blob_with_sha1(&file);
+            blob.write_object_to_file(&file)?;
+        }
+        Ok(entry)
+    }
+
+    // add -u / commit -a
+    // Restages the tracked files matching pathspec that changed in the working tree,
+    // and drops the entries of deleted files. New files are not added
+    // Files whose stat data did not change are not hashed again
+    // Unmerged paths are resolved with their file, or removed if it was deleted
+    pub fn update_tracked(pathspec: &str) -> std::io::Result<()> {
+        let root = &RepoRust::get_root().absolute_path;
+        let index = match Self::read_index() {
+            Ok(index) => index,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let index_mtime = status::index_mtime();
+        let mut entries = BTreeMap::new();
+        for (path, entry) in index.entries {
+            if !worktree::matches_pathspec(&path, pathspec) {
+                entries.insert(path, entry);
+                continue;
+            }
+            let file_path = root.join(&path);
+            match status::worktree_change(&file_path, &entry, index_mtime)? {
+                WorktreeState::Unchanged => {
+                    entries.insert(path, entry);
+                }
+                WorktreeState::Refreshed(entry) => {
+                    entries.insert(path, entry);
+                }
+                WorktreeState::Changed(Change::Deleted) => {}
+                WorktreeState::Changed(_) => {
+                    let metadata = file_path.symlink_metadata()?;
+                    let entry =
+                        Self::stage_file(&file_path, &metadata, entry.mode == MODE_GITLINK)?;
+                    entries.insert(path, entry);
+                }
+            }
+        }
+        let mut conflicts = BTreeMap::new();
+        for (path, stages) in index.conflicts {
+            if !worktree::matches_pathspec(&path, pathspec) {
+                conflicts.insert(path, stages);
+                continue;
+            }
+            let file_path = root.join(&path);
+            if let Ok(metadata) = file_path.symlink_metadata() {
+                let is_gitlink = stages.iter().any(|entry| entry.mode == MODE_GITLINK);
+                let entry = Self::stage_file(&file_path, &metadata, is_gitlink)?;
+                entries.insert(path, entry);
+            }
+        }
+        Self::from_entries(entries)
+            .with_conflicts(conflicts)
+            .write_index_to_file()
+    }
+
     fn exists_in_git_ignore(path: &Path, is_dir: bool) -> bool {
+        Self::ignore_matcher().matched(path, is_dir).is_ignore()
+    }
+
+    // Patterns of .gitrust_ignore. Paths given to it must be absolute
+    pub fn ignore_matcher() -> Gitignore {
         let root = &RepoRust::get_root().absolute_path;
         let mut builder = GitignoreBuilder::new(root);
         builder.add(root.join(".gitrust_ignore"));
-        let matcher = builder.build().unwrap();
-
-        matcher.matched(path, is_dir).is_ignore()
+        builder.build().unwrap()
     }
 
     pub fn sha1_entry(file: &[u8]) -> [u8; 20] {
@@ -286,21 +407,67 @@ impl Index {
         result.into()
     }
 
-    pub fn index_entry_from_file(path: &PathBuf) -> std::io::Result<IndexEntry> {
-        let metadata = path.metadata()?;
+    // Mode as git stores it. Only the executable bit of the owner is kept
+    pub fn mode_from_metadata(metadata: &Metadata) -> u32 {
+        if metadata.is_symlink() {
+            MODE_SYMLINK
+        } else if metadata.mode() & 0o100 != 0 {
+            MODE_EXECUTABLE
+        } else {
+            MODE_FILE
+        }
+    }
+
+    // What is stored in the blob. The path it points to for a symlink
+    pub fn file_content(path: &Path, metadata: &Metadata) -> std::io::Result<Vec<u8>> {
+        if metadata.is_symlink() {
+            return Ok(std::fs::read_link(path)?.as_os_str().as_bytes().to_vec());
+        }
+        std::fs::read(path)
+    }
+
+    pub fn index_entry_from_file(path: &Path) -> std::io::Result<IndexEntry> {
+        let metadata = path.symlink_metadata()?;
+        let file = Self::file_content(path, &metadata)?;
+        let sha1 = Self::sha1_entry(&file);
+        Self::index_entry(path, &metadata, Self::mode_from_metadata(&metadata), sha1)
+    }
+
+    // A nested repo (submodule) is added as the commit its HEAD points to
+    pub fn index_entry_from_gitlink(path: &Path) -> std::io::Result<IndexEntry> {
+        let commit = git2::Repository::open(path)
+            .and_then(|repo| repo.head()?.peel_to_commit().map(|commit| commit.id()))
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("'{}' does not have a commit checked out", path.display()),
+                )
+            })?;
+        let sha1: [u8; 20] = commit.as_bytes().try_into().unwrap();
+        Self::index_entry(path, &path.symlink_metadata()?, MODE_GITLINK, sha1)
+    }
+
+    // Entry with the given mode and hash. Metadata is read from the working tree
+    pub fn index_entry(
+        path: &Path,
+        metadata: &Metadata,
+        mode: u32,
+        sha1: [u8; 20],
+    ) -> std::io::Result<IndexEntry> {
         let ctime = metadata.ctime() as u32;
         let ctime_nanos = metadata.ctime_nsec() as u32;
         let mtime = metadata.mtime() as u32;
         let mtime_nanos = metadata.mtime_nsec() as u32;
         let dev = metadata.dev() as u32;
         let ino = metadata.ino() as u32;
-        let mode = metadata.mode();
         let uid = metadata.uid();
         let gid = metadata.gid();
-        let file_size = metadata.size() as u32;
-
-        let file = std::fs::read(path)?;
-        let sha1 = Self::sha1_entry(&file);
+        // Same as git, 0 for a submodule
+        let file_size = if mode == MODE_GITLINK {
+            0
+        } else {
+            metadata.size() as u32
+        };
 
         // Start with the absolute path and strip it
         // to allow both tests dirs and normal operation
@@ -343,7 +510,7 @@ impl Index {
         Ok(entry)
     }
 
-    fn write_index_to_file(&self) -> std::io::Result<()> {
+    pub fn write_index_to_file(&self) -> std::io::Result<()> {
         let mut buffer = Vec::new();
         let index_path = &RepoRust::get_root()
             .absolute_path
@@ -353,7 +520,14 @@ impl Index {
         let header = self.header();
         let header_bytes = header.to_bytes();
         buffer.extend_from_slice(&header_bytes);
-        for entry in self.entries.values() {
+        // Sorted by path, then by stage
+        let mut entries: Vec<&IndexEntry> = self
+            .entries
+            .values()
+            .chain(self.conflicts.values().flatten())
+            .collect();
+        entries.sort_by(|a, b| (&a.path, a.stage()).cmp(&(&b.path, b.stage())));
+        for entry in entries {
             buffer.extend_from_slice(&entry.to_bytes());
         }
 
@@ -381,16 +555,26 @@ impl Index {
         let total_entries = u32::from_be_bytes(header.entries);
 
         let mut entries: BTreeMap<String, IndexEntry> = BTreeMap::new();
+        let mut conflicts: BTreeMap<String, Vec<IndexEntry>> = BTreeMap::new();
         let mut bytes_read = 12;
         for _ in 0..total_entries {
             let (entry, size) = IndexEntry::from_bytes(&file[bytes_read..])?;
             let path = str::from_utf8(&entry.path)
                 .map_err(|_| std::io::Error::other("Invalid path when parsing IndexEntry"))?
                 .to_string();
-            entries.insert(path, entry);
             bytes_read += size;
+            match entry.stage() {
+                0 => {
+                    entries.insert(path, entry);
+                }
+                _ => conflicts.entry(path).or_default().push(entry),
+            }
         }
-        Ok(Self { header, entries })
+        Ok(Self {
+            header,
+            entries,
+            conflicts,
+        })
     }
 
     pub fn ls_index() -> std::io::Result<BTreeMap<String, IndexEntry>> {
@@ -0,0 +1,624 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::{
+    diff, gc,
+    objects::{
+        ObjectType,
+        commit::{Autors, Commit},
+    },
+    refs, revision,
+};
+
+#[cfg(test)]
+mod test;
+
+pub enum Format {
+    // commit, Author and Date lines, then the indented message
+    Medium,
+    // <abbreviated hash> <subject>
+    Oneline,
+    // --format=<placeholders>
+    Custom(String),
+}
+
+impl Format {
+    // Pretty format names, or format:<placeholders>. A string with a % is taken as placeholders
+    pub fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "medium" => return Ok(Self::Medium),
+            "oneline" => return Ok(Self::Oneline),
+            _ => {}
+        }
+        if let Some(format) = value
+            .strip_prefix("format:")
+            .or_else(|| value.strip_prefix("tformat:"))
+        {
+            return Ok(Self::Custom(format.to_string()));
+        }
+        if value.contains('%') {
+            return Ok(Self::Custom(value.to_string()));
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid --pretty format: {value}"),
+        ))
+    }
+}
+
+pub struct LogOptions {
+    pub max_count: Option<usize>,
+    // Unix timestamps, compared with the committer date
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    // Substrings of "name <email>" of the author, and of the message
+    pub author: Option<String>,
+    pub grep: Option<String>,
+    pub format: Format,
+    pub graph: bool,
+    // Ref names next to the commits they point to
+    pub decorate: bool,
+    // Only the commits changing these paths
+    pub paths: Vec<String>,
+    // --follow: the history of a single file goes on before it was renamed
+    pub follow: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self {
+            max_count: None,
+            since: None,
+            until: None,
+            author: None,
+            grep: None,
+            format: Format::Medium,
+            graph: false,
+            decorate: false,
+            paths: Vec::new(),
+            follow: false,
+        }
+    }
+}
+
+impl LogOptions {
+    fn matches(&self, commit: &Commit) -> bool {
+        let timestamp = commit.committer.timestamp;
+        let author = format!(
+            "{} <{}>",
+            commit.text(&commit.author.name),
+            commit.text(&commit.author.email)
+        );
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp <= until)
+            && self.author.as_ref().is_none_or(|a| author.contains(a))
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|g| commit.text(&commit.message).contains(g))
+    }
+
+    fn filters(&self) -> bool {
+        self.since.is_some()
+            || self.until.is_some()
+            || self.author.is_some()
+            || self.grep.is_some()
+            || !self.paths.is_empty()
+    }
+}
+
+pub struct LogEntry {
+    pub hash: String,
+    pub commit: Commit,
+}
+
+// The history of revs, newest first. A commit always comes before its parents,
+// otherwise the most recent committer date goes first (git log --date-order)
+pub fn walk(revs: &[String]) -> std::io::Result<Vec<LogEntry>> {
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut starts = Vec::new();
+    let mut stack = Vec::new();
+    for rev in revs {
+        let hash = revision::resolve_as(rev, ObjectType::Commit)?;
+        starts.push(hash.clone());
+        stack.push(hash);
+    }
+    while let Some(hash) = stack.pop() {
+        if commits.contains_key(&hash) {
+            continue;
+        }
+        let commit = Commit::decode(&hash)?;
+        stack.extend(commit.parents_hash.iter().cloned());
+        commits.insert(hash, commit);
+    }
+
+    // Number of children of each commit not shown yet
+    let mut children: HashMap<&str, usize> = HashMap::new();
+    for commit in commits.values() {
+        for parent in &commit.parents_hash {
+            *children.entry(parent.as_str()).or_default() += 1;
+        }
+    }
+    // Ties are broken by the order commits became ready
+    let mut sequence = 0;
+    let mut ready = BinaryHeap::new();
+    let mut queued = HashSet::new();
+    for hash in &starts {
+        if !children.contains_key(hash.as_str()) && queued.insert(hash.as_str()) {
+            ready.push((
+                commits[hash].committer.timestamp,
+                Reverse(sequence),
+                hash.as_str(),
+            ));
+            sequence += 1;
+        }
+    }
+    let mut order = Vec::new();
+    while let Some((_, _, hash)) = ready.pop() {
+        order.push(hash.to_string());
+        for parent in &commits[hash].parents_hash {
+            let count = children.get_mut(parent.as_str()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                let timestamp = commits[parent].committer.timestamp;
+                ready.push((timestamp, Reverse(sequence), parent.as_str()));
+                sequence += 1;
+            }
+        }
+    }
+    Ok(order
+        .into_iter()
+        .map(|hash| {
+            let commit = commits.remove(&hash).unwrap();
+            LogEntry { hash, commit }
+        })
+        .collect())
+}
+
+// The first count commits of revs, most recent committer date first. Only the commits
+// shown and their parents are read. Same as walk, unless a commit is not older than its
+// children (same as git log without --date-order)
+pub fn walk_first(revs: &[String], count: usize) -> std::io::Result<Vec<LogEntry>> {
+    let mut pending = Vec::new();
+    for rev in revs {
+        pending.push(revision::resolve_as(rev, ObjectType::Commit)?);
+    }
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut seen = HashSet::new();
+    // Ties are broken by the order commits were found
+    let mut sequence = 0;
+    let mut ready = BinaryHeap::new();
+    let mut entries = Vec::new();
+    while entries.len() < count {
+        for hash in pending.drain(..) {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            let commit = Commit::decode(&hash)?;
+            ready.push((commit.committer.timestamp, Reverse(sequence), hash.clone()));
+            sequence += 1;
+            commits.insert(hash, commit);
+        }
+        let Some((_, _, hash)) = ready.pop() else {
+            break;
+        };
+        let commit = commits.remove(&hash).unwrap();
+        pending.extend(commit.parents_hash.iter().cloned());
+        entries.push(LogEntry { hash, commit });
+    }
+    Ok(entries)
+}
+
+// Commits of the history of hash that no ref (nor HEAD) can reach, newest first
+// These are lost when a detached HEAD moves away from them
+pub fn orphaned(hash: &str) -> std::io::Result<Vec<LogEntry>> {
+    let mut tips: Vec<String> = refs::read_ref("HEAD")?.into_iter().collect();
+    for (name, _) in refs::list_refs("refs")? {
+        if let Ok(commit) = revision::resolve_as(&name, ObjectType::Commit) {
+            tips.push(commit);
+        }
+    }
+    let reachable: HashSet<String> = match tips.is_empty() {
+        true => HashSet::new(),
+        false => walk(&tips)?.into_iter().map(|entry| entry.hash).collect(),
+    };
+    Ok(walk(&[hash.to_string()])?
+        .into_iter()
+        .filter(|entry| !reachable.contains(&entry.hash))
+        .collect())
+}
+
+// The output of git log for revs
+pub fn log(revs: &[String], options: &LogOptions) -> std::io::Result<String> {
+    // Filters and the graph need the whole history, -n alone does not
+    let entries = match options.max_count {
+        Some(count) if !options.filters() && !options.graph => walk_first(revs, count)?,
+        _ => walk(revs)?,
+    };
+    let touching = match options.paths.is_empty() {
+        true => None,
+        false => Some(touching_paths(&entries, &options.paths, options.follow)?),
+    };
+    let matching: HashSet<&str> = entries
+        .iter()
+        .filter(|entry| options.matches(&entry.commit))
+        .filter(|entry| touching.as_ref().is_none_or(|t| t.contains(&entry.hash)))
+        .map(|entry| entry.hash.as_str())
+        .collect();
+    let parents = match options.filters() {
+        true => rewrite_parents(&entries, &matching),
+        false => entries
+            .iter()
+            .map(|entry| (entry.hash.clone(), entry.commit.parents_hash.clone()))
+            .collect(),
+    };
+    let shown: Vec<&LogEntry> = entries
+        .iter()
+        .filter(|entry| matching.contains(entry.hash.as_str()))
+        .take(options.max_count.unwrap_or(usize::MAX))
+        .collect();
+
+    let decorations = decorations()?;
+    let mut graph = Graph::default();
+    let mut output = String::new();
+    for (i, entry) in shown.iter().enumerate() {
+        // %d and %D work without --decorate
+        let labels = match options.decorate || matches!(options.format, Format::Custom(_)) {
+            true => decorations.get(&entry.hash).map(Vec::as_slice),
+            false => None,
+        };
+        let labels = labels.unwrap_or_default();
+        let mut lines = format_commit(&entry.hash, &entry.commit, &options.format, labels)?;
+        // A blank line between commits in the medium format
+        if matches!(options.format, Format::Medium) && i + 1 < shown.len() {
+            lines.push(String::new());
+        }
+        if !options.graph {
+            for line in lines {
+                output.push_str(&line);
+                output.push('\n');
+            }
+            continue;
+        }
+        let mut prefixes = graph.draw(&entry.hash, &parents[&entry.hash]);
+        while prefixes.len() < lines.len() {
+            prefixes.push(graph.bars());
+        }
+        lines.resize(prefixes.len(), String::new());
+        // Same as git, the text starts after the columns left open by the commit, so a
+        // merge row is "*   merge" and lines up with the "| * " rows below it
+        let width = prefixes
+            .iter()
+            .map(String::len)
+            .chain([graph.bars().len()])
+            .max()
+            .unwrap_or(0);
+        for (prefix, line) in prefixes.iter().zip(lines) {
+            let line = format!("{prefix:<width$} {line}");
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+    }
+    Ok(output)
+}
+
+// Commits whose tree differs from the tree of their first parent at paths
+// With follow, a single file is followed across renames: once the commit that added
+// it as a rename is found, the older commits are matched against its old name
+fn touching_paths(
+    entries: &[LogEntry],
+    paths: &[String],
+    follow: bool,
+) -> std::io::Result<HashSet<String>> {
+    if follow && paths.len() != 1 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--follow requires exactly one pathspec",
+        ));
+    }
+    let mut paths = paths.to_vec();
+    let mut touching = HashSet::new();
+    for entry in entries {
+        let tree = revision::resolve_as(&entry.hash, ObjectType::Tree)?;
+        let parent_tree = match entry.commit.parents_hash.first() {
+            Some(parent) => Some(revision::resolve_as(parent, ObjectType::Tree)?),
+            None => None,
+        };
+        if !follow {
+            if !diff::diff_trees(parent_tree.as_deref(), Some(&tree), &paths)?.is_empty() {
+                touching.insert(entry.hash.clone());
+            }
+            continue;
+        }
+        // The source of a rename is only found with all the changes of the commit
+        let changes = diff::diff_trees(parent_tree.as_deref(), Some(&tree), &[])?;
+        let changes = diff::detect_renames(changes, diff::DEFAULT_SIMILARITY, false)?;
+        let Some(change) = changes.iter().find(|change| change.path == paths[0]) else {
+            continue;
+        };
+        touching.insert(entry.hash.clone());
+        if let Some(source) = &change.source {
+            paths[0] = source.path.clone();
+        }
+    }
+    Ok(touching)
+}
+
+// With filters, the graph links each shown commit to its nearest shown ancestors
+fn rewrite_parents(entries: &[LogEntry], shown: &HashSet<&str>) -> HashMap<String, Vec<String>> {
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    // Parents come after their children, so they are done first from the end
+    let mut hidden_ancestors: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in entries.iter().rev() {
+        let mut nearest: Vec<String> = Vec::new();
+        for parent in &entry.commit.parents_hash {
+            let found = match shown.contains(parent.as_str()) {
+                true => std::slice::from_ref(parent),
+                false => hidden_ancestors
+                    .get(parent.as_str())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+            };
+            for hash in found {
+                if !nearest.contains(hash) {
+                    nearest.push(hash.clone());
+                }
+            }
+        }
+        match shown.contains(entry.hash.as_str()) {
+            true => parents.insert(entry.hash.clone(), nearest),
+            false => hidden_ancestors.insert(&entry.hash, nearest),
+        };
+    }
+    parents
+}
+
+// Ref names of each commit: HEAD first ("HEAD -> main" when HEAD is on a branch
+// pointing to the commit), then branches, remote-tracking branches and tags
+pub fn decorations() -> std::io::Result<HashMap<String, Vec<String>>> {
+    let mut decorations: HashMap<String, Vec<String>> = HashMap::new();
+    let head_branch = refs::head_branch()?;
+    if let Some(head) = refs::read_ref("HEAD")? {
+        let label = match &head_branch {
+            Some(branch) => format!("HEAD -> {}", revision::shorten_ref(branch)),
+            None => "HEAD".to_string(),
+        };
+        decorations.entry(head).or_default().push(label);
+    }
+    for (name, _) in refs::list_refs("refs")? {
+        if head_branch.as_ref() == Some(&name) {
+            continue;
+        }
+        // Tags can point to other objects than commits
+        let Ok(commit) = revision::resolve_as(&name, ObjectType::Commit) else {
+            continue;
+        };
+        let label = match name.strip_prefix("refs/tags/") {
+            Some(tag) => format!("tag: {tag}"),
+            None => revision::shorten_ref(&name).to_string(),
+        };
+        decorations.entry(commit).or_default().push(label);
+    }
+    Ok(decorations)
+}
+
+// The lines of one commit, without the graph. labels are its ref names
+pub fn format_commit(
+    hash: &str,
+    commit: &Commit,
+    format: &Format,
+    labels: &[String],
+) -> std::io::Result<Vec<String>> {
+    let abbreviate = |oid: &str| revision::abbreviate(oid, revision::DEFAULT_ABBREV);
+    let message = commit.text(&commit.message);
+    let (subject, body) = split_message(&message);
+    let decoration = match labels.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", labels.join(", ")),
+    };
+    match format {
+        Format::Oneline => Ok(vec![format!("{}{decoration} {subject}", abbreviate(hash)?)]),
+        Format::Medium => {
+            let mut lines = vec![format!("commit {hash}{decoration}")];
+            if commit.parents_hash.len() > 1 {
+                let parents = commit
+                    .parents_hash
+                    .iter()
+                    .map(|p| abbreviate(p))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                lines.push(format!("Merge: {}", parents.join(" ")));
+            }
+            lines.push(format!(
+                "Author: {} <{}>",
+                commit.text(&commit.author.name),
+                commit.text(&commit.author.email)
+            ));
+            lines.push(format!("Date:   {}", format_date(&commit.author)));
+            lines.push(String::new());
+            for line in message.trim_end().lines() {
+                lines.push(format!("    {line}").trim_end().to_string());
+            }
+            Ok(lines)
+        }
+        Format::Custom(placeholders) => {
+            let mut output = String::new();
+            let mut chars = placeholders.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    output.push(c);
+                    continue;
+                }
+                let rest = chars.as_str();
+                let (value, skip) = match rest.get(..2) {
+                    Some("an") => (commit.text(&commit.author.name), 2),
+                    Some("ae") => (commit.text(&commit.author.email), 2),
+                    Some("ad") => (format_date(&commit.author), 2),
+                    _ => match rest.chars().next() {
+                        Some('H') => (hash.to_string(), 1),
+                        Some('h') => (abbreviate(hash)?, 1),
+                        Some('s') => (subject.to_string(), 1),
+                        Some('b') => (body.to_string(), 1),
+                        Some('d') => (decoration.clone(), 1),
+                        Some('D') => (labels.join(", "), 1),
+                        Some('n') => ("\n".to_string(), 1),
+                        Some('%') => ("%".to_string(), 1),
+                        // Unknown placeholders are printed as they are
+                        _ => ("%".to_string(), 0),
+                    },
+                };
+                output.push_str(&value);
+                for _ in 0..skip {
+                    chars.next();
+                }
+            }
+            Ok(output.split('\n').map(str::to_string).collect())
+        }
+    }
+}
+
+// The subject is the first paragraph, the body is the rest
+fn split_message(message: &str) -> (String, String) {
+    let message = message.trim_start_matches('\n');
+    let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+    let subject = subject.lines().collect::<Vec<_>>().join(" ");
+    (subject, body.trim_end().to_string())
+}
+
+// Thu Oct 17 12:00:00 2026 +0200, in the timezone of the author
+pub fn format_date(person: &Autors) -> String {
+    let timezone = &person.timezone;
+    let offset = timezone
+        .get(1..3)
+        .zip(timezone.get(3..5))
+        .and_then(|(h, m)| Some(h.parse::<i32>().ok()? * 3600 + m.parse::<i32>().ok()? * 60))
+        .map(|seconds| match timezone.starts_with('-') {
+            true => -seconds,
+            false => seconds,
+        })
+        .and_then(FixedOffset::east_opt)
+        .unwrap_or(FixedOffset::east_opt(0).unwrap());
+    match DateTime::from_timestamp(person.timestamp, 0) {
+        Some(date) => date
+            .with_timezone(&offset)
+            .format("%a %b %-d %H:%M:%S %Y %z")
+            .to_string(),
+        None => person.timestamp.to_string(),
+    }
+}
+
+// Values of --since and --until: 2026-10-17, "2026-10-17 12:00:00", @<unix time>,
+// now, yesterday or a relative date like 2.weeks.ago or "3 days ago"
+pub fn parse_date(value: &str) -> std::io::Result<i64> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid date: {value}"),
+        )
+    };
+    if let Some(timestamp) = value.strip_prefix('@') {
+        return timestamp.parse().map_err(|_| invalid());
+    }
+    let local = |date: NaiveDateTime| Local.from_local_datetime(&date).earliest();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = local(date.and_hms_opt(0, 0, 0).unwrap()).ok_or_else(invalid)?;
+        return Ok(date.timestamp());
+    }
+    for pattern in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, pattern) {
+            return Ok(local(date).ok_or_else(invalid)?.timestamp());
+        }
+    }
+    let relative = match value.trim() {
+        "yesterday" => "1.day.ago".to_string(),
+        value => value.replace(' ', "."),
+    };
+    match gc::parse_expiry(&relative) {
+        Ok(Some(time)) => Ok(DateTime::<Local>::from(time).timestamp()),
+        _ => Err(invalid()),
+    }
+}
+
+// Draws the lines of --graph. Each column waits for a commit, the leftmost
+// one is the first parent of the commit drawn before
+#[derive(Default)]
+struct Graph {
+    columns: Vec<String>,
+}
+
+impl Graph {
+    // The row of the commit, then the lines that lead to its parents
+    fn draw(&mut self, hash: &str, parents: &[String]) -> Vec<String> {
+        let column = match self.columns.iter().position(|c| c == hash) {
+            Some(column) => column,
+            None => {
+                self.columns.push(hash.to_string());
+                self.columns.len() - 1
+            }
+        };
+        let row: Vec<&str> = (0..self.columns.len())
+            .map(|i| if i == column { "*" } else { "|" })
+            .collect();
+        let mut lines = vec![row.join(" ")];
+
+        // A parent already waited for by another column is joined there
+        let mut columns: Vec<String> = Vec::new();
+        for (i, waiting) in self.columns.iter().enumerate() {
+            if i != column {
+                if !columns.contains(waiting) {
+                    columns.push(waiting.clone());
+                }
+                continue;
+            }
+            for parent in parents {
+                let elsewhere = self
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .any(|(j, c)| j != column && c == parent);
+                if !elsewhere && !columns.contains(parent) {
+                    columns.push(parent.clone());
+                }
+            }
+        }
+        // Positions in chars. Each line moves an edge by one column
+        let target = |hash: &String| 2 * columns.iter().position(|c| c == hash).unwrap();
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        for (i, waiting) in self.columns.iter().enumerate() {
+            match i == column {
+                true => edges.extend(parents.iter().map(|p| (2 * i, target(p)))),
+                false => edges.push((2 * i, target(waiting))),
+            }
+        }
+        while edges.iter().any(|(from, to)| from != to) {
+            let width = edges
+                .iter()
+                .map(|(from, to)| from.max(to) + 1)
+                .max()
+                .unwrap();
+            let mut line = vec![b' '; width];
+            for (from, to) in edges.iter_mut() {
+                if *from < *to {
+                    line[*from + 1] = b'\\';
+                    *from += 2;
+                } else if *from > *to {
+                    line[*from - 1] = b'/';
+                    *from -= 2;
+                } else {
+                    line[*from] = b'|';
+                }
+            }
+            lines.push(String::from_utf8(line).unwrap().trim_end().to_string());
+        }
+        self.columns = columns;
+        lines
+    }
+
+    // Lines of a commit past the row and the lines to its parents
+    fn bars(&self) -> String {
+        vec!["|"; self.columns.len()].join(" ")
+    }
+}
@@ -0,0 +1,374 @@
+use std::path::PathBuf;
+
+use crate::{
+    git_rust::RepoRust,
+    log::{self, Format, LogOptions},
+    objects::{self, ObjectType},
+    refs::{self, write_ref},
+    test_common::{run_test, run_test_matches},
+};
+
+fn write_commit(message: &str, parents: &[&str], author: &str, timestamp: i64) -> String {
+    let blob = objects::write_object(ObjectType::Blob, message.as_bytes()).unwrap();
+    let mut tree = b"100644 file.txt\0".to_vec();
+    tree.extend_from_slice(&hex::decode(&blob).unwrap());
+    let tree = objects::write_object(ObjectType::Tree, &tree).unwrap();
+    let mut commit = format!("tree {tree}\n");
+    for parent in parents {
+        commit.push_str(&format!("parent {parent}\n"));
+    }
+    commit.push_str(&format!(
+        "author {author} <{author}@example.com> {timestamp} +0200\n"
+    ));
+    commit.push_str(&format!(
+        "committer {author} <{author}@example.com> {timestamp} +0200\n\n{message}\n"
+    ));
+    objects::write_object(ObjectType::Commit, commit.as_bytes()).unwrap()
+}
+
+fn subjects(options: LogOptions) -> String {
+    let options = LogOptions {
+        format: Format::Custom("%s".to_string()),
+        ..options
+    };
+    log::log(&["HEAD".to_string()], &options).unwrap()
+}
+
+// c1 - c2 ----- merge
+//    \ side /
+fn write_history() -> [String; 4] {
+    let c1 = write_commit("c1", &[], "alice", 1_700_000_000);
+    let c2 = write_commit("c2", &[&c1], "bob", 1_700_001_000);
+    let side = write_commit("side", &[&c1], "alice", 1_700_002_000);
+    let merge = write_commit("merge", &[&c2, &side], "bob", 1_700_003_000);
+    write_ref("refs/heads/master", &merge).unwrap();
+    [c1, c2, side, merge]
+}
+
+#[test]
+fn test_log_order_and_graph() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let [c1, c2, side, merge] = write_history();
+
+        let order: Vec<String> = log::walk(&["HEAD".to_string()])
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.hash)
+            .collect();
+        assert_eq!(order, [merge.clone(), side, c2.clone(), c1.clone()]);
+
+        assert_eq!(subjects(LogOptions::default()), "merge\nside\nc2\nc1\n");
+        let graph = subjects(LogOptions {
+            graph: true,
+            ..Default::default()
+        });
+        assert_eq!(graph, "*   merge\n|\\\n| * side\n* | c2\n|/\n* c1\n");
+        // Same row as git log --graph --oneline for a merge
+        let oneline = LogOptions {
+            format: Format::Oneline,
+            graph: true,
+            ..Default::default()
+        };
+        let graph = log::log(&["HEAD".to_string()], &oneline).unwrap();
+        assert_eq!(
+            graph.lines().next().unwrap(),
+            format!("*   {} merge", &merge[..7])
+        );
+
+        // Two starting points, the second one is already in the history of the first
+        let options = LogOptions {
+            format: Format::Custom("%s".to_string()),
+            graph: true,
+            ..Default::default()
+        };
+        let revs = [c2.clone(), merge.clone()];
+        let graph = log::log(&revs, &options).unwrap();
+        assert_eq!(graph, "*   merge\n|\\\n| * side\n* | c2\n|/\n* c1\n");
+
+        // A root commit ends its column
+        let other = write_commit("other", &[], "carol", 1_700_000_500);
+        let revs = [merge, other];
+        let graph = log::log(&revs, &options).unwrap();
+        assert!(graph.ends_with("|/\n| * other\n* c1\n"), "{graph}");
+    });
+}
+
+#[test]
+fn test_log_filters() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        write_history();
+
+        let author = |author: &str| LogOptions {
+            author: Some(author.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(subjects(author("alice")), "side\nc1\n");
+        assert_eq!(subjects(author("bob@example")), "merge\nc2\n");
+        let grep = LogOptions {
+            grep: Some("c".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(subjects(grep), "c2\nc1\n");
+        let count = LogOptions {
+            max_count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(subjects(count), "merge\nside\n");
+        let range = LogOptions {
+            since: Some(1_700_001_000),
+            until: Some(1_700_002_000),
+            ..Default::default()
+        };
+        assert_eq!(subjects(range), "side\nc2\n");
+
+        // Hidden commits are skipped by the graph lines
+        let graph = LogOptions {
+            author: Some("bob".to_string()),
+            graph: true,
+            ..Default::default()
+        };
+        assert_eq!(subjects(graph), "* merge\n* c2\n");
+        let graph = LogOptions {
+            author: Some("alice".to_string()),
+            graph: true,
+            ..Default::default()
+        };
+        assert_eq!(subjects(graph), "* side\n* c1\n");
+    });
+}
+
+#[test]
+fn test_log_max_count_stops_walking() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // The parent of c1 is missing, so only a walk that stops before it works
+        let missing = "1".repeat(40);
+        let c1 = write_commit("c1", &[&missing], "alice", 1_700_000_000);
+        let c2 = write_commit("c2", &[&c1], "bob", 1_700_001_000);
+        write_ref("refs/heads/master", &c2).unwrap();
+
+        assert!(log::log(&["HEAD".to_string()], &LogOptions::default()).is_err());
+        let count = |count| LogOptions {
+            max_count: Some(count),
+            ..Default::default()
+        };
+        assert_eq!(subjects(count(2)), "c2\nc1\n");
+        assert_eq!(subjects(count(0)), "");
+        // A filter still needs every commit
+        let grep = LogOptions {
+            grep: Some("c".to_string()),
+            ..count(1)
+        };
+        assert!(log::log(&["HEAD".to_string()], &grep).is_err());
+    });
+}
+
+#[test]
+fn test_log_formats() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let [_, c2, side, merge] = write_history();
+
+        let format = |format: &str| LogOptions {
+            format: Format::parse(format).unwrap(),
+            max_count: Some(1),
+            ..Default::default()
+        };
+        let output = log::log(&["HEAD".to_string()], &format("%H %h %an %ae%n%ad %% %x")).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{merge} {} bob bob@example.com\nWed Nov 15 01:03:20 2023 +0200 % %x\n",
+                &merge[..7]
+            )
+        );
+        let output = log::log(&["HEAD".to_string()], &format("oneline")).unwrap();
+        assert_eq!(output, format!("{} merge\n", &merge[..7]));
+        assert!(Format::parse("fuller").is_err());
+
+        let output = log::log(&["HEAD".to_string()], &format("medium")).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "commit {merge}\nMerge: {} {}\nAuthor: bob <bob@example.com>\n\
+                 Date:   Wed Nov 15 01:03:20 2023 +0200\n\n    merge\n",
+                &c2[..7],
+                &side[..7]
+            )
+        );
+
+        // Subject and body of a message with paragraphs
+        let body = write_commit(
+            "Subject\n\nFirst\n\nSecond",
+            &[&merge],
+            "A U Thor",
+            1_700_004_000,
+        );
+        write_ref("refs/heads/master", &body).unwrap();
+        let output = log::log(&["HEAD".to_string()], &format("%an: %s|%b")).unwrap();
+        assert_eq!(output, "A U Thor: Subject|First\n\nSecond\n");
+
+        assert_eq!(log::parse_date("@1700000000").unwrap(), 1_700_000_000);
+        assert!(log::parse_date("2023-11-15").is_ok());
+        assert!(log::parse_date("2023-11-15 01:03:20").is_ok());
+        let ago = log::parse_date("2 weeks ago").unwrap();
+        assert_eq!(ago, log::parse_date("2.weeks.ago").unwrap());
+        assert!(log::parse_date("never").is_err());
+        assert!(log::parse_date("someday").is_err());
+    });
+}
+
+#[test]
+fn test_log_decorations() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let [c1, c2, _, merge] = write_history();
+        write_ref("refs/tags/v1", &c1).unwrap();
+        write_ref("refs/remotes/origin/master", &c2).unwrap();
+        write_ref("refs/heads/topic", &merge).unwrap();
+
+        let decorated = |format: Format, decorate: bool| {
+            let options = LogOptions {
+                format,
+                decorate,
+                ..Default::default()
+            };
+            log::log(&["HEAD".to_string()], &options).unwrap()
+        };
+        let output = decorated(Format::parse("%s%d").unwrap(), false);
+        assert_eq!(
+            output,
+            "merge (HEAD -> master, topic)\nside\nc2 (origin/master)\nc1 (tag: v1)\n"
+        );
+        let output = decorated(Format::parse("%D|%s").unwrap(), false);
+        assert!(
+            output.ends_with("|side\norigin/master|c2\ntag: v1|c1\n"),
+            "{output}"
+        );
+        let output = decorated(Format::Oneline, true);
+        assert!(output.starts_with(&format!("{} (HEAD -> master, topic) merge\n", &merge[..7])));
+        let output = decorated(Format::Oneline, false);
+        assert!(output.starts_with(&format!("{} merge\n", &merge[..7])));
+
+        // A detached HEAD is shown by itself
+        refs::set_head_detached(&c2, "").unwrap();
+        let output = decorated(Format::Medium, true);
+        assert!(
+            output.starts_with(&format!("commit {c2} (HEAD, origin/master)\n")),
+            "{output}"
+        );
+        assert_eq!(log::decorations().unwrap()[&merge], ["master", "topic"]);
+    });
+}
+
+#[test]
+fn test_log_command() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let args = run_test_matches(vec!["", "log"]);
+        assert!(RepoRust::log(&args).is_err());
+
+        // Commits made by commit can be read back
+        std::fs::write(path.join("file.txt"), "one\n").unwrap();
+        let args = run_test_matches(vec!["", "add", "file.txt"]);
+        RepoRust::add(&args).unwrap();
+        let args = run_test_matches(vec!["", "commit", "-m", "first"]);
+        RepoRust::commit(&args).unwrap();
+        let output = log::log(&["HEAD".to_string()], &LogOptions::default()).unwrap();
+        assert!(output.ends_with("\n\n    first\n"), "{output}");
+
+        let args = run_test_matches(vec![
+            "",
+            "log",
+            "--graph",
+            "--oneline",
+            "-n",
+            "1",
+            "--since",
+            "2.days.ago",
+        ]);
+        assert!(RepoRust::log(&args).is_ok());
+        let args = run_test_matches(vec!["", "log", "--format=%h %s", "--author", "x"]);
+        assert!(RepoRust::log(&args).is_ok());
+        let args = run_test_matches(vec!["", "log", "--until", "tomorrow"]);
+        assert!(RepoRust::log(&args).is_err());
+        let args = run_test_matches(vec!["", "log", "missing"]);
+        assert!(RepoRust::log(&args).is_err());
+    });
+}
+
+#[test]
+fn test_log_paths_and_follow() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let commit = |message: &str| {
+            let args = run_test_matches(vec!["", "add", "-A"]);
+            RepoRust::add(&args).unwrap();
+            let args = run_test_matches(vec!["", "commit", "-m", message]);
+            RepoRust::commit(&args).unwrap();
+        };
+        std::fs::write(path.join("a.txt"), "1\n2\n3\n4\n").unwrap();
+        std::fs::write(path.join("other.txt"), "other\n").unwrap();
+        commit("add a");
+        std::fs::write(path.join("a.txt"), "1\n2\n3\n4\n5\n").unwrap();
+        commit("edit a");
+        std::fs::write(path.join("other.txt"), "other 2\n").unwrap();
+        commit("edit other");
+        std::fs::rename(path.join("a.txt"), path.join("b.txt")).unwrap();
+        commit("rename a to b");
+        std::fs::write(path.join("b.txt"), "1\n2\n3\n4\n5\n6\n").unwrap();
+        commit("edit b");
+
+        let log = |paths: &[&str], follow: bool| {
+            subjects(LogOptions {
+                paths: paths.iter().map(|path| path.to_string()).collect(),
+                follow,
+                ..LogOptions::default()
+            })
+        };
+        assert_eq!(log(&["b.txt"], false), "edit b\nrename a to b\n");
+        assert_eq!(
+            log(&["b.txt"], true),
+            "edit b\nrename a to b\nedit a\nadd a\n"
+        );
+        assert_eq!(log(&["a.txt"], false), "rename a to b\nedit a\nadd a\n");
+        assert_eq!(
+            log(&["other.txt", "b.txt"], false),
+            "edit b\nrename a to b\nedit other\nadd a\n"
+        );
+        let options = LogOptions {
+            paths: vec!["a.txt".to_string(), "b.txt".to_string()],
+            follow: true,
+            ..LogOptions::default()
+        };
+        assert!(log::log(&["HEAD".to_string()], &options).is_err());
+
+        let args = run_test_matches(vec!["", "log", "--follow", "--oneline", "--", "b.txt"]);
+        assert!(RepoRust::log(&args).is_ok());
+    });
+}
@@ -1,7 +1,20 @@
+mod branch;
+mod config;
+mod diff;
+mod gc;
 mod git_rust;
 mod index;
+mod log;
+mod merge;
 mod objects;
+mod odb;
+mod pack;
+mod reflog;
+mod refs;
 mod requests;
+mod revision;
+mod status;
+mod worktree;
 
 #[cfg(test)]
 mod test_common;
@@ -49,7 +62,76 @@ fn main() -> std::io::Result<()> {
         .subcommand(
             Command::new("add")
                 .about("Update the index using the content found in the working tree.")
-                .arg(Arg::new("path").required(true).value_name("path")),
+                .arg(
+                    Arg::new("path")
+                        .required_unless_present_any(["update", "all"])
+                        .value_name("path"),
+                )
+                .arg(
+                    Arg::new("update")
+                        .short('u')
+                        .long("update")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("all")
+                        .help("Stage modified and deleted tracked files. New files are not added"),
+                )
+                .arg(
+                    Arg::new("all")
+                        .short('A')
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Stage modified, deleted and new files"),
+                ),
+        )
+        // git rm
+        .subcommand(
+            Command::new("rm")
+                .about("Remove files from the working tree and from the index")
+                .arg(
+                    Arg::new("pathspec")
+                        .value_name("PATHSPEC")
+                        .num_args(1..)
+                        .required(true)
+                        .help("Files to remove. A folder needs -r."),
+                )
+                .arg(
+                    Arg::new("cached")
+                        .long("cached")
+                        .action(ArgAction::SetTrue)
+                        .help("Only remove the files from the index. The working tree files are kept."),
+                )
+                .arg(
+                    Arg::new("recursive")
+                        .short('r')
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the files of a folder."),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove files with staged content or local changes."),
+                ),
+        )
+        // git mv
+        .subcommand(
+            Command::new("mv")
+                .about("Move or rename a file or a directory")
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATHS")
+                        .num_args(2..)
+                        .required(true)
+                        .help("<source> <destination> or <source>... <destination folder>"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Overwrite an existing destination file."),
+                ),
         )
         // git ls-files
         .subcommand(
@@ -83,6 +165,106 @@ fn main() -> std::io::Result<()> {
                         .help("Commit message (if not provided, reads from stdin)"),
                 ),
         )
+        // git status
+        .subcommand(
+            Command::new("status")
+                .about("Show the working tree status")
+                .arg(
+                    Arg::new("short")
+                        .short('s')
+                        .long("short")
+                        .action(ArgAction::SetTrue)
+                        .help("Give the output in the short format."),
+                )
+                .arg(
+                    Arg::new("porcelain")
+                        .long("porcelain")
+                        .value_name("VERSION")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("v1")
+                        .value_parser(["v1"])
+                        .help("Give the output in a stable format for scripts. Same as --short."),
+                ),
+        )
+        // git diff
+        .subcommand(
+            Command::new("diff")
+                .about("Show changes between commits, commit and working tree, etc")
+                .arg(
+                    Arg::new("args")
+                        .value_name("REV")
+                        .num_args(0..)
+                        .help("Up to two commits to compare, then the paths to limit the diff to."),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATHS")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Limit the diff to these paths."),
+                )
+                .arg(
+                    Arg::new("cached")
+                        .long("cached")
+                        .visible_alias("staged")
+                        .action(ArgAction::SetTrue)
+                        .help("Compare the index with HEAD, or with the given commit."),
+                )
+                .arg(
+                    Arg::new("unified")
+                        .short('U')
+                        .long("unified")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Generate diffs with N lines of context. Default 3."),
+                )
+                .arg(
+                    Arg::new("find-renames")
+                        .short('M')
+                        .long("find-renames")
+                        .value_name("N")
+                        .num_args(0..=1)
+                        .default_missing_value("")
+                        .help("Detect renames. A pair is a rename when it is N% similar, 50% by default: -M90%"),
+                )
+                .arg(
+                    Arg::new("find-copies")
+                        .short('C')
+                        .long("find-copies")
+                        .value_name("N")
+                        .num_args(0..=1)
+                        .default_missing_value("")
+                        .help("Detect copies as well as renames. Same threshold as -M: -C75%"),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .long("stat")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["name-only", "name-status", "raw"])
+                        .help("Show the number of lines changed in each file."),
+                )
+                .arg(
+                    Arg::new("name-only")
+                        .long("name-only")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["name-status", "raw"])
+                        .help("Show only the names of the changed files."),
+                )
+                .arg(
+                    Arg::new("name-status")
+                        .long("name-status")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("raw")
+                        .help("Show the names and the status of the changed files."),
+                )
+                .arg(
+                    Arg::new("raw")
+                        .long("raw")
+                        .action(ArgAction::SetTrue)
+                        .help("Show modes, hashes and status of the changed files."),
+                ),
+        )
         // commit
         .subcommand(
             Command::new("commit").about("Record changes to the repository")
@@ -95,9 +277,526 @@ fn main() -> std::io::Result<()> {
                 .short('m')
                 .value_name("MESSAGE")
                 .help("Add a commit message.")))
+        // git log
+        .subcommand(
+            Command::new("log")
+                .about("Show commit logs")
+                .arg(
+                    Arg::new("rev")
+                        .value_name("REV")
+                        .num_args(1..)
+                        .help("Show the history of these revisions. Defaults to HEAD."),
+                )
+                .arg(
+                    Arg::new("oneline")
+                        .long("oneline")
+                        .action(ArgAction::SetTrue)
+                        .help("The abbreviated hash and the subject on one line."),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .visible_alias("pretty")
+                        .value_name("FORMAT")
+                        .help("oneline, medium or placeholders: %H %h %an %ae %ad %s %b %n"),
+                )
+                .arg(
+                    Arg::new("max-count")
+                        .short('n')
+                        .long("max-count")
+                        .value_name("NUMBER")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Limit the number of commits shown."),
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .visible_alias("after")
+                        .value_name("DATE")
+                        .help("Show commits more recent than a date."),
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .visible_alias("before")
+                        .value_name("DATE")
+                        .help("Show commits older than a date."),
+                )
+                .arg(
+                    Arg::new("author")
+                        .long("author")
+                        .value_name("PATTERN")
+                        .help("Only commits whose author name or email contains the pattern."),
+                )
+                .arg(
+                    Arg::new("grep")
+                        .long("grep")
+                        .value_name("PATTERN")
+                        .help("Only commits whose message contains the pattern."),
+                )
+                .arg(
+                    Arg::new("graph")
+                        .long("graph")
+                        .action(ArgAction::SetTrue)
+                        .help("Draw the history as a graph on the left of the output."),
+                )
+                .arg(
+                    Arg::new("decorate")
+                        .long("decorate")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the branches, tags and HEAD pointing to each commit."),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATHS")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Only the commits changing these paths."),
+                )
+                .arg(
+                    Arg::new("follow")
+                        .long("follow")
+                        .action(ArgAction::SetTrue)
+                        .help("Continue the history of a single file beyond renames."),
+                ),
+        )
+        // git branch
+        .subcommand(
+            Command::new("branch")
+                .about("List, create, or delete branches")
+                .arg(
+                    Arg::new("args")
+                        .value_name("BRANCH")
+                        .num_args(0..)
+                        .help("<name> [<start-point>], or the branches for -d, -m, -u"),
+                )
+                .arg(
+                    Arg::new("delete")
+                        .short('d')
+                        .long("delete")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete a branch. It must be merged in its upstream, or in HEAD."),
+                )
+                .arg(
+                    Arg::new("force-delete")
+                        .short('D')
+                        .action(ArgAction::SetTrue)
+                        .help("Delete a branch, merged or not. Same as -d -f."),
+                )
+                .arg(
+                    Arg::new("move")
+                        .short('m')
+                        .long("move")
+                        .action(ArgAction::SetTrue)
+                        .help("Rename a branch, with its reflog and config."),
+                )
+                .arg(
+                    Arg::new("force-move")
+                        .short('M')
+                        .action(ArgAction::SetTrue)
+                        .help("Rename a branch even if the new name exists. Same as -m -f."),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Reset an existing branch to the start point."),
+                )
+                .arg(
+                    Arg::new("remotes")
+                        .short('r')
+                        .long("remotes")
+                        .action(ArgAction::SetTrue)
+                        .help("List the remote-tracking branches."),
+                )
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("List both local and remote-tracking branches."),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the hash and subject of each branch."),
+                )
+                .arg(
+                    Arg::new("set-upstream-to")
+                        .short('u')
+                        .long("set-upstream-to")
+                        .value_name("UPSTREAM")
+                        .help("Set the upstream of a branch (the current one by default)."),
+                )
+                .arg(
+                    Arg::new("unset-upstream")
+                        .long("unset-upstream")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the upstream of a branch (the current one by default)."),
+                ),
+        )
+        // git switch
+        .subcommand(
+            Command::new("switch")
+                .about("Switch branches")
+                .arg(
+                    Arg::new("target")
+                        .value_name("BRANCH")
+                        .help("The branch to switch to, or the start point of a new branch."),
+                )
+                .arg(
+                    Arg::new("create")
+                        .short('c')
+                        .long("create")
+                        .value_name("NEW_BRANCH")
+                        .help("Create a new branch at the start point (HEAD by default) and switch to it."),
+                )
+                .arg(
+                    Arg::new("force-create")
+                        .short('C')
+                        .long("force-create")
+                        .value_name("NEW_BRANCH")
+                        .help("Same as -c, but an existing branch is reset to the start point."),
+                )
+                .arg(
+                    Arg::new("detach")
+                        .long("detach")
+                        .action(ArgAction::SetTrue)
+                        .help("Switch to a commit without a branch (detached HEAD)."),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .visible_alias("discard-changes")
+                        .action(ArgAction::SetTrue)
+                        .help("Discard the local changes of the index and the working tree."),
+                ),
+        )
+        // git checkout
+        .subcommand(
+            Command::new("checkout")
+                .about("Switch branches or restore working tree files")
+                .arg(
+                    Arg::new("args")
+                        .value_name("REV")
+                        .num_args(0..)
+                        .help("The branch or commit to check out, or <tree-ish> <paths>..."),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATHS")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Files to restore from the index, or from <tree-ish> if given."),
+                )
+                .arg(
+                    Arg::new("create")
+                        .short('b')
+                        .value_name("NEW_BRANCH")
+                        .help("Create a new branch at the start point (HEAD by default) and switch to it."),
+                )
+                .arg(
+                    Arg::new("force-create")
+                        .short('B')
+                        .value_name("NEW_BRANCH")
+                        .help("Same as -b, but an existing branch is reset to the start point."),
+                )
+                .arg(
+                    Arg::new("detach")
+                        .long("detach")
+                        .action(ArgAction::SetTrue)
+                        .help("Check out a commit without a branch, even if a branch is given."),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Discard the local changes of the index and the working tree."),
+                ),
+        )
+        // git reset
+        .subcommand(
+            Command::new("reset")
+                .about("Reset current HEAD to the specified state")
+                .arg(
+                    Arg::new("args")
+                        .value_name("REV")
+                        .num_args(0..)
+                        .help("The commit to reset to (HEAD by default), or [<tree-ish>] <paths>..."),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATHS")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Files to unstage: their index entries are reset to <tree-ish>."),
+                )
+                .arg(
+                    Arg::new("soft")
+                        .long("soft")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["mixed", "hard"])
+                        .help("Only move the branch. The index and the working tree are kept."),
+                )
+                .arg(
+                    Arg::new("mixed")
+                        .long("mixed")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("hard")
+                        .help("Move the branch and reset the index, but not the working tree. Default."),
+                )
+                .arg(
+                    Arg::new("hard")
+                        .long("hard")
+                        .action(ArgAction::SetTrue)
+                        .help("Move the branch and reset the index and the working tree. Local changes are lost."),
+                ),
+        )
+        // git merge
+        .subcommand(
+            Command::new("merge")
+                .about("Join two development histories together")
+                .arg(
+                    Arg::new("rev")
+                        .value_name("REV")
+                        .required_unless_present_any(["continue", "abort"])
+                        .help("The commit to merge into the current branch."),
+                )
+                .arg(
+                    Arg::new("ff-only")
+                        .long("ff-only")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("no-ff")
+                        .help("Only fast-forward. Fail if a merge commit would be needed."),
+                )
+                .arg(
+                    Arg::new("no-ff")
+                        .long("no-ff")
+                        .action(ArgAction::SetTrue)
+                        .help("Create a merge commit even when the merge could fast-forward."),
+                )
+                .arg(
+                    Arg::new("message")
+                        .short('m')
+                        .value_name("MESSAGE")
+                        .help("Message of the merge commit."),
+                )
+                .arg(
+                    Arg::new("allow-unrelated-histories")
+                        .long("allow-unrelated-histories")
+                        .action(ArgAction::SetTrue)
+                        .help("Merge histories with no common ancestor, from an empty base."),
+                )
+                .arg(
+                    Arg::new("continue")
+                        .long("continue")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["rev", "abort"])
+                        .help("Commit the merge once the conflicts are resolved."),
+                )
+                .arg(
+                    Arg::new("abort")
+                        .long("abort")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("rev")
+                        .help("Abort the merge and go back to the state before it."),
+                ),
+        )
+        // git reflog
+        .subcommand(
+            Command::new("reflog")
+                .about("Manage reflog information")
+                .args_conflicts_with_subcommands(true)
+                .arg(
+                    Arg::new("ref")
+                        .value_name("REF")
+                        .help("Show the reflog of a ref. Default HEAD."),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show the entries of a reflog, newest first")
+                        .arg(Arg::new("ref").value_name("REF")),
+                )
+                .subcommand(
+                    Command::new("expire")
+                        .about("Prune old reflog entries")
+                        .arg(
+                            Arg::new("expire")
+                                .long("expire")
+                                .value_name("TIME")
+                                .help("Remove the entries older than this. Default 90.days.ago."),
+                        )
+                        .arg(
+                            Arg::new("expire-unreachable")
+                                .long("expire-unreachable")
+                                .value_name("TIME")
+                                .help("Remove the entries older than this that are not reachable from the ref. Default 30.days.ago."),
+                        )
+                        .arg(
+                            Arg::new("all")
+                                .long("all")
+                                .action(ArgAction::SetTrue)
+                                .help("Process the reflogs of all the refs."),
+                        )
+                        .arg(Arg::new("refs").value_name("REF").num_args(0..)),
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .about("Delete single entries from the reflog")
+                        .arg(
+                            Arg::new("entries")
+                                .value_name("REF@{N}")
+                                .num_args(1..)
+                                .required(true),
+                        ),
+                ),
+        )
+        // git tag
+        .subcommand(
+            Command::new("tag")
+                .about("Create, list or delete a tag")
+                .arg(Arg::new("name").value_name("TAGNAME").help("The name of the tag."))
+                .arg(
+                    Arg::new("object")
+                        .value_name("OBJECT")
+                        .help("The object the tag points to. Defaults to HEAD."),
+                )
+                .arg(
+                    Arg::new("annotate")
+                        .short('a')
+                        .action(ArgAction::SetTrue)
+                        .help("Make an annotated tag object. Requires a message."),
+                )
+                .arg(
+                    Arg::new("message")
+                        .short('m')
+                        .value_name("MESSAGE")
+                        .help("The tag message. Implies -a."),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .action(ArgAction::SetTrue)
+                        .help("Replace an existing tag with the given name."),
+                )
+                .arg(
+                    Arg::new("delete")
+                        .short('d')
+                        .action(ArgAction::SetTrue)
+                        .help("Delete the tag with the given name."),
+                )
+                .arg(
+                    Arg::new("list")
+                        .short('l')
+                        .action(ArgAction::SetTrue)
+                        .help("List all the tags. Default when no name is given."),
+                ),
+        )
+        // git pack-refs
+        .subcommand(
+            Command::new("pack-refs")
+                .about("Pack heads and tags for efficient repository access")
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Pack all the refs. By default only tags and the refs already packed."),
+                )
+                .arg(
+                    Arg::new("no-prune")
+                        .long("no-prune")
+                        .action(ArgAction::SetTrue)
+                        .help("Keep the loose refs after packing them."),
+                ),
+        )
+        // git rev-parse
+        .subcommand(
+            Command::new("rev-parse")
+                .about("Pick out and massage parameters")
+                .arg(
+                    Arg::new("rev")
+                        .value_name("REV")
+                        .num_args(1..)
+                        .help("Revisions to resolve. Example: HEAD~2, v1.0^{tree}, master@{u}"),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .action(ArgAction::SetTrue)
+                        .help("Check that exactly one revision is given and that it exists."),
+                )
+                .arg(
+                    Arg::new("short")
+                        .long("short")
+                        .value_name("N")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("7")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Print the shortest unique hash of at least N chars. Default 7."),
+                )
+                .arg(
+                    Arg::new("abbrev-ref")
+                        .long("abbrev-ref")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the short name of the ref instead of the hash."),
+                ),
+        )
+        // git index-pack
+        .subcommand(
+            Command::new("index-pack")
+                .about("Build pack index file for an existing packed archive")
+                .arg(
+                    Arg::new("pack")
+                        .required(true)
+                        .value_name("PACK_FILE")
+                        .help("The .pack file to index. The .idx is written next to it."),
+                ),
+        )
+        // git repack
+        .subcommand(
+            Command::new("repack")
+                .about("Pack all reachable objects into a single pack")
+                .arg(
+                    Arg::new("delete")
+                        .short('d')
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the packs and loose objects made redundant by the new pack."),
+                )
+                .arg(
+                    Arg::new("window")
+                        .long("window")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Number of objects tried as delta base for each object. Default 10."),
+                )
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Maximum length of a delta chain. Default 50."),
+                ),
+        )
+        // git gc
+        .subcommand(
+            Command::new("gc")
+                .about("Cleanup unnecessary files and optimize the local repository")
+                .arg(
+                    Arg::new("prune")
+                        .long("prune")
+                        .value_name("DATE")
+                        .help("Prune unreachable objects older than DATE (now, never, 2.weeks.ago). Default 2.weeks.ago."),
+                ),
+        )
         .subcommand(
             Command::new("clone")
-                .about("")
+                .about("Clone a repository into a new directory")
                 .arg(
                     Arg::new("url")
                         .required(true)
@@ -130,7 +829,7 @@ fn main() -> std::io::Result<()> {
                     Arg::new("directory")
                         .required(true)
                         .value_name("DIR")
-                        .help("The local directory you wish the clone into."),
+                        .help("The local repository you wish to fetch into."),
                 ),
         )
         .get_matches();
@@ -143,10 +842,27 @@ fn main() -> std::io::Result<()> {
         Some(("hash-object", args)) => RepoRust::hash_object(args)?,
         Some(("ls-tree", args)) => RepoRust::ls_tree(args)?,
         Some(("add", args)) => RepoRust::add(args)?,
+        Some(("rm", args)) => RepoRust::rm(args)?,
+        Some(("mv", args)) => RepoRust::mv(args)?,
         Some(("ls-files", args)) => RepoRust::ls_files(args)?,
         Some(("write-tree", args)) => RepoRust::write_tree(args)?,
         Some(("commit-tree", args)) => RepoRust::commit_tree(args)?,
+        Some(("status", args)) => RepoRust::status(args)?,
+        Some(("diff", args)) => RepoRust::diff(args)?,
         Some(("commit", args)) => RepoRust::commit(args)?,
+        Some(("log", args)) => RepoRust::log(args)?,
+        Some(("branch", args)) => RepoRust::branch(args)?,
+        Some(("switch", args)) => RepoRust::switch(args)?,
+        Some(("checkout", args)) => RepoRust::checkout(args)?,
+        Some(("reset", args)) => RepoRust::reset(args)?,
+        Some(("merge", args)) => RepoRust::merge(args)?,
+        Some(("reflog", args)) => RepoRust::reflog(args)?,
+        Some(("tag", args)) => RepoRust::tag(args)?,
+        Some(("pack-refs", args)) => RepoRust::pack_refs(args)?,
+        Some(("rev-parse", args)) => RepoRust::rev_parse(args)?,
+        Some(("index-pack", args)) => RepoRust::index_pack(args)?,
+        Some(("repack", args)) => RepoRust::repack(args)?,
+        Some(("gc", args)) => RepoRust::gc(args)?,
         Some(("fetch", args)) => RepoRust::fetch(args)?,
         Some(("clone", args)) => RepoRust::clone(args)?,
         Some((_, _)) | None => {}
@@ -0,0 +1,389 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    path::PathBuf,
+};
+
+use crate::{
+    diff::{self, EditKind},
+    git_rust::{BASE_DIR, RepoRust},
+    index::{Index, IndexEntry},
+    objects::{
+        self, ObjectType,
+        blob::Blob,
+        tree::{MODE_GITLINK, MODE_SYMLINK, Tree},
+    },
+    refs, revision, status,
+    worktree::{self, FileState},
+};
+
+#[cfg(test)]
+mod test;
+
+const MARKER_SIZE: usize = 7;
+
+// Best common ancestor of two commits: reachable from both, and not an ancestor of
+// another common one. With several of them (criss-cross merges), the first found
+pub fn merge_base(a: &str, b: &str) -> std::io::Result<Option<String>> {
+    let mut reachable = HashSet::new();
+    let mut stack = vec![a.to_string()];
+    while let Some(commit) = stack.pop() {
+        if reachable.insert(commit.clone()) {
+            stack.extend(revision::parents(&commit)?);
+        }
+    }
+    // Walked from b, stopping at the commits reachable from a
+    let mut common = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([b.to_string()]);
+    while let Some(commit) = queue.pop_front() {
+        if !seen.insert(commit.clone()) {
+            continue;
+        }
+        if reachable.contains(&commit) {
+            common.push(commit);
+            continue;
+        }
+        queue.extend(revision::parents(&commit)?);
+    }
+    for candidate in &common {
+        let mut redundant = false;
+        for other in &common {
+            if other != candidate && revision::is_ancestor(candidate, other)? {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            return Ok(Some(candidate.clone()));
+        }
+    }
+    Ok(None)
+}
+
+pub struct MergedContent {
+    pub content: Vec<u8>,
+    pub conflicts: usize,
+}
+
+// Three-way merge of the lines of a file (diff3). A chunk changed on one side only
+// is taken from that side. Chunks changed differently on both sides are conflicts,
+// written between markers with the label of each side
+pub fn merge_lines(base: &[u8], ours: &[u8], theirs: &[u8], labels: (&str, &str)) -> MergedContent {
+    let (base, ours, theirs) = (diff::lines(base), diff::lines(ours), diff::lines(theirs));
+    let ours_match = matches(&base, &ours);
+    let theirs_match = matches(&base, &theirs);
+    let mut content = Vec::new();
+    let mut conflicts = 0;
+    let (mut o, mut a, mut b) = (0, 0, 0);
+    loop {
+        // Lines kept by both sides
+        while o < base.len() && ours_match[o] == Some(a) && theirs_match[o] == Some(b) {
+            content.extend_from_slice(base[o]);
+            (o, a, b) = (o + 1, a + 1, b + 1);
+        }
+        if o == base.len() && a == ours.len() && b == theirs.len() {
+            break;
+        }
+        // The chunk ends at the next line of base kept by both sides
+        let (end_o, end_a, end_b) = (o..base.len())
+            .find_map(|i| Some((i, ours_match[i]?, theirs_match[i]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (base_chunk, ours_chunk, theirs_chunk) =
+            (&base[o..end_o], &ours[a..end_a], &theirs[b..end_b]);
+        if ours_chunk == base_chunk {
+            content.extend(theirs_chunk.concat());
+        } else if theirs_chunk == base_chunk || ours_chunk == theirs_chunk {
+            content.extend(ours_chunk.concat());
+        } else {
+            conflicts += 1;
+            content.extend(format!("{} {}\n", "<".repeat(MARKER_SIZE), labels.0).as_bytes());
+            push_lines(&mut content, ours_chunk);
+            content.extend(format!("{}\n", "=".repeat(MARKER_SIZE)).as_bytes());
+            push_lines(&mut content, theirs_chunk);
+            content.extend(format!("{} {}\n", ">".repeat(MARKER_SIZE), labels.1).as_bytes());
+        }
+        (o, a, b) = (end_o, end_a, end_b);
+    }
+    MergedContent { content, conflicts }
+}
+
+// For each line of base, the line of other it is kept as
+fn matches(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for edit in diff::myers(base, other) {
+        if edit.kind == EditKind::Equal {
+            matches[edit.old] = Some(edit.new);
+        }
+    }
+    matches
+}
+
+// Lines inside conflict markers. A last line without newline gets one
+fn push_lines(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    content.extend(lines.concat());
+    if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        content.push(b'\n');
+    }
+}
+
+// Result of merging trees
+#[derive(Default)]
+pub struct TreeMerge {
+    // Files of the merged tree. Conflicted paths have the file left in the working tree
+    pub files: BTreeMap<String, FileState>,
+    // Base, ours and theirs of each conflicted path
+    pub conflicts: BTreeMap<String, [Option<FileState>; 3]>,
+    // Auto-merging and CONFLICT lines, as git prints them
+    pub messages: Vec<String>,
+}
+
+// Three-way merge of ours and theirs. A path changed on one side only takes that
+// side, a file changed on both is merged line by line. Renames are not detected
+pub fn merge_trees(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: (&str, &str),
+) -> std::io::Result<TreeMerge> {
+    let base = worktree::tree_files(base)?;
+    let ours = worktree::tree_files(Some(ours))?;
+    let theirs = worktree::tree_files(Some(theirs))?;
+    let paths: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut merge = TreeMerge::default();
+    for path in paths {
+        let (b, o, t) = (
+            base.get(path).copied(),
+            ours.get(path).copied(),
+            theirs.get(path).copied(),
+        );
+        let kept = if o == t || b == t {
+            o
+        } else if b == o {
+            t
+        } else {
+            // Changed on both sides
+            match (o, t) {
+                (Some(o), Some(t)) => {
+                    merge.messages.push(format!("Auto-merging {path}"));
+                    let (state, conflict) = merge_file(b, o, t, labels)?;
+                    if conflict {
+                        let kind = if b.is_some() { "content" } else { "add/add" };
+                        merge
+                            .messages
+                            .push(format!("CONFLICT ({kind}): Merge conflict in {path}"));
+                        merge.conflicts.insert(path.clone(), [b, Some(o), Some(t)]);
+                    }
+                    Some(state)
+                }
+                (o, t) => {
+                    let (deleted, modified) = match o {
+                        Some(_) => (labels.1, labels.0),
+                        None => (labels.0, labels.1),
+                    };
+                    merge.messages.push(format!(
+                        "CONFLICT (modify/delete): {path} deleted in {deleted} and modified in {modified}.  Version {modified} of {path} left in tree."
+                    ));
+                    merge.conflicts.insert(path.clone(), [b, o, t]);
+                    o.or(t)
+                }
+            }
+        };
+        if let Some(state) = kept {
+            merge.files.insert(path.clone(), state);
+        }
+    }
+    Ok(merge)
+}
+
+// Merges a file changed on both sides. Returns the merged file, and true if it has
+// conflicts. Symlinks, submodules and binary files are not merged: ours is kept
+fn merge_file(
+    base: Option<FileState>,
+    ours: FileState,
+    theirs: FileState,
+    labels: (&str, &str),
+) -> std::io::Result<(FileState, bool)> {
+    // A mode changed on one side only is kept
+    let mode = match base {
+        Some((mode, _)) if mode == ours.0 => theirs.0,
+        _ => ours.0,
+    };
+    if ours.1 == theirs.1 {
+        return Ok(((mode, ours.1), false));
+    }
+    let mergeable = |mode: u32| mode != MODE_SYMLINK && mode != MODE_GITLINK;
+    if !mergeable(ours.0) || !mergeable(theirs.0) || base.is_some_and(|base| !mergeable(base.0)) {
+        return Ok((ours, true));
+    }
+    let base_content = match base {
+        Some((_, hash)) => blob_content(&hash)?,
+        None => Vec::new(),
+    };
+    let (ours_content, theirs_content) = (blob_content(&ours.1)?, blob_content(&theirs.1)?);
+    if [&base_content, &ours_content, &theirs_content]
+        .iter()
+        .any(|content| diff::is_binary(content))
+    {
+        return Ok((ours, true));
+    }
+    let merged = merge_lines(&base_content, &ours_content, &theirs_content, labels);
+    let hash = objects::write_object(ObjectType::Blob, &merged.content)?;
+    let hash: [u8; 20] = hex::decode(hash)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| std::io::Error::other("Invalid blob hash"))?;
+    Ok(((mode, hash), merged.conflicts > 0))
+}
+
+fn blob_content(hash: &[u8; 20]) -> std::io::Result<Vec<u8>> {
+    Blob::decode_object(&objects::read_object(&hex::encode(hash))?)
+}
+
+// Writes the trees holding files and returns the hash of the root one
+pub fn write_tree(files: &BTreeMap<String, FileState>) -> std::io::Result<String> {
+    if files.is_empty() {
+        return objects::write_object(ObjectType::Tree, &[]);
+    }
+    let entries = files
+        .iter()
+        .map(|(path, &state)| (path.clone(), worktree::unstat_entry(path, state)))
+        .collect();
+    let (trees, root) = Tree::build_trees(&Tree::group_entries_for_tree_build(entries));
+    Tree::write_object_to_file(trees)?;
+    Ok(hex::encode(root))
+}
+
+// Moves the index and the working tree from ours_tree to the result of a merge
+// Conflicted files are written with their markers and recorded in stages 1 to 3
+// Staged changes, and local changes to the paths the merge touches, abort it first
+// Returns the hash of the merged tree
+pub fn apply(ours_tree: &str, merge: &TreeMerge) -> std::io::Result<String> {
+    let ours = worktree::tree_files(Some(ours_tree))?;
+    let status = status::status()?;
+    let touched = |path: &String| {
+        merge.files.get(path) != ours.get(path) || merge.conflicts.contains_key(path)
+    };
+    let changed: BTreeSet<&String> = status
+        .staged
+        .keys()
+        .chain(status.unstaged.keys().filter(|path| touched(path)))
+        .collect();
+    if !changed.is_empty() {
+        let paths: String = changed.iter().map(|path| format!("\t{path}\n")).collect();
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Your local changes to the following files would be overwritten by merge:\n{paths}Please commit your changes before you merge.\nAborting"
+            ),
+        ));
+    }
+
+    let merged_tree = write_tree(&merge.files)?;
+    worktree::switch_tree(Some(ours_tree), &merged_tree, false)?;
+    if merge.conflicts.is_empty() {
+        return Ok(merged_tree);
+    }
+    let mut index = Index::read_index()?;
+    for (path, sides) in &merge.conflicts {
+        index.entries.remove(path);
+        let stages: Vec<IndexEntry> = (1..)
+            .zip(sides)
+            .filter_map(|(stage, side)| {
+                let mut entry = worktree::unstat_entry(path, (*side)?);
+                entry.flags |= stage << 12;
+                Some(entry)
+            })
+            .collect();
+        index.conflicts.insert(path.clone(), stages);
+    }
+    Index::from_entries(index.entries)
+        .with_conflicts(index.conflicts)
+        .write_index_to_file()?;
+    Ok(merged_tree)
+}
+
+fn state_path(name: &str) -> PathBuf {
+    RepoRust::get_root().absolute_path.join(BASE_DIR).join(name)
+}
+
+// The commit being merged, while a merge waits to be committed
+pub fn merge_head() -> std::io::Result<Option<String>> {
+    match std::fs::read_to_string(state_path("MERGE_HEAD")) {
+        Ok(hash) => Ok(Some(hash.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Message prepared for the merge commit, without its comment lines
+pub fn merge_message() -> std::io::Result<String> {
+    let message = match std::fs::read_to_string(state_path("MERGE_MSG")) {
+        Ok(message) => message,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(e),
+    };
+    let lines: Vec<&str> = message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    Ok(lines.join("\n").trim_end().to_string())
+}
+
+// Merge branch 'name', or the kind of ref that was merged
+pub fn default_message(rev: &str) -> std::io::Result<String> {
+    let full_name = revision::resolve_ref_name(rev)?.unwrap_or_default();
+    let kinds = [
+        ("refs/heads/", "branch"),
+        ("refs/remotes/", "remote-tracking branch"),
+        ("refs/tags/", "tag"),
+    ];
+    for (prefix, kind) in kinds {
+        if let Some(name) = full_name.strip_prefix(prefix) {
+            return Ok(format!("Merge {kind} '{name}'"));
+        }
+    }
+    Ok(format!("Merge commit '{rev}'"))
+}
+
+// MERGE_HEAD and MERGE_MSG. The conflicted paths are listed as comments
+pub fn write_state(theirs: &str, message: &str, conflicts: &[&String]) -> std::io::Result<()> {
+    let mut message = format!("{message}\n");
+    if !conflicts.is_empty() {
+        message.push_str("\n# Conflicts:\n");
+        for path in conflicts {
+            message.push_str(&format!("#\t{path}\n"));
+        }
+    }
+    std::fs::write(state_path("MERGE_HEAD"), format!("{theirs}\n"))?;
+    std::fs::write(state_path("MERGE_MSG"), message)
+}
+
+pub fn clear_state() -> std::io::Result<()> {
+    for name in ["MERGE_HEAD", "MERGE_MSG"] {
+        match std::fs::remove_file(state_path(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// merge --abort: back to HEAD. Local changes are lost, same as reset --hard
+pub fn abort() -> std::io::Result<()> {
+    if merge_head()?.is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "There is no merge to abort (MERGE_HEAD missing).",
+        ));
+    }
+    let tree = match refs::read_ref("HEAD")? {
+        Some(head) => Some(revision::resolve_as(&head, ObjectType::Tree)?),
+        None => None,
+    };
+    worktree::reset_hard(tree.as_deref())?;
+    clear_state()
+}
@@ -0,0 +1,289 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    index::Index,
+    merge,
+    objects::{self, ObjectType, commit::Commit, tree::MODE_FILE},
+    refs,
+    status::{self, Conflict},
+    test_common::{run_test, run_test_matches},
+};
+
+fn run(args: Vec<&str>) -> std::io::Result<()> {
+    let matches = run_test_matches(args.clone());
+    match args[1] {
+        "add" => RepoRust::add(&matches),
+        "commit" => RepoRust::commit(&matches),
+        "branch" => RepoRust::branch(&matches),
+        "switch" => RepoRust::switch(&matches),
+        "reset" => RepoRust::reset(&matches),
+        "rm" => RepoRust::rm(&matches),
+        _ => RepoRust::merge(&matches),
+    }
+}
+
+fn commit_file(path: &Path, name: &str, content: &str, message: &str) -> String {
+    std::fs::write(path.join(name), content).unwrap();
+    run(vec!["", "add", name]).unwrap();
+    run(vec!["", "commit", "-m", message]).unwrap();
+    refs::read_ref("HEAD").unwrap().unwrap()
+}
+
+#[test]
+fn test_merge_lines() {
+    let base = b"1\n2\n3\n4\n5\n";
+    let merged = merge::merge_lines(
+        base,
+        b"1\ntwo\n3\n4\n5\n",
+        b"1\n2\n3\n4\nfive\n",
+        ("HEAD", "b"),
+    );
+    assert_eq!(merged.conflicts, 0);
+    assert_eq!(merged.content, b"1\ntwo\n3\n4\nfive\n");
+
+    // The same change on both sides is not a conflict
+    let merged = merge::merge_lines(base, b"1\n2\n3\n", b"1\n2\n3\n", ("HEAD", "b"));
+    assert_eq!(merged.conflicts, 0);
+    assert_eq!(merged.content, b"1\n2\n3\n");
+
+    let merged = merge::merge_lines(
+        base,
+        b"1\nours\n3\n4\n5\n",
+        b"1\ntheirs\n3\n4\n5",
+        ("HEAD", "b"),
+    );
+    assert_eq!(merged.conflicts, 1);
+    assert_eq!(
+        String::from_utf8(merged.content).unwrap(),
+        "1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> b\n3\n4\n5"
+    );
+
+    // Added on both sides without base
+    let merged = merge::merge_lines(b"", b"a\n", b"b", ("HEAD", "b"));
+    assert_eq!(
+        String::from_utf8(merged.content).unwrap(),
+        "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> b\n"
+    );
+}
+
+#[test]
+fn test_merge_base_and_fast_forward() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let base = commit_file(&path, "a.txt", "a\n", "base");
+        run(vec!["", "branch", "topic"]).unwrap();
+        run(vec!["", "switch", "topic"]).unwrap();
+        let topic = commit_file(&path, "b.txt", "b\n", "topic");
+        run(vec!["", "switch", "master"]).unwrap();
+        assert_eq!(
+            merge::merge_base(&base, &topic).unwrap(),
+            Some(base.clone())
+        );
+
+        run(vec!["", "merge", "--ff-only", "topic"]).unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(topic.clone()));
+        assert_eq!(std::fs::read_to_string(path.join("b.txt")).unwrap(), "b\n");
+        assert_eq!(refs::read_ref("ORIG_HEAD").unwrap(), Some(base.clone()));
+        // Nothing left to merge
+        run(vec!["", "merge", "topic"]).unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(topic.clone()));
+
+        // Diverged: no fast-forward
+        let master = commit_file(&path, "a.txt", "a2\n", "master");
+        run(vec!["", "switch", "topic"]).unwrap();
+        let diverged = commit_file(&path, "b.txt", "b2\n", "topic 2");
+        assert_eq!(merge::merge_base(&master, &diverged).unwrap(), Some(topic));
+        assert!(run(vec!["", "merge", "--ff-only", "master"]).is_err());
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(diverged));
+    })
+}
+
+#[test]
+fn test_merge_commit_and_no_ff() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        commit_file(&path, "a.txt", "1\n2\n3\n4\n5\n", "base");
+        run(vec!["", "branch", "topic"]).unwrap();
+        let ours = commit_file(&path, "a.txt", "one\n2\n3\n4\n5\n", "ours");
+        run(vec!["", "switch", "topic"]).unwrap();
+        commit_file(&path, "a.txt", "1\n2\n3\n4\nfive\n", "theirs");
+        let theirs = commit_file(&path, "b.txt", "b\n", "new file");
+        run(vec!["", "switch", "master"]).unwrap();
+
+        run(vec!["", "merge", "topic"]).unwrap();
+        let head = refs::read_ref("HEAD").unwrap().unwrap();
+        let commit = Commit::decode(&head).unwrap();
+        assert_eq!(commit.parents_hash, vec![ours, theirs.clone()]);
+        assert_eq!(commit.text(&commit.message).trim(), "Merge branch 'topic'");
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "one\n2\n3\n4\nfive\n"
+        );
+        assert_eq!(std::fs::read_to_string(path.join("b.txt")).unwrap(), "b\n");
+        assert!(status::status().unwrap().is_clean());
+        assert!(!path.join(BASE_DIR).join("MERGE_HEAD").exists());
+
+        // HEAD is an ancestor of topic again, but a merge commit is made
+        run(vec!["", "switch", "topic"]).unwrap();
+        let topic = commit_file(&path, "c.txt", "c\n", "topic 2");
+        run(vec!["", "switch", "master"]).unwrap();
+        run(vec!["", "merge", "--no-ff", "-m", "no ff", "topic"]).unwrap();
+        let commit = Commit::decode(&refs::read_ref("HEAD").unwrap().unwrap()).unwrap();
+        assert_eq!(commit.parents_hash, vec![head, topic]);
+        assert_eq!(commit.text(&commit.message).trim(), "no ff");
+    })
+}
+
+#[test]
+fn test_merge_conflicts_continue_and_abort() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        commit_file(&path, "a.txt", "1\n2\n3\n", "base");
+        commit_file(&path, "b.txt", "b\n", "base b");
+        run(vec!["", "branch", "topic"]).unwrap();
+        commit_file(&path, "a.txt", "1\nours\n3\n", "ours");
+        let ours = commit_file(&path, "b.txt", "b ours\n", "ours b");
+        run(vec!["", "switch", "topic"]).unwrap();
+        commit_file(&path, "a.txt", "1\ntheirs\n3\n", "theirs");
+        std::fs::remove_file(path.join("b.txt")).unwrap();
+        run(vec!["", "add", "-A"]).unwrap();
+        run(vec!["", "commit", "-m", "delete b"]).unwrap();
+        let theirs = refs::read_ref("HEAD").unwrap().unwrap();
+        run(vec!["", "switch", "master"]).unwrap();
+
+        assert!(run(vec!["", "merge", "topic"]).is_err());
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(ours.clone()));
+        assert_eq!(merge::merge_head().unwrap(), Some(theirs.clone()));
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n3\n"
+        );
+        // Modified here, deleted there: our version stays
+        assert_eq!(
+            std::fs::read_to_string(path.join("b.txt")).unwrap(),
+            "b ours\n"
+        );
+        let index = Index::read_index().unwrap();
+        let stages: Vec<u16> = index.conflicts["a.txt"].iter().map(|e| e.stage()).collect();
+        assert_eq!(stages, vec![1, 2, 3]);
+        assert!(!index.entries.contains_key("a.txt"));
+        let status = status::status().unwrap();
+        assert_eq!(status.unmerged["a.txt"], Conflict::BothModified);
+        assert_eq!(status.unmerged["b.txt"], Conflict::DeletedByThem);
+        assert!(status.untracked.is_empty());
+        assert!(status.short().contains("UU a.txt\nUD b.txt\n"));
+        // Not committed while conflicts are left
+        assert!(run(vec!["", "merge", "--continue"]).is_err());
+
+        // Aborted: back to HEAD
+        run(vec!["", "merge", "--abort"]).unwrap();
+        assert_eq!(merge::merge_head().unwrap(), None);
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "1\nours\n3\n"
+        );
+        assert!(Index::read_index().unwrap().conflicts.is_empty());
+        assert!(status::status().unwrap().is_clean());
+
+        // Resolved and continued
+        assert!(run(vec!["", "merge", "topic"]).is_err());
+        std::fs::write(path.join("a.txt"), "1\nboth\n3\n").unwrap();
+        run(vec!["", "add", "a.txt"]).unwrap();
+        // Deleted as in topic
+        run(vec!["", "rm", "b.txt"]).unwrap();
+        assert!(!path.join("b.txt").exists());
+        assert!(Index::read_index().unwrap().conflicts.is_empty());
+        assert!(status::status().unwrap().merging);
+        run(vec!["", "merge", "--continue"]).unwrap();
+        let commit = Commit::decode(&refs::read_ref("HEAD").unwrap().unwrap()).unwrap();
+        assert_eq!(commit.parents_hash, vec![ours, theirs]);
+        assert_eq!(commit.text(&commit.message).trim(), "Merge branch 'topic'");
+        assert_eq!(merge::merge_head().unwrap(), None);
+        assert!(status::status().unwrap().is_clean());
+    })
+}
+
+#[test]
+fn test_reset_ends_merge() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        commit_file(&path, "a.txt", "base\n", "base");
+        run(vec!["", "branch", "topic"]).unwrap();
+        commit_file(&path, "a.txt", "ours\n", "ours");
+        run(vec!["", "switch", "topic"]).unwrap();
+        commit_file(&path, "a.txt", "theirs\n", "theirs");
+        run(vec!["", "switch", "master"]).unwrap();
+
+        assert!(run(vec!["", "merge", "topic"]).is_err());
+        assert!(run(vec!["", "reset", "--soft"]).is_err());
+        run(vec!["", "reset", "--hard"]).unwrap();
+        assert_eq!(merge::merge_head().unwrap(), None);
+        assert!(!path.join(BASE_DIR).join("MERGE_MSG").exists());
+        assert!(!status::status().unwrap().merging);
+
+        // Mixed reset too
+        assert!(run(vec!["", "merge", "topic"]).is_err());
+        run(vec!["", "reset"]).unwrap();
+        assert_eq!(merge::merge_head().unwrap(), None);
+        assert!(Index::read_index().unwrap().conflicts.is_empty());
+
+        // The next commit is not a merge
+        let head = commit_file(&path, "a.txt", "after reset\n", "after reset");
+        let commit = Commit::decode(&head).unwrap();
+        assert_eq!(commit.parents_hash.len(), 1);
+        assert_eq!(commit.text(&commit.message).trim(), "after reset");
+    })
+}
+
+#[test]
+fn test_merge_unrelated_histories() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let head = commit_file(&path, "a.txt", "a\n", "first");
+        // A root commit that shares nothing with master
+        let blob = objects::write_object(ObjectType::Blob, b"other\n").unwrap();
+        let hash = hex::decode(blob).unwrap().try_into().unwrap();
+        let tree =
+            merge::write_tree(&[("other.txt".to_string(), (MODE_FILE, hash))].into()).unwrap();
+        let other = Commit::encode(&tree, vec![], "other root")
+            .unwrap()
+            .write_commit_to_file()
+            .unwrap();
+        refs::write_ref("refs/heads/other", &other).unwrap();
+        assert_eq!(merge::merge_base(&head, &other).unwrap(), None);
+
+        assert!(run(vec!["", "merge", "other"]).is_err());
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(head.clone()));
+        assert!(!path.join("other.txt").exists());
+
+        run(vec!["", "merge", "--allow-unrelated-histories", "other"]).unwrap();
+        let commit = Commit::decode(&refs::read_ref("HEAD").unwrap().unwrap()).unwrap();
+        assert_eq!(commit.parents_hash, vec![head, other]);
+        assert_eq!(
+            std::fs::read_to_string(path.join("other.txt")).unwrap(),
+            "other\n"
+        );
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a\n");
+    })
+}
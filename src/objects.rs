@@ -1,28 +1,29 @@
 #![allow(dead_code)]
 
-use std::{
-    fmt::Display,
-    io::{Read, Write},
-    path::PathBuf,
-};
+use std::{fmt::Display, io::Write};
+
+use hex::ToHex;
+use sha1::{Digest, Sha1};
 
 use crate::{
-    git_rust::RepoRust,
-    objects::{blob::Blob, commit::Commit, tree::Tree},
+    objects::{blob::Blob, commit::Commit, tag::Tag, tree::Tree},
+    odb,
 };
 
 pub mod blob;
 pub mod commit;
+pub mod tag;
 pub mod tree;
 
 #[cfg(test)]
 mod test;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 #[derive(Debug)]
@@ -52,12 +53,8 @@ impl Header {
             .parse()
             .map_err(|_| std::io::Error::other("Missing size field"))?;
 
-        let object_type = match vec[0] {
-            "blob" => ObjectType::Blob,
-            "tree" => ObjectType::Tree,
-            "commit" => ObjectType::Commit,
-            _ => return Err(std::io::Error::other("Invalid object type")),
-        };
+        let object_type = ObjectType::from_name(vec[0])
+            .ok_or_else(|| std::io::Error::other("Invalid object type"))?;
         Ok(Self {
             object: object_type,
             size,
@@ -74,11 +71,7 @@ impl Header {
 }
 
 pub fn cat_file(hash: &str, pretty: bool) -> std::io::Result<Vec<u8>> {
-    let root_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
-    let (folder_name, file_name) = hash.split_at(2);
-    let file_path = root_path.join(folder_name).join(file_name);
-    let file = std::fs::read(file_path)?;
-    let de_compressed_file = de_compress(&file)?;
+    let de_compressed_file = read_object(hash)?;
 
     let header = Header::from_binary(&de_compressed_file)?;
     let mut content: Vec<u8> = Vec::new();
@@ -93,7 +86,7 @@ pub fn cat_file(hash: &str, pretty: bool) -> std::io::Result<Vec<u8>> {
                 let tree = Tree::decode_object(hash)?;
                 println!("{tree}");
             } else {
-                content = Tree::de_compress(&file)?;
+                content = de_compressed_file;
                 std::io::stdout().write_all(&content)?;
             }
         }
@@ -101,26 +94,50 @@ pub fn cat_file(hash: &str, pretty: bool) -> std::io::Result<Vec<u8>> {
             let commit = Commit::decode(hash)?;
             print!("{commit}");
         }
+        ObjectType::Tag => {
+            let tag = Tag::decode(hash)?;
+            print!("{tag}");
+        }
     }
     Ok(content)
 }
 
-pub fn de_compress(content: &[u8]) -> std::io::Result<Vec<u8>> {
-    let mut decompressed = Vec::new();
-    let cursor = std::io::Cursor::new(content);
-    let mut decoder = flate2::bufread::ZlibDecoder::new(cursor);
-    decoder.read_to_end(&mut decompressed)?;
-    Ok(decompressed)
+// Reads an object through the object database and returns it with its header
+// The decode functions of each type parse this
+pub fn read_object(hash: &str) -> std::io::Result<Vec<u8>> {
+    let (object_type, content) = odb::database().read(hash)?;
+    let mut object = format!("{object_type} {}\0", content.len()).into_bytes();
+    object.extend_from_slice(&content);
+    Ok(object)
+}
+
+// Hash of an object, the same as git hash-object -t <kind>
+pub fn hash_object(kind: &str, content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{kind} {}\0", content.len()).as_bytes());
+    hasher.update(content);
+    hasher.finalize().encode_hex::<String>()
 }
 
-pub fn get_object_path(hash: &str) -> Option<PathBuf> {
-    let root_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
-    let (folder_name, file_name) = hash.split_at(2);
-    let file_path = root_path.join(folder_name).join(file_name);
-    if file_path.exists() {
-        Some(file_path)
-    } else {
-        None
+// Writes any object through the object database. content has no header
+// Returns the hash of the object
+pub fn write_object(object_type: ObjectType, content: &[u8]) -> std::io::Result<String> {
+    odb::database().write(object_type, content)
+}
+
+pub fn object_exists(hash: &str) -> bool {
+    odb::database().exists(hash)
+}
+
+impl ObjectType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blob" => Some(Self::Blob),
+            "tree" => Some(Self::Tree),
+            "commit" => Some(Self::Commit),
+            "tag" => Some(Self::Tag),
+            _ => None,
+        }
     }
 }
 
@@ -130,6 +147,7 @@ impl Display for ObjectType {
             Self::Blob => f.write_str("blob"),
             Self::Tree => f.write_str("tree"),
             Self::Commit => f.write_str("commit"),
+            Self::Tag => f.write_str("tag"),
         }
     }
 }
@@ -1,14 +1,13 @@
-use std::{fmt::Display, io::Write, path::PathBuf};
+use std::{fmt::Display, path::PathBuf};
 
 use clap::ArgMatches;
-use flate2::{Compress, Compression, write::ZlibEncoder};
 use hex::ToHex;
 use sha1::{Digest, Sha1};
 use tracing::instrument;
 
 use crate::{
     git_rust::RepoRust,
-    objects::{Header, ObjectType},
+    objects::{self, Header, ObjectType},
 };
 
 #[derive(Debug)]
@@ -65,19 +64,7 @@ impl Blob {
     }
 
     pub fn write_object_to_file(&self, file: &[u8]) -> std::io::Result<()> {
-        let objects_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
-        let folder_path = objects_path.join(&self.folder);
-        let file_path = folder_path.join(&self.file);
-        if !folder_path.exists() {
-            std::fs::create_dir(folder_path)?;
-        }
-        let new_blob = std::fs::File::create(file_path)?;
-        let mut enc =
-            ZlibEncoder::new_with_compress(new_blob, Compress::new(Compression::best(), true));
-        let header = format!("blob {}\0", file.len());
-        let full_blob = [header.as_bytes(), file].concat();
-
-        enc.write_all(&full_blob)?;
+        objects::write_object(ObjectType::Blob, file)?;
         Ok(())
     }
 
@@ -121,11 +108,7 @@ impl Blob {
     }
 
     pub fn blob_exists(hash: [u8; 20]) -> bool {
-        let root = &RepoRust::get_root().absolute_path;
-        let obj_path = RepoRust::get_object_folder(root);
-        let hex_hash = hex::encode(hash);
-        let (folder_name, file_name) = hex_hash.split_at(2);
-        obj_path.join(folder_name).join(file_name).exists()
+        objects::object_exists(&hex::encode(hash))
     }
 }
 
@@ -1,17 +1,12 @@
-use std::{
-    fs::OpenOptions,
-    io::Write,
-    path::{Path, PathBuf},
-};
-
 use chrono::Local;
-use flate2::{Compress, Compression, write::ZlibEncoder};
 use hex::ToHex;
 use sha1::{Digest, Sha1};
 
 use crate::{
-    git_rust::{BASE_DIR, RepoRust},
-    objects::{self, Header},
+    config::Config,
+    diff::{self, FileChange, FileStat},
+    objects::{self, Header, ObjectType},
+    status::Change,
 };
 
 pub struct Commit {
@@ -20,7 +15,11 @@ pub struct Commit {
     pub parents_hash: Vec<String>,
     pub author: Autors,
     pub committer: Autors,
-    pub message: String,
+    // Headers after the committer (encoding, mergetag, gpgsig...), in order
+    // Values of more than one line are kept with their '\n' and without the leading space
+    // The message and the headers are kept as bytes, they need not be UTF-8 (see Commit::text)
+    pub extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    pub message: Vec<u8>,
 }
 
 #[derive(Debug)]
@@ -28,35 +27,47 @@ pub struct CommitSummary {
     pub branch: String,
     pub commit_hash: String,
     pub message: String,
-    // pub file_changes: Vec<FileChange>,
+    // First commit of the history
+    pub root: bool,
+    // Parent tree -> new tree, with renames, and the lines changed in each file
+    pub file_changes: Vec<FileChange>,
+    pub stats: Vec<FileStat>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Autors {
-    name: String,
-    email: String,
-    timestamp: i64,
-    timezone: String,
+    // In the encoding of the commit, UTF-8 unless it has an encoding header
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    pub timestamp: i64,
+    pub timezone: String,
 }
 
 impl Autors {
+    // <keyword> <name> <<email>> <timestamp> <timezone>. The name can have spaces
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        let string = str::from_utf8(bytes).ok()?;
-        let mut components = string.split(' ');
-        let authors = components.next()?;
-        if authors != "author" && authors != "committer" {
+        let space = bytes.iter().position(|b| *b == b' ')?;
+        let (keyword, rest) = (&bytes[..space], &bytes[space + 1..]);
+        if keyword != b"author" && keyword != b"committer" && keyword != b"tagger" {
             return None;
         }
 
-        let name = components.next()?.to_string();
-
-        let email_field = components.next()?;
-        let email_start = email_field.find('<')? + 1;
-        let email_end = email_field.find('>')?;
-        let email = email_field[email_start..email_end].to_string();
-
-        let timestamp: i64 = components.next()?.parse().ok()?;
-        let timezone = components.next()?.to_string();
+        let email_start = rest.iter().position(|b| *b == b'<')?;
+        let email_end = email_start + rest[email_start..].iter().position(|b| *b == b'>')?;
+        // Only the separator is dropped, so odd spacing in the name is written back as it was
+        let name = &rest[..email_start];
+        let name = name.strip_suffix(b" ").unwrap_or(name).to_vec();
+        let email = rest[email_start + 1..email_end].to_vec();
+
+        // " <timestamp> <timezone>". Anything after the timestamp is kept in the
+        // timezone, and other spacing is refused, so that to_bytes gives back the same line
+        let date = str::from_utf8(rest[email_end + 1..].strip_prefix(b" ")?).ok()?;
+        let (text, timezone) = date.split_once(' ')?;
+        let timestamp: i64 = text.parse().ok()?;
+        if timestamp.to_string() != text {
+            return None;
+        }
+        let timezone = timezone.to_string();
 
         Some(Self {
             name,
@@ -66,12 +77,53 @@ impl Autors {
         })
     }
 
+    // The user, with the current time. See identity for where the user comes from
+    pub fn now() -> std::io::Result<Self> {
+        let now = Local::now();
+        let timestamp = now.timestamp();
+        let offset = now.offset().utc_minus_local();
+        let hours = offset / 3600;
+        let minutes = (offset.abs() % 3600) / 60;
+        let sign = if offset >= 0 { '-' } else { '+' };
+        let timezone = format!("{}{:<02}{:02}", sign, hours.abs(), minutes);
+
+        let (name, email) = Self::identity()?;
+        Ok(Self {
+            name: name.into_bytes(),
+            email: email.into_bytes(),
+            timestamp,
+            timezone,
+        })
+    }
+
+    // user.name and user.email from the config of the repo, then from the
+    // GIT_COMMITTER_* and GIT_AUTHOR_* variables, then from the git config of the user
+    // Without any of them, the login name and <login>@localhost
+    fn identity() -> std::io::Result<(String, String)> {
+        let config = Config::read()?;
+        let git_config = git2::Config::open_default().ok();
+        let lookup = |key: &str, variables: [&str; 2]| {
+            config
+                .get(key)
+                .map(str::to_string)
+                .or_else(|| variables.iter().find_map(|v| std::env::var(v).ok()))
+                .or_else(|| git_config.as_ref()?.get_string(key).ok())
+                .filter(|value| !value.is_empty())
+        };
+        let name = lookup("user.name", ["GIT_COMMITTER_NAME", "GIT_AUTHOR_NAME"])
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let email = lookup("user.email", ["GIT_COMMITTER_EMAIL", "GIT_AUTHOR_EMAIL"])
+            .unwrap_or_else(|| format!("{name}@localhost"));
+        Ok((name, email))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut contents: Vec<u8> = Vec::new();
-        contents.extend_from_slice(self.name.as_bytes());
+        contents.extend_from_slice(&self.name);
         contents.push(b' ');
         contents.push(b'<');
-        contents.extend_from_slice(self.email.as_bytes());
+        contents.extend_from_slice(&self.email);
         contents.push(b'>');
         contents.push(b' ');
         contents.extend_from_slice(self.timestamp.to_string().as_bytes());
@@ -98,67 +150,41 @@ impl Commit {
                 contents.push(b'\n');
             }
         }
+        contents.extend_from_slice(b"author ");
         contents.extend_from_slice(&self.author.to_bytes());
         contents.push(b'\n');
+        contents.extend_from_slice(b"committer ");
         contents.extend_from_slice(&self.committer.to_bytes());
         contents.push(b'\n');
+        for (name, value) in &self.extra_headers {
+            contents.extend_from_slice(name);
+            contents.push(b' ');
+            for byte in value {
+                contents.push(*byte);
+                if *byte == b'\n' {
+                    contents.push(b' ');
+                }
+            }
+            contents.push(b'\n');
+        }
         contents.push(b'\n');
-        contents.extend_from_slice(self.message.as_bytes());
+        contents.extend_from_slice(&self.message);
         contents
     }
 
-    pub fn read_head() -> std::io::Result<String> {
-        let root = &RepoRust::get_root().absolute_path;
-        let head_path = root.join(BASE_DIR).join("HEAD");
-        let head_bytes = std::fs::read(head_path)?;
-        let head_str = str::from_utf8(&head_bytes).unwrap();
-        Ok(head_str.into())
-    }
-
-    // Returns Ok() where some is the relative path to the branch file. Which may or may not exist yet.
-    // Returns Err for a detached head
-    pub fn get_branch_from_head(head_str: &str) -> std::io::Result<PathBuf> {
-        let root: &PathBuf = &RepoRust::get_root().absolute_path;
-        if !head_str.starts_with("ref: ") {
-            return Err(std::io::Error::other("Detached head. Not implemented"));
-        }
-        let branch = &head_str["refs: ".len() - 1..];
-        let branch = branch.strip_suffix('\n').unwrap_or(branch);
-        let branch_path = root.join(BASE_DIR).join(Path::new(branch));
-        Ok(branch_path)
-    }
-
-    // Compares the new tree hash with the last tree
-    // Does not handle merged commits
-    // Returns Ok(branch) if the branch file exists
-    pub fn get_branch_commit() -> std::io::Result<String> {
-        let head_str = Self::read_head()?;
-        // Read the branch file
-        let branch_path = Self::get_branch_from_head(&head_str)?;
-        if !branch_path.exists() {
-            std::fs::File::create(&branch_path)?;
-            return Err(std::io::Error::other(
-                "Initial commit — no parent commit yet",
-            ));
+    // The message, or a name, as text. Latin-1 commits say so in an encoding header,
+    // anything else is read as UTF-8
+    pub fn text(&self, bytes: &[u8]) -> String {
+        let latin1 = self.extra_headers.iter().any(|(name, value)| {
+            name == b"encoding"
+                && ["ISO-8859-1", "LATIN1", "ISO8859-1"]
+                    .iter()
+                    .any(|encoding| value.eq_ignore_ascii_case(encoding.as_bytes()))
+        });
+        match latin1 {
+            true => bytes.iter().map(|b| *b as char).collect(),
+            false => String::from_utf8_lossy(bytes).to_string(),
         }
-        let branch_hash = std::fs::read(branch_path)?;
-        let branch_hash_str = str::from_utf8(&branch_hash).unwrap().to_string();
-        Ok(branch_hash_str)
-    }
-
-    // Returns the name of the branch updated
-    pub fn update_branch_hash(hash: &str) -> std::io::Result<String> {
-        let head_str = Self::read_head()?;
-        let branch_path = Self::get_branch_from_head(&head_str)
-            .ok()
-            .ok_or_else(|| std::io::Error::other("Detached head. Not implemented"))?;
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&branch_path)?;
-        file.write_all(hash.as_bytes())?;
-        let branch_name = branch_path.file_name().unwrap().to_string_lossy();
-        Ok(branch_name.into())
     }
 
     pub fn get_tree_from_commit(commit: &str) -> std::io::Result<String> {
@@ -174,15 +200,21 @@ impl Commit {
                 "No tree provided",
             ));
         }
-        objects::get_object_path(tree_hash).ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Tree object not found")
-        })?;
+        if !objects::object_exists(tree_hash) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Tree object not found",
+            ));
+        }
         // Check if the parents are valid
         if !commit.is_empty() {
             for hash in &commit {
-                objects::get_object_path(hash).ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "Commit object not found")
-                })?;
+                if !objects::object_exists(hash) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "Commit object not found",
+                    ));
+                }
             }
         }
         // Check parents have no duplicates
@@ -195,35 +227,8 @@ impl Commit {
             }
         }
 
-        let now = Local::now();
-        let timestamp = now.timestamp();
-        let offset = now.offset().utc_minus_local();
-        let hours = offset / 3600;
-        let minutes = (offset.abs() % 3600) / 60;
-        let sign = if offset >= 0 { '-' } else { '+' };
-        let timezone = format!("{}{:<02}{:02}", sign, hours.abs(), minutes);
-
-        let git2_repo = git2::Repository::discover(".").expect("Could not find .git repo");
-        let config = git2_repo.config().expect("Could not fetch git config");
-        let name = config
-            .get_string("user.name")
-            .expect("Could not fetch git name");
-        let email = config
-            .get_string("user.email")
-            .expect("Could not fetch git email");
-
-        let author = Autors {
-            name: name.clone(),
-            email: email.clone(),
-            timestamp,
-            timezone: timezone.clone(),
-        };
-        let committer = Autors {
-            name,
-            email,
-            timestamp,
-            timezone,
-        };
+        let author = Autors::now()?;
+        let committer = author.clone();
 
         let temp_header = Header {
             object: objects::ObjectType::Commit,
@@ -236,7 +241,8 @@ impl Commit {
             parents_hash,
             author,
             committer,
-            message: message.into(),
+            extra_headers: Vec::new(),
+            message: message.as_bytes().to_vec(),
         };
         let contents = commit.to_bytes();
         let header = Header {
@@ -248,102 +254,81 @@ impl Commit {
         Ok(commit)
     }
 
-    // Split by new line. Each line starts with these words (as bytes):
+    // The headers end at the first empty line, the message is everything after it:
     // tree <40-char SHA>\n
-    // parent <40-char SHA>\n (optional)
-    // parent <40-char SHA>\n (optional)
+    // parent <40-char SHA>\n (optional, can appear multiple times)
     // author ...\n
     // committer ...\n
+    // <other headers>\n (optional. Lines starting with a space continue the header above)
     // \n
     // <commit message>
-    // Used by cat-file
+    // to_bytes gives back the same bytes, so the hash does not change
     pub fn decode(hash: &str) -> std::io::Result<Self> {
-        let root_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
-
-        let (folder_name, file_name) = hash.split_at(2);
-        let file_path = root_path.join(folder_name).join(file_name);
-        let file_content = std::fs::read(file_path)?;
-        let bytes_output = objects::de_compress(&file_content)?;
+        let bytes_output = objects::read_object(hash)?;
         let header = Header::from_binary(&bytes_output)?;
 
         let start = header.head_length();
-        let contents = bytes_output[start + 1..]
-            .split(|b| *b == b'\n')
-            .collect::<Vec<&[u8]>>();
+        let content = &bytes_output[start + 1..];
+        let (headers, message) = match content.windows(2).position(|w| w == b"\n\n") {
+            Some(pos) => (&content[..pos], &content[pos + 2..]),
+            None => (content.strip_suffix(b"\n").unwrap_or(content), &[][..]),
+        };
+        let invalid = |field: &str| std::io::Error::other(format!("Commit {hash}: {field}"));
+
+        // Continuation lines are joined to the header they belong to
+        let mut fields: Vec<(&[u8], Vec<u8>)> = Vec::new();
+        for line in headers.split(|b| *b == b'\n') {
+            match (line.strip_prefix(b" "), fields.last_mut()) {
+                (Some(continuation), Some((_, value))) => {
+                    value.push(b'\n');
+                    value.extend_from_slice(continuation);
+                }
+                _ => {
+                    let space = line.iter().position(|b| *b == b' ').unwrap_or(line.len());
+                    let value = line.get(space + 1..).unwrap_or_default();
+                    fields.push((&line[..space], value.to_vec()));
+                }
+            }
+        }
 
-        let mut tree_hash = String::new();
+        let mut tree_hash = None;
         let mut parents_hash: Vec<String> = Vec::new();
-        let mut author = Autors::default();
-        let mut committer = Autors::default();
-        let mut message = String::new();
-
-        for line in contents {
-            if line.is_empty() {
-                continue;
-            }
-            if line.starts_with(b"tree ") {
-                let hash_bytes = &line["tree ".len()..];
-                tree_hash = str::from_utf8(hash_bytes).unwrap().to_string();
-            } else if line.starts_with(b"parent ") {
-                let hash_bytes = &line["parent ".len()..];
-                let parent_hash_str = str::from_utf8(hash_bytes).unwrap().to_string();
-                parents_hash.push(parent_hash_str);
-            } else if line.starts_with(b"author ") {
-                match Autors::from_bytes(line) {
-                    Some(a) => author = a,
-                    // Should not be able to create a commit without author
-                    None => {
-                        return Err(std::io::Error::other("Author field missing"));
-                    }
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = Vec::new();
+        for (name, value) in fields {
+            let line = [name, b" ", &value].concat();
+            let hash = || String::from_utf8_lossy(&value).to_string();
+            match name {
+                b"tree" => tree_hash = Some(hash()),
+                b"parent" => parents_hash.push(hash()),
+                b"author" => {
+                    author =
+                        Some(Autors::from_bytes(&line).ok_or_else(|| invalid("Invalid author"))?)
                 }
-            } else if line.starts_with(b"committer ") {
-                match Autors::from_bytes(line) {
-                    Some(a) => committer = a,
-                    // Should not be able to create a commit without committer
-                    None => {
-                        return Err(std::io::Error::other("Comitter field missing"));
-                    }
+                b"committer" => {
+                    committer = Some(
+                        Autors::from_bytes(&line).ok_or_else(|| invalid("Invalid committer"))?,
+                    )
                 }
-            } else {
-                message = str::from_utf8(line).unwrap().to_string();
+                _ => extra_headers.push((name.to_vec(), value)),
             }
         }
 
         Ok(Self {
             header,
-            tree_hash,
+            tree_hash: tree_hash.ok_or_else(|| invalid("Tree field missing"))?,
             parents_hash,
-            author,
-            committer,
-            message,
+            author: author.ok_or_else(|| invalid("Author field missing"))?,
+            committer: committer.ok_or_else(|| invalid("Comitter field missing"))?,
+            extra_headers,
+            message: message.to_vec(),
         })
     }
 
     // Returns the hash of the new commit
     pub fn write_commit_to_file(&self) -> std::io::Result<String> {
-        let objects_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
-
-        let header = format!("commit {}\0", self.header.size);
-        let commit_bytes = &self.to_bytes();
-
-        let hash = Self::sha1_commit(commit_bytes);
-        let (folder_name, file_name) = hash.split_at(2);
-        let folder_path = objects_path.join(folder_name);
-        let file_path = folder_path.join(file_name);
-        if !folder_path.exists() {
-            std::fs::create_dir(folder_path)?;
-        }
-        let new_commit = std::fs::File::create(file_path)?;
-
-        let mut content: Vec<u8> = Vec::new();
-        let mut enc =
-            ZlibEncoder::new_with_compress(new_commit, Compress::new(Compression::best(), true));
-
-        content.extend_from_slice(header.as_bytes());
-        content.extend_from_slice(commit_bytes);
-        enc.write_all(&content)?;
-        enc.finish()?;
-        Ok(hash)
+        objects::write_object(ObjectType::Commit, &self.to_bytes())
     }
 
     pub fn sha1_commit(content: &[u8]) -> String {
@@ -360,34 +345,98 @@ impl std::fmt::Display for Autors {
         write!(
             f,
             "{} <{}> {} {}",
-            self.name, self.email, self.timestamp, self.timezone
+            String::from_utf8_lossy(&self.name),
+            String::from_utf8_lossy(&self.email),
+            self.timestamp,
+            self.timezone
         )
     }
 }
 
 impl std::fmt::Display for Commit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "tree {}", self.tree_hash)?;
-
-        for parent in &self.parents_hash {
-            writeln!(f, "parent {parent}")?;
+        // Same as git cat-file -p, the object as it is stored
+        let contents = self.to_bytes();
+        write!(f, "{}", String::from_utf8_lossy(&contents))?;
+        if !contents.ends_with(b"\n") {
+            writeln!(f)?;
         }
+        Ok(())
+    }
+}
 
-        writeln!(f, "author {}", self.author)?;
-        writeln!(f, "committer {}", self.committer)?;
-        writeln!(f)?;
-        writeln!(f, "{}", self.message.trim_end())
+impl CommitSummary {
+    // What changed since the parent commit, same as git diff --stat -M
+    pub fn new(
+        branch: String,
+        commit_hash: String,
+        message: String,
+        parent: Option<&str>,
+        tree_hash: &str,
+    ) -> std::io::Result<Self> {
+        let parent_tree = match parent {
+            Some(parent) => Some(Commit::get_tree_from_commit(parent)?),
+            None => None,
+        };
+        let file_changes = diff::diff_trees(parent_tree.as_deref(), Some(tree_hash), &[])?;
+        let file_changes = diff::detect_renames(file_changes, diff::DEFAULT_SIMILARITY, false)?;
+        let mut stats = Vec::new();
+        for change in &file_changes {
+            let (old, new) = change.files()?;
+            let path = change.display_path(" => ");
+            stats.push(FileStat::new(&path, old.as_ref(), new.as_ref()));
+        }
+        Ok(Self {
+            branch,
+            commit_hash,
+            message,
+            root: parent.is_none(),
+            file_changes,
+            stats,
+        })
     }
 }
 
+// Same as git:
+// [main (root-commit) 1a2b3c4] subject
+//  2 files changed, 3 insertions(+), 1 deletion(-)
+//  create mode 100644 new.txt
+//  rename old.txt => moved.txt (90%)
 impl std::fmt::Display for CommitSummary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let root = if self.root { " (root-commit)" } else { "" };
         writeln!(
             f,
-            "[{} {}] {}",
+            "[{}{root} {}] {}",
             self.branch,
-            &self.commit_hash[..8],
-            self.message
-        )
+            &self.commit_hash[..7],
+            self.message.lines().next().unwrap_or_default()
+        )?;
+        write!(f, "{}", diff::stat_summary(&self.stats))?;
+        for change in &self.file_changes {
+            let path = &change.path;
+            match (change.change(), &change.old, &change.new) {
+                (Change::Added, _, Some(new)) => {
+                    writeln!(f, " create mode {:06o} {path}", new.mode)?;
+                }
+                (Change::Deleted, Some(old), _) => {
+                    writeln!(f, " delete mode {:06o} {path}", old.mode)?;
+                }
+                (Change::Renamed | Change::Copied, _, _) => {
+                    let source = change.source.as_ref().unwrap();
+                    let kind = if source.copy { "copy" } else { "rename" };
+                    writeln!(f, " {kind} {} => {path} ({}%)", source.path, source.score)?;
+                }
+                (_, Some(old), Some(new)) if old.mode != new.mode => {
+                    writeln!(
+                        f,
+                        " mode change {:06o} => {:06o} {path}",
+                        old.mode, new.mode
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,215 @@
+use crate::{
+    objects::{self, Header, ObjectType, commit::Autors},
+    refs, revision,
+};
+
+// An annotated tag. Lightweight tags are only a ref and have no object
+pub struct Tag {
+    pub header: Header,
+    pub object: String,
+    pub object_type: ObjectType,
+    pub tag: String,
+    // Very old tags were written without a tagger
+    pub tagger: Option<Autors>,
+    // Headers after the tagger, and the message, as bytes. Same as Commit
+    pub extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    pub message: Vec<u8>,
+}
+
+impl Tag {
+    // object is the hash of the tagged object. Usually a commit
+    pub fn encode(object: &str, tag: &str, message: &str) -> std::io::Result<Self> {
+        Self::check_name(tag)?;
+        let object_bytes = objects::read_object(object).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Object {object} not found"),
+            )
+        })?;
+        let object_type = Header::from_binary(&object_bytes)?.object;
+
+        // Same as git, the message always ends with a new line
+        let mut message = message.to_string();
+        if !message.is_empty() && !message.ends_with('\n') {
+            message.push('\n');
+        }
+
+        let mut tag = Self {
+            header: Header {
+                object: ObjectType::Tag,
+                size: 0,
+            },
+            object: object.to_string(),
+            object_type,
+            tag: tag.to_string(),
+            tagger: Some(Autors::now()?),
+            extra_headers: Vec::new(),
+            message: message.into_bytes(),
+        };
+        tag.header.size = tag.to_bytes().len();
+        Ok(tag)
+    }
+
+    // Format of a tag object:
+    // object <40-char SHA>\n
+    // type <type of the tagged object>\n
+    // tag <name>\n
+    // tagger <name> <<email>> <timestamp> <timezone>\n (optional)
+    // <other headers>\n (optional. Lines starting with a space continue the header above)
+    // \n
+    // <tag message>
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut contents: Vec<u8> = Vec::new();
+        contents.extend_from_slice(format!("object {}\n", self.object).as_bytes());
+        contents.extend_from_slice(format!("type {}\n", self.object_type).as_bytes());
+        contents.extend_from_slice(format!("tag {}\n", self.tag).as_bytes());
+        if let Some(tagger) = &self.tagger {
+            contents.extend_from_slice(b"tagger ");
+            contents.extend_from_slice(&tagger.to_bytes());
+            contents.push(b'\n');
+        }
+        for (name, value) in &self.extra_headers {
+            contents.extend_from_slice(name);
+            contents.push(b' ');
+            for byte in value {
+                contents.push(*byte);
+                if *byte == b'\n' {
+                    contents.push(b' ');
+                }
+            }
+            contents.push(b'\n');
+        }
+        contents.push(b'\n');
+        contents.extend_from_slice(&self.message);
+        contents
+    }
+
+    // The message is everything after the first empty line, kept as is
+    pub fn decode(hash: &str) -> std::io::Result<Self> {
+        let bytes_output = objects::read_object(hash)?;
+        let header = Header::from_binary(&bytes_output)?;
+        if !matches!(header.object, ObjectType::Tag) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Object {hash} is a {}, not a tag", header.object),
+            ));
+        }
+        let content = &bytes_output[header.head_length() + 1..];
+        let (headers, message) = match content.windows(2).position(|w| w == b"\n\n") {
+            Some(pos) => (&content[..pos], &content[pos + 2..]),
+            None => (content.strip_suffix(b"\n").unwrap_or(content), &[][..]),
+        };
+
+        // Continuation lines are joined to the header they belong to
+        let mut fields: Vec<(&[u8], Vec<u8>)> = Vec::new();
+        for line in headers.split(|&b| b == b'\n') {
+            match (line.strip_prefix(b" "), fields.last_mut()) {
+                (Some(continuation), Some((_, value))) => {
+                    value.push(b'\n');
+                    value.extend_from_slice(continuation);
+                }
+                _ => {
+                    let space = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+                    let value = line.get(space + 1..).unwrap_or_default();
+                    fields.push((&line[..space], value.to_vec()));
+                }
+            }
+        }
+
+        let mut object = None;
+        let mut object_type = None;
+        let mut tag = None;
+        let mut tagger = None;
+        let mut extra_headers = Vec::new();
+        for (name, value) in fields {
+            let text = || {
+                String::from_utf8(value.clone())
+                    .map_err(|_| std::io::Error::other("Invalid tag object"))
+            };
+            match name {
+                b"object" => object = Some(text()?),
+                b"type" => object_type = ObjectType::from_name(&text()?),
+                b"tag" => tag = Some(text()?),
+                b"tagger" => {
+                    let line = [name, b" ", &value].concat();
+                    tagger = Some(
+                        Autors::from_bytes(&line)
+                            .ok_or_else(|| std::io::Error::other("Invalid tagger field"))?,
+                    );
+                }
+                _ => extra_headers.push((name.to_vec(), value)),
+            }
+        }
+
+        let missing = |field: &str| std::io::Error::other(format!("Tag {hash} has no {field}"));
+        Ok(Self {
+            header,
+            object: object.ok_or_else(|| missing("object"))?,
+            object_type: object_type.ok_or_else(|| missing("type"))?,
+            tag: tag.ok_or_else(|| missing("name"))?,
+            tagger,
+            extra_headers,
+            message: message.to_vec(),
+        })
+    }
+
+    // Returns the hash of the new tag
+    pub fn write_tag_to_file(&self) -> std::io::Result<String> {
+        objects::write_object(ObjectType::Tag, &self.to_bytes())
+    }
+
+    pub fn sha1_tag(content: &[u8]) -> String {
+        objects::hash_object("tag", content)
+    }
+
+    pub fn check_name(name: &str) -> std::io::Result<()> {
+        if !refs::check_name(name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("'{name}' is not a valid tag name."),
+            ));
+        }
+        Ok(())
+    }
+
+    // Creates refs/tags/<name>. hash is a tag object for annotated tags
+    pub fn create_ref(name: &str, hash: &str, force: bool) -> std::io::Result<()> {
+        Self::check_name(name)?;
+        let ref_name = format!("refs/tags/{name}");
+        let old = refs::read_ref(&ref_name)?;
+        if old.is_some() && !force {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("tag '{name}' already exists"),
+            ));
+        }
+        let mut transaction = refs::Transaction::new();
+        transaction.compare_and_swap(&ref_name, old.as_deref(), hash);
+        transaction.commit()
+    }
+
+    // Returns the hash the tag pointed to
+    pub fn delete_ref(name: &str) -> std::io::Result<String> {
+        refs::delete_ref(&format!("refs/tags/{name}"))?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("tag '{name}' not found."),
+            )
+        })
+    }
+
+    // Names of all the tags, sorted. Tags can be in folders (v1/beta)
+    pub fn list() -> std::io::Result<Vec<String>> {
+        Ok(refs::list_refs("refs/tags")?
+            .into_iter()
+            .map(|(name, _)| revision::shorten_ref(&name).to_string())
+            .collect())
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Same as git cat-file -p, the object as it is stored
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
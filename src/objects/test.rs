@@ -1,7 +1,7 @@
 use std::{
     collections::{BTreeMap, HashSet},
     io::Write,
-    os::unix::fs::MetadataExt,
+    os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
 };
 
@@ -10,8 +10,14 @@ use git2::IndexAddOption;
 use crate::{
     git_rust::{self, BASE_DIR, RepoRust},
     index::Index,
-    objects::{self, blob, commit::Commit, tree::Tree},
-    test_common::{run_test, run_test_matches},
+    objects::{
+        self, ObjectType, blob,
+        commit::{Commit, CommitSummary},
+        tag::Tag,
+        tree::Tree,
+    },
+    refs,
+    test_common::{git2_commit_files, run_test, run_test_matches},
 };
 
 #[test]
@@ -193,7 +199,7 @@ fn test_hash_file_in_temp_folder() {
         // Create a dir
         let dir_1 = path.join("new_dir");
         let dir_path_str_1 = dir_1.to_str().unwrap();
-        std::fs::create_dir_all(&dir_path_str_1).unwrap();
+        std::fs::create_dir_all(dir_path_str_1).unwrap();
 
         let args = run_test_matches(vec!["", "hash-object", "-w", &dir_path_str_1]);
         let result_2 = blob::Blob::encode_object(&args);
@@ -284,7 +290,7 @@ fn test_git_add_files() {
             .join("index");
 
         // INDEX one file
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         let result = git_rust::RepoRust::add(&add_args);
         assert!(result.is_ok());
         result.unwrap();
@@ -312,7 +318,7 @@ fn test_git_add_files() {
         assert_eq!(entry.file_size, metadata.len() as u32);
         assert_eq!(entry.dev, metadata.dev() as u32);
         assert_eq!(entry.ino, metadata.ino() as u32);
-        assert_eq!(entry.mode, metadata.mode());
+        assert_eq!(entry.mode, 0o100644);
         assert_eq!(entry.uid, metadata.uid());
         assert_eq!(entry.gid, metadata.gid());
 
@@ -338,7 +344,7 @@ fn test_git_add_files() {
         let mut file_2 = std::fs::File::create(&file_path_2).unwrap();
         file_2.write_all(b"this is second test").unwrap();
 
-        let add_args_2 = run_test_matches(vec!["", "add", &format!("{}", "test2.txt")]);
+        let add_args_2 = run_test_matches(vec!["", "add", "test2.txt"]);
         let result = git_rust::RepoRust::add(&add_args_2);
         assert!(result.is_ok());
         result.unwrap();
@@ -366,7 +372,7 @@ fn test_git_add_files() {
         assert_eq!(entry_2.file_size, metadata_2.len() as u32);
         assert_eq!(entry_2.dev, metadata_2.dev() as u32);
         assert_eq!(entry_2.ino, metadata_2.ino() as u32);
-        assert_eq!(entry_2.mode, metadata_2.mode());
+        assert_eq!(entry_2.mode, 0o100644);
         assert_eq!(entry_2.uid, metadata_2.uid());
         assert_eq!(entry_2.gid, metadata_2.gid());
 
@@ -404,7 +410,7 @@ fn test_git_add_same_file_twice() {
         git_rust::RepoRust::init().unwrap();
 
         // INDEX file once
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
         let index = Index::read_index().unwrap();
 
@@ -567,14 +573,14 @@ fn test_compare_index_with_git() {
 
         assert!(path_folder_1.exists());
         assert!(path_folder_2.exists());
-        assert!(path_folder_1.join(format!("file_in_dir1_0")).exists());
-        assert!(path_folder_1.join(format!("file_in_dir1_1")).exists());
-        assert!(path_folder_1.join(format!("file_in_dir1_2")).exists());
-        assert!(path_folder_1.join(format!("file_in_dir1_3")).exists());
-        assert!(path_folder_1.join(format!("file_in_dir1_4")).exists());
-        assert!(path_folder_2.join(format!("file_in_dir2_0")).exists());
-        assert!(path_folder_2.join(format!("file_in_dir2_1")).exists());
-        assert!(path_folder_2.join(format!("file_in_dir2_2")).exists());
+        assert!(path_folder_1.join("file_in_dir1_0").exists());
+        assert!(path_folder_1.join("file_in_dir1_1").exists());
+        assert!(path_folder_1.join("file_in_dir1_2").exists());
+        assert!(path_folder_1.join("file_in_dir1_3").exists());
+        assert!(path_folder_1.join("file_in_dir1_4").exists());
+        assert!(path_folder_2.join("file_in_dir2_0").exists());
+        assert!(path_folder_2.join("file_in_dir2_1").exists());
+        assert!(path_folder_2.join("file_in_dir2_2").exists());
         assert!(path.join(PathBuf::from("test1.txt")).exists());
         assert!(path.join(PathBuf::from("test2.txt")).exists());
 
@@ -819,7 +825,7 @@ fn test_write_tree_one_file_in_root() {
         use git2::Repository;
         let repo = Repository::init(&path).unwrap();
         let mut index = repo.index().unwrap();
-        index.add_path(&Path::new("test1.txt")).unwrap();
+        index.add_path(Path::new("test1.txt")).unwrap();
         index.write().unwrap();
         let _tree_oid = index.write_tree().unwrap();
 
@@ -912,7 +918,7 @@ fn test_write_tree_multiple_folders_1() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1119,7 +1125,7 @@ fn test_write_tree_multiple_folders_2() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1294,7 +1300,7 @@ fn test_write_tree_multiple_folders_3() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1464,7 +1470,7 @@ fn test_write_tree_multiple_folders_4() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1635,7 +1641,7 @@ fn test_write_tree_multiple_folders_5() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1801,7 +1807,7 @@ fn test_write_tree_multiple_folders_6() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -1964,7 +1970,7 @@ fn test_write_tree_multiple_folders_7() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -2126,7 +2132,7 @@ fn test_write_tree_multiple_folders_8() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -2229,7 +2235,7 @@ fn test_write_tree_multiple_folders_9() {
         // init, add and write-tree with git_rust
         let git_path = &setup.test_dir;
         use git2::Repository;
-        let repo = Repository::init(&git_path).unwrap();
+        let repo = Repository::init(git_path).unwrap();
         let mut index = repo.index().unwrap();
         index
             .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
@@ -2303,7 +2309,7 @@ fn test_write_commit_tree_normal_op() {
         git_rust::RepoRust::init().unwrap();
 
         // Stage it
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
 
         // Bypass the write-tree functions to get the tree_hash
@@ -2347,7 +2353,7 @@ fn test_write_commit_tree_internal() {
         git_rust::RepoRust::init().unwrap();
 
         // Stage it
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
 
         // Bypass the write-tree functions to get the tree_hash
@@ -2365,7 +2371,7 @@ fn test_write_commit_tree_internal() {
 
         assert_eq!(commit_1.tree_hash, tree_hash_str);
         assert_eq!(commit_1.parents_hash, parent_commit_1);
-        assert_eq!(commit_1.message, message_1);
+        assert_eq!(commit_1.message, message_1.as_bytes());
 
         // Create another file
         let file_path_2 = path.join("test2.txt");
@@ -2373,7 +2379,7 @@ fn test_write_commit_tree_internal() {
         file_2.write_all(b"this is a test").unwrap();
 
         // Stage it
-        let add_args_2 = run_test_matches(vec!["", "add", &format!("{}", "test2.txt")]);
+        let add_args_2 = run_test_matches(vec!["", "add", "test2.txt"]);
         git_rust::RepoRust::add(&add_args_2).unwrap();
 
         // Bypass the write-tree functions to get the tree_hash
@@ -2384,14 +2390,14 @@ fn test_write_commit_tree_internal() {
         let write_tree_args = run_test_matches(vec!["", "write-tree"]);
         git_rust::RepoRust::write_tree(&write_tree_args).unwrap();
 
-        let parent_commit_2: Vec<String> = Vec::from(commit_1.parents_hash);
+        let parent_commit_2: Vec<String> = commit_1.parents_hash;
         let message_2 = "This is a test commit 1".to_string();
         let commit_2 =
             Commit::encode(&tree_hash_str_2, parent_commit_2.clone(), &message_2).unwrap();
 
         assert_eq!(commit_2.tree_hash, tree_hash_str_2);
         assert_eq!(commit_2.parents_hash, parent_commit_2);
-        assert_eq!(commit_2.message, message_1);
+        assert_eq!(commit_2.message, message_1.as_bytes());
     });
 }
 
@@ -2411,7 +2417,7 @@ fn test_write_commit_tree_bad_tree() {
         git_rust::RepoRust::init().unwrap();
 
         // Stage it
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
 
         // Bypass the write-tree functions to get the tree_hash
@@ -2498,7 +2504,7 @@ fn test_write_commit_normal() {
         );
 
         // Stage the file
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test1.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
 
         // Make sure branch does not exist - Initial commit
@@ -2513,7 +2519,7 @@ fn test_write_commit_normal() {
         // Branch file should exist
         assert!(branch.exists());
         let branch_content = std::fs::read(&branch).unwrap();
-        let commit_1 = str::from_utf8(&branch_content).unwrap();
+        let commit_1 = str::from_utf8(&branch_content).unwrap().trim_end();
         assert_eq!(commit_1.len(), 40);
 
         // commit again - should fail
@@ -2529,7 +2535,7 @@ fn test_write_commit_normal() {
         file_2.write_all(b"this is another test file").unwrap();
 
         // Stage the file
-        let add_args = run_test_matches(vec!["", "add", &format!("{}", "test2.txt")]);
+        let add_args = run_test_matches(vec!["", "add", "test2.txt"]);
         git_rust::RepoRust::add(&add_args).unwrap();
 
         // Delete the branch file
@@ -2542,8 +2548,407 @@ fn test_write_commit_normal() {
         result.unwrap();
 
         let branch_content_2 = std::fs::read(&branch).unwrap();
-        let commit_2 = str::from_utf8(&branch_content_2).unwrap();
+        let commit_2 = str::from_utf8(&branch_content_2).unwrap().trim_end();
         assert_ne!(commit_2, commit_1);
         assert_eq!(commit_2.len(), 40);
     });
 }
+
+#[test]
+fn test_tag_round_trip_with_git() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        // Annotated tag made by git
+        let (repo, commit) = git2_commit_files(&path.join("remote"), &[("a.txt", "a\n")]);
+        let sig = git2::Signature::now("A U Thor", "author@example.com").unwrap();
+        let target = repo.find_object(commit, None).unwrap();
+        let tag_id = repo
+            .tag("v1.0", &target, &sig, "Release 1.0\n\nWith a body\n", false)
+            .unwrap();
+        let raw = repo.odb().unwrap().read(tag_id).unwrap().data().to_vec();
+
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+        let hash = objects::write_object(ObjectType::Tag, &raw).unwrap();
+        assert_eq!(hash, tag_id.to_string());
+
+        let tag = Tag::decode(&hash).unwrap();
+        assert_eq!(tag.object, commit.to_string());
+        assert!(matches!(tag.object_type, ObjectType::Commit));
+        assert_eq!(tag.tag, "v1.0");
+        assert!(tag.tagger.is_some());
+        assert_eq!(tag.message, b"Release 1.0\n\nWith a body\n");
+        assert_eq!(tag.to_bytes(), raw);
+        assert_eq!(Tag::sha1_tag(&tag.to_bytes()), hash);
+        assert_eq!(tag.to_string().as_bytes(), raw);
+
+        // cat-file accepts tags
+        let args = run_test_matches(vec!["", "cat-file", "-p", &hash]);
+        assert!(RepoRust::cat_file(&args).is_ok());
+
+        // Other headers and a Latin-1 message are written back as they were
+        let raw = [
+            format!("object {commit}\ntype commit\ntag v1.1\n").as_bytes(),
+            b"tagger Jos\xe9 <jose@example.com> 1700000000 +0100\n",
+            b"encoding ISO-8859-1\n",
+            b"x-note first\n second\n",
+            b"\n",
+            b"Caf\xe9\n",
+        ]
+        .concat();
+        let hash = objects::write_object(ObjectType::Tag, &raw).unwrap();
+        let tag = Tag::decode(&hash).unwrap();
+        assert_eq!(tag.tagger.as_ref().unwrap().name, b"Jos\xe9");
+        assert_eq!(
+            tag.extra_headers[1],
+            (b"x-note".to_vec(), b"first\nsecond".to_vec())
+        );
+        assert_eq!(tag.message, b"Caf\xe9\n");
+        assert_eq!(tag.to_bytes(), raw);
+        assert_eq!(Tag::sha1_tag(&tag.to_bytes()), hash);
+    });
+}
+
+#[test]
+fn test_tag_command() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        std::fs::write(path.join("test1.txt"), b"this is a test").unwrap();
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+
+        // Nothing to tag yet
+        let tag_args = run_test_matches(vec!["", "tag", "v1"]);
+        assert!(RepoRust::tag(&tag_args).is_err());
+
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
+        RepoRust::add(&add_args).unwrap();
+        let commit_args = run_test_matches(vec!["", "commit", "-m", "Test commit"]);
+        RepoRust::commit(&commit_args).unwrap();
+        let head = crate::refs::read_ref("HEAD").unwrap().unwrap();
+        let tags_dir = path.join(BASE_DIR).join("refs/tags");
+
+        // Lightweight
+        RepoRust::tag(&tag_args).unwrap();
+        let v1 = std::fs::read_to_string(tags_dir.join("v1")).unwrap();
+        assert_eq!(v1.trim_end(), head);
+        assert!(RepoRust::tag(&tag_args).is_err());
+        let force_args = run_test_matches(vec!["", "tag", "-f", "v1"]);
+        assert!(RepoRust::tag(&force_args).is_ok());
+
+        // Annotated. -m implies -a
+        let tag_args = run_test_matches(vec!["", "tag", "release/v2", "-m", "Second release"]);
+        RepoRust::tag(&tag_args).unwrap();
+        let v2 = std::fs::read_to_string(tags_dir.join("release/v2")).unwrap();
+        let tag = Tag::decode(v2.trim_end()).unwrap();
+        assert_eq!(tag.object, head);
+        assert_eq!(tag.tag, "release/v2");
+        assert_eq!(tag.message, b"Second release\n");
+        let tag_args = run_test_matches(vec!["", "tag", "-a", "v3"]);
+        assert!(RepoRust::tag(&tag_args).is_err());
+
+        assert_eq!(Tag::list().unwrap(), vec!["release/v2", "v1"]);
+
+        let tag_args = run_test_matches(vec!["", "tag", "bad..name"]);
+        assert!(RepoRust::tag(&tag_args).is_err());
+
+        let delete_args = run_test_matches(vec!["", "tag", "-d", "v1"]);
+        RepoRust::tag(&delete_args).unwrap();
+        assert!(!tags_dir.join("v1").exists());
+        assert!(RepoRust::tag(&delete_args).is_err());
+    });
+}
+
+#[test]
+fn test_write_tree_with_modes_matches_git() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+
+        // foo.txt, the tree foo and foo-bar: git sorts trees as "foo/"
+        std::fs::create_dir_all(path.join("foo")).unwrap();
+        std::fs::write(path.join("foo.txt"), "text\n").unwrap();
+        std::fs::write(path.join("foo-bar"), "text\n").unwrap();
+        std::fs::write(path.join("foo/run.sh"), "#!/bin/sh\necho hi\n").unwrap();
+        std::fs::set_permissions(
+            path.join("foo/run.sh"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("foo/run.sh", path.join("link")).unwrap();
+        // A nested repo is a submodule
+        let (_, sub_commit) = git2_commit_files(&path.join("sub"), &[("a.txt", "a\n")]);
+
+        let add_args = run_test_matches(vec!["", "add", ""]);
+        git_rust::RepoRust::add(&add_args).unwrap();
+        let index = Index::read_index().unwrap();
+        assert_eq!(index.entries["foo.txt"].mode, 0o100644);
+        assert_eq!(index.entries["foo/run.sh"].mode, 0o100755);
+        assert_eq!(index.entries["link"].mode, 0o120000);
+        assert_eq!(index.entries["sub"].mode, 0o160000);
+        assert_eq!(index.entries["sub"].sha1, sub_commit.as_bytes());
+        assert!(!index.entries.contains_key("sub/a.txt"));
+        let (trees, root_hash) = Tree::encode_object().unwrap();
+        Tree::write_object_to_file(trees).unwrap();
+
+        // Same tree built with git
+        let repo = git2::Repository::init(path.join("expected")).unwrap();
+        let text = repo.blob(b"text\n").unwrap();
+        let script = repo.blob(b"#!/bin/sh\necho hi\n").unwrap();
+        let target = repo.blob(b"foo/run.sh").unwrap();
+        let mut foo = repo.treebuilder(None).unwrap();
+        foo.insert("run.sh", script, 0o100755).unwrap();
+        let foo = foo.write().unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        root.insert("foo.txt", text, 0o100644).unwrap();
+        root.insert("foo-bar", text, 0o100644).unwrap();
+        root.insert("foo", foo, 0o040000).unwrap();
+        root.insert("link", target, 0o120000).unwrap();
+        root.insert("sub", sub_commit, 0o160000).unwrap();
+        let expected = root.write().unwrap();
+        assert_eq!(hex::encode(root_hash), expected.to_string());
+
+        // Parsed back without losing the modes
+        let tree = Tree::decode_object(&expected.to_string()).unwrap();
+        let modes: Vec<(&str, &str, ObjectType)> = tree
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.mode.as_str(), e.object_type))
+            .collect();
+        assert_eq!(
+            modes,
+            vec![
+                ("foo-bar", "100644", ObjectType::Blob),
+                ("foo.txt", "100644", ObjectType::Blob),
+                ("foo", "40000", ObjectType::Tree),
+                ("link", "120000", ObjectType::Blob),
+                ("sub", "160000", ObjectType::Commit),
+            ]
+        );
+        assert_eq!(
+            tree.entries[2].to_string(),
+            format!("040000 tree {foo} foo")
+        );
+        let link = objects::read_object(&target.to_string()).unwrap();
+        assert!(link.ends_with(b"foo/run.sh"));
+
+        // Broken trees are errors: truncated hash, missing name end, name not UTF-8
+        let hash = [0xAB; 20];
+        for content in [
+            [b"100644 a.txt\0".as_slice(), &hash[..10]].concat(),
+            b"100644 a.txt".to_vec(),
+            b"100644".to_vec(),
+            [b"100644 caf\xe9\0".as_slice(), &hash].concat(),
+        ] {
+            let broken = objects::write_object(ObjectType::Tree, &content).unwrap();
+            let error = Tree::decode_object(&broken).err().unwrap();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    });
+}
+
+#[test]
+fn test_commit_round_trip_with_extra_headers() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+
+        let tree = objects::write_object(ObjectType::Tree, b"").unwrap();
+        let parent = "1".repeat(40);
+        let raw = format!(
+            "tree {tree}\n\
+             parent {parent}\n\
+             author Jane Q. Doe <jane@example.com> 1700000000 -0530\n\
+             committer  Committer Name  <c@example.com> 1700000100 +0100\n\
+             encoding ISO-8859-1\n\
+             mergetag object {parent}\n \
+             type commit\n \
+             tag v1\n \
+             tagger Jane Q. Doe <jane@example.com> 1700000000 -0530\n \
+             \n \
+             Release\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \
+             \n \
+             iQEzBAABCAAdFiEE\n \
+             -----END PGP SIGNATURE-----\n\
+             \n\
+             Subject line\n\nFirst paragraph\n\n\nAfter two blank lines\n"
+        );
+        let hash = objects::write_object(ObjectType::Commit, raw.as_bytes()).unwrap();
+
+        let commit = Commit::decode(&hash).unwrap();
+        assert_eq!(commit.tree_hash, tree);
+        assert_eq!(commit.parents_hash, vec![parent.clone()]);
+        assert_eq!(commit.author.name, b"Jane Q. Doe");
+        assert_eq!(commit.author.email, b"jane@example.com");
+        assert_eq!(commit.author.timezone, "-0530");
+        assert_eq!(commit.committer.timestamp, 1700000100);
+        let names: Vec<&[u8]> = commit
+            .extra_headers
+            .iter()
+            .map(|(name, _)| name.as_slice())
+            .collect();
+        assert_eq!(names, [&b"encoding"[..], b"mergetag", b"gpgsig"]);
+        assert_eq!(
+            commit.extra_headers[2].1,
+            b"-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"
+        );
+        assert!(commit.extra_headers[1].1.ends_with(b"-0530\n\nRelease"));
+        assert_eq!(
+            commit.message,
+            b"Subject line\n\nFirst paragraph\n\n\nAfter two blank lines\n"
+        );
+
+        // Same bytes, same hash
+        assert_eq!(commit.to_bytes(), raw.as_bytes());
+        assert_eq!(Commit::sha1_commit(&commit.to_bytes()), hash);
+        assert_eq!(commit.to_string(), raw);
+
+        // Broken headers are reported
+        let broken = format!("tree {tree}\nauthor nobody\n\nmessage\n");
+        let hash = objects::write_object(ObjectType::Commit, broken.as_bytes()).unwrap();
+        assert!(Commit::decode(&hash).is_err());
+    });
+}
+
+#[test]
+fn test_commit_round_trip_with_latin1_bytes() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+
+        // "José Müller" and "Café" in Latin-1, which is not valid UTF-8
+        let tree = objects::write_object(ObjectType::Tree, b"").unwrap();
+        let raw = [
+            format!("tree {tree}\n").as_bytes(),
+            b"author Jos\xe9 M\xfcller <jose@example.com> 1700000000 +0100\n",
+            b"committer Jos\xe9 M\xfcller <jose@example.com> 1700000000 +0100\n",
+            b"encoding ISO-8859-1\n",
+            b"x-note caf\xe9\n",
+            b"\n",
+            b"Caf\xe9\n",
+        ]
+        .concat();
+        let hash = objects::write_object(ObjectType::Commit, &raw).unwrap();
+
+        let commit = Commit::decode(&hash).unwrap();
+        assert_eq!(commit.author.name, b"Jos\xe9 M\xfcller");
+        assert_eq!(commit.extra_headers[1].1, b"caf\xe9");
+        assert_eq!(commit.message, b"Caf\xe9\n");
+        assert_eq!(commit.to_bytes(), raw);
+        assert_eq!(Commit::sha1_commit(&commit.to_bytes()), hash);
+
+        // Only the text shown to the user is converted
+        assert_eq!(commit.text(&commit.author.name), "José Müller");
+        assert_eq!(commit.text(&commit.message), "Café\n");
+
+        // A date with other spacing is refused instead of being written back differently
+        let odd =
+            format!("tree {tree}\nauthor A <a@b>  1700000000 +0100\ncommitter A <a@b> 1 +0000\n\n");
+        let hash = objects::write_object(ObjectType::Commit, odd.as_bytes()).unwrap();
+        assert!(Commit::decode(&hash).is_err());
+    });
+}
+
+#[test]
+fn test_commit_decode_commit_made_by_git() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        let (repo, first) = git2_commit_files(&path.join("remote"), &[("a.txt", "a\n")]);
+        let sig = git2::Signature::now("A U Thor", "author@example.com").unwrap();
+        let tree = repo.find_commit(first).unwrap().tree().unwrap();
+        let parent = repo.find_commit(first).unwrap();
+        let message = "Second commit\n\n- one\n- two\n\nSigned-off-by: A U Thor\n";
+        let second = repo
+            .commit(None, &sig, &sig, message, &tree, &[&parent])
+            .unwrap();
+
+        git_rust::RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        git_rust::RepoRust::init().unwrap();
+        for oid in [first, second] {
+            let raw = repo.odb().unwrap().read(oid).unwrap().data().to_vec();
+            objects::write_object(ObjectType::Commit, &raw).unwrap();
+        }
+
+        let commit = Commit::decode(&second.to_string()).unwrap();
+        assert_eq!(commit.author.name, b"A U Thor");
+        assert_eq!(commit.committer.email, b"author@example.com");
+        assert_eq!(commit.parents_hash, vec![first.to_string()]);
+        assert_eq!(commit.message, message.as_bytes());
+        assert_eq!(Commit::sha1_commit(&commit.to_bytes()), second.to_string());
+
+        let args = run_test_matches(vec!["", "cat-file", "-p", &first.to_string()]);
+        assert!(RepoRust::cat_file(&args).is_ok());
+    });
+}
+
+#[test]
+fn test_commit_summary_stats() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let commit = |message: &str| {
+            let args = run_test_matches(vec!["", "add", "-A"]);
+            RepoRust::add(&args).unwrap();
+            let args = run_test_matches(vec!["", "commit", "-m", message]);
+            RepoRust::commit(&args).unwrap();
+            let head = refs::read_ref("HEAD").unwrap().unwrap();
+            let commit = Commit::decode(&head).unwrap();
+            let summary = CommitSummary::new(
+                "master".to_string(),
+                head.clone(),
+                commit.text(&commit.message),
+                commit.parents_hash.first().map(String::as_str),
+                &commit.tree_hash,
+            )
+            .unwrap();
+            (head, summary.to_string())
+        };
+        std::fs::write(path.join("a.txt"), "1\n2\n3\n4\n").unwrap();
+        std::fs::write(path.join("b.txt"), "b\n").unwrap();
+        std::fs::write(path.join("run.sh"), "echo\n").unwrap();
+        let (head, summary) = commit("first\n\nbody");
+        assert_eq!(
+            summary,
+            format!(
+                "[master (root-commit) {}] first\n \
+                 3 files changed, 6 insertions(+)\n \
+                 create mode 100644 a.txt\n \
+                 create mode 100644 b.txt\n \
+                 create mode 100644 run.sh\n",
+                &head[..7]
+            )
+        );
+
+        std::fs::rename(path.join("a.txt"), path.join("c.txt")).unwrap();
+        std::fs::write(path.join("c.txt"), "1\n2\n3\n4\n5\n").unwrap();
+        std::fs::remove_file(path.join("b.txt")).unwrap();
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(path.join("run.sh"), permissions).unwrap();
+        let (head, summary) = commit("second");
+        assert_eq!(
+            summary,
+            format!(
+                "[master {}] second\n \
+                 3 files changed, 1 insertion(+), 1 deletion(-)\n \
+                 delete mode 100644 b.txt\n \
+                 rename a.txt => c.txt (80%)\n \
+                 mode change 100644 => 100755 run.sh\n",
+                &head[..7]
+            )
+        );
+    });
+}
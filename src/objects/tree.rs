@@ -1,17 +1,32 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use flate2::{Compress, Compression, write::ZlibEncoder};
 use hex::ToHex;
 use sha1::{Digest, Sha1};
 
 use crate::index::{Index, IndexEntry};
-use crate::{
-    git_rust::RepoRust,
-    objects::{Header, ObjectType},
-};
+use crate::objects::{self, Header, ObjectType};
+
+// Modes of tree entries. The index stores the same values
+// Trees write them in octal without leading zeros: 100644, 40000...
+pub const MODE_FILE: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_TREE: u32 = 0o40000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+// Type of the object an entry points to. Symlinks are blobs holding the target path
+// Gitlinks (submodules) are commits of another repo
+// Old trees can have other permissions (100664). They are still blobs
+pub fn object_type_from_mode(mode: u32) -> Option<ObjectType> {
+    match mode & 0o170000 {
+        0o100000 | MODE_SYMLINK => Some(ObjectType::Blob),
+        MODE_TREE => Some(ObjectType::Tree),
+        MODE_GITLINK => Some(ObjectType::Commit),
+        _ => None,
+    }
+}
 
 pub struct TreeEntry {
     pub mode: String,
@@ -42,7 +57,6 @@ impl Tree {
 
     // ls-tree
     pub fn decode_object(hash_str: &str) -> std::io::Result<Self> {
-        let root_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
         let hash_vec = hex::decode(hash_str).map_err(|e| {
             std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -53,13 +67,9 @@ impl Tree {
             std::io::Error::new(std::io::ErrorKind::InvalidInput, "SHA1 must be 20 bytes")
         })?;
 
-        let (folder_name, file_name) = hash_str.split_at(2);
-        let file_path = root_path.join(folder_name).join(file_name);
-        let file_content = std::fs::read(file_path)?;
-
-        let bytes_output = Self::de_compress(&file_content)?;
+        let bytes_output = objects::read_object(hash_str)?;
         let header = Header::from_binary(&bytes_output)?;
-        let entries = Self::get_tree_entries(&bytes_output, &header);
+        let entries = Self::get_tree_entries(&bytes_output, &header)?;
 
         let tree = Self {
             header,
@@ -70,59 +80,30 @@ impl Tree {
     }
 
     pub fn write_object_to_file(trees: Vec<Self>) -> std::io::Result<()> {
-        let objects_path = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path);
         for tree in trees {
             let mut content: Vec<u8> = Vec::new();
-            let hex_hash = hex::encode(tree.hash);
-            let (folder_name, file_name) = hex_hash.split_at(2);
-
-            let folder_path = objects_path.join(folder_name);
-            let file_path = folder_path.join(file_name);
-
-            if !folder_path.exists() {
-                std::fs::create_dir(folder_path)?;
-            }
-            let new_tree = std::fs::File::create(file_path)?;
-
-            let mut enc =
-                ZlibEncoder::new_with_compress(new_tree, Compress::new(Compression::best(), true));
-
-            let header = format!("tree {}\0", tree.header.size);
-
-            content.extend_from_slice(header.as_bytes());
-
             for entry in tree.entries {
-                let mode = match entry.object_type {
-                    ObjectType::Blob => "100644",
-                    ObjectType::Tree => "40000",
-                    ObjectType::Commit => "160000",
-                };
-                let tree_name = entry.name;
-                content.extend_from_slice(mode.as_bytes());
+                content.extend_from_slice(entry.mode.as_bytes());
                 content.push(b' ');
-                content.extend_from_slice(tree_name.as_bytes());
+                content.extend_from_slice(entry.name.as_bytes());
                 content.push(0);
                 content.extend_from_slice(&entry.hash);
             }
-            enc.write_all(&content)?;
-            enc.finish()?;
+            objects::write_object(ObjectType::Tree, &content)?;
         }
         Ok(())
     }
 
     // Will create and sort Tree struct given a Vec of TreeEntries
+    // Same order as git: trees are sorted as if their name ended with '/'
+    // "foo.txt" comes before the tree "foo" ('.' < '/')
     pub fn from_entries(mut entries: Vec<TreeEntry>) -> Self {
-        entries.sort_by(|a, b| {
-            let name_cmp = a.name.as_bytes().cmp(b.name.as_bytes());
-            if name_cmp == std::cmp::Ordering::Equal {
-                match (&a.object_type, &b.object_type) {
-                    (ObjectType::Blob, ObjectType::Tree) => std::cmp::Ordering::Less,
-                    (ObjectType::Tree, ObjectType::Blob) => std::cmp::Ordering::Greater,
-                    _ => std::cmp::Ordering::Equal,
-                }
-            } else {
-                name_cmp
+        entries.sort_by_cached_key(|entry| {
+            let mut key = entry.name.as_bytes().to_vec();
+            if matches!(entry.object_type, ObjectType::Tree) {
+                key.push(b'/');
             }
+            key
         });
         let size: usize = entries
             .iter()
@@ -146,12 +127,7 @@ impl Tree {
         let mut hasher = Sha1::new();
         let mut content = Vec::new();
         for tree_entry in entries {
-            let mode_str = match tree_entry.object_type {
-                ObjectType::Blob => "100644",
-                ObjectType::Tree => "40000",
-                ObjectType::Commit => "160000",
-            };
-            content.extend_from_slice(mode_str.as_bytes());
+            content.extend_from_slice(tree_entry.mode.as_bytes());
             content.push(b' ');
             content.extend_from_slice(tree_entry.name.as_bytes());
             content.push(0);
@@ -163,18 +139,8 @@ impl Tree {
         hasher.finalize().into()
     }
 
-    // Decompresses the contents of a tree to be read/displayed
-    pub fn de_compress(content: &[u8]) -> std::io::Result<Vec<u8>> {
-        use std::io::Read;
-        let mut decompressed = Vec::new();
-        let cursor = std::io::Cursor::new(content);
-        let mut decoder = flate2::bufread::ZlibDecoder::new(cursor);
-        decoder.read_to_end(&mut decompressed)?;
-        Ok(decompressed)
-    }
-
     // Parses the contents of a tree objects into a Vec of TreeEntries
-    pub fn get_tree_entries(bytes_output: &[u8], head: &Header) -> Vec<TreeEntry> {
+    pub fn get_tree_entries(bytes_output: &[u8], head: &Header) -> std::io::Result<Vec<TreeEntry>> {
         // head.head_length() = length of the head, in order to skip it
         // head.size = size of the content to parse starting after head.head_length()
 
@@ -182,31 +148,43 @@ impl Tree {
         // object-> 100644+b' '+test.txt+b'\0'+63aa9936a393155f43c2b03d42d79b1c83290f41
         // Output-> 100644 blob 63aa9936a393155f43c2b03d42d79b1c83290f41 file.txt
 
+        // A truncated entry or a name that is not UTF-8 is an error, not a panic
+        let invalid = |message: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{message} in tree"),
+            )
+        };
         let mut entries: Vec<TreeEntry> = vec![];
         let mut i = head.head_length() + 1;
-        while i < head.size {
-            let mut start = i;
-            while bytes_output[i] != b' ' {
-                i += 1;
-            }
-            let mode = str::from_utf8(&bytes_output[start..i]).unwrap().to_string();
-            let objecttype: ObjectType;
-            match mode.as_str() {
-                "100644" => objecttype = ObjectType::Blob,
-                "40000" => objecttype = ObjectType::Tree,
-                _ => {
-                    panic!("Invalid object type.")
-                }
-            }
-            start = i + 1;
-            while bytes_output[i] != b'\0' {
-                i += 1;
-            }
-            let name = str::from_utf8(&bytes_output[start..i]).unwrap().to_string();
-            start = i + 1;
-            let mut hash = [0_u8; 20];
-            hash.copy_from_slice(&bytes_output[start..start + 20]);
-            i += 21;
+        let end = bytes_output.len();
+        while i < end {
+            let space = bytes_output[i..]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| invalid("Invalid entry"))?;
+            let mode = str::from_utf8(&bytes_output[i..i + space])
+                .map_err(|_| invalid("Invalid mode"))?
+                .to_string();
+            let objecttype = u32::from_str_radix(&mode, 8)
+                .ok()
+                .and_then(object_type_from_mode)
+                .ok_or_else(|| invalid(&format!("Invalid mode {mode}")))?;
+            let start = i + space + 1;
+            let null = bytes_output[start..]
+                .iter()
+                .position(|&b| b == b'\0')
+                .ok_or_else(|| invalid("Invalid entry"))?;
+            let name = str::from_utf8(&bytes_output[start..start + null])
+                .map_err(|_| invalid("Name that is not UTF-8"))?
+                .to_string();
+            let hash_start = start + null + 1;
+            let hash: [u8; 20] = bytes_output
+                .get(hash_start..hash_start + 20)
+                .ok_or_else(|| invalid("Truncated entry"))?
+                .try_into()
+                .unwrap();
+            i = hash_start + 20;
             let tree = TreeEntry {
                 mode,
                 object_type: objecttype,
@@ -215,7 +193,31 @@ impl Tree {
             };
             entries.push(tree);
         }
-        entries
+        Ok(entries)
+    }
+
+    // Walks a tree recursively and returns all the blobs under it
+    // Key is the full path from the root of the tree. Example: "dir1/dir2/file.txt"
+    pub fn flatten(hash_str: &str) -> std::io::Result<BTreeMap<String, TreeEntry>> {
+        let mut files: BTreeMap<String, TreeEntry> = BTreeMap::new();
+        let mut stack = vec![(String::new(), hash_str.to_string())];
+        while let Some((prefix, hash)) = stack.pop() {
+            let tree = Self::decode_object(&hash)?;
+            for entry in tree.entries {
+                let path = if prefix.is_empty() {
+                    entry.name.clone()
+                } else {
+                    format!("{prefix}/{}", entry.name)
+                };
+                match entry.object_type {
+                    ObjectType::Tree => stack.push((path, hex::encode(entry.hash))),
+                    _ => {
+                        files.insert(path, entry);
+                    }
+                }
+            }
+        }
+        Ok(files)
     }
 
     // write-tree command
@@ -254,11 +256,11 @@ impl Tree {
         let mut tree_list: HashMap<PathBuf, Self> = HashMap::new();
         for (path, children) in entries_by_folder {
             let mut tree_entries: Vec<TreeEntry> = Vec::new();
-            // Create the blob for each file
+            // Create the blob for each file. Submodules are a commit
             for (child, entry) in children {
                 let blob_entry = TreeEntry {
-                    mode: entry.mode.to_string(),
-                    object_type: ObjectType::Blob,
+                    mode: format!("{:o}", entry.mode),
+                    object_type: object_type_from_mode(entry.mode).unwrap_or(ObjectType::Blob),
                     name: child.to_str().unwrap().to_string(),
                     hash: entry.sha1,
                 };
@@ -293,7 +295,7 @@ impl Tree {
                         .to_string_lossy()
                         .into_owned();
                     let tree_entry = TreeEntry {
-                        mode: "40000".to_string(),
+                        mode: format!("{MODE_TREE:o}"),
                         object_type: ObjectType::Tree,
                         name: tree_name,
                         hash: child_tree.hash,
@@ -332,9 +334,10 @@ impl Tree {
 
 impl Display for TreeEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Same as git ls-tree: 040000 for trees
         write!(
             f,
-            "{} {} {} {}",
+            "{:0>6} {} {} {}",
             &self.mode,
             &self.object_type,
             &self.hash.encode_hex::<String>(),
@@ -354,3 +357,29 @@ impl Display for Tree {
         Ok(())
     }
 }
+
+// Returns (mode, name, hash) for each entry of the tree content (without header)
+// Any mode is accepted. Used where only the links matter (gc, revisions)
+pub fn raw_entries(content: &[u8]) -> std::io::Result<Vec<(String, String, String)>> {
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < content.len() {
+        let space = content[i..]
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(|| std::io::Error::other("Invalid tree entry"))?;
+        let null = content[i + space..]
+            .iter()
+            .position(|&b| b == b'\0')
+            .ok_or_else(|| std::io::Error::other("Invalid tree entry"))?;
+        let mode = String::from_utf8_lossy(&content[i..i + space]).to_string();
+        let name = String::from_utf8_lossy(&content[i + space + 1..i + space + null]).to_string();
+        let hash_start = i + space + null + 1;
+        let hash = content
+            .get(hash_start..hash_start + 20)
+            .ok_or_else(|| std::io::Error::other("Truncated tree entry"))?;
+        entries.push((mode, name, hex::encode(hash)));
+        i = hash_start + 20;
+    }
+    Ok(entries)
+}
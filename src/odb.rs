@@ -0,0 +1,345 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use flate2::{Compress, Compression, write::ZlibEncoder};
+use thread_local::ThreadLocal;
+
+use crate::{
+    git_rust::RepoRust,
+    objects::{self, ObjectType},
+    pack::{self, reader},
+};
+
+#[cfg(test)]
+mod test;
+
+// Storage of the objects. Oids are the hex SHA-1 of the object, header included
+// Contents never include the header ("blob 12\0")
+pub trait ObjectDatabase {
+    // Returns NotFound if the object is not in the database
+    fn read(&self, oid: &str) -> std::io::Result<(ObjectType, Vec<u8>)>;
+    // Returns the oid. Writing an object that already exists does nothing
+    fn write(&self, object_type: ObjectType, content: &[u8]) -> std::io::Result<String>;
+    fn exists(&self, oid: &str) -> bool;
+    // The oids of all the objects, in no particular order
+    fn iter(&self) -> std::io::Result<Box<dyn Iterator<Item = String> + '_>>;
+    // The oids starting with prefix (lowercase hex), without listing every object when
+    // the storage can avoid it
+    fn with_prefix(&self, prefix: &str) -> std::io::Result<Vec<String>> {
+        Ok(self.iter()?.filter(|oid| oid.starts_with(prefix)).collect())
+    }
+}
+
+pub type SharedDatabase = Arc<dyn ObjectDatabase + Send + Sync>;
+
+// Replaces the database of the repo, for this thread
+static DATABASE: ThreadLocal<Mutex<Option<SharedDatabase>>> = ThreadLocal::new();
+
+// The database every command goes through
+// Loose objects and packs of the repo, unless another database was plugged in with set_database
+pub fn database() -> SharedDatabase {
+    let cell = DATABASE.get_or(|| Mutex::new(None));
+    match &*cell.lock().unwrap() {
+        Some(database) => database.clone(),
+        None => Arc::new(RepositoryDatabase::for_repo()),
+    }
+}
+
+#[allow(dead_code)]
+pub fn set_database(database: SharedDatabase) {
+    let cell = DATABASE.get_or(|| Mutex::new(None));
+    *cell.lock().unwrap() = Some(database);
+}
+
+// Back to the loose objects and packs of the repo
+pub fn reset_database() {
+    if let Some(cell) = DATABASE.get() {
+        cell.lock().unwrap().take();
+    }
+}
+
+fn not_found(oid: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("Object {oid} not found"),
+    )
+}
+
+// objects/xx/yyyy... zlib compressed, header included
+pub struct LooseDatabase {
+    objects_dir: PathBuf,
+}
+
+impl LooseDatabase {
+    pub fn new(objects_dir: &Path) -> Self {
+        Self {
+            objects_dir: objects_dir.to_path_buf(),
+        }
+    }
+
+    pub fn for_repo() -> Self {
+        Self::new(&RepoRust::get_object_folder(
+            &RepoRust::get_root().absolute_path,
+        ))
+    }
+
+    // Path of the file of the object. It may not exist
+    pub fn path(&self, oid: &str) -> Option<PathBuf> {
+        if oid.len() < 3 {
+            return None;
+        }
+        let (folder_name, file_name) = oid.split_at(2);
+        Some(self.objects_dir.join(folder_name).join(file_name))
+    }
+}
+
+impl ObjectDatabase for LooseDatabase {
+    fn read(&self, oid: &str) -> std::io::Result<(ObjectType, Vec<u8>)> {
+        let path = self
+            .path(oid)
+            .filter(|p| p.is_file())
+            .ok_or_else(|| not_found(oid))?;
+        let file = std::fs::read(path)?;
+        let mut object = Vec::new();
+        flate2::bufread::ZlibDecoder::new(file.as_slice()).read_to_end(&mut object)?;
+
+        let header = objects::Header::from_binary(&object)?;
+        let content = object[header.head_length() + 1..].to_vec();
+        if content.len() != header.size {
+            return Err(std::io::Error::other(format!(
+                "Object {oid} is corrupt: size {} in the header, {} read",
+                header.size,
+                content.len()
+            )));
+        }
+        Ok((header.object, content))
+    }
+
+    fn write(&self, object_type: ObjectType, content: &[u8]) -> std::io::Result<String> {
+        let oid = objects::hash_object(&object_type.to_string(), content);
+        let path = self.path(&oid).unwrap();
+        if path.exists() {
+            return Ok(oid);
+        }
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let new_object = std::fs::File::create(path)?;
+        let mut enc =
+            ZlibEncoder::new_with_compress(new_object, Compress::new(Compression::best(), true));
+        enc.write_all(format!("{object_type} {}\0", content.len()).as_bytes())?;
+        enc.write_all(content)?;
+        enc.finish()?;
+        Ok(oid)
+    }
+
+    fn exists(&self, oid: &str) -> bool {
+        self.path(oid).is_some_and(|p| p.is_file())
+    }
+
+    fn iter(&self) -> std::io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let mut oids = Vec::new();
+        if !self.objects_dir.is_dir() {
+            return Ok(Box::new(oids.into_iter()));
+        }
+        for folder in std::fs::read_dir(&self.objects_dir)? {
+            let folder = folder?;
+            let folder_name = folder.file_name().to_string_lossy().to_string();
+            // Skips pack and info
+            if folder_name.len() != 2 || !folder.path().is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(folder.path())? {
+                let oid = format!("{folder_name}{}", file?.file_name().to_string_lossy());
+                if oid.len() == 40 && oid.chars().all(|c| c.is_ascii_hexdigit()) {
+                    oids.push(oid);
+                }
+            }
+        }
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    // Only the folder of the first two chars is read
+    fn with_prefix(&self, prefix: &str) -> std::io::Result<Vec<String>> {
+        if prefix.len() < 2 {
+            return Ok(self.iter()?.filter(|oid| oid.starts_with(prefix)).collect());
+        }
+        let (folder_name, rest) = prefix.split_at(2);
+        let files = match std::fs::read_dir(self.objects_dir.join(folder_name)) {
+            Ok(files) => files,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut oids = Vec::new();
+        for file in files {
+            let file_name = file?.file_name().to_string_lossy().to_string();
+            if file_name.len() == 38 && file_name.starts_with(rest) {
+                oids.push(format!("{folder_name}{file_name}"));
+            }
+        }
+        Ok(oids)
+    }
+}
+
+// objects/pack/*.pack with their .idx. Read only, packs are written by repack and fetch
+pub struct PackedDatabase {
+    pack_dir: PathBuf,
+}
+
+impl PackedDatabase {
+    pub fn new(pack_dir: &Path) -> Self {
+        Self {
+            pack_dir: pack_dir.to_path_buf(),
+        }
+    }
+
+    pub fn for_repo() -> Self {
+        Self::new(&RepoRust::get_object_folder(&RepoRust::get_root().absolute_path).join("pack"))
+    }
+
+    // Path of the pack containing the object
+    pub fn find_pack(&self, oid: &str) -> Option<PathBuf> {
+        let hash = hash_bytes(oid)?;
+        reader::packs_in(&self.pack_dir)
+            .ok()?
+            .into_iter()
+            .find(|pack| pack.contains(&hash))
+            .map(|pack| pack.path.clone())
+    }
+}
+
+impl ObjectDatabase for PackedDatabase {
+    fn read(&self, oid: &str) -> std::io::Result<(ObjectType, Vec<u8>)> {
+        let hash = hash_bytes(oid).ok_or_else(|| not_found(oid))?;
+        for pack in reader::packs_in(&self.pack_dir)? {
+            if let Some(object) = pack.read_object(&hash) {
+                let (object_type, content) = object?;
+                let object_type = pack::type_name(object_type)
+                    .and_then(ObjectType::from_name)
+                    .ok_or_else(|| std::io::Error::other("Invalid object type in packfile"))?;
+                return Ok((object_type, content));
+            }
+        }
+        Err(not_found(oid))
+    }
+
+    fn write(&self, _object_type: ObjectType, _content: &[u8]) -> std::io::Result<String> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Objects cannot be added to a pack. Write them loose and repack",
+        ))
+    }
+
+    fn exists(&self, oid: &str) -> bool {
+        self.find_pack(oid).is_some()
+    }
+
+    fn iter(&self) -> std::io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let mut oids = Vec::new();
+        for pack in reader::packs_in(&self.pack_dir)? {
+            oids.extend(pack.index.hashes.iter().map(hex::encode));
+        }
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    fn with_prefix(&self, prefix: &str) -> std::io::Result<Vec<String>> {
+        let mut oids = Vec::new();
+        for pack in reader::packs_in(&self.pack_dir)? {
+            oids.extend(pack.index.with_prefix(prefix));
+        }
+        Ok(oids)
+    }
+}
+
+// Nothing touches the disk. Used by tooling and tests
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct MemoryDatabase {
+    objects: Mutex<HashMap<String, (ObjectType, Vec<u8>)>>,
+}
+
+impl ObjectDatabase for MemoryDatabase {
+    fn read(&self, oid: &str) -> std::io::Result<(ObjectType, Vec<u8>)> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(oid)
+            .cloned()
+            .ok_or_else(|| not_found(oid))
+    }
+
+    fn write(&self, object_type: ObjectType, content: &[u8]) -> std::io::Result<String> {
+        let oid = objects::hash_object(&object_type.to_string(), content);
+        self.objects
+            .lock()
+            .unwrap()
+            .entry(oid.clone())
+            .or_insert_with(|| (object_type, content.to_vec()));
+        Ok(oid)
+    }
+
+    fn exists(&self, oid: &str) -> bool {
+        self.objects.lock().unwrap().contains_key(oid)
+    }
+
+    fn iter(&self) -> std::io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let oids: Vec<String> = self.objects.lock().unwrap().keys().cloned().collect();
+        Ok(Box::new(oids.into_iter()))
+    }
+}
+
+// What git does: loose objects first, then the packs. New objects are written loose
+pub struct RepositoryDatabase {
+    pub loose: LooseDatabase,
+    pub packed: PackedDatabase,
+}
+
+impl RepositoryDatabase {
+    pub fn for_repo() -> Self {
+        Self {
+            loose: LooseDatabase::for_repo(),
+            packed: PackedDatabase::for_repo(),
+        }
+    }
+}
+
+impl ObjectDatabase for RepositoryDatabase {
+    fn read(&self, oid: &str) -> std::io::Result<(ObjectType, Vec<u8>)> {
+        match self.loose.read(oid) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.packed.read(oid),
+            result => result,
+        }
+    }
+
+    fn write(&self, object_type: ObjectType, content: &[u8]) -> std::io::Result<String> {
+        let oid = objects::hash_object(&object_type.to_string(), content);
+        if self.packed.exists(&oid) {
+            return Ok(oid);
+        }
+        self.loose.write(object_type, content)
+    }
+
+    fn exists(&self, oid: &str) -> bool {
+        self.loose.exists(oid) || self.packed.exists(oid)
+    }
+
+    // An object can be both loose and packed. It is returned once
+    fn iter(&self) -> std::io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let oids: BTreeSet<String> = self.loose.iter()?.chain(self.packed.iter()?).collect();
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    fn with_prefix(&self, prefix: &str) -> std::io::Result<Vec<String>> {
+        let mut oids = self.loose.with_prefix(prefix)?;
+        oids.extend(self.packed.with_prefix(prefix)?);
+        oids.sort();
+        oids.dedup();
+        Ok(oids)
+    }
+}
+
+fn hash_bytes(oid: &str) -> Option<[u8; 20]> {
+    hex::decode(oid).ok()?.try_into().ok()
+}
@@ -0,0 +1,134 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    gc::{self, RepackOptions},
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType, commit::Commit},
+    odb::{
+        self, LooseDatabase, MemoryDatabase, ObjectDatabase, PackedDatabase, RepositoryDatabase,
+    },
+    test_common::{run_test, run_test_matches},
+};
+
+#[test]
+fn test_repository_database_loose_and_packed() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let database = RepositoryDatabase::for_repo();
+        let blob = database.write(ObjectType::Blob, b"hello\n").unwrap();
+        // Same hash as git hash-object
+        assert_eq!(blob, "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert!(database.loose.exists(&blob));
+        assert!(!database.packed.exists(&blob));
+        let (object_type, content) = database.read(&blob).unwrap();
+        assert_eq!(object_type, ObjectType::Blob);
+        assert_eq!(content, b"hello\n");
+
+        let missing = "0123456789012345678901234567890123456789";
+        assert_eq!(
+            database.read(missing).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert!(!database.exists(missing));
+
+        // Move it to a pack. Reads fall back to the pack
+        crate::refs::write_ref("refs/tags/blob", &blob).unwrap();
+        gc::repack(&RepackOptions {
+            delete_redundant: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!database.loose.exists(&blob));
+        assert!(database.packed.exists(&blob));
+        assert_eq!(database.read(&blob).unwrap().1, b"hello\n");
+        assert_eq!(
+            database.iter().unwrap().collect::<Vec<_>>(),
+            vec![blob.clone()]
+        );
+
+        // Packs are read only. Objects already packed are not written again
+        assert_eq!(
+            PackedDatabase::for_repo()
+                .write(ObjectType::Blob, b"new")
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+        database.write(ObjectType::Blob, b"hello\n").unwrap();
+        assert!(!database.loose.exists(&blob));
+
+        // Prefix lookups see loose and packed objects
+        let loose = database.write(ObjectType::Blob, b"loose\n").unwrap();
+        assert_eq!(
+            database.with_prefix(&blob[..7]).unwrap(),
+            vec![blob.clone()]
+        );
+        assert_eq!(
+            database.with_prefix(&loose[..2]).unwrap(),
+            vec![loose.clone()]
+        );
+        let mut all = vec![blob.clone(), loose.clone()];
+        all.sort();
+        assert_eq!(database.with_prefix("").unwrap(), all);
+        assert!(database.with_prefix(&missing[..6]).unwrap().is_empty());
+    });
+}
+
+#[test]
+fn test_loose_database_rejects_corrupt_objects() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        let loose = LooseDatabase::new(&path);
+        let blob = loose.write(ObjectType::Blob, b"some content").unwrap();
+
+        // Header says 5 bytes
+        let file = loose.path(&blob).unwrap();
+        let mut enc = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut enc, b"blob 5\0some content").unwrap();
+        std::fs::write(&file, enc.finish().unwrap()).unwrap();
+        assert!(loose.read(&blob).is_err());
+        assert_eq!(loose.iter().unwrap().collect::<Vec<_>>(), vec![blob]);
+    });
+}
+
+#[test]
+fn test_commands_use_plugged_in_database() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let memory = Arc::new(MemoryDatabase::default());
+        odb::set_database(memory.clone());
+
+        std::fs::write(path.join("test1.txt"), b"this is a test").unwrap();
+        let add_args = run_test_matches(vec!["", "add", "test1.txt"]);
+        RepoRust::add(&add_args).unwrap();
+        let commit_args = run_test_matches(vec!["", "commit", "-m", "Test commit"]);
+        RepoRust::commit(&commit_args).unwrap();
+        let head = crate::refs::read_ref("HEAD").unwrap().unwrap();
+
+        // Blob, tree and commit. Nothing was written to objects
+        assert_eq!(memory.iter().unwrap().count(), 3);
+        assert!(memory.exists(&head));
+        assert_eq!(
+            std::fs::read_dir(path.join(BASE_DIR).join("objects"))
+                .unwrap()
+                .count(),
+            0
+        );
+        let tree = Commit::get_tree_from_commit(&head).unwrap();
+        assert_eq!(memory.read(&tree).unwrap().0, ObjectType::Tree);
+        let args = run_test_matches(vec!["", "cat-file", "-p", &head]);
+        assert!(RepoRust::cat_file(&args).is_ok());
+
+        // Back to the files of the repo
+        odb::reset_database();
+        assert!(!objects::object_exists(&head));
+    });
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::{Crc, read::ZlibDecoder};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    objects::{self, ObjectType},
+    pack::{delta::apply_delta, idx::PackIndex, reader::read_base_outside_pack},
+    requests::GitObject,
+};
+
+pub mod delta;
+pub mod idx;
+pub mod reader;
+pub mod writer;
+
+#[cfg(test)]
+mod test;
+
+// Object types as stored in the pack entries
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+// Name used in the header of the loose object
+// Deltas have no name
+pub fn type_name(object_type: u8) -> Option<&'static str> {
+    match object_type {
+        OBJ_COMMIT => Some("commit"),
+        OBJ_TREE => Some("tree"),
+        OBJ_BLOB => Some("blob"),
+        OBJ_TAG => Some("tag"),
+        _ => None,
+    }
+}
+
+pub fn type_code(object_type: &ObjectType) -> u8 {
+    match object_type {
+        ObjectType::Commit => OBJ_COMMIT,
+        ObjectType::Tree => OBJ_TREE,
+        ObjectType::Blob => OBJ_BLOB,
+        ObjectType::Tag => OBJ_TAG,
+    }
+}
+
+// The offset of an ofs-delta is a big-endian varint
+// Each continuation adds 1 before shifting, so that there is only one way to encode a number
+pub fn read_ofs_delta_offset(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u64> {
+    let mut byte = cursor.read_u8()?;
+    let mut offset = (byte & 0x7F) as u64;
+    while byte & 0x80 != 0 {
+        byte = cursor.read_u8()?;
+        offset = offset
+            .checked_add(1)
+            .filter(|offset| offset.leading_zeros() >= 7)
+            .map(|offset| (offset << 7) | (byte & 0x7F) as u64)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "ofs-delta offset too large",
+                )
+            })?;
+    }
+    Ok(offset)
+}
+
+pub fn read_type_and_size(cursor: &mut Cursor<&[u8]>) -> std::io::Result<(u8, usize)> {
+    let mut size: usize;
+    let mut shift = 4;
+    let mut first_byte = [0u8; 1];
+
+    cursor.read_exact(&mut first_byte)?;
+    let byte = first_byte[0];
+    let object_type = (byte >> 4) & 0b111;
+    size = (byte & 0x0F) as usize;
+
+    // 0x80 = 10000000
+    if byte & 0x80 != 0 {
+        loop {
+            // Read the continuation byte again
+            let mut b = [0u8; 1];
+            cursor.read_exact(&mut b)?;
+
+            // More continuation bytes than a usize holds
+            if shift >= usize::BITS {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Object size too large",
+                ));
+            }
+            // 0x7F = 01111111
+            size |= ((b[0] & 0x7F) as usize) << shift;
+            shift += 7;
+            if b[0] & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    Ok((object_type, size))
+}
+
+// Process the raw packfile
+// The format of the raw packfile:
+// 4 bytes - the word "PACK"
+// 4 bytes - the version. Usually 0002
+// 4 bytes - number of objects (big endian)
+// the rest - Object entries
+// last 20 bytes - SHA-1 checksum
+//
+// Format of object entries:
+// Header + Zlib compressed data
+// Header:
+// 7 6 5 4 3 2 1 0
+// C T T T S S S S
+// bites 0-3 - Size bits (S)
+// bites 4-6 - Object type (T)
+// bit   7   - Continuation bit (C)
+// Object types: 1 = commit / 2- tree / 3 - blob / 4 - tag / 6 - ofs-delta / 7 ref-delta
+//
+// Example 1 - 0b01100010
+// bites 0-3 -> 0100 (2 in decimal) -> check the continuation bit
+// bites 4-6 -> 011 -> (3 in decimal) Blob
+// bit   7   -> 0 -> stop reading header
+// When continuation bit 1 you, read another 7 bits of the size from the next byte
+// Example 2 - 0b111110100
+// Next byte :
+// bit   7   - another continuation bit
+// bites 0-6 - next 7 bits of the size value
+// Calculating the size. Example:
+// Byte 1: 0b10010011 -> size bits 0b0011 (3 in decimal)
+// Byte 2: 0b10000101 -> size bits 0b0000101 (5 in decimal)
+// Byte 3: 0b00000010 -> size bits 0b0000010 (2 in decimal)
+// Shifting:
+// 17 16 15 14 13 12 11 10 09 08 07 06 05 04 03 02 01 00
+//  0  0  0  0  0  1  0  0  0  0  0  1  0  1  0  0  1  1 -> 4179 in decimal
+// |Bytes 3              |Byte 2               |Byte 1  |
+//    (0b0000010 << 11)  +   (0000101 << 4)    +  0011
+//
+// Deltas (6 and 7) are resolved against their base once all the entries are read
+// ofs-delta -> the base is at <offset of this entry> - <offset>. Always earlier in the pack
+// ref-delta -> the base is the object with that hash. In the pack or already in the repo
+pub fn read_entries(packfile: &[u8]) -> std::io::Result<Vec<PackEntry>> {
+    let mut cursor = Cursor::new(packfile);
+
+    let mut pack = [0u8; 4];
+    cursor.read_exact(&mut pack)?;
+
+    if &pack != b"PACK" {
+        return Err(std::io::Error::other("Invalid packfile header"));
+    }
+
+    let version = cursor.read_u32::<BigEndian>()?;
+    if version != 2 && version != 3 {
+        return Err(std::io::Error::other("Invalid packfile version"));
+    }
+
+    let object_count = cursor.read_u32::<BigEndian>()?;
+    // The count is not trusted: every entry takes at least a byte of the pack
+    let mut entries: Vec<PackEntry> =
+        Vec::with_capacity((object_count as usize).min(packfile.len()));
+
+    for _ in 0..object_count {
+        let entry_offset = cursor.position();
+        let (object_type, size) = read_type_and_size(&mut cursor)?;
+
+        let base = match object_type {
+            OBJ_OFS_DELTA => {
+                let offset = read_ofs_delta_offset(&mut cursor)?;
+                let base_offset = entry_offset.checked_sub(offset).ok_or_else(|| {
+                    std::io::Error::other("ofs-delta base offset out of the packfile")
+                })?;
+                Some(DeltaBase::Offset(base_offset))
+            }
+            OBJ_REF_DELTA => {
+                let mut base_id = [0u8; 20];
+                cursor.read_exact(&mut base_id)?;
+                Some(DeltaBase::Hash(hex::encode(base_id)))
+            }
+            _ => None,
+        };
+
+        let start_offset = cursor.position();
+
+        let remaining = &packfile[start_offset as usize..];
+        let mut decoder = ZlibDecoder::new(remaining);
+
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data)?;
+
+        let consumed = decoder.total_in();
+
+        cursor.set_position(start_offset + consumed);
+
+        // The CRC covers the whole entry, as stored in the pack
+        let mut crc = Crc::new();
+        crc.update(&packfile[entry_offset as usize..cursor.position() as usize]);
+
+        entries.push(PackEntry {
+            offset: entry_offset,
+            crc: crc.sum(),
+            object: GitObject {
+                object_type,
+                size,
+                data,
+            },
+            base,
+        });
+    }
+    Ok(entries)
+}
+
+pub struct PackEntry {
+    pub offset: u64,
+    pub crc: u32,
+    pub object: GitObject,
+    pub base: Option<DeltaBase>,
+}
+
+pub enum DeltaBase {
+    Offset(u64),
+    Hash(String),
+}
+
+pub struct ResolvedObject {
+    pub offset: u64,
+    pub crc: u32,
+    pub hash: String,
+    pub object: GitObject,
+}
+
+// Replaces every delta with the object it represents
+// A base can be a delta itself, so keep going until nothing else can be resolved
+// The objects are returned in the same order as in the pack
+pub fn resolve_deltas(entries: Vec<PackEntry>) -> std::io::Result<Vec<ResolvedObject>> {
+    let offsets: HashMap<u64, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.offset, i))
+        .collect();
+    let mut hashes: HashMap<String, usize> = HashMap::new();
+    let mut resolved: Vec<Option<ResolvedObject>> = Vec::with_capacity(entries.len());
+    let mut pending: Vec<(usize, PackEntry)> = Vec::new();
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        if entry.base.is_some() {
+            pending.push((i, entry));
+            resolved.push(None);
+            continue;
+        }
+        let kind = entry
+            .object
+            .kind()
+            .ok_or_else(|| std::io::Error::other("Invalid object type in packfile"))?;
+        let hash = objects::hash_object(kind, &entry.object.data);
+        hashes.insert(hash.clone(), i);
+        resolved.push(Some(ResolvedObject {
+            offset: entry.offset,
+            crc: entry.crc,
+            hash,
+            object: entry.object,
+        }));
+    }
+
+    while !pending.is_empty() {
+        let mut still_pending = Vec::new();
+        let count = pending.len();
+        for (i, entry) in pending {
+            let base_object = match entry.base.as_ref().unwrap() {
+                DeltaBase::Offset(offset) => {
+                    let index = offsets.get(offset).ok_or_else(|| {
+                        std::io::Error::other(format!("Missing delta base at offset {offset}"))
+                    })?;
+                    resolved[*index]
+                        .as_ref()
+                        .map(|o| (o.object.object_type, o.object.data.clone()))
+                }
+                DeltaBase::Hash(hash) => match hashes.get(hash) {
+                    Some(index) => resolved[*index]
+                        .as_ref()
+                        .map(|o| (o.object.object_type, o.object.data.clone())),
+                    // Thin packs can use objects we already have as base
+                    None => read_base_outside_pack(hash).ok(),
+                },
+            };
+            let Some((object_type, base_data)) = base_object else {
+                still_pending.push((i, entry));
+                continue;
+            };
+            let data = apply_delta(&base_data, &entry.object.data)?;
+            let object = GitObject {
+                object_type,
+                size: data.len(),
+                data,
+            };
+            let hash = objects::hash_object(object.kind().unwrap(), &object.data);
+            hashes.insert(hash.clone(), i);
+            resolved[i] = Some(ResolvedObject {
+                offset: entry.offset,
+                crc: entry.crc,
+                hash,
+                object,
+            });
+        }
+        // No progress means the remaining bases are not in the pack
+        if still_pending.len() == count {
+            let missing = match still_pending[0].1.base.as_ref().unwrap() {
+                DeltaBase::Offset(offset) => format!("at offset {offset}"),
+                DeltaBase::Hash(hash) => hash.clone(),
+            };
+            return Err(std::io::Error::other(format!(
+                "Missing delta base {missing}"
+            )));
+        }
+        pending = still_pending;
+    }
+
+    Ok(resolved.into_iter().map(|o| o.unwrap()).collect())
+}
+
+// Checks the trailing checksum, resolves every object and writes the .idx next to the .pack
+// Returns the checksum of the pack, as hex
+pub fn index_pack(pack_path: &Path) -> std::io::Result<String> {
+    let packfile = std::fs::read(pack_path)?;
+    let checksum = verify_checksum(&packfile)?;
+    let objects = resolve_deltas(read_entries(&packfile)?)?;
+    let index = PackIndex::from_objects(&objects, checksum)?;
+    std::fs::write(pack_path.with_extension("idx"), index.to_bytes())?;
+    Ok(hex::encode(checksum))
+}
+
+// The last 20 bytes of a pack are the SHA-1 of everything before them
+pub fn verify_checksum(packfile: &[u8]) -> std::io::Result<[u8; 20]> {
+    if packfile.len() < 32 {
+        return Err(std::io::Error::other("Packfile too short"));
+    }
+    let (content, checksum) = packfile.split_at(packfile.len() - 20);
+    let mut hasher = Sha1::new();
+    hasher.update(content);
+    let expected: [u8; 20] = hasher.finalize().into();
+    if expected != checksum {
+        return Err(std::io::Error::other(format!(
+            "Packfile checksum mismatch: expected {}, got {}",
+            hex::encode(expected),
+            hex::encode(checksum)
+        )));
+    }
+    Ok(expected)
+}
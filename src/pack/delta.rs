@@ -0,0 +1,219 @@
+// Format of a delta (the data of ofs-delta and ref-delta entries, once decompressed):
+// varint - size of the base object
+// varint - size of the object after applying the delta
+// the rest - instructions
+//
+// The sizes are little-endian varints. 7 bits per byte, bit 7 is the continuation bit
+//
+// Instructions:
+// Copy   -> 1xxxxxxx followed by up to 4 offset bytes and up to 3 size bytes
+//           bits 0-3 say which offset bytes follow, bits 4-6 say which size bytes follow
+//           Missing bytes are 0. A size of 0 means 0x10000
+//           Copies size bytes from the base object, starting at offset
+// Insert -> 0xxxxxxx followed by xxxxxxx bytes of data (1 to 127)
+//           The data is added as is to the new object
+// 0x00 is reserved and invalid
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut i = 0;
+    let base_size = read_size(delta, &mut i)?;
+    if base_size != base.len() {
+        return Err(std::io::Error::other(format!(
+            "Delta base size mismatch: expected {base_size}, got {}",
+            base.len()
+        )));
+    }
+    let result_size = read_size(delta, &mut i)?;
+    // The size comes from the delta, it is only trusted as far as the base and the inserts go
+    let mut result: Vec<u8> = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+
+    while i < delta.len() {
+        let instruction = delta[i];
+        i += 1;
+        if instruction & 0x80 != 0 {
+            // Copy from the base
+            let mut offset = 0usize;
+            for bit in 0..4 {
+                if instruction & (1 << bit) != 0 {
+                    offset |= (*delta.get(i).ok_or_else(truncated)? as usize) << (8 * bit);
+                    i += 1;
+                }
+            }
+            let mut size = 0usize;
+            for bit in 0..3 {
+                if instruction & (1 << (4 + bit)) != 0 {
+                    size |= (*delta.get(i).ok_or_else(truncated)? as usize) << (8 * bit);
+                    i += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let chunk = base
+                .get(offset..offset + size)
+                .ok_or_else(|| std::io::Error::other("Delta copy out of the base object"))?;
+            result.extend_from_slice(chunk);
+        } else if instruction != 0 {
+            // Insert new data
+            let size = instruction as usize;
+            let chunk = delta.get(i..i + size).ok_or_else(truncated)?;
+            result.extend_from_slice(chunk);
+            i += size;
+        } else {
+            return Err(std::io::Error::other("Invalid delta instruction 0x00"));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(std::io::Error::other(format!(
+            "Delta result size mismatch: expected {result_size}, got {}",
+            result.len()
+        )));
+    }
+    Ok(result)
+}
+
+// Reads a little-endian varint used for the sizes in the delta header
+pub fn read_size(delta: &[u8], i: &mut usize) -> std::io::Result<usize> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        // More continuation bytes than a usize holds
+        if shift >= usize::BITS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Delta size too large",
+            ));
+        }
+        let byte = *delta.get(*i).ok_or_else(truncated)?;
+        *i += 1;
+        size |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(size)
+}
+
+fn truncated() -> std::io::Error {
+    std::io::Error::other("Truncated delta")
+}
+
+// Blocks of the base are indexed by their content
+// Matches shorter than a block are not worth a copy instruction
+const BLOCK_SIZE: usize = 16;
+// Largest size a single copy instruction can hold with the size 0 = 0x10000 rule
+const MAX_COPY_SIZE: usize = 0x10000;
+// An insert instruction holds at most 127 bytes
+const MAX_INSERT_SIZE: usize = 0x7F;
+// Candidates checked for each block. Keeps highly repetitive content from going quadratic
+const MAX_CANDIDATES: usize = 8;
+
+// Creates a delta that turns base into target. See apply_delta for the format
+// Every block of the base is indexed. For each position in the target,
+// the block starting there is looked up and the longest match is extended forward
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta: Vec<u8> = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let mut blocks: std::collections::HashMap<&[u8], Vec<usize>> = std::collections::HashMap::new();
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= base.len() {
+        let candidates = blocks
+            .entry(&base[offset..offset + BLOCK_SIZE])
+            .or_default();
+        if candidates.len() < MAX_CANDIDATES {
+            candidates.push(offset);
+        }
+        offset += BLOCK_SIZE;
+    }
+
+    let mut insert: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < target.len() {
+        let mut best: Option<(usize, usize)> = None;
+        if i + BLOCK_SIZE <= target.len()
+            && let Some(candidates) = blocks.get(&target[i..i + BLOCK_SIZE])
+        {
+            for &start in candidates {
+                let length = base[start..]
+                    .iter()
+                    .zip(&target[i..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if best.is_none_or(|(_, best_length)| length > best_length) {
+                    best = Some((start, length));
+                }
+            }
+        }
+        match best {
+            Some((start, length)) if length >= BLOCK_SIZE => {
+                flush_insert(&mut delta, &mut insert);
+                write_copy(&mut delta, start, length);
+                i += length;
+            }
+            _ => {
+                insert.push(target[i]);
+                if insert.len() == MAX_INSERT_SIZE {
+                    flush_insert(&mut delta, &mut insert);
+                }
+                i += 1;
+            }
+        }
+    }
+    flush_insert(&mut delta, &mut insert);
+    delta
+}
+
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let mut byte = (size & 0x7F) as u8;
+        size >>= 7;
+        if size != 0 {
+            byte |= 0x80;
+        }
+        delta.push(byte);
+        if size == 0 {
+            break;
+        }
+    }
+}
+
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    if insert.is_empty() {
+        return;
+    }
+    delta.push(insert.len() as u8);
+    delta.append(insert);
+}
+
+// Long matches are split in several copy instructions
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut length: usize) {
+    while length > 0 {
+        let size = length.min(MAX_COPY_SIZE);
+        let mut instruction = 0x80u8;
+        let mut args: Vec<u8> = Vec::new();
+        for bit in 0..4 {
+            let byte = ((offset >> (8 * bit)) & 0xFF) as u8;
+            if byte != 0 {
+                instruction |= 1 << bit;
+                args.push(byte);
+            }
+        }
+        // A size of 0x10000 is written as no size bytes
+        if size != MAX_COPY_SIZE {
+            for bit in 0..3 {
+                let byte = ((size >> (8 * bit)) & 0xFF) as u8;
+                if byte != 0 {
+                    instruction |= 1 << (4 + bit);
+                    args.push(byte);
+                }
+            }
+        }
+        delta.push(instruction);
+        delta.extend_from_slice(&args);
+        offset += size;
+        length -= size;
+    }
+}
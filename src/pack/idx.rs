@@ -0,0 +1,210 @@
+// Format of a pack index (.idx) version 2. All numbers are big-endian:
+// 4 bytes          - magic number \377tOc
+// 4 bytes          - version. Always 2
+// 256 * 4 bytes    - fanout table. Entry N is the number of objects whose first byte is <= N
+//                    The last entry is the total number of objects
+// N * 20 bytes     - object names, sorted
+// N * 4 bytes      - CRC32 of the packed data of each object
+// N * 4 bytes      - offset of each object in the pack
+//                    If the MSB is set, the other 31 bits are an index in the next table
+// M * 8 bytes      - 64 bit offsets, for packs bigger than 2GB
+// 20 bytes         - SHA-1 checksum of the pack
+// 20 bytes         - SHA-1 checksum of all of the above
+use sha1::{Digest, Sha1};
+
+use crate::pack::ResolvedObject;
+
+pub const IDX_MAGIC: [u8; 4] = [0xFF, b't', b'O', b'c'];
+
+#[allow(dead_code)]
+pub struct PackIndex {
+    pub fanout: [u32; 256],
+    pub hashes: Vec<[u8; 20]>,
+    pub crcs: Vec<u32>,
+    pub offsets: Vec<u64>,
+    pub pack_checksum: [u8; 20],
+}
+
+impl PackIndex {
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<Self> {
+        if bytes.len() < 8 + 256 * 4 + 40 || bytes[..4] != IDX_MAGIC {
+            return Err(std::io::Error::other("Invalid pack index header"));
+        }
+        let version = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        if version != 2 {
+            return Err(std::io::Error::other(format!(
+                "Unsupported pack index version {version}"
+            )));
+        }
+
+        let mut fanout = [0u32; 256];
+        for (i, entry) in fanout.iter_mut().enumerate() {
+            let start = 8 + i * 4;
+            *entry = u32::from_be_bytes(bytes[start..start + 4].try_into().unwrap());
+        }
+        // Each entry counts the objects of the ones before, so it never decreases. The
+        // last one is the number of objects, so no range in find goes past the hashes
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(std::io::Error::other("Invalid fanout table in pack index"));
+        }
+        let count = fanout[255] as usize;
+
+        let hashes_start = 8 + 256 * 4;
+        let crcs_start = hashes_start + count * 20;
+        let offsets_start = crcs_start + count * 4;
+        let large_offsets_start = offsets_start + count * 4;
+        if bytes.len() < large_offsets_start + 40 {
+            return Err(std::io::Error::other("Truncated pack index"));
+        }
+
+        let mut hashes = Vec::with_capacity(count);
+        let mut crcs = Vec::with_capacity(count);
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = hashes_start + i * 20;
+            hashes.push(bytes[start..start + 20].try_into().unwrap());
+            let start = crcs_start + i * 4;
+            crcs.push(u32::from_be_bytes(
+                bytes[start..start + 4].try_into().unwrap(),
+            ));
+
+            let start = offsets_start + i * 4;
+            let offset = u32::from_be_bytes(bytes[start..start + 4].try_into().unwrap());
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(offset as u64);
+            } else {
+                let large_index = (offset & 0x7FFF_FFFF) as usize;
+                let start = large_offsets_start + large_index * 8;
+                let large = bytes
+                    .get(start..start + 8)
+                    .ok_or_else(|| std::io::Error::other("Invalid large offset in pack index"))?;
+                offsets.push(u64::from_be_bytes(large.try_into().unwrap()));
+            }
+        }
+
+        let checksum_start = bytes.len() - 40;
+        let pack_checksum = bytes[checksum_start..checksum_start + 20]
+            .try_into()
+            .unwrap();
+
+        Ok(Self {
+            fanout,
+            hashes,
+            crcs,
+            offsets,
+            pack_checksum,
+        })
+    }
+
+    // The fanout table gives the range of hashes starting with the same byte
+    // Binary search inside that range
+    pub fn find(&self, hash: &[u8; 20]) -> Option<u64> {
+        let first = hash[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let end = self.fanout[first] as usize;
+        let position = self.hashes[start..end].binary_search(hash).ok()?;
+        Some(self.offsets[start + position])
+    }
+
+    // The hashes starting with prefix (hex). The fanout table gives the range of the
+    // first byte, the rest is compared as text
+    pub fn with_prefix(&self, prefix: &str) -> Vec<String> {
+        let range = match prefix
+            .get(..2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        {
+            Some(first) => {
+                let first = first as usize;
+                let start = match first {
+                    0 => 0,
+                    _ => self.fanout[first - 1] as usize,
+                };
+                start..self.fanout[first] as usize
+            }
+            None => 0..self.hashes.len(),
+        };
+        self.hashes[range]
+            .iter()
+            .map(hex::encode)
+            .filter(|oid| oid.starts_with(prefix))
+            .collect()
+    }
+
+    // Builds the index from the objects of a pack. They do not need to be sorted
+    pub fn from_objects(
+        objects: &[ResolvedObject],
+        pack_checksum: [u8; 20],
+    ) -> std::io::Result<Self> {
+        let mut entries: Vec<([u8; 20], u32, u64)> = Vec::with_capacity(objects.len());
+        for object in objects {
+            let hash: [u8; 20] = hex::decode(&object.hash)
+                .ok()
+                .and_then(|h| h.try_into().ok())
+                .ok_or_else(|| std::io::Error::other("Invalid object hash"))?;
+            entries.push((hash, object.crc, object.offset));
+        }
+        Ok(Self::from_entries(entries, pack_checksum))
+    }
+
+    // Each entry is the hash, the CRC and the offset of an object in the pack
+    pub fn from_entries(mut entries: Vec<([u8; 20], u32, u64)>, pack_checksum: [u8; 20]) -> Self {
+        entries.sort_by_key(|e| e.0);
+        entries.dedup_by(|a, b| a.0 == b.0);
+
+        let mut fanout = [0u32; 256];
+        for (hash, _, _) in &entries {
+            fanout[hash[0] as usize] += 1;
+        }
+        for i in 1..256 {
+            fanout[i] += fanout[i - 1];
+        }
+
+        Self {
+            fanout,
+            hashes: entries.iter().map(|e| e.0).collect(),
+            crcs: entries.iter().map(|e| e.1).collect(),
+            offsets: entries.iter().map(|e| e.2).collect(),
+            pack_checksum,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        buf.extend_from_slice(&IDX_MAGIC);
+        buf.extend_from_slice(&2u32.to_be_bytes());
+        for count in &self.fanout {
+            buf.extend_from_slice(&count.to_be_bytes());
+        }
+        for hash in &self.hashes {
+            buf.extend_from_slice(hash);
+        }
+        for crc in &self.crcs {
+            buf.extend_from_slice(&crc.to_be_bytes());
+        }
+        // Offsets that do not fit in 31 bits go in the 64 bit table
+        let mut large_offsets: Vec<u64> = Vec::new();
+        for offset in &self.offsets {
+            if *offset < 0x8000_0000 {
+                buf.extend_from_slice(&(*offset as u32).to_be_bytes());
+            } else {
+                let large_index = large_offsets.len() as u32 | 0x8000_0000;
+                buf.extend_from_slice(&large_index.to_be_bytes());
+                large_offsets.push(*offset);
+            }
+        }
+        for offset in large_offsets {
+            buf.extend_from_slice(&offset.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.pack_checksum);
+
+        let mut hasher = Sha1::new();
+        hasher.update(&buf);
+        let checksum = hasher.finalize();
+        buf.extend_from_slice(&checksum);
+        buf
+    }
+}
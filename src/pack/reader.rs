@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use flate2::read::ZlibDecoder;
+use thread_local::ThreadLocal;
+
+use crate::{
+    git_rust::RepoRust,
+    odb,
+    pack::{
+        OBJ_OFS_DELTA, OBJ_REF_DELTA, delta::apply_delta, idx::PackIndex, read_ofs_delta_offset,
+        read_type_and_size, type_code,
+    },
+};
+
+// Packs are never modified once written, so they are kept in memory after the first read
+// Keyed by the path of the .pack file
+static PACKS: ThreadLocal<Mutex<HashMap<PathBuf, Arc<PackFile>>>> = ThreadLocal::new();
+// The packs of each pack folder, with the modification time of the folder when it was
+// read. Adding or removing a pack changes it, so the folder is only read again then
+static PACK_LISTS: ThreadLocal<Mutex<HashMap<PathBuf, PackList>>> = ThreadLocal::new();
+
+type PackList = (SystemTime, Vec<Arc<PackFile>>);
+
+// Longest delta chain git reads (MAX_DEPTH in pack-objects)
+pub const MAX_DELTA_DEPTH: usize = 4095;
+
+// A .pack file and its .idx
+pub struct PackFile {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+    pub index: PackIndex,
+}
+
+impl PackFile {
+    pub fn open(pack_path: &Path) -> std::io::Result<Self> {
+        let data = std::fs::read(pack_path)?;
+        if data.len() < 32 || &data[..4] != b"PACK" {
+            return Err(std::io::Error::other("Invalid packfile header"));
+        }
+        let index_bytes = std::fs::read(pack_path.with_extension("idx"))?;
+        let index = PackIndex::from_bytes(&index_bytes)?;
+        if index.pack_checksum != data[data.len() - 20..] {
+            return Err(std::io::Error::other(format!(
+                "Pack index does not match {}",
+                pack_path.display()
+            )));
+        }
+        Ok(Self {
+            path: pack_path.to_path_buf(),
+            data,
+            index,
+        })
+    }
+
+    pub fn contains(&self, hash: &[u8; 20]) -> bool {
+        self.index.find(hash).is_some()
+    }
+
+    // Returns the type (1-4) and the content of the object
+    pub fn read_object(&self, hash: &[u8; 20]) -> Option<std::io::Result<(u8, Vec<u8>)>> {
+        let offset = self.index.find(hash)?;
+        Some(self.read_at(offset))
+    }
+
+    // Reads the entry at offset. Deltas are applied on their base, recursively
+    pub fn read_at(&self, offset: u64) -> std::io::Result<(u8, Vec<u8>)> {
+        self.read_at_depth(offset, 0)
+    }
+
+    // depth is the number of deltas above this entry. A chain cannot be longer than the
+    // objects in the pack, nor than git allows, so a longer one is a loop (a delta on itself)
+    fn read_at_depth(&self, offset: u64, depth: usize) -> std::io::Result<(u8, Vec<u8>)> {
+        if depth > MAX_DELTA_DEPTH.min(self.index.hashes.len()) {
+            return Err(std::io::Error::other(format!(
+                "Delta chain too deep at offset {offset} in {}",
+                self.path.display()
+            )));
+        }
+        let mut cursor = Cursor::new(self.data.as_slice());
+        cursor.set_position(offset);
+        let (object_type, _size) = read_type_and_size(&mut cursor)?;
+        let base = match object_type {
+            OBJ_OFS_DELTA => {
+                let base_offset = offset
+                    .checked_sub(read_ofs_delta_offset(&mut cursor)?)
+                    .ok_or_else(|| std::io::Error::other("ofs-delta base out of the packfile"))?;
+                Some(self.read_at_depth(base_offset, depth + 1)?)
+            }
+            OBJ_REF_DELTA => {
+                let mut base_id = [0u8; 20];
+                cursor.read_exact(&mut base_id)?;
+                let base = match self.index.find(&base_id) {
+                    Some(base_offset) => self.read_at_depth(base_offset, depth + 1)?,
+                    None => read_base_outside_pack(&hex::encode(base_id))?,
+                };
+                Some(base)
+            }
+            _ => None,
+        };
+
+        let start = cursor.position() as usize;
+        let mut decoder = ZlibDecoder::new(&self.data[start..]);
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data)?;
+
+        match base {
+            Some((base_type, base_data)) => Ok((base_type, apply_delta(&base_data, &data)?)),
+            None => Ok((object_type, data)),
+        }
+    }
+}
+
+// Base of a ref-delta that lives in another pack or is loose
+// Returns the type (1-4) and the content of the object
+pub fn read_base_outside_pack(hash: &str) -> std::io::Result<(u8, Vec<u8>)> {
+    let (object_type, content) = odb::database()
+        .read(hash)
+        .map_err(|_| std::io::Error::other(format!("Missing delta base {hash}")))?;
+    Ok((type_code(&object_type), content))
+}
+
+// All the packs in objects/pack of the repo that have an index
+pub fn packs() -> std::io::Result<Vec<Arc<PackFile>>> {
+    packs_in(&RepoRust::get_object_folder(&RepoRust::get_root().absolute_path).join("pack"))
+}
+
+pub fn packs_in(pack_dir: &Path) -> std::io::Result<Vec<Arc<PackFile>>> {
+    let modified = match std::fs::metadata(pack_dir) {
+        Ok(metadata) if metadata.is_dir() => metadata.modified()?,
+        Ok(_) => return Ok(Vec::new()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let lists = PACK_LISTS.get_or(|| Mutex::new(HashMap::new()));
+    let mut lists = lists.lock().unwrap();
+    if let Some((time, packs)) = lists.get(pack_dir)
+        && *time == modified
+    {
+        return Ok(packs.clone());
+    }
+
+    let cache = PACKS.get_or(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap();
+
+    let mut packs = Vec::new();
+    for entry in std::fs::read_dir(pack_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "pack") || !path.with_extension("idx").exists()
+        {
+            continue;
+        }
+        let pack = match cache.get(&path) {
+            Some(pack) => pack.clone(),
+            None => {
+                let pack = Arc::new(PackFile::open(&path)?);
+                cache.insert(path, pack.clone());
+                pack
+            }
+        };
+        packs.push(pack);
+    }
+    lists.insert(pack_dir.to_path_buf(), (modified, packs.clone()));
+    Ok(packs)
+}
+
+// Drops the packs kept in memory. Used after packs are deleted
+pub fn clear_cache() {
+    if let Some(cache) = PACKS.get() {
+        cache.lock().unwrap().clear();
+    }
+    if let Some(lists) = PACK_LISTS.get() {
+        lists.lock().unwrap().clear();
+    }
+}
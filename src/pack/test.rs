@@ -0,0 +1,383 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, blob::Blob, commit::Commit, tree::Tree},
+    odb::PackedDatabase,
+    pack::{
+        OBJ_BLOB, OBJ_OFS_DELTA, OBJ_REF_DELTA,
+        delta::{apply_delta, create_delta, read_size},
+        idx::PackIndex,
+        index_pack, read_entries, read_ofs_delta_offset, read_type_and_size,
+        reader::{PackFile, packs_in},
+        resolve_deltas,
+        writer::{PackObject, name_hash, write_pack},
+    },
+    test_common::{git2_commit_files, run_test},
+};
+
+#[test]
+fn test_apply_delta_copy_and_insert() {
+    let base = b"The quick brown fox jumps over the lazy dog";
+    let mut delta: Vec<u8> = vec![base.len() as u8, 0];
+    // Copy "The quick " (offset 0, size 10)
+    delta.extend_from_slice(&[0b1001_0000, 10]);
+    // Insert "red"
+    delta.push(3);
+    delta.extend_from_slice(b"red");
+    // Copy " fox jumps over the lazy dog" (offset 15, size 28)
+    delta.extend_from_slice(&[0b1001_0001, 15, 28]);
+    let expected = b"The quick red fox jumps over the lazy dog";
+    delta[1] = expected.len() as u8;
+
+    let result = apply_delta(base, &delta).unwrap();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_apply_delta_rejects_wrong_base() {
+    let base = b"short base";
+    // Expects a base of 20 bytes
+    let delta = vec![20, 1, 1, b'a'];
+    assert!(apply_delta(base, &delta).is_err());
+
+    // Reserved instruction
+    let delta = vec![base.len() as u8, 1, 0];
+    assert!(apply_delta(base, &delta).is_err());
+}
+
+#[test]
+fn test_read_delta_size_varint() {
+    // 0b1_0010011, 0b0_0000101 -> 0b101_0010011 = 659
+    let bytes = [0b1001_0011, 0b0000_0101];
+    let mut i = 0;
+    assert_eq!(read_size(&bytes, &mut i).unwrap(), 659);
+    assert_eq!(i, 2);
+
+    // Continuation bits that never end are an error, not an overflow
+    let bytes = [0xFF; 20];
+    let mut i = 0;
+    assert!(read_size(&bytes, &mut i).is_err());
+}
+
+// Sizes, offsets and counts read from a corrupt pack are errors, not panics or aborts
+#[test]
+fn test_corrupt_sizes_are_errors() {
+    let continuation = [0xFF; 20];
+    let mut cursor = std::io::Cursor::new(&continuation[..]);
+    let error = read_ofs_delta_offset(&mut cursor).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let mut cursor = std::io::Cursor::new(&continuation[..]);
+    let error = read_type_and_size(&mut cursor).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    // A delta saying its result is close to usize::MAX bytes
+    let mut delta = vec![4];
+    delta.extend_from_slice(&[0xFF; 9]);
+    delta.extend_from_slice(&[0x01, 0b1001_0000, 4]);
+    assert!(apply_delta(b"base", &delta).is_err());
+
+    // A header with 2^32 - 1 objects and nothing after it
+    let mut packfile = b"PACK".to_vec();
+    packfile.extend_from_slice(&2u32.to_be_bytes());
+    packfile.extend_from_slice(&u32::MAX.to_be_bytes());
+    assert!(read_entries(&packfile).is_err());
+}
+
+#[test]
+fn test_create_delta_round_trip() {
+    let mut base = String::new();
+    for i in 0..500 {
+        base.push_str(&format!("line {i} of the base\n"));
+    }
+    let mut target = base.replace("line 250 of", "changed line");
+    target.insert_str(0, "new first line\n");
+    target.push_str("new last line\n");
+
+    let delta = create_delta(base.as_bytes(), target.as_bytes());
+    assert_eq!(
+        apply_delta(base.as_bytes(), &delta).unwrap(),
+        target.as_bytes()
+    );
+    assert!(delta.len() < target.len() / 10);
+
+    // Nothing in common. Everything is inserted
+    let delta = create_delta(b"abc", b"something else entirely");
+    assert_eq!(
+        apply_delta(b"abc", &delta).unwrap(),
+        b"something else entirely"
+    );
+    // Copies longer than 0x10000 are split
+    let big = vec![7u8; 0x30000];
+    let delta = create_delta(&big, &big);
+    assert_eq!(apply_delta(&big, &delta).unwrap(), big);
+}
+
+#[test]
+fn test_write_pack_readable_by_git() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        let mut content = String::new();
+        for i in 0..300 {
+            content.push_str(&format!("line {i} of file.txt\n"));
+        }
+        let mut objects = Vec::new();
+        for version in 0..5 {
+            content.push_str(&format!("version {version}\n"));
+            let hash = objects::hash_object("blob", content.as_bytes());
+            objects.push(PackObject {
+                hash: hex::decode(hash).unwrap().try_into().unwrap(),
+                object_type: OBJ_BLOB,
+                data: content.clone().into_bytes(),
+                name_hash: name_hash("file.txt"),
+            });
+        }
+        let pack = write_pack(&objects, 10, 50).unwrap();
+        assert!(pack.deltas > 0);
+
+        // Every delta is an ofs-delta and resolves to the original objects
+        let entries = read_entries(&pack.data).unwrap();
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.object.object_type == OBJ_OFS_DELTA)
+        );
+        let mut resolved: Vec<String> = resolve_deltas(entries)
+            .unwrap()
+            .into_iter()
+            .map(|o| o.hash)
+            .collect();
+        let mut expected: Vec<String> = objects.iter().map(|o| hex::encode(o.hash)).collect();
+        resolved.sort();
+        expected.sort();
+        assert_eq!(resolved, expected);
+
+        // git indexes the pack the same way
+        let repo = git2::Repository::init_bare(path.join("git")).unwrap();
+        let odb = repo.odb().unwrap();
+        let mut writer = odb.packwriter().unwrap();
+        std::io::Write::write_all(&mut writer, &pack.data).unwrap();
+        writer.commit().unwrap();
+        for object in &objects {
+            let git_object = odb
+                .read(git2::Oid::from_bytes(&object.hash).unwrap())
+                .unwrap();
+            assert_eq!(git_object.data(), object.data);
+        }
+        let git_idx = std::fs::read_dir(path.join("git/objects/pack"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().unwrap() == "idx")
+            .unwrap();
+        assert_eq!(std::fs::read(git_idx).unwrap(), pack.index.to_bytes());
+    });
+}
+
+#[test]
+fn test_read_objects_from_pack() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        let remote = path.join("remote");
+        let mut content = String::new();
+        for i in 0..200 {
+            content.push_str(&format!("line number {i} of the file\n"));
+        }
+        git2_commit_files(&remote, &[("file.txt", &content), ("dir/a.txt", "a\n")]);
+        content.push_str("one more line\n");
+        let (repo, commit) = git2_commit_files(&remote, &[("file.txt", &content)]);
+
+        let local = path.join("local");
+        std::fs::create_dir(&local).unwrap();
+        RepoRust::new_repo(local.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let pack_dir = local.join(BASE_DIR).join("objects/pack");
+        std::fs::create_dir_all(&pack_dir).unwrap();
+        let mut builder = repo.packbuilder().unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push(commit).unwrap();
+        builder.insert_walk(&mut walk).unwrap();
+        builder.write(&pack_dir, 0o644).unwrap();
+
+        // Nothing is loose
+        let loose = std::fs::read_dir(local.join(BASE_DIR).join("objects"))
+            .unwrap()
+            .count();
+        assert_eq!(loose, 1);
+
+        let git_commit = repo.find_commit(commit).unwrap();
+        let decoded = Commit::decode(&commit.to_string()).unwrap();
+        assert_eq!(decoded.tree_hash, git_commit.tree_id().to_string());
+        assert_eq!(decoded.parents_hash.len(), 1);
+
+        let tree = Tree::decode_object(&decoded.tree_hash).unwrap();
+        let names: Vec<&str> = tree.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["dir", "file.txt"]);
+
+        // The blob of the second commit is a delta in the pack
+        let file_entry = &tree.entries[1];
+        let blob = objects::read_object(&hex::encode(file_entry.hash)).unwrap();
+        assert_eq!(Blob::decode_object(&blob).unwrap(), content.as_bytes());
+        assert!(Blob::blob_exists(file_entry.hash));
+        assert!(
+            PackedDatabase::for_repo()
+                .find_pack(&hex::encode(file_entry.hash))
+                .unwrap()
+                .extension()
+                .unwrap()
+                == "pack"
+        );
+
+        // Missing objects are still missing
+        let missing = "0123456789012345678901234567890123456789";
+        assert!(!objects::object_exists(missing));
+        assert_eq!(
+            objects::read_object(missing).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    });
+}
+
+#[test]
+fn test_pack_index_lookup() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        let files: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("file_{i}.txt"), format!("content {i}\n")))
+            .collect();
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(n, c)| (n.as_str(), c.as_str()))
+            .collect();
+        let (repo, commit) = git2_commit_files(&path, &files);
+        let pack_dir = path.join("pack");
+        std::fs::create_dir(&pack_dir).unwrap();
+        let mut builder = repo.packbuilder().unwrap();
+        builder.insert_commit(commit).unwrap();
+        builder.write(&pack_dir, 0o644).unwrap();
+
+        let idx_path = std::fs::read_dir(&pack_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().unwrap() == "idx")
+            .unwrap();
+        let pack = PackFile::open(&idx_path.with_extension("pack")).unwrap();
+        // 50 blobs, a tree and a commit
+        assert_eq!(pack.index.hashes.len(), 52);
+        assert_eq!(pack.index.fanout[255], 52);
+
+        let odb = repo.odb().unwrap();
+        for hash in &pack.index.hashes {
+            let (object_type, data) = pack.read_object(hash).unwrap().unwrap();
+            let git_object = odb.read(git2::Oid::from_bytes(hash).unwrap()).unwrap();
+            assert_eq!(data, git_object.data());
+            assert_eq!(
+                crate::pack::type_name(object_type).unwrap(),
+                git_object.kind().str()
+            );
+        }
+        assert!(pack.read_object(&[0u8; 20]).is_none());
+        assert!(pack.read_object(&[0xFF; 20]).is_none());
+
+        // The folder is only listed again once it changes
+        let first = packs_in(&pack_dir).unwrap();
+        let second = packs_in(&pack_dir).unwrap();
+        assert_eq!(first.len(), 1);
+        assert!(Arc::ptr_eq(&first[0], &second[0]));
+        std::fs::remove_file(&idx_path).unwrap();
+        assert!(packs_in(&pack_dir).unwrap().is_empty());
+    });
+}
+
+#[test]
+fn test_pack_index_rejects_bad_fanout() {
+    let entries = vec![([0x10; 20], 0, 12), ([0x20; 20], 0, 40)];
+    let bytes = PackIndex::from_entries(entries, [0; 20]).to_bytes();
+    assert!(PackIndex::from_bytes(&bytes).is_ok());
+
+    // Entry 0 says 100 objects start with 0x00, more than the total
+    let mut broken = bytes.clone();
+    broken[8..12].copy_from_slice(&100u32.to_be_bytes());
+    assert!(PackIndex::from_bytes(&broken).is_err());
+
+    // Entry 0x15 goes back from 1 to 0
+    let mut broken = bytes;
+    let start = 8 + 0x15 * 4;
+    broken[start..start + 4].copy_from_slice(&0u32.to_be_bytes());
+    assert!(PackIndex::from_bytes(&broken).is_err());
+}
+
+#[test]
+fn test_read_delta_on_itself() {
+    let hash = [0xAB; 20];
+    let pack = |entry: &[u8]| PackFile {
+        path: PathBuf::from("loop.pack"),
+        data: [b"PACK\0\0\0\x02\0\0\0\x01", entry].concat(),
+        index: PackIndex::from_entries(vec![(hash, 0, 12)], [0; 20]),
+    };
+
+    // ofs-delta with an offset of 0: its base is the entry itself
+    let pack_ofs = pack(&[(OBJ_OFS_DELTA << 4) | 1, 0]);
+    assert!(pack_ofs.read_at(12).is_err());
+
+    // ref-delta whose base is its own hash
+    let pack_ref = pack(&[[(OBJ_REF_DELTA << 4) | 1].as_slice(), &hash].concat());
+    assert!(pack_ref.read_object(&hash).unwrap().is_err());
+}
+
+#[test]
+fn test_index_pack_matches_git() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        let remote = path.join("remote");
+        let mut content = String::new();
+        for i in 0..300 {
+            content.push_str(&format!("line {i}\n"));
+        }
+        git2_commit_files(&remote, &[("a.txt", &content), ("b/c.txt", "c\n")]);
+        content.push_str("last line\n");
+        let (repo, commit) = git2_commit_files(&remote, &[("a.txt", &content)]);
+
+        let git_dir = path.join("git_pack");
+        std::fs::create_dir(&git_dir).unwrap();
+        let mut builder = repo.packbuilder().unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push(commit).unwrap();
+        builder.insert_walk(&mut walk).unwrap();
+        builder.write(&git_dir, 0o644).unwrap();
+        let git_pack = std::fs::read_dir(&git_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().unwrap() == "pack")
+            .unwrap();
+
+        // Index a copy of the pack with git_rust
+        let rust_dir = path.join("rust_pack");
+        std::fs::create_dir(&rust_dir).unwrap();
+        let rust_pack = rust_dir.join(git_pack.file_name().unwrap());
+        std::fs::copy(&git_pack, &rust_pack).unwrap();
+        let checksum = index_pack(&rust_pack).unwrap();
+
+        let pack_bytes = std::fs::read(&rust_pack).unwrap();
+        assert_eq!(checksum, hex::encode(&pack_bytes[pack_bytes.len() - 20..]));
+        let git_idx = std::fs::read(git_pack.with_extension("idx")).unwrap();
+        let rust_idx = std::fs::read(rust_pack.with_extension("idx")).unwrap();
+        assert_eq!(git_idx, rust_idx);
+
+        // Corrupt the pack
+        let mut corrupted = pack_bytes.clone();
+        corrupted[20] ^= 0xFF;
+        let corrupted_path = rust_dir.join("corrupted.pack");
+        std::fs::write(&corrupted_path, corrupted).unwrap();
+        let err = index_pack(&corrupted_path).unwrap_err();
+        assert!(err.to_string().starts_with("Packfile checksum mismatch"));
+        assert!(!corrupted_path.with_extension("idx").exists());
+    });
+}
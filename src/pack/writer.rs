@@ -0,0 +1,171 @@
+use std::io::Write;
+
+use flate2::{Compression, Crc, write::ZlibEncoder};
+use sha1::{Digest, Sha1};
+
+use crate::pack::{OBJ_OFS_DELTA, delta::create_delta, idx::PackIndex};
+
+// Same defaults as git pack-objects
+pub const DEFAULT_WINDOW: usize = 10;
+pub const DEFAULT_DEPTH: usize = 50;
+
+// Objects smaller than this are stored whole. The delta would not be much smaller
+const MIN_DELTA_SIZE: usize = 50;
+
+// An object to be written in a pack
+// name_hash groups objects with the same file name, so they are tried as bases of each other
+pub struct PackObject {
+    pub hash: [u8; 20],
+    pub object_type: u8,
+    pub data: Vec<u8>,
+    pub name_hash: u32,
+}
+
+pub struct WrittenPack {
+    pub data: Vec<u8>,
+    pub index: PackIndex,
+    pub deltas: usize,
+}
+
+// Same as git's pack_name_hash. The last characters weigh the most,
+// so files with the same extension end up close to each other
+pub fn name_hash(name: &str) -> u32 {
+    let mut hash: u32 = 0;
+    for c in name.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        hash = (hash >> 2).wrapping_add((c as u32) << 24);
+    }
+    hash
+}
+
+// Picking the delta bases:
+// 1. The objects are sorted by type, name hash and size (biggest first)
+// 2. Each object is compared with the previous <window> objects of the same type
+// 3. The smallest delta is kept, if it is less than half of the object
+// Bases that are already <depth> deltas deep are skipped, so chains stay short
+// Every delta is written after its base, as an ofs-delta
+pub fn write_pack(
+    objects: &[PackObject],
+    window: usize,
+    depth: usize,
+) -> std::io::Result<WrittenPack> {
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&objects[a], &objects[b]);
+        a.object_type
+            .cmp(&b.object_type)
+            .then(a.name_hash.cmp(&b.name_hash))
+            .then(b.data.len().cmp(&a.data.len()))
+            .then(a.hash.cmp(&b.hash))
+    });
+
+    let mut bases: Vec<Option<(usize, Vec<u8>)>> = (0..objects.len()).map(|_| None).collect();
+    let mut depths = vec![0usize; objects.len()];
+    for (position, &i) in order.iter().enumerate() {
+        let target = &objects[i];
+        if target.data.len() < MIN_DELTA_SIZE {
+            continue;
+        }
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for &j in order[position.saturating_sub(window)..position]
+            .iter()
+            .rev()
+        {
+            let base = &objects[j];
+            if base.object_type != target.object_type || depths[j] >= depth {
+                continue;
+            }
+            let max_size = best
+                .as_ref()
+                .map_or(target.data.len() / 2, |(_, delta)| delta.len());
+            let delta = create_delta(&base.data, &target.data);
+            if delta.len() < max_size {
+                best = Some((j, delta));
+            }
+        }
+        if let Some((j, delta)) = best {
+            depths[i] = depths[j] + 1;
+            bases[i] = Some((j, delta));
+        }
+    }
+
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(b"PACK");
+    data.extend_from_slice(&2u32.to_be_bytes());
+    data.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    // Objects keep the order they were given in. A base missing when its delta comes is written first
+    let mut offsets: Vec<Option<u64>> = vec![None; objects.len()];
+    let mut entries: Vec<([u8; 20], u32, u64)> = Vec::with_capacity(objects.len());
+    for i in 0..objects.len() {
+        let mut chain = Vec::new();
+        let mut next = Some(i);
+        while let Some(k) = next {
+            if offsets[k].is_some() {
+                break;
+            }
+            chain.push(k);
+            next = bases[k].as_ref().map(|(base, _)| *base);
+        }
+        for k in chain.into_iter().rev() {
+            let offset = data.len() as u64;
+            match &bases[k] {
+                Some((base, delta)) => {
+                    write_type_and_size(&mut data, OBJ_OFS_DELTA, delta.len());
+                    write_ofs_delta_offset(&mut data, offset - offsets[*base].unwrap());
+                    compress(&mut data, delta)?;
+                }
+                None => {
+                    write_type_and_size(&mut data, objects[k].object_type, objects[k].data.len());
+                    compress(&mut data, &objects[k].data)?;
+                }
+            }
+            let mut crc = Crc::new();
+            crc.update(&data[offset as usize..]);
+            offsets[k] = Some(offset);
+            entries.push((objects[k].hash, crc.sum(), offset));
+        }
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(&data);
+    let checksum: [u8; 20] = hasher.finalize().into();
+    data.extend_from_slice(&checksum);
+
+    Ok(WrittenPack {
+        data,
+        index: PackIndex::from_entries(entries, checksum),
+        deltas: bases.iter().filter(|base| base.is_some()).count(),
+    })
+}
+
+// Inverse of read_type_and_size. 4 bits of the size in the first byte, then 7 per byte
+fn write_type_and_size(data: &mut Vec<u8>, object_type: u8, size: usize) {
+    let mut byte = (object_type << 4) | (size & 0x0F) as u8;
+    let mut size = size >> 4;
+    while size != 0 {
+        data.push(byte | 0x80);
+        byte = (size & 0x7F) as u8;
+        size >>= 7;
+    }
+    data.push(byte);
+}
+
+// Inverse of read_ofs_delta_offset. 1 is removed before each shift
+fn write_ofs_delta_offset(data: &mut Vec<u8>, offset: u64) {
+    let mut bytes = vec![(offset & 0x7F) as u8];
+    let mut offset = offset >> 7;
+    while offset != 0 {
+        offset -= 1;
+        bytes.push(0x80 | (offset & 0x7F) as u8);
+        offset >>= 7;
+    }
+    bytes.reverse();
+    data.extend_from_slice(&bytes);
+}
+
+fn compress(data: &mut Vec<u8>, content: &[u8]) -> std::io::Result<()> {
+    let mut encoder = ZlibEncoder::new(data, Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()?;
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::path::PathBuf;
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    objects::commit::Autors,
+    refs::{self, LockFile},
+    revision,
+};
+
+#[cfg(test)]
+mod test;
+
+// Old hash of the first update of a ref, and new hash of a deletion
+pub const ZERO_HASH: &str = "0000000000000000000000000000000000000000";
+// Same as gc.reflogExpire and gc.reflogExpireUnreachable
+pub const DEFAULT_EXPIRE: &str = "90.days.ago";
+pub const DEFAULT_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+
+// One line of logs/<ref>:
+// <old hash> <new hash> <name> <<email>> <timestamp> <timezone>\t<message>
+pub struct ReflogEntry {
+    pub old: String,
+    pub new: String,
+    pub committer: Autors,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = header.split_once(' ')?;
+        let (new, person) = rest.split_once(' ')?;
+        let committer = Autors::from_bytes(format!("committer {person}").as_bytes())?;
+        Some(Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            committer,
+            message: message.to_string(),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        let person = String::from_utf8_lossy(&self.committer.to_bytes()).to_string();
+        format!("{} {} {person}\t{}\n", self.old, self.new, self.message)
+    }
+}
+
+fn log_path(name: &str) -> PathBuf {
+    RepoRust::get_root()
+        .absolute_path
+        .join(BASE_DIR)
+        .join("logs")
+        .join(name)
+}
+
+// Same as core.logAllRefUpdates=true: HEAD, branches and remote-tracking branches
+// Other refs (tags) only when they already have a reflog
+pub fn should_log(name: &str) -> bool {
+    name == "HEAD"
+        || name.starts_with("refs/heads/")
+        || name.starts_with("refs/remotes/")
+        || log_path(name).is_file()
+}
+
+// Full name of the ref a reflog command is given. HEAD is the reflog of HEAD
+// itself, not of the current branch
+pub fn ref_name(name: &str) -> std::io::Result<String> {
+    if name == "HEAD" || name == "@" || name.is_empty() {
+        return Ok("HEAD".to_string());
+    }
+    revision::resolve_ref_name(name)?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("reflog could not find ref {name}"),
+        )
+    })
+}
+
+// master@{2} -> (refs/heads/master, 2)
+pub fn parse_selector(rev: &str) -> std::io::Result<(String, usize)> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("not a reflog: {rev}"),
+        )
+    };
+    let (name, selector) = rev.split_once("@{").ok_or_else(invalid)?;
+    let n = selector
+        .strip_suffix('}')
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    Ok((ref_name(name)?, n))
+}
+
+// Entries of a reflog, oldest first. Empty if the ref has no reflog
+pub fn read(name: &str) -> std::io::Result<Vec<ReflogEntry>> {
+    let log = match std::fs::read_to_string(log_path(name)) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    // A line that cannot be read is an error, expire and delete would drop it otherwise
+    log.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            ReflogEntry::parse(line).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid line {} in the reflog of {name}", i + 1),
+                )
+            })
+        })
+        .collect()
+}
+
+fn write(name: &str, entries: &[ReflogEntry]) -> std::io::Result<()> {
+    let mut lock = LockFile::acquire(&log_path(name))?;
+    let content: String = entries.iter().map(ReflogEntry::to_line).collect();
+    lock.write(content.as_bytes())?;
+    lock.commit()
+}
+
+// Adds an entry for an update of a ref, by the user of the config, now
+// The message is kept on a single line
+pub fn append(name: &str, old: Option<&str>, new: &str, message: &str) -> std::io::Result<()> {
+    let path = log_path(name);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let entry = ReflogEntry {
+        old: old.unwrap_or(ZERO_HASH).to_string(),
+        new: new.to_string(),
+        committer: Autors::now()?,
+        message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+    let mut log = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    std::io::Write::write_all(&mut log, entry.to_line().as_bytes())
+}
+
+// Full names of the refs with a reflog, sorted
+pub fn list() -> std::io::Result<Vec<String>> {
+    let logs_dir = log_path("");
+    let mut names = Vec::new();
+    let mut stack = vec![logs_dir.clone()];
+    while let Some(dir) = stack.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().is_none_or(|ext| ext != "lock") {
+                let name = path.strip_prefix(&logs_dir).unwrap().to_string_lossy();
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// @{n}: the value of the ref n updates ago. @{0} is the value after the last update
+pub fn nth(name: &str, n: usize) -> std::io::Result<String> {
+    let entries = read(name)?;
+    let index = entries.len().checked_sub(n + 1).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "log for '{}' only has {} entries",
+                revision::shorten_ref(name),
+                entries.len()
+            ),
+        )
+    })?;
+    Ok(entries[index].new.clone())
+}
+
+// @{date}: the value the ref had at that time. Before the first entry, the value
+// the ref had before it
+pub fn at(name: &str, timestamp: i64) -> std::io::Result<String> {
+    let entries = read(name)?;
+    let first = entries.first().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("log for '{}' is empty", revision::shorten_ref(name)),
+        )
+    })?;
+    if let Some(entry) = entries
+        .iter()
+        .rev()
+        .find(|entry| entry.committer.timestamp <= timestamp)
+    {
+        return Ok(entry.new.clone());
+    }
+    Ok(match first.old.as_str() {
+        ZERO_HASH => first.new.clone(),
+        old => old.to_string(),
+    })
+}
+
+// reflog expire: removes the entries older than expire, and the entries older
+// than expire_unreachable whose commit is no longer reachable from the ref
+// None keeps the entries. Returns the number of entries removed
+pub fn expire(
+    name: &str,
+    expire: Option<i64>,
+    expire_unreachable: Option<i64>,
+) -> std::io::Result<usize> {
+    let entries = read(name)?;
+    let tip = refs::read_ref(name)?;
+    let total = entries.len();
+    let mut kept = Vec::new();
+    for entry in entries {
+        let timestamp = entry.committer.timestamp;
+        if expire.is_some_and(|expire| timestamp <= expire) {
+            continue;
+        }
+        if expire_unreachable.is_some_and(|expire| timestamp <= expire) {
+            let reachable = match &tip {
+                Some(tip) => revision::is_ancestor(&entry.new, tip).unwrap_or(false),
+                None => false,
+            };
+            if !reachable {
+                continue;
+            }
+        }
+        kept.push(entry);
+    }
+    let removed = total - kept.len();
+    if removed > 0 {
+        write(name, &kept)?;
+    }
+    Ok(removed)
+}
+
+// reflog delete <ref>@{n}
+pub fn delete(name: &str, n: usize) -> std::io::Result<()> {
+    let mut entries = read(name)?;
+    let index = entries.len().checked_sub(n + 1).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "reflog entry '{}@{{{n}}}' not found",
+                revision::shorten_ref(name)
+            ),
+        )
+    })?;
+    entries.remove(index);
+    write(name, &entries)
+}
+
+// The hashes every reflog entry points to, for gc
+pub fn hashes() -> std::io::Result<Vec<String>> {
+    let mut hashes = Vec::new();
+    for name in list()? {
+        for entry in read(&name)? {
+            hashes.extend(
+                [entry.old, entry.new]
+                    .into_iter()
+                    .filter(|hash| hash != ZERO_HASH),
+            );
+        }
+    }
+    Ok(hashes)
+}
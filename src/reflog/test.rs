@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use crate::{
+    config::Config,
+    git_rust::{BASE_DIR, RepoRust},
+    reflog, refs, revision,
+    test_common::{run_test, run_test_matches},
+};
+
+fn commit(path: &std::path::Path, file: &str, message: &str) -> String {
+    std::fs::write(path.join(file), message).unwrap();
+    let args = run_test_matches(vec!["", "add", file]);
+    RepoRust::add(&args).unwrap();
+    let args = run_test_matches(vec!["", "commit", "-m", message]);
+    RepoRust::commit(&args).unwrap();
+    refs::read_ref("HEAD").unwrap().unwrap()
+}
+
+fn messages(name: &str) -> Vec<String> {
+    reflog::read(name)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.message)
+        .collect()
+}
+
+fn reflog(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "reflog"], args].concat();
+    RepoRust::reflog(&run_test_matches(args))
+}
+
+#[test]
+fn test_reflog_records_updates() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let first = commit(&path, "a.txt", "first");
+        let second = commit(&path, "a.txt", "second\n\nbody");
+        let args = run_test_matches(vec!["", "switch", "-c", "topic", "HEAD~1"]);
+        RepoRust::switch(&args).unwrap();
+        let third = commit(&path, "b.txt", "third");
+        let args = run_test_matches(vec!["", "switch", "--detach", "master"]);
+        RepoRust::switch(&args).unwrap();
+
+        assert_eq!(
+            messages("refs/heads/master"),
+            vec!["commit (initial): first", "commit: second"]
+        );
+        assert_eq!(
+            messages("refs/heads/topic"),
+            vec!["branch: Created from HEAD~1", "commit: third"]
+        );
+        assert_eq!(
+            messages("HEAD"),
+            vec![
+                "commit (initial): first".to_string(),
+                "commit: second".to_string(),
+                "checkout: moving from master to topic".to_string(),
+                "commit: third".to_string(),
+                format!("checkout: moving from topic to {second}"),
+            ]
+        );
+        let entries = reflog::read("HEAD").unwrap();
+        assert_eq!(entries[0].old, reflog::ZERO_HASH);
+        assert_eq!((&entries[2].old, &entries[2].new), (&second, &first));
+        assert_eq!((&entries[3].old, &entries[3].new), (&first, &third));
+
+        // Previous values through the revision parser
+        assert_eq!(revision::resolve("HEAD@{0}").unwrap(), second);
+        assert_eq!(revision::resolve("HEAD@{1}").unwrap(), third);
+        assert_eq!(revision::resolve("HEAD@{1}~1").unwrap(), first);
+        assert_eq!(revision::resolve("topic@{1}").unwrap(), first);
+        assert_eq!(revision::resolve("master@{now}").unwrap(), second);
+        assert!(revision::resolve("topic@{5}").is_err());
+
+        // Renamed with its reflog. Deleted with it
+        refs::rename_ref("refs/heads/topic", "refs/heads/feature").unwrap();
+        assert!(!path.join(BASE_DIR).join("logs/refs/heads/topic").exists());
+        assert_eq!(
+            messages("refs/heads/feature").last().unwrap(),
+            "Branch: renamed refs/heads/topic to refs/heads/feature"
+        );
+        refs::delete_ref("refs/heads/feature").unwrap();
+        assert!(reflog::read("refs/heads/feature").unwrap().is_empty());
+        // Tags have no reflog
+        refs::write_ref("refs/tags/v1", &first).unwrap();
+        assert_eq!(
+            reflog::list().unwrap(),
+            vec!["HEAD".to_string(), "refs/heads/master".to_string()]
+        );
+    });
+}
+
+#[test]
+fn test_reflog_identity_from_repo_config() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let mut config = Config::read().unwrap();
+        config.set("user.name", "Repo User").unwrap();
+        config.set("user.email", "repo@example.com").unwrap();
+        config.write().unwrap();
+        commit(&path, "a.txt", "first");
+
+        let entries = reflog::read("HEAD").unwrap();
+        assert_eq!(entries[0].committer.name, b"Repo User");
+        assert_eq!(entries[0].committer.email, b"repo@example.com");
+    });
+}
+
+#[test]
+fn test_reflog_dates_expire_and_delete() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let first = commit(&path, "a.txt", "first");
+        let second = commit(&path, "a.txt", "second");
+        let third = commit(&path, "a.txt", "third");
+
+        // Two entries from long ago, one from today
+        let zero = reflog::ZERO_HASH;
+        let now = chrono::Local::now().timestamp();
+        let log = format!(
+            "{zero} {first} A U Thor <author@example.com> 1700000000 +0000\tcommit (initial): first\n\
+             {first} {second} A U Thor <author@example.com> 1700100000 +0000\tcommit: second\n\
+             {second} {third} A U Thor <author@example.com> {now} +0000\tcommit: third\n"
+        );
+        let log_path = path.join(BASE_DIR).join("logs/refs/heads/master");
+        std::fs::write(&log_path, &log).unwrap();
+
+        assert_eq!(revision::resolve("master@{yesterday}").unwrap(), second);
+        assert_eq!(revision::resolve("@{2023-11-15}").unwrap(), first);
+        assert_eq!(revision::resolve("master@{2 weeks ago}").unwrap(), second);
+        // Before the first entry
+        assert_eq!(revision::resolve("master@{2020-01-01}").unwrap(), first);
+        assert!(revision::resolve("master@{someday}").is_err());
+
+        // third is no longer reachable from master
+        refs::write_ref("refs/heads/master", &second).unwrap();
+        reflog(&[
+            "expire",
+            "--expire=never",
+            "--expire-unreachable=now",
+            "master",
+        ])
+        .unwrap();
+        assert_eq!(
+            messages("refs/heads/master"),
+            vec!["commit (initial): first", "commit: second", ""]
+        );
+        reflog(&["expire", "--expire=1.day.ago", "master"]).unwrap();
+        assert_eq!(messages("refs/heads/master"), vec![""]);
+
+        // The rewind of master is in the reflog of HEAD too
+        reflog(&["delete", "HEAD@{0}", "HEAD@{2}"]).unwrap();
+        assert_eq!(
+            messages("HEAD"),
+            vec!["commit (initial): first", "commit: third"]
+        );
+        assert!(reflog(&["delete", "HEAD@{3}"]).is_err());
+        assert!(reflog(&["delete", "HEAD"]).is_err());
+        reflog(&["show", "master"]).unwrap();
+        reflog(&["master"]).unwrap();
+        assert!(reflog(&["missing"]).is_err());
+
+        // A broken line is reported, and expire does not rewrite the file without it
+        let broken = format!("{log}not a reflog line\n");
+        std::fs::write(&log_path, &broken).unwrap();
+        let e = reflog::read("refs/heads/master").err().unwrap();
+        assert!(e.to_string().contains("invalid line 4"), "{e}");
+        assert!(reflog(&["expire", "--expire=now", "master"]).is_err());
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), broken);
+    });
+}
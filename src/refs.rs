@@ -0,0 +1,614 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    objects::ObjectType,
+    odb, reflog, revision,
+};
+
+#[cfg(test)]
+mod test;
+
+const MAX_SYMREF_DEPTH: usize = 5;
+const PACKED_REFS: &str = "packed-refs";
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+fn git_dir() -> PathBuf {
+    RepoRust::get_root().absolute_path.join(BASE_DIR)
+}
+
+// Simplified git check-ref-format, for the part after refs/heads/ or refs/tags/
+pub fn check_name(name: &str) -> bool {
+    !(name.is_empty()
+        || name.starts_with('-')
+        || name.starts_with('.')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.ends_with(".lock")
+        || name.contains("..")
+        || name.contains("//")
+        || name.contains("/.")
+        || name.contains("@{")
+        || name == "@"
+        || name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c)))
+}
+
+// <file>.lock, created exclusively so only one process can update the file
+// On commit the lock is renamed over the file. Dropped before, it is removed
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> std::io::Result<Self> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Unable to create '{}': File exists.\n\
+                         Another process seems to be running in this repository. \
+                         If it crashed, remove the file manually to continue.",
+                        lock_path.display()
+                    ),
+                ),
+                _ => e,
+            })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            lock_path,
+            file,
+            committed: false,
+        })
+    }
+
+    pub fn write(&mut self, content: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(content)
+    }
+
+    // The new content is on disk before it replaces the file
+    pub fn commit(mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.lock_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+pub struct PackedRef {
+    pub hash: String,
+    // For annotated tags, the object the tag points to, peeled to a non-tag
+    pub peeled: Option<String>,
+}
+
+// packed-refs has one "<hash> <name>" line per ref, sorted by name. An annotated
+// tag is followed by "^<peeled hash>". Comment lines start with #
+pub fn packed_refs() -> std::io::Result<BTreeMap<String, PackedRef>> {
+    let packed = match std::fs::read_to_string(git_dir().join(PACKED_REFS)) {
+        Ok(packed) => packed,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut refs: BTreeMap<String, PackedRef> = BTreeMap::new();
+    let mut last: Option<String> = None;
+    for line in packed.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            if let Some(packed) = last.as_ref().and_then(|name| refs.get_mut(name)) {
+                packed.peeled = Some(peeled.to_string());
+            }
+            continue;
+        }
+        let (hash, name) = line.split_once(' ').ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected line in packed-refs: {line}"),
+            )
+        })?;
+        let packed = PackedRef {
+            hash: hash.to_string(),
+            peeled: None,
+        };
+        refs.insert(name.to_string(), packed);
+        last = Some(name.to_string());
+    }
+    Ok(refs)
+}
+
+fn write_packed_refs(
+    lock: &mut LockFile,
+    refs: &BTreeMap<String, PackedRef>,
+) -> std::io::Result<()> {
+    let mut content = PACKED_REFS_HEADER.to_string();
+    for (name, packed) in refs {
+        content.push_str(&format!("{} {name}\n", packed.hash));
+        if let Some(peeled) = &packed.peeled {
+            content.push_str(&format!("^{peeled}\n"));
+        }
+    }
+    lock.write(content.as_bytes())
+}
+
+enum LooseRef {
+    Hash(String),
+    Symbolic(String),
+}
+
+fn read_loose(name: &str) -> std::io::Result<Option<LooseRef>> {
+    let path = git_dir().join(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)?;
+    // FETCH_HEAD has more than the hash on each line
+    let content = content.lines().next().unwrap_or("").trim();
+    Ok(match content.strip_prefix("ref: ") {
+        Some(target) => Some(LooseRef::Symbolic(target.to_string())),
+        None => content
+            .split_whitespace()
+            .next()
+            .map(|hash| LooseRef::Hash(hash.to_string())),
+    })
+}
+
+// Follows symbolic refs. Loose refs first, then packed-refs
+// Returns None if the ref does not exist or the branch has no commit yet
+pub fn read_ref(name: &str) -> std::io::Result<Option<String>> {
+    let mut seen = HashSet::new();
+    let mut name = name.to_string();
+    while seen.insert(name.clone()) {
+        if seen.len() > MAX_SYMREF_DEPTH {
+            return Err(std::io::Error::other(format!(
+                "Too many levels of symbolic refs for {name}"
+            )));
+        }
+        match read_loose(&name)? {
+            Some(LooseRef::Hash(hash)) => return Ok(Some(hash)),
+            Some(LooseRef::Symbolic(target)) => name = target,
+            None => return Ok(packed_refs()?.remove(&name).map(|packed| packed.hash)),
+        }
+    }
+    Err(std::io::Error::other(format!(
+        "Symbolic ref loop detected at {name}"
+    )))
+}
+
+// The ref a symbolic ref points to. None if it is not symbolic (a detached HEAD)
+pub fn symbolic_ref(name: &str) -> std::io::Result<Option<String>> {
+    Ok(match read_loose(name)? {
+        Some(LooseRef::Symbolic(target)) => Some(target),
+        _ => None,
+    })
+}
+
+// Full names and hashes of the refs under prefix (refs/heads/), sorted by name
+// Loose refs hide packed ones. Symbolic refs such as refs/remotes/origin/HEAD are skipped
+pub fn list_refs(prefix: &str) -> std::io::Result<Vec<(String, String)>> {
+    let git_dir = git_dir();
+    let mut refs: BTreeMap<String, String> = packed_refs()?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, packed)| (name, packed.hash))
+        .collect();
+    let mut stack = vec![git_dir.join(prefix)];
+    while let Some(dir) = stack.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            // Another process is updating the ref
+            if path.extension().is_some_and(|ext| ext == "lock") {
+                continue;
+            }
+            let name = path.strip_prefix(&git_dir).unwrap().to_string_lossy();
+            if let Some(LooseRef::Hash(hash)) = read_loose(&name)? {
+                refs.insert(name.to_string(), hash);
+            }
+        }
+    }
+    Ok(refs.into_iter().collect())
+}
+
+// What a ref must hold for an update to go through
+enum Expected {
+    Any,
+    Missing,
+    Hash(String),
+}
+
+struct RefUpdate {
+    name: String,
+    // None deletes the ref
+    new: Option<String>,
+    old: Expected,
+}
+
+// Updates several refs at once. Every ref is locked and checked before any is
+// written, so either all the updates are made or none
+#[derive(Default)]
+pub struct Transaction {
+    updates: Vec<RefUpdate>,
+    // Reflog message of the updates
+    message: String,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, name: &str, new: &str) -> &mut Self {
+        self.push(name, Some(new), Expected::Any)
+    }
+
+    // Updates the ref only if it still holds old. None if it must not exist yet
+    pub fn compare_and_swap(&mut self, name: &str, old: Option<&str>, new: &str) -> &mut Self {
+        let old = match old {
+            Some(old) => Expected::Hash(old.to_string()),
+            None => Expected::Missing,
+        };
+        self.push(name, Some(new), old)
+    }
+
+    pub fn delete(&mut self, name: &str) -> &mut Self {
+        self.push(name, None, Expected::Any)
+    }
+
+    // Written to the reflog of every updated ref: "commit: <subject>"
+    pub fn message(&mut self, message: &str) -> &mut Self {
+        self.message = message.to_string();
+        self
+    }
+
+    fn push(&mut self, name: &str, new: Option<&str>, old: Expected) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: new.map(str::to_string),
+            old,
+        });
+        self
+    }
+
+    pub fn commit(mut self) -> std::io::Result<()> {
+        let git_dir = git_dir();
+        self.updates.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(pair) = self
+            .updates
+            .windows(2)
+            .find(|pair| pair[0].name == pair[1].name)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("multiple updates for ref '{}' not allowed", pair[0].name),
+            ));
+        }
+
+        // 1. Lock and check every ref
+        let mut packed = packed_refs()?;
+        let existing: Vec<String> = list_refs("refs")?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let deleted: Vec<&String> = self
+            .updates
+            .iter()
+            .filter(|update| update.new.is_none())
+            .map(|update| &update.name)
+            .collect();
+        let mut locks = Vec::new();
+        let mut olds = Vec::new();
+        for update in &self.updates {
+            let cannot_lock = |reason: String| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("cannot lock ref '{}': {reason}", update.name),
+                )
+            };
+            if update.new.is_some() {
+                check_conflicts(&update.name, &existing, &self.updates).map_err(cannot_lock)?;
+            }
+            // A ref deleted here is in the way of this one (a -> a/b). It is
+            // locked once the deleted ref file is gone
+            let blocked = deleted.iter().any(|name| {
+                update.name.starts_with(&format!("{name}/")) && git_dir.join(name).is_file()
+            });
+            let lock = match blocked {
+                true => None,
+                false => Some(lock_ref(&git_dir.join(&update.name)).map_err(
+                    |e| match e.kind() {
+                        std::io::ErrorKind::AlreadyExists => e,
+                        _ => cannot_lock("there is a folder in the way".to_string()),
+                    },
+                )?),
+            };
+            let current = match read_loose(&update.name)? {
+                Some(LooseRef::Hash(hash)) => Some(hash),
+                Some(LooseRef::Symbolic(_)) => read_ref(&update.name)?,
+                None => packed.get(&update.name).map(|packed| packed.hash.clone()),
+            };
+            match (&update.old, &current) {
+                (Expected::Missing, Some(_)) => {
+                    return Err(cannot_lock("reference already exists".to_string()));
+                }
+                (Expected::Hash(old), Some(current)) if old != current => {
+                    return Err(cannot_lock(format!("is at {current} but expected {old}")));
+                }
+                (Expected::Hash(old), None) => {
+                    return Err(cannot_lock(format!("unable to resolve, expected {old}")));
+                }
+                _ => {}
+            }
+            locks.push(lock);
+            olds.push(current);
+        }
+
+        // 2. Deleted refs leave packed-refs first, so they never come back
+        let packed_deletes: Vec<&&String> = deleted
+            .iter()
+            .filter(|name| packed.contains_key(name.as_str()))
+            .collect();
+        if !packed_deletes.is_empty() {
+            let mut packed_lock = LockFile::acquire(&git_dir.join(PACKED_REFS))?;
+            for name in packed_deletes {
+                packed.remove(name.as_str());
+            }
+            write_packed_refs(&mut packed_lock, &packed)?;
+            packed_lock.commit()?;
+        }
+
+        // 3. Remove the deleted loose refs, then write the others
+        let mut writes = Vec::new();
+        for ((update, lock), old) in self.updates.iter().zip(locks).zip(olds) {
+            let Some(new) = &update.new else {
+                let path = git_dir.join(&update.name);
+                if path.is_file() {
+                    std::fs::remove_file(&path)?;
+                }
+                drop(lock);
+                remove_empty_ref_dirs(&git_dir, &update.name);
+                let logs_dir = git_dir.join("logs");
+                if logs_dir.join(&update.name).is_file() {
+                    std::fs::remove_file(logs_dir.join(&update.name))?;
+                    remove_empty_ref_dirs(&logs_dir, &update.name);
+                }
+                continue;
+            };
+            writes.push((new, update, lock, old));
+        }
+        // A branch moved through HEAD is in the reflog of HEAD too
+        let head = head_branch()?;
+        let head_updated = self.updates.iter().any(|update| update.name == "HEAD");
+        for (new, update, lock, old) in writes {
+            let mut lock = match lock {
+                Some(lock) => lock,
+                None => lock_ref(&git_dir.join(&update.name))?,
+            };
+            lock.write(format!("{new}\n").as_bytes())?;
+            lock.commit()?;
+            if reflog::should_log(&update.name) {
+                reflog::append(&update.name, old.as_deref(), new, &self.message)?;
+            }
+            if !head_updated && head.as_deref() == Some(update.name.as_str()) {
+                reflog::append("HEAD", old.as_deref(), new, &self.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Creates the folders of a ref and locks it. An empty folder left where
+// the ref goes is removed first
+fn lock_ref(path: &Path) -> std::io::Result<LockFile> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    if path.is_dir() {
+        std::fs::remove_dir(path)?;
+    }
+    LockFile::acquire(path)
+}
+
+// refs/heads/a and refs/heads/a/b cannot both exist: one is a file, the other a folder
+fn check_conflicts(name: &str, existing: &[String], updates: &[RefUpdate]) -> Result<(), String> {
+    let deleted = |other: &String| {
+        updates
+            .iter()
+            .any(|update| update.new.is_none() && &update.name == other)
+    };
+    let prefix = format!("{name}/");
+    for other in existing.iter().filter(|other| !deleted(other)) {
+        if other.starts_with(&prefix) || name.starts_with(&format!("{other}/")) {
+            return Err(format!("'{other}' exists"));
+        }
+    }
+    Ok(())
+}
+
+// Writes a ref. name is the full name: refs/heads/main
+pub fn write_ref(name: &str, hash: &str) -> std::io::Result<()> {
+    let mut transaction = Transaction::new();
+    transaction.update(name, hash);
+    transaction.commit()
+}
+
+// Removes a ref, loose and packed, and its reflog. Returns the hash it pointed to
+pub fn delete_ref(name: &str) -> std::io::Result<Option<String>> {
+    let old = read_ref(name)?;
+    if old.is_some() {
+        let mut transaction = Transaction::new();
+        transaction.delete(name);
+        transaction.commit()?;
+    }
+    Ok(old)
+}
+
+// Moves a ref and its reflog to a new name
+pub fn rename_ref(old: &str, new: &str) -> std::io::Result<()> {
+    let git_dir = git_dir();
+    let hash = read_ref(old)?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("ref '{old}' not found"),
+        )
+    })?;
+    // Copied before the transaction, which adds the rename to it
+    let new_log = git_dir.join("logs").join(new);
+    let log = std::fs::read(git_dir.join("logs").join(old)).ok();
+    if let Some(log) = &log {
+        std::fs::create_dir_all(new_log.parent().unwrap())?;
+        std::fs::write(&new_log, log)?;
+    }
+    let mut transaction = Transaction::new();
+    transaction
+        .delete(old)
+        .update(new, &hash)
+        .message(&format!("Branch: renamed {old} to {new}"));
+    transaction.commit().inspect_err(|_| {
+        if log.is_some() {
+            let _ = std::fs::remove_file(&new_log);
+        }
+    })
+}
+
+// Folders left empty by a deleted file, up to stop (excluded)
+pub fn remove_empty_dirs(path: &Path, stop: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == stop || !current.starts_with(stop) || std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+// Folders left empty by a deleted ref. refs/heads, refs/tags... are kept
+fn remove_empty_ref_dirs(root: &Path, name: &str) {
+    let top: PathBuf = Path::new(name).components().take(2).collect();
+    remove_empty_dirs(&root.join(name), &root.join(top));
+}
+
+// Writes "ref: <target>" to a symbolic ref such as HEAD
+pub fn write_symref(name: &str, target: &str) -> std::io::Result<()> {
+    let path = git_dir().join(name);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let mut lock = LockFile::acquire(&path)?;
+    lock.write(format!("ref: {target}\n").as_bytes())?;
+    lock.commit()
+}
+
+// Full name of the branch HEAD points to. None when HEAD is detached
+// The branch may not exist yet, before the first commit
+pub fn head_branch() -> std::io::Result<Option<String>> {
+    symbolic_ref("HEAD")
+}
+
+// Points HEAD to a branch
+pub fn set_head_branch(name: &str) -> std::io::Result<()> {
+    write_symref("HEAD", name)
+}
+
+// Detaches HEAD at a commit
+pub fn set_head_detached(hash: &str, message: &str) -> std::io::Result<()> {
+    let mut transaction = Transaction::new();
+    transaction.update("HEAD", hash).message(message);
+    transaction.commit()
+}
+
+// Moves the current branch to hash, or HEAD itself when detached. old is the
+// commit HEAD must still be at (None before the first commit), else nothing changes
+// Returns the short name of the branch, None when detached
+pub fn update_head(
+    hash: &str,
+    old: Option<&str>,
+    message: &str,
+) -> std::io::Result<Option<String>> {
+    let branch = head_branch()?;
+    let name = branch.as_deref().unwrap_or("HEAD");
+    let mut transaction = Transaction::new();
+    transaction
+        .compare_and_swap(name, old, hash)
+        .message(message);
+    transaction.commit()?;
+    Ok(branch.map(|branch| revision::shorten_ref(&branch).to_string()))
+}
+
+pub struct PackSummary {
+    pub packed: usize,
+    pub pruned: usize,
+}
+
+// pack-refs: moves loose refs into packed-refs, with the peeled value of annotated
+// tags. Tags and the refs already packed are always packed, other refs with all
+// The loose files are removed after, unless prune is false
+pub fn pack_refs(all: bool, prune: bool) -> std::io::Result<PackSummary> {
+    let git_dir = git_dir();
+    let mut packed_lock = LockFile::acquire(&git_dir.join(PACKED_REFS))?;
+    let mut packed = packed_refs()?;
+    let loose: Vec<(String, String)> = list_refs("refs")?
+        .into_iter()
+        .filter(|(name, _)| git_dir.join(name).is_file())
+        .filter(|(name, _)| all || name.starts_with("refs/tags/") || packed.contains_key(name))
+        .collect();
+    for (name, hash) in &loose {
+        // A tag object is peeled to what it points to
+        let peeled = match odb::database().read(hash) {
+            Ok((ObjectType::Tag, _)) => Some(revision::resolve(&format!("{hash}^{{}}"))?),
+            _ => None,
+        };
+        let entry = PackedRef {
+            hash: hash.clone(),
+            peeled,
+        };
+        packed.insert(name.clone(), entry);
+    }
+    write_packed_refs(&mut packed_lock, &packed)?;
+    packed_lock.commit()?;
+
+    let mut pruned = 0;
+    if prune {
+        for (name, hash) in &loose {
+            let path = git_dir.join(name);
+            // Not removed if another process changed it meanwhile
+            let lock = LockFile::acquire(&path)?;
+            if matches!(read_loose(name)?, Some(LooseRef::Hash(current)) if &current == hash) {
+                std::fs::remove_file(&path)?;
+                pruned += 1;
+            }
+            drop(lock);
+            remove_empty_ref_dirs(&git_dir, name);
+        }
+    }
+    Ok(PackSummary {
+        packed: loose.len(),
+        pruned,
+    })
+}
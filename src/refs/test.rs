@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType},
+    refs,
+    test_common::run_test,
+};
+
+fn write_commit(message: &str) -> String {
+    let tree = objects::write_object(ObjectType::Tree, b"").unwrap();
+    let commit = format!(
+        "tree {tree}\n\
+         author A U Thor <author@example.com> 1700000000 +0000\n\
+         committer A U Thor <author@example.com> 1700000000 +0000\n\n{message}"
+    );
+    objects::write_object(ObjectType::Commit, commit.as_bytes()).unwrap()
+}
+
+#[test]
+fn test_refs_write_rename_delete_and_list() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let git_dir = path.join(BASE_DIR);
+        let (one, two) = ("1".repeat(40), "2".repeat(40));
+
+        // Nothing committed yet, HEAD points to a branch that does not exist
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/master");
+        assert_eq!(
+            refs::update_head(&one, None, "").unwrap().unwrap(),
+            "master"
+        );
+        refs::write_ref("refs/heads/feature/login", &two).unwrap();
+        refs::write_ref("refs/remotes/origin/master", &one).unwrap();
+        std::fs::write(
+            git_dir.join("refs/remotes/origin/HEAD"),
+            "ref: refs/remotes/origin/master\n",
+        )
+        .unwrap();
+        assert_eq!(
+            refs::list_refs("refs/heads").unwrap(),
+            vec![
+                ("refs/heads/feature/login".to_string(), two.clone()),
+                ("refs/heads/master".to_string(), one.clone()),
+            ]
+        );
+        // Symbolic refs are not listed
+        assert_eq!(refs::list_refs("refs/remotes").unwrap().len(), 1);
+
+        // The reflog moves with the ref, empty folders are removed
+        let log = format!(
+            "{} {two} A U Thor <a@example.com> 1700000000 +0000\tbranch: Created\n",
+            "0".repeat(40)
+        );
+        std::fs::create_dir_all(git_dir.join("logs/refs/heads/feature")).unwrap();
+        std::fs::write(git_dir.join("logs/refs/heads/feature/login"), &log).unwrap();
+        refs::rename_ref("refs/heads/feature/login", "refs/heads/login").unwrap();
+        assert!(!git_dir.join("refs/heads/feature").exists());
+        assert!(!git_dir.join("logs/refs/heads/feature").exists());
+        let new_log = std::fs::read_to_string(git_dir.join("logs/refs/heads/login")).unwrap();
+        assert!(new_log.starts_with(&log));
+        assert!(
+            new_log.ends_with("\tBranch: renamed refs/heads/feature/login to refs/heads/login\n")
+        );
+        assert_eq!(
+            refs::read_ref("refs/heads/login").unwrap(),
+            Some(two.clone())
+        );
+        assert!(refs::rename_ref("refs/heads/missing", "refs/heads/other").is_err());
+
+        assert_eq!(
+            refs::delete_ref("refs/heads/login").unwrap(),
+            Some(two.clone())
+        );
+        assert!(!git_dir.join("logs/refs/heads/login").exists());
+        assert!(git_dir.join("refs/heads").is_dir());
+        assert_eq!(refs::delete_ref("refs/heads/login").unwrap(), None);
+
+        // Detached HEAD moves by itself
+        refs::set_head_detached(&one, "").unwrap();
+        assert_eq!(refs::head_branch().unwrap(), None);
+        assert_eq!(refs::update_head(&two, Some(&one), "").unwrap(), None);
+        assert_eq!(
+            std::fs::read_to_string(git_dir.join("HEAD")).unwrap(),
+            format!("{two}\n")
+        );
+        assert_eq!(refs::read_ref("refs/heads/master").unwrap(), Some(one));
+    });
+}
+
+#[test]
+fn test_check_ref_names() {
+    for name in ["main", "feature/login", "v1.0", "fix-#12", "a.b/c"] {
+        assert!(refs::check_name(name), "{name}");
+    }
+    for name in [
+        "", "-main", ".hidden", "dir/", "end.", "x.lock", "a..b", "a//b", "a/.b", "a@{1}", "@",
+        "a b", "a~1", "a^", "a:b", "a?", "a*", "a[", "a\\b",
+    ] {
+        assert!(!refs::check_name(name), "{name}");
+    }
+}
+
+#[test]
+fn test_packed_refs() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let git_dir = path.join(BASE_DIR);
+        let commit = write_commit("first\n");
+        let tag = format!(
+            "object {commit}\ntype commit\ntag v1.0\n\
+             tagger A U Thor <author@example.com> 1700000000 +0000\n\nRelease\n"
+        );
+        let tag = objects::write_object(ObjectType::Tag, tag.as_bytes()).unwrap();
+        refs::write_ref("refs/heads/master", &commit).unwrap();
+        refs::write_ref("refs/heads/topic/one", &commit).unwrap();
+        refs::write_ref("refs/tags/v1.0", &tag).unwrap();
+
+        // Only the tags without --all
+        let summary = refs::pack_refs(false, true).unwrap();
+        assert_eq!((summary.packed, summary.pruned), (1, 1));
+        assert!(!git_dir.join("refs/tags/v1.0").exists());
+        assert!(git_dir.join("refs/tags").is_dir());
+
+        let summary = refs::pack_refs(true, true).unwrap();
+        assert_eq!((summary.packed, summary.pruned), (2, 2));
+        assert!(!git_dir.join("refs/heads/topic").exists());
+        assert_eq!(
+            std::fs::read_to_string(git_dir.join("packed-refs")).unwrap(),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {commit} refs/heads/master\n\
+                 {commit} refs/heads/topic/one\n\
+                 {tag} refs/tags/v1.0\n\
+                 ^{commit}\n"
+            )
+        );
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(commit.clone()));
+        assert_eq!(refs::list_refs("refs/tags").unwrap().len(), 1);
+
+        // A loose ref hides the packed one
+        let second = write_commit("second\n");
+        refs::write_ref("refs/heads/master", &second).unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(second.clone()));
+        assert_eq!(
+            refs::list_refs("refs/heads").unwrap()[0],
+            ("refs/heads/master".to_string(), second.clone())
+        );
+
+        // Deleted from packed-refs as well
+        refs::delete_ref("refs/heads/topic/one").unwrap();
+        assert_eq!(refs::read_ref("refs/heads/topic/one").unwrap(), None);
+        assert!(
+            !refs::packed_refs()
+                .unwrap()
+                .contains_key("refs/heads/topic/one")
+        );
+        // The folder of a packed ref can hold a new loose ref
+        refs::write_ref("refs/heads/topic", &second).unwrap();
+        // Without prune, packed-refs and the loose file both hold the ref
+        assert_eq!(refs::pack_refs(true, false).unwrap().pruned, 0);
+        assert!(git_dir.join("refs/heads/topic").is_file());
+        assert_eq!(
+            refs::packed_refs().unwrap()["refs/heads/topic"].hash,
+            second
+        );
+    });
+}
+
+#[test]
+fn test_transactions() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let git_dir = path.join(BASE_DIR);
+        let (one, two) = ("1".repeat(40), "2".repeat(40));
+        refs::write_ref("refs/heads/master", &one).unwrap();
+
+        // One stale value and nothing is written
+        let mut transaction = refs::Transaction::new();
+        transaction
+            .compare_and_swap("refs/heads/master", Some(&two), &one)
+            .update("refs/heads/other", &two);
+        let e = transaction.commit().unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("cannot lock ref 'refs/heads/master'")
+        );
+        assert_eq!(refs::read_ref("refs/heads/other").unwrap(), None);
+        assert!(!git_dir.join("refs/heads/master.lock").exists());
+        assert!(!git_dir.join("refs/heads/other.lock").exists());
+
+        let mut transaction = refs::Transaction::new();
+        transaction
+            .compare_and_swap("refs/heads/master", Some(&one), &two)
+            .compare_and_swap("refs/heads/other", None, &one);
+        transaction.commit().unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(two.clone()));
+        assert_eq!(
+            refs::read_ref("refs/heads/other").unwrap(),
+            Some(one.clone())
+        );
+
+        // A ref cannot be created twice
+        let mut transaction = refs::Transaction::new();
+        transaction.compare_and_swap("refs/heads/other", None, &two);
+        assert!(transaction.commit().is_err());
+
+        // refs/heads/other is a file, refs/heads/other/x cannot be created
+        let e = refs::write_ref("refs/heads/other/x", &one).unwrap_err();
+        assert!(e.to_string().contains("'refs/heads/other' exists"));
+        // Unless other is deleted in the same transaction
+        let mut transaction = refs::Transaction::new();
+        transaction
+            .delete("refs/heads/other")
+            .update("refs/heads/other/x", &one);
+        transaction.commit().unwrap();
+        assert_eq!(
+            refs::read_ref("refs/heads/other/x").unwrap(),
+            Some(one.clone())
+        );
+
+        // Another process holds the lock
+        std::fs::write(git_dir.join("refs/heads/master.lock"), "").unwrap();
+        let e = refs::write_ref("refs/heads/master", &one).unwrap_err();
+        assert!(e.to_string().starts_with("Unable to create"));
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(two.clone()));
+        std::fs::remove_file(git_dir.join("refs/heads/master.lock")).unwrap();
+
+        // Symbolic ref loop
+        std::fs::write(git_dir.join("refs/heads/a"), "ref: refs/heads/b\n").unwrap();
+        std::fs::write(git_dir.join("refs/heads/b"), "ref: refs/heads/a\n").unwrap();
+        assert!(refs::read_ref("refs/heads/a").is_err());
+    });
+}
@@ -1,6 +1,10 @@
+pub mod clone;
 pub mod fetch;
 mod protocol;
 
+#[cfg(test)]
+mod test;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct UploadPack {
@@ -39,13 +43,13 @@ impl UploadPack {
                     capabilities = GitRef::read_capabilities(&line);
                 }
                 s if s.starts_with("refs/heads") => {
-                    refs = GitRef::read_refs(&line);
+                    refs.extend(GitRef::read_refs(&line));
                 }
                 s if s.starts_with("refs/tags") => {
-                    tags = GitRef::read_refs(&line);
+                    tags.extend(GitRef::read_refs(&line));
                 }
                 s if s.starts_with("refs/pull") => {
-                    pulls = GitRef::read_refs(&line);
+                    pulls.extend(GitRef::read_refs(&line));
                 }
                 // Optional
                 // Looks at the HEAD line, for symrefs that do not start with "symref=HEAD:"
@@ -100,17 +104,13 @@ impl GitRef {
     }
 
     fn read_head(res: &str) -> Self {
-        dbg!(res);
         let components = res.splitn(2, " ").collect::<Vec<_>>();
         let hash = components[0].to_string();
         let comps = components[1]
             .split(' ')
             .filter(|x| x.starts_with("symref=HEAD:"))
             .collect::<Vec<&str>>()[0];
-        dbg!(comps);
         let name = comps.strip_prefix("symref=HEAD:").unwrap().to_string();
-        dbg!(&hash);
-        dbg!(&name);
         Self { name, hash }
     }
 
@@ -136,8 +136,16 @@ impl GitRef {
 
 #[derive(Debug)]
 #[allow(dead_code)]
-struct GitObject {
+pub struct GitObject {
     pub object_type: u8,
     pub size: usize,
     pub data: Vec<u8>,
 }
+
+impl GitObject {
+    // Name used in the header of the loose object
+    // Deltas (6 and 7) have no name
+    pub fn kind(&self) -> Option<&'static str> {
+        crate::pack::type_name(self.object_type)
+    }
+}
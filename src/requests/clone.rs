@@ -0,0 +1,109 @@
+use crate::{
+    config::Config, git_rust::RepoRust, objects::commit::Commit, pack, reflog, refs,
+    requests::UploadPack, worktree,
+};
+
+pub const REMOTE_NAME: &str = "origin";
+
+// Process of cloning, once the repo has been initialized:
+// 1. Fetch the advertisement and the packfile (see fetch)
+// 2. Store the packfile in objects/pack and index it
+// 3. Create the refs:
+//      refs/remotes/origin/<branch> for every advertised branch
+//      refs/tags/<tag> for every advertised tag
+//      refs/heads/<default branch> and HEAD pointing to it
+// 4. Write the remote and the upstream of the default branch to the config
+// 5. Check out the tree of HEAD into the working tree and the index
+pub fn clone_from_pack(url: &str, uploadpack: &UploadPack, packfile: &[u8]) -> std::io::Result<()> {
+    store_packfile(packfile)?;
+    let head_commit = write_refs(url, uploadpack)?;
+    write_config(url, uploadpack)?;
+    if let Some(commit) = head_commit {
+        let tree_hash = Commit::get_tree_from_commit(&commit)?;
+        worktree::checkout_tree(&tree_hash)?;
+    }
+    Ok(())
+}
+
+// Stores the packfile as is in objects/pack and writes its index
+// Returns the number of objects in the pack
+pub fn store_packfile(packfile: &[u8]) -> std::io::Result<usize> {
+    let checksum = hex::encode(pack::verify_checksum(packfile)?);
+    let pack_dir = RepoRust::get_object_folder(&RepoRust::get_root().absolute_path).join("pack");
+    std::fs::create_dir_all(&pack_dir)?;
+    // Written under a temporary name, so a pack that cannot be indexed is not left behind
+    let temp_path = pack_dir.join(format!("tmp_pack_{checksum}.pack"));
+    std::fs::write(&temp_path, packfile)?;
+    if let Err(e) = pack::index_pack(&temp_path) {
+        let _ = std::fs::remove_file(&temp_path);
+        let _ = std::fs::remove_file(temp_path.with_extension("idx"));
+        return Err(e);
+    }
+    // The .pack first: it is not read until its .idx is there
+    let pack_path = pack_dir.join(format!("pack-{checksum}.pack"));
+    std::fs::rename(&temp_path, &pack_path)?;
+    std::fs::rename(
+        temp_path.with_extension("idx"),
+        pack_path.with_extension("idx"),
+    )?;
+    let object_count = u32::from_be_bytes(packfile[8..12].try_into().unwrap());
+    Ok(object_count as usize)
+}
+
+// Creates the refs from the advertisement, all in one transaction
+// Returns the commit HEAD points to, if the remote has one
+pub fn write_refs(url: &str, uploadpack: &UploadPack) -> std::io::Result<Option<String>> {
+    let message = format!("clone: from {url}");
+    let mut transaction = refs::Transaction::new();
+    transaction.message(&message);
+    for git_ref in &uploadpack.refs {
+        if let Some(branch) = git_ref.name.strip_prefix("refs/heads/") {
+            transaction.update(
+                &format!("refs/remotes/{REMOTE_NAME}/{branch}"),
+                &git_ref.hash,
+            );
+        }
+    }
+    for git_ref in &uploadpack.tags {
+        // Peeled tags (^{}) point to the tagged object. Not a real ref
+        if git_ref.name.ends_with("^{}") {
+            continue;
+        }
+        transaction.update(&git_ref.name, &git_ref.hash);
+    }
+    if let Some(head) = &uploadpack.head {
+        transaction.update(&head.name, &head.hash);
+    }
+    transaction.commit()?;
+
+    let Some(head) = &uploadpack.head else {
+        return Ok(None);
+    };
+    refs::set_head_branch(&head.name)?;
+    reflog::append("HEAD", None, &head.hash, &message)?;
+    if let Some(branch) = head.name.strip_prefix("refs/heads/") {
+        refs::write_symref(
+            &format!("refs/remotes/{REMOTE_NAME}/HEAD"),
+            &format!("refs/remotes/{REMOTE_NAME}/{branch}"),
+        )?;
+    }
+    Ok(Some(head.hash.clone()))
+}
+
+// Writes the remote and sets it as upstream of the default branch
+fn write_config(url: &str, uploadpack: &UploadPack) -> std::io::Result<()> {
+    let mut config = Config::read()?;
+    config.set("core.repositoryformatversion", "0")?;
+    config.set(&format!("remote.{REMOTE_NAME}.url"), url)?;
+    config.set(
+        &format!("remote.{REMOTE_NAME}.fetch"),
+        &format!("+refs/heads/*:refs/remotes/{REMOTE_NAME}/*"),
+    )?;
+    if let Some(head) = &uploadpack.head
+        && let Some(branch) = head.name.strip_prefix("refs/heads/")
+    {
+        config.set(&format!("branch.{branch}.remote"), REMOTE_NAME)?;
+        config.set(&format!("branch.{branch}.merge"), &head.name)?;
+    }
+    config.write()
+}
@@ -1,11 +1,9 @@
-use std::io::{Cursor, Read};
-
-use byteorder::{BigEndian, ReadBytesExt};
-use flate2::read::ZlibDecoder;
-
-use crate::requests::{
-    GitObject, GitRef, UploadPack,
-    protocol::{get_request, post_request},
+use crate::{
+    pack::{read_entries, resolve_deltas},
+    requests::{
+        GitObject, GitRef, UploadPack,
+        protocol::{get_request, post_request},
+    },
 };
 
 // Process of making a fetch request
@@ -16,30 +14,37 @@ use crate::requests::{
 // 4. POST the payload to /git-upload-pack (The actual fetch request)
 //      -> Receive the commit and objects
 // 5. Decode the response
-// 6. Extract and process .pack file contents (objects, commits, etc.)
-pub fn fetch(url: &str, _dir: &str) -> std::io::Result<()> {
+// Returns the advertisement and the raw packfile. Processing the packfile is left to the caller
+pub fn fetch(url: &str) -> std::io::Result<(UploadPack, Vec<u8>)> {
     let payload = get_request(url)
         .map_err(|_| std::io::Error::other("Error fetching the git-upload-pack"))?;
     let content = read_pkt_lines(&payload);
-    // for line in &content[..5] {
-    //     let text = String::from_utf8(line.to_vec()).unwrap();
-    //     dbg!(text);
-    // }
 
     let uploadpack = UploadPack::from_response(content);
 
-    if uploadpack.head.is_none() {
+    let Some(head) = &uploadpack.head else {
         return Err(std::io::Error::other("missing HEAD"));
+    };
+    // Want everything that was advertised. Peeled tags (^{}) are not real refs
+    let mut want_commits: Vec<GitRef> = vec![GitRef {
+        name: head.name.clone(),
+        hash: head.hash.clone(),
+    }];
+    for git_ref in uploadpack.refs.iter().chain(uploadpack.tags.iter()) {
+        if git_ref.name.ends_with("^{}") || want_commits.iter().any(|w| w.hash == git_ref.hash) {
+            continue;
+        }
+        want_commits.push(GitRef {
+            name: git_ref.name.clone(),
+            hash: git_ref.hash.clone(),
+        });
     }
-    let want_commits = vec![uploadpack.head.unwrap()];
 
     let want_payload = write_pkt_lines(want_commits);
-    let object_payload = post_request(url, want_payload).unwrap();
+    let object_payload = post_request(url, want_payload)
+        .map_err(|_| std::io::Error::other("Error fetching the packfile"))?;
     let packfile_bytes = extract_packfile(&object_payload);
-
-    let objects = unpack_packfile(&packfile_bytes).unwrap();
-    dbg!(objects.len());
-    Ok(())
+    Ok((uploadpack, packfile_bytes))
 }
 
 // Parsing the Pkt-Line Format. Example:
@@ -154,129 +159,10 @@ fn extract_packfile(mut data: &[u8]) -> Vec<u8> {
     packfile
 }
 
-// Process the raw packfile
-// The format of the raw packfile:
-// 4 bytes - the word "PACK"
-// 4 bytes - the version. Usually 0002
-// 4 bytes - number of objects (big endian)
-// the rest - Object entries
-// last 20 bytes - SHA-1 checksum
-//
-// Format of object entries:
-// Header + Zlib compressed data
-// Header:
-// 7 6 5 4 3 2 1 0
-// C T T T S S S S
-// bites 0-3 - Size bits (S)
-// bites 4-6 - Object type (T)
-// bit   7   - Continuation bit (C)
-// Object types: 1 = commit / 2- tree / 3 - blob / 4 - tag / 6 - ofs-delta / 7 ref-delta
-//
-// Example 1 - 0b01100010
-// bites 0-3 -> 0100 (2 in decimal) -> check the continuation bit
-// bites 4-6 -> 011 -> (3 in decimal) Blob
-// bit   7   -> 0 -> stop reading header
-// When continuation bit 1 you, read another 7 bits of the size from the next byte
-// Example 2 - 0b111110100
-// Next byte :
-// bit   7   - another continuation bit
-// bites 0-6 - next 7 bits of the size value
-// Calculating the size. Example:
-// Byte 1: 0b10010011 -> size bits 0b0011 (3 in decimal)
-// Byte 2: 0b10000101 -> size bits 0b0000101 (5 in decimal)
-// Byte 3: 0b00000010 -> size bits 0b0000010 (2 in decimal)
-// Shifting:
-// 17 16 15 14 13 12 11 10 09 08 07 06 05 04 03 02 01 00
-//  0  0  0  0  0  1  0  0  0  0  0  1  0  1  0  0  1  1 -> 4179 in decimal
-// |Bytes 3              |Byte 2               |Byte 1  |
-//    (0b0000010 << 11)  +   (0000101 << 4)    +  0011
-fn unpack_packfile(packfile: &[u8]) -> std::io::Result<Vec<GitObject>> {
-    let mut cursor = Cursor::new(packfile);
-
-    let mut pack = [0u8; 4];
-    cursor.read_exact(&mut pack)?;
-
-    if &pack != b"PACK" {
-        return Err(std::io::Error::other("Invalid packfile header"));
-    }
-
-    let version = cursor.read_u32::<BigEndian>()?;
-    if version != 2 && version != 3 {
-        return Err(std::io::Error::other("Invalid packfile version"));
-    }
-
-    let object_count = cursor.read_u32::<BigEndian>()?;
-    dbg!(object_count);
-    let mut objects = Vec::new();
-
-    for _ in 0..object_count {
-        let (object_type, size) = read_type_and_size(&mut cursor)?;
-
-        match object_type {
-            6 => {
-                // If ofs-delta
-                let mut offset = 0usize;
-                loop {
-                    let byte = cursor.read_u8()?;
-                    offset = (offset << 7) | ((byte & 0x7F) as usize);
-                    if byte & 0x80 == 0 {
-                        break;
-                    }
-                }
-            }
-            // If ref-delta
-            7 => {
-                let mut base_id = [0u8; 20];
-                cursor.read_exact(&mut base_id)?;
-            }
-            _ => {}
-        };
-
-        let start_offset = cursor.position();
-
-        let remaining = &packfile[start_offset as usize..];
-        let mut decoder = ZlibDecoder::new(remaining);
-
-        let mut data = Vec::new();
-        decoder.read_to_end(&mut data).unwrap();
-
-        let consumed = decoder.total_in();
-
-        cursor.set_position(start_offset + consumed);
-
-        objects.push(GitObject {
-            object_type,
-            size,
-            data,
-        });
-    }
-    Ok(objects)
-}
-
-fn read_type_and_size(cursor: &mut Cursor<&[u8]>) -> std::io::Result<(u8, usize)> {
-    let mut size: usize;
-    let mut shift = 4;
-    let mut first_byte = [0u8; 1];
-
-    cursor.read_exact(&mut first_byte)?;
-    let byte = first_byte[0];
-    let object_type = (byte >> 4) & 0b111;
-    size = (byte & 0x0F) as usize;
-
-    // 0x80 = 10000000
-    if byte & 0x80 != 0 {
-        loop {
-            // Read the continuation byte again
-            let mut b = [0u8; 1];
-            cursor.read_exact(&mut b)?;
-
-            // 0x7F = 01111111
-            size |= ((b[0] & 0x7F) as usize) << shift;
-            shift += 7;
-            if b[0] & 0x80 == 0 {
-                break;
-            }
-        }
-    }
-    Ok((object_type, size))
+// Objects of the raw packfile, with the deltas resolved. See pack::read_entries
+#[allow(dead_code)]
+pub fn unpack_packfile(packfile: &[u8]) -> std::io::Result<Vec<GitObject>> {
+    let entries = read_entries(packfile)?;
+    let objects = resolve_deltas(entries)?;
+    Ok(objects.into_iter().map(|o| o.object).collect())
 }
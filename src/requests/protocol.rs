@@ -22,7 +22,6 @@ pub fn get_request(url: &str) -> Result<Vec<u8>, reqwest::Error> {
 #[allow(dead_code)]
 pub fn post_request(url: &str, payload: Vec<u8>) -> Result<Vec<u8>, reqwest::Error> {
     let url = format!("{url}.git/git-upload-pack");
-    dbg!(&url);
     let content = "application/x-git-upload-pack-request";
     let client = Client::new();
 
@@ -0,0 +1,302 @@
+use std::{io::Write, os::unix::fs::MetadataExt, path::PathBuf};
+
+use sha1::Digest;
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    index::Index,
+    objects,
+    requests::{
+        GitRef, UploadPack,
+        clone::{clone_from_pack, store_packfile},
+        fetch::unpack_packfile,
+    },
+    test_common::{git2_commit_files, git2_pack_commit, run_test},
+};
+
+fn upload_pack_for(branch: &str, hash: &str) -> UploadPack {
+    UploadPack {
+        head: Some(GitRef {
+            name: format!("refs/heads/{branch}"),
+            hash: hash.to_string(),
+        }),
+        refs: vec![GitRef {
+            name: format!("refs/heads/{branch}"),
+            hash: hash.to_string(),
+        }],
+        tags: Vec::new(),
+        pulls: Vec::new(),
+        symrefs: Vec::new(),
+        capabilities: Vec::new(),
+    }
+}
+
+#[test]
+fn test_clone_from_pack() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        // Remote repo created with git
+        let remote = path.join("remote");
+        let (repo, commit) = git2_commit_files(
+            &remote,
+            &[
+                ("readme.md", "# Read me\n"),
+                ("src/main.rs", "fn main() {}\n"),
+                ("src/lib/mod.rs", "pub mod lib;\n"),
+            ],
+        );
+        let packfile = git2_pack_commit(&repo, commit);
+
+        let local = path.join("local");
+        std::fs::create_dir(&local).unwrap();
+        RepoRust::new_repo(local.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let uploadpack = upload_pack_for("main", &commit.to_string());
+        clone_from_pack("https://example.com/repo", &uploadpack, &packfile).unwrap();
+
+        // HEAD and refs
+        let git_dir = local.join(BASE_DIR);
+        let head = std::fs::read_to_string(git_dir.join("HEAD")).unwrap();
+        assert_eq!(head, "ref: refs/heads/main\n");
+        let main = std::fs::read_to_string(git_dir.join("refs/heads/main")).unwrap();
+        assert_eq!(main.trim_end(), commit.to_string());
+        let remote_main =
+            std::fs::read_to_string(git_dir.join("refs/remotes/origin/main")).unwrap();
+        assert_eq!(remote_main.trim_end(), commit.to_string());
+        let config = std::fs::read_to_string(git_dir.join("config")).unwrap();
+        assert!(config.contains("url = https://example.com/repo"));
+
+        // The pack is stored as is, with its index
+        let pack_dir = git_dir.join("objects/pack");
+        let pack_files: Vec<PathBuf> = std::fs::read_dir(&pack_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(pack_files.len(), 2);
+        assert!(pack_files.iter().any(|p| p.extension().unwrap() == "idx"));
+
+        // Working tree
+        let main_rs = std::fs::read_to_string(local.join("src/main.rs")).unwrap();
+        assert_eq!(main_rs, "fn main() {}\n");
+        assert!(local.join("src/lib/mod.rs").exists());
+
+        // Index matches the tree of the commit
+        let index = Index::read_index().unwrap();
+        let paths: Vec<&String> = index.entries.keys().collect();
+        assert_eq!(paths, vec!["readme.md", "src/lib/mod.rs", "src/main.rs"]);
+        let git_tree = repo.find_commit(commit).unwrap().tree().unwrap();
+        let git_entry = git_tree
+            .get_path(std::path::Path::new("src/main.rs"))
+            .unwrap();
+        assert_eq!(index.entries["src/main.rs"].sha1, git_entry.id().as_bytes());
+    });
+}
+
+#[test]
+fn test_clone_into_non_empty_dir_fails() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        std::fs::write(path.join("file.txt"), "content").unwrap();
+
+        let matches = clap::Command::new("git_rust")
+            .subcommand(
+                clap::Command::new("clone")
+                    .arg(clap::Arg::new("url").required(true))
+                    .arg(clap::Arg::new("directory").required(true)),
+            )
+            .get_matches_from(vec![
+                "",
+                "clone",
+                "https://example.com/repo",
+                path.to_str().unwrap(),
+            ]);
+        let (_, args) = matches.subcommand().unwrap();
+        let result = RepoRust::clone(args);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+    });
+}
+
+#[test]
+fn test_unpack_packfile_resolves_deltas() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        // Similar versions of a big file, so the pack contains deltas
+        let remote = path.join("remote");
+        let mut content = String::new();
+        for i in 0..200 {
+            content.push_str(&format!("line number {i} of the file\n"));
+        }
+        let (repo, _) = git2_commit_files(&remote, &[("file.txt", &content)]);
+        content.push_str("one more line\n");
+        git2_commit_files(&remote, &[("file.txt", &content)]);
+        let content = content.replace("line number 100", "line changed 100");
+        let (repo_2, last_commit) = git2_commit_files(&remote, &[("file.txt", &content)]);
+        drop(repo);
+        let packfile = git2_pack_commit(&repo_2, last_commit);
+
+        // Check that the pack really has deltas
+        let object_count = u32::from_be_bytes(packfile[8..12].try_into().unwrap());
+        let objects = unpack_packfile(&packfile).unwrap();
+        assert_eq!(objects.len(), object_count as usize);
+        assert_eq!(objects.len(), 9);
+
+        let odb = repo_2.odb().unwrap();
+        for object in &objects {
+            assert!((1..=4).contains(&object.object_type));
+            let hash = objects::hash_object(object.kind().unwrap(), &object.data);
+            let git_object = odb.read(git2::Oid::from_str(&hash).unwrap()).unwrap();
+            assert_eq!(git_object.data(), object.data.as_slice());
+        }
+    });
+}
+
+// PACK header + one ref-delta entry whose base does not exist
+#[test]
+fn test_unpack_packfile_missing_base() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let delta: Vec<u8> = vec![4, 4, 0b1001_0000, 4];
+        let mut packfile: Vec<u8> = Vec::new();
+        packfile.extend_from_slice(b"PACK");
+        packfile.extend_from_slice(&2u32.to_be_bytes());
+        packfile.extend_from_slice(&1u32.to_be_bytes());
+        // Type 7, size 4
+        packfile.push((7 << 4) | delta.len() as u8);
+        packfile.extend_from_slice(&[0xAB; 20]);
+        packfile.extend_from_slice(&zlib(&delta));
+        packfile.extend_from_slice(&[0; 20]);
+
+        let result = unpack_packfile(&packfile);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("Missing delta base {}", hex::encode([0xAB; 20]))
+        );
+    });
+}
+
+// A pack that cannot be indexed is not left in objects/pack
+#[test]
+fn test_store_packfile_removes_pack_on_error() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        let delta: Vec<u8> = vec![4, 4, 0b1001_0000, 4];
+        let mut packfile: Vec<u8> = Vec::new();
+        packfile.extend_from_slice(b"PACK");
+        packfile.extend_from_slice(&2u32.to_be_bytes());
+        packfile.extend_from_slice(&1u32.to_be_bytes());
+        packfile.push((7 << 4) | delta.len() as u8);
+        packfile.extend_from_slice(&[0xAB; 20]);
+        packfile.extend_from_slice(&zlib(&delta));
+        let checksum: [u8; 20] = sha1::Sha1::digest(&packfile).into();
+        packfile.extend_from_slice(&checksum);
+
+        assert!(store_packfile(&packfile).is_err());
+        let pack_dir = path.join(BASE_DIR).join("objects/pack");
+        assert_eq!(std::fs::read_dir(pack_dir).unwrap().count(), 0);
+    });
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut enc = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    enc.write_all(data).unwrap();
+    enc.finish().unwrap()
+}
+
+// A blob followed by an ofs-delta of that blob, followed by an ofs-delta of the delta
+#[test]
+fn test_unpack_packfile_ofs_delta_chain() {
+    let base = b"hello world";
+    let mut packfile: Vec<u8> = Vec::new();
+    packfile.extend_from_slice(b"PACK");
+    packfile.extend_from_slice(&2u32.to_be_bytes());
+    packfile.extend_from_slice(&3u32.to_be_bytes());
+
+    let base_offset = packfile.len();
+    packfile.push((3 << 4) | base.len() as u8);
+    packfile.extend_from_slice(&zlib(base));
+
+    // "hello world" -> "hello there"
+    let delta_1: Vec<u8> = vec![11, 11, 0b1001_0000, 6, 5, b't', b'h', b'e', b'r', b'e'];
+    let delta_1_offset = packfile.len();
+    packfile.push((6 << 4) | delta_1.len() as u8);
+    packfile.push((delta_1_offset - base_offset) as u8);
+    packfile.extend_from_slice(&zlib(&delta_1));
+
+    // "hello there" -> "hello there!"
+    let delta_2: Vec<u8> = vec![11, 12, 0b1001_0000, 11, 1, b'!'];
+    let delta_2_offset = packfile.len();
+    packfile.push((6 << 4) | delta_2.len() as u8);
+    packfile.push((delta_2_offset - delta_1_offset) as u8);
+    packfile.extend_from_slice(&zlib(&delta_2));
+    packfile.extend_from_slice(&[0; 20]);
+
+    let objects = unpack_packfile(&packfile).unwrap();
+    assert_eq!(objects.len(), 3);
+    assert_eq!(objects[1].data, b"hello there");
+    assert_eq!(objects[2].data, b"hello there!");
+    assert!(objects.iter().all(|o| o.object_type == 3));
+}
+
+#[test]
+fn test_clone_checks_out_modes() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+
+        // Remote with a script, a symlink and a submodule
+        let remote = path.join("remote");
+        let repo = git2::Repository::init(&remote).unwrap();
+        let script = repo.blob(b"#!/bin/sh\n").unwrap();
+        let target = repo.blob(b"run.sh").unwrap();
+        let submodule = git2::Oid::from_str(&"ab".repeat(20)).unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        root.insert("run.sh", script, 0o100755).unwrap();
+        root.insert("link", target, 0o120000).unwrap();
+        root.insert("lib", submodule, 0o160000).unwrap();
+        let tree = repo.find_tree(root.write().unwrap()).unwrap();
+        let sig = git2::Signature::now("A U Thor", "author@example.com").unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &sig, &sig, "modes", &tree, &[])
+            .unwrap();
+        let packfile = git2_pack_commit(&repo, commit);
+
+        let local = path.join("local");
+        std::fs::create_dir(&local).unwrap();
+        RepoRust::new_repo(local.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let uploadpack = upload_pack_for("main", &commit.to_string());
+        clone_from_pack("https://example.com/repo", &uploadpack, &packfile).unwrap();
+
+        let script_mode = std::fs::metadata(local.join("run.sh")).unwrap().mode();
+        assert_eq!(script_mode & 0o111, 0o111);
+        assert_eq!(
+            std::fs::read_link(local.join("link")).unwrap(),
+            PathBuf::from("run.sh")
+        );
+        assert!(local.join("lib").is_dir());
+
+        let index = Index::read_index().unwrap();
+        assert_eq!(index.entries["run.sh"].mode, 0o100755);
+        assert_eq!(index.entries["link"].mode, 0o120000);
+        assert_eq!(index.entries["link"].sha1, target.as_bytes());
+        assert_eq!(index.entries["lib"].mode, 0o160000);
+        assert_eq!(index.entries["lib"].sha1, submodule.as_bytes());
+    });
+}
@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+
+use crate::{
+    config::Config,
+    index::Index,
+    log,
+    objects::{ObjectType, tree},
+    odb, reflog, refs,
+};
+
+#[cfg(test)]
+mod test;
+
+// Shortest prefix accepted for an object, same as git
+const MIN_PREFIX: usize = 4;
+// Length of the hashes printed by rev-parse --short
+pub const DEFAULT_ABBREV: usize = 7;
+
+// Where a name is looked for, in order (gitrevisions). The first that exists wins
+const REF_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+
+fn unknown(rev: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("ambiguous argument '{rev}': unknown revision or path not in the working tree."),
+    )
+}
+
+// Resolves a revision to the full hash of an object. Supported:
+// <sha1> (4 chars or more), <refname>, @, <rev>@{upstream}, <rev>@{n},
+// <rev>~n, <rev>^n, <rev>^{type}, <rev>^{}, <rev>:<path> and :<path> (the index)
+pub fn resolve(rev: &str) -> std::io::Result<String> {
+    if let Some((tree_ish, path)) = split_path(rev) {
+        return resolve_path(tree_ish, path);
+    }
+    let (name, selector, operators) = split_revision(rev)?;
+    let oid = resolve_base(rev, name, selector)?;
+    apply_operators(rev, oid, operators)
+}
+
+// Resolves and peels to the given type. Example: a tag or a commit given for a tree
+pub fn resolve_as(rev: &str, target: ObjectType) -> std::io::Result<String> {
+    peel(rev, &resolve(rev)?, Some(target))
+}
+
+// Full name of the ref a revision names, if it is one. Used by rev-parse --abbrev-ref
+// HEAD is the current branch, or HEAD itself when detached
+pub fn resolve_ref_name(rev: &str) -> std::io::Result<Option<String>> {
+    if split_path(rev).is_some() {
+        return Ok(None);
+    }
+    let (name, selector, operators) = split_revision(rev)?;
+    if !operators.is_empty() {
+        return Ok(None);
+    }
+    let name = if name == "@" { "HEAD" } else { name };
+    match selector {
+        None if name == "HEAD" => Ok(Some(
+            refs::symbolic_ref("HEAD")?.unwrap_or(name.to_string()),
+        )),
+        None => Ok(dwim_ref(name)?.map(|(full_name, _)| full_name)),
+        Some(selector) if is_upstream(selector) => upstream(&branch_ref(rev, name)?).map(Some),
+        Some(_) => Ok(None),
+    }
+}
+
+// refs/heads/main -> main, refs/remotes/origin/main -> origin/main
+pub fn shorten_ref(full_name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| full_name.strip_prefix(prefix))
+        .unwrap_or(full_name)
+}
+
+// The shortest prefix of at least min_len chars that no other object shares
+pub fn abbreviate(oid: &str, min_len: usize) -> std::io::Result<String> {
+    // Only the objects sharing the first min_len chars can need a longer prefix
+    let len = min_len.clamp(MIN_PREFIX, oid.len());
+    let shared = odb::database()
+        .with_prefix(&oid[..len])?
+        .iter()
+        .filter(|other| *other != oid)
+        .map(|other| {
+            other
+                .bytes()
+                .zip(oid.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+    let len = (shared + 1).max(min_len).clamp(MIN_PREFIX, oid.len());
+    Ok(oid[..len].to_string())
+}
+
+// Returns the full name and the hash of the first ref matching the rules
+fn dwim_ref(name: &str) -> std::io::Result<Option<(String, String)>> {
+    if name.is_empty() || name.contains("..") || name.starts_with('/') {
+        return Ok(None);
+    }
+    for (prefix, suffix) in REF_RULES {
+        // Files in the git dir are only refs when named like HEAD or FETCH_HEAD
+        if prefix.is_empty()
+            && !name.starts_with("refs/")
+            && !name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+        {
+            continue;
+        }
+        let full_name = format!("{prefix}{name}{suffix}");
+        if let Some(oid) = refs::read_ref(&full_name)? {
+            return Ok(Some((full_name, oid)));
+        }
+    }
+    Ok(None)
+}
+
+// Splits <rev>:<path>. The ':' of ^{...} is not a separator
+fn split_path(rev: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some((&rev[..i], &rev[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+// master@{u}~2^{tree} -> ("master", Some("u"), "~2^{tree}")
+fn split_revision(rev: &str) -> std::io::Result<(&str, Option<&str>, &str)> {
+    let end = rev.find(['~', '^']).unwrap_or(rev.len());
+    let (name, operators) = rev.split_at(end);
+    match name.find("@{") {
+        Some(at) => {
+            let selector = name[at + 2..]
+                .strip_suffix('}')
+                .ok_or_else(|| unknown(rev))?;
+            Ok((&name[..at], Some(selector), operators))
+        }
+        None => Ok((name, None, operators)),
+    }
+}
+
+fn is_upstream(selector: &str) -> bool {
+    selector.eq_ignore_ascii_case("u") || selector.eq_ignore_ascii_case("upstream")
+}
+
+fn resolve_base(rev: &str, name: &str, selector: Option<&str>) -> std::io::Result<String> {
+    let name = if name == "@" { "HEAD" } else { name };
+    let Some(selector) = selector else {
+        if name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(name.to_lowercase());
+        }
+        if let Some((_, oid)) = dwim_ref(name)? {
+            return Ok(oid);
+        }
+        return find_unique_prefix(name)?.ok_or_else(|| unknown(rev));
+    };
+
+    let full_name = if is_upstream(selector) {
+        upstream(&branch_ref(rev, name)?)?
+    } else {
+        // @{n} and @{date} alone are the reflog of the current branch, not of HEAD
+        let full_name = match name {
+            "" => branch_ref(rev, name)?,
+            name => dwim_ref(name)?.ok_or_else(|| unknown(rev))?.0,
+        };
+        if let Ok(n) = selector.parse::<usize>() {
+            return reflog::nth(&full_name, n);
+        }
+        let timestamp = log::parse_date(selector).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unsupported selector '@{{{selector}}}' in '{rev}'"),
+            )
+        })?;
+        return reflog::at(&full_name, timestamp);
+    };
+    refs::read_ref(&full_name)?.ok_or_else(|| unknown(rev))
+}
+
+// The branch a name refers to. Empty and HEAD are the current branch
+fn branch_ref(rev: &str, name: &str) -> std::io::Result<String> {
+    if name.is_empty() || name == "HEAD" {
+        return refs::symbolic_ref("HEAD")?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "HEAD does not point to a branch",
+            )
+        });
+    }
+    let full_name = format!("refs/heads/{name}");
+    if refs::read_ref(&full_name)?.is_none() {
+        return Err(unknown(rev));
+    }
+    Ok(full_name)
+}
+
+// Remote-tracking ref of a branch, from branch.<name>.remote and branch.<name>.merge
+pub fn upstream(branch_ref: &str) -> std::io::Result<String> {
+    let branch = branch_ref.strip_prefix("refs/heads/").unwrap_or(branch_ref);
+    let config = Config::read()?;
+    let remote = config.get(&format!("branch.{branch}.remote"));
+    let merge = config.get(&format!("branch.{branch}.merge"));
+    let (Some(remote), Some(merge)) = (remote, merge) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no upstream configured for branch '{branch}'"),
+        ));
+    };
+    // "." is the local repo
+    if remote == "." {
+        return Ok(merge.to_string());
+    }
+    let merge = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+    Ok(format!("refs/remotes/{remote}/{merge}"))
+}
+
+fn find_unique_prefix(prefix: &str) -> std::io::Result<Option<String>> {
+    if prefix.len() < MIN_PREFIX
+        || prefix.len() > 40
+        || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Ok(None);
+    }
+    let prefix = prefix.to_lowercase();
+    let database = odb::database();
+    let mut candidates = database.with_prefix(&prefix)?;
+    if candidates.len() < 2 {
+        return Ok(candidates.pop());
+    }
+    candidates.sort();
+    let mut message = format!("short object ID {prefix} is ambiguous\nhint: The candidates are:");
+    for oid in candidates {
+        let object_type = database.read(&oid)?.0;
+        message.push_str(&format!(
+            "\nhint:   {} {object_type}",
+            abbreviate(&oid, DEFAULT_ABBREV)?
+        ));
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+// ~n: n-th first parent. ^n: n-th parent, ^0 the commit itself
+// ^{type}: peels tags (and commits for tree). ^{}: peels tags only
+fn apply_operators(rev: &str, mut oid: String, operators: &str) -> std::io::Result<String> {
+    let mut rest = operators;
+    while let Some(operator) = rest.chars().next() {
+        if operator != '~' && operator != '^' {
+            return Err(unknown(rev));
+        }
+        rest = &rest[operator.len_utf8()..];
+        if operator == '^' && rest.starts_with('{') {
+            let end = rest.find('}').ok_or_else(|| unknown(rev))?;
+            let target = match &rest[1..end] {
+                "" => None,
+                "object" => {
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+                kind => Some(ObjectType::from_name(kind).ok_or_else(|| unknown(rev))?),
+            };
+            oid = peel(rev, &oid, target)?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let n: usize = match digits {
+            0 => 1,
+            _ => rest[..digits].parse().map_err(|_| unknown(rev))?,
+        };
+        rest = &rest[digits..];
+
+        let commit = peel(rev, &oid, Some(ObjectType::Commit))?;
+        oid = match operator {
+            '~' => {
+                let mut commit = commit;
+                for _ in 0..n {
+                    commit = parents(&commit)?
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| unknown(rev))?;
+                }
+                commit
+            }
+            '^' if n == 0 => commit,
+            _ => parents(&commit)?
+                .into_iter()
+                .nth(n - 1)
+                .ok_or_else(|| unknown(rev))?,
+        };
+    }
+    Ok(oid)
+}
+
+// Follows tags, and commits to their tree, until an object of the given type
+// None stops at the first object that is not a tag. rev is only used for errors
+fn peel(rev: &str, oid: &str, target: Option<ObjectType>) -> std::io::Result<String> {
+    let database = odb::database();
+    let mut oid = oid.to_string();
+    loop {
+        let (object_type, content) = database.read(&oid)?;
+        let peeled = match target {
+            Some(target) => object_type == target,
+            None => object_type != ObjectType::Tag,
+        };
+        if peeled {
+            return Ok(oid);
+        }
+        let next = match object_type {
+            ObjectType::Tag => header_values(&content, "object").pop(),
+            ObjectType::Commit if target == Some(ObjectType::Tree) => {
+                header_values(&content, "tree").pop()
+            }
+            _ => None,
+        };
+        oid = next.ok_or_else(|| {
+            let target = target.map(|t| t.to_string()).unwrap_or_default();
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{rev}: expected {target} type, but the object dereferences to {object_type} type"
+                ),
+            )
+        })?;
+    }
+}
+
+pub fn parents(commit: &str) -> std::io::Result<Vec<String>> {
+    let (_, content) = odb::database().read(commit)?;
+    Ok(header_values(&content, "parent"))
+}
+
+// True if ancestor is commit or can be reached from it by following parents
+pub fn is_ancestor(ancestor: &str, commit: &str) -> std::io::Result<bool> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![commit.to_string()];
+    while let Some(current) = stack.pop() {
+        if current == ancestor {
+            return Ok(true);
+        }
+        if seen.insert(current.clone()) {
+            stack.extend(parents(&current)?);
+        }
+    }
+    Ok(false)
+}
+
+// Values of a header of a commit or tag. The headers end at the first empty line
+fn header_values(content: &[u8], key: &str) -> Vec<String> {
+    content
+        .split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let line = String::from_utf8_lossy(line);
+            line.strip_prefix(key)?
+                .strip_prefix(' ')
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+// :<path> is the index (:0:<path> is stage 0). <rev>:<path> is a path in the tree of rev
+fn resolve_path(tree_ish: &str, path: &str) -> std::io::Result<String> {
+    if tree_ish.is_empty() {
+        let path = path.strip_prefix("0:").unwrap_or(path);
+        let index = Index::read_index()?;
+        return index
+            .entries
+            .get(path)
+            .map(|entry| hex::encode(entry.sha1))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("path '{path}' does not exist in the index"),
+                )
+            });
+    }
+
+    let mut oid = resolve_as(tree_ish, ObjectType::Tree)?;
+    let not_found = || {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("path '{path}' does not exist in '{tree_ish}'"),
+        )
+    };
+    let database = odb::database();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let (object_type, content) = database.read(&oid)?;
+        if object_type != ObjectType::Tree {
+            return Err(not_found());
+        }
+        oid = tree::raw_entries(&content)?
+            .into_iter()
+            .find(|(_, entry_name, _)| entry_name == name)
+            .map(|(_, _, entry_hash)| entry_hash)
+            .ok_or_else(not_found)?;
+    }
+    Ok(oid)
+}
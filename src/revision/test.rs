@@ -0,0 +1,242 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    objects::{self, ObjectType},
+    odb::{self, MemoryDatabase, ObjectDatabase},
+    refs::write_ref,
+    revision,
+    test_common::{run_test, run_test_matches},
+};
+
+// Tree with file.txt and dir/sub.txt
+fn write_tree(content: &str) -> String {
+    let blob = objects::write_object(ObjectType::Blob, content.as_bytes()).unwrap();
+    let mut sub_tree = b"100644 sub.txt\0".to_vec();
+    sub_tree.extend_from_slice(&hex::decode(&blob).unwrap());
+    let sub_tree = objects::write_object(ObjectType::Tree, &sub_tree).unwrap();
+    let mut tree = b"40000 dir\0".to_vec();
+    tree.extend_from_slice(&hex::decode(&sub_tree).unwrap());
+    tree.extend_from_slice(b"100644 file.txt\0");
+    tree.extend_from_slice(&hex::decode(&blob).unwrap());
+    objects::write_object(ObjectType::Tree, &tree).unwrap()
+}
+
+fn write_commit(content: &str, parents: &[&str]) -> String {
+    let mut commit = format!("tree {}\n", write_tree(content));
+    for parent in parents {
+        commit.push_str(&format!("parent {parent}\n"));
+    }
+    commit.push_str("author A U Thor <author@example.com> 1700000000 +0000\n");
+    commit.push_str("committer A U Thor <author@example.com> 1700000000 +0000\n\n");
+    commit.push_str(content);
+    objects::write_object(ObjectType::Commit, commit.as_bytes()).unwrap()
+}
+
+fn write_tag(object: &str, name: &str) -> String {
+    let tag = format!(
+        "object {object}\ntype commit\ntag {name}\ntagger A U Thor <author@example.com> 1700000000 +0000\n\nrelease\n"
+    );
+    objects::write_object(ObjectType::Tag, tag.as_bytes()).unwrap()
+}
+
+#[test]
+fn test_resolve_ancestry_and_peeling() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // c1 - c2 - merge
+        //    \ side /
+        let c1 = write_commit("one\n", &[]);
+        let c2 = write_commit("two\n", &[&c1]);
+        let side = write_commit("side\n", &[&c1]);
+        let merge = write_commit("merge\n", &[&c2, &side]);
+        write_ref("refs/heads/master", &merge).unwrap();
+        let tag = write_tag(&c2, "v1");
+        write_ref("refs/tags/v1", &tag).unwrap();
+
+        for rev in ["HEAD", "@", "master", "heads/master", "refs/heads/master"] {
+            assert_eq!(revision::resolve(rev).unwrap(), merge, "{rev}");
+        }
+        assert_eq!(revision::resolve("HEAD^").unwrap(), c2);
+        assert_eq!(revision::resolve("HEAD~1").unwrap(), c2);
+        assert_eq!(revision::resolve("HEAD^2").unwrap(), side);
+        assert_eq!(revision::resolve("HEAD~2").unwrap(), c1);
+        assert_eq!(revision::resolve("master^2~").unwrap(), c1);
+        assert_eq!(revision::resolve("HEAD^0").unwrap(), merge);
+
+        // Tags are peeled by ^{} and by the ancestry operators
+        assert_eq!(revision::resolve("v1").unwrap(), tag);
+        assert_eq!(revision::resolve("v1^{}").unwrap(), c2);
+        assert_eq!(revision::resolve("v1^{commit}").unwrap(), c2);
+        assert_eq!(revision::resolve("v1~1").unwrap(), c1);
+        let tree = revision::resolve("v1^{tree}").unwrap();
+        assert_eq!(revision::resolve_as("v1", ObjectType::Tree).unwrap(), tree);
+
+        // Paths in a tree
+        assert_eq!(revision::resolve("v1:").unwrap(), tree);
+        let blob = objects::hash_object("blob", b"two\n");
+        assert_eq!(revision::resolve("v1:dir/sub.txt").unwrap(), blob);
+        assert_eq!(revision::resolve("master~1:file.txt").unwrap(), blob);
+
+        let kind = |rev: &str| revision::resolve(rev).unwrap_err().kind();
+        assert_eq!(kind("HEAD~3"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("HEAD^3"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("master:missing.txt"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("master:file.txt/x"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("v1:file.txt^{tree}"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("nope"), std::io::ErrorKind::NotFound);
+        // Only ~ and ^ can follow a suffix, whatever the character
+        assert_eq!(kind("HEAD~1x"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("HEAD~é"), std::io::ErrorKind::NotFound);
+        assert_eq!(kind("HEAD^2é"), std::io::ErrorKind::NotFound);
+        let error = revision::resolve(&format!("{blob}^{{tree}}")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().ends_with("dereferences to blob type"));
+    });
+}
+
+#[test]
+fn test_resolve_abbreviated_hashes() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        let memory = Arc::new(MemoryDatabase::default());
+        odb::set_database(memory.clone());
+
+        // Writes blobs until two of them start with the same 4 chars
+        let mut first = String::new();
+        let mut second = String::new();
+        for i in 0.. {
+            let oid = memory
+                .write(ObjectType::Blob, format!("blob {i}").as_bytes())
+                .unwrap();
+            let twin = memory
+                .iter()
+                .unwrap()
+                .find(|other| *other != oid && other[..4] == oid[..4]);
+            if let Some(twin) = twin {
+                (first, second) = (twin, oid);
+                break;
+            }
+        }
+
+        let error = revision::resolve(&first[..4]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("is ambiguous"));
+        assert!(error.to_string().contains(&first[..7]));
+        assert!(error.to_string().contains(&second[..7]));
+
+        let unique = revision::abbreviate(&first, 4).unwrap();
+        assert!(unique.len() > 4);
+        assert_eq!(revision::resolve(&unique).unwrap(), first);
+        assert_eq!(revision::resolve(&unique.to_uppercase()).unwrap(), first);
+        assert_eq!(revision::resolve(&second).unwrap(), second);
+        // Too short to be a hash
+        assert!(revision::resolve(&first[..3]).is_err());
+    });
+}
+
+#[test]
+fn test_resolve_refs_upstream_and_reflog() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let git_dir = path.join(BASE_DIR);
+
+        // Nothing committed yet
+        assert!(revision::resolve("HEAD").is_err());
+
+        let old = write_commit("old\n", &[]);
+        let new = write_commit("new\n", &[&old]);
+        let remote = write_commit("remote\n", &[&new]);
+        write_ref("refs/heads/master", &new).unwrap();
+        write_ref("refs/remotes/origin/master", &remote).unwrap();
+        std::fs::write(
+            git_dir.join("refs/remotes/origin/HEAD"),
+            "ref: refs/remotes/origin/master\n",
+        )
+        .unwrap();
+        std::fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled fully-peeled sorted\n{old} refs/tags/packed\n"),
+        )
+        .unwrap();
+        assert_eq!(revision::resolve("origin").unwrap(), remote);
+        assert_eq!(revision::resolve("packed").unwrap(), old);
+
+        // Upstream comes from the config, as written by clone
+        assert!(revision::resolve("@{u}").is_err());
+        std::fs::write(
+            git_dir.join("config"),
+            "[branch \"master\"]\n\tremote = origin\n\tmerge = refs/heads/master\n",
+        )
+        .unwrap();
+        assert_eq!(revision::resolve("@{u}").unwrap(), remote);
+        assert_eq!(revision::resolve("master@{upstream}~1").unwrap(), new);
+        let name = revision::resolve_ref_name("HEAD@{u}").unwrap().unwrap();
+        assert_eq!(revision::shorten_ref(&name), "origin/master");
+        let name = revision::resolve_ref_name("@").unwrap().unwrap();
+        assert_eq!(name, "refs/heads/master");
+
+        // Newest entry last
+        let zero = "0".repeat(40);
+        std::fs::create_dir_all(git_dir.join("logs/refs/heads")).unwrap();
+        std::fs::write(
+            git_dir.join("logs/refs/heads/master"),
+            format!(
+                "{zero} {old} A U Thor <author@example.com> 1700000000 +0000\tcommit (initial): old\n\
+                 {old} {new} A U Thor <author@example.com> 1700000100 +0000\tcommit: new\n"
+            ),
+        )
+        .unwrap();
+        assert_eq!(revision::resolve("master@{0}").unwrap(), new);
+        assert_eq!(revision::resolve("@{1}").unwrap(), old);
+        assert!(revision::resolve("master@{2}").is_err());
+        assert_eq!(revision::resolve("master@{yesterday}").unwrap(), new);
+    });
+}
+
+#[test]
+fn test_commands_take_revisions() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let first = write_commit("first\n", &[]);
+        let second = write_commit("second\n", &[&first]);
+        write_ref("refs/heads/master", &second).unwrap();
+
+        let file = format!("{}:file.txt", &second[..7]);
+        let args = run_test_matches(vec!["", "cat-file", "-p", &file]);
+        assert!(RepoRust::cat_file(&args).is_ok());
+        let args = run_test_matches(vec!["", "cat-file", "-p", "ab"]);
+        assert!(RepoRust::cat_file(&args).is_err());
+        let args = run_test_matches(vec!["", "ls-tree", "HEAD~1"]);
+        assert!(RepoRust::ls_tree(&args).is_ok());
+
+        let args = run_test_matches(vec!["", "tag", "v0", "HEAD^"]);
+        RepoRust::tag(&args).unwrap();
+        let tag_ref = std::fs::read_to_string(path.join(BASE_DIR).join("refs/tags/v0")).unwrap();
+        assert_eq!(tag_ref.trim_end(), first);
+
+        let args = run_test_matches(vec!["", "rev-parse", "--short", "HEAD", "v0", "@{5}"]);
+        assert!(RepoRust::rev_parse(&args).is_err());
+        let args = run_test_matches(vec!["", "rev-parse", "--short=10", "HEAD", "v0^{tree}"]);
+        assert!(RepoRust::rev_parse(&args).is_ok());
+        let args = run_test_matches(vec!["", "rev-parse", "--abbrev-ref", "HEAD"]);
+        assert!(RepoRust::rev_parse(&args).is_ok());
+        let args = run_test_matches(vec!["", "rev-parse", "--verify", "HEAD", "v0"]);
+        assert!(RepoRust::rev_parse(&args).is_err());
+        let missing = "1".repeat(40);
+        let args = run_test_matches(vec!["", "rev-parse", "--verify", &missing]);
+        assert!(RepoRust::rev_parse(&args).is_err());
+    });
+}
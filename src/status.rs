@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    os::unix::fs::MetadataExt,
+    path::Path,
+};
+
+use ignore::gitignore::Gitignore;
+
+use crate::{
+    diff::{self, FileChange, Side},
+    git_rust::{BASE_DIR, RepoRust},
+    index::{Index, IndexEntry},
+    merge,
+    objects::{
+        ObjectType,
+        tree::{MODE_GITLINK, Tree},
+    },
+    refs, revision,
+};
+
+#[cfg(test)]
+mod test;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+impl Change {
+    // Letter used by status --short
+    pub fn letter(&self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Deleted => 'D',
+            Self::TypeChanged => 'T',
+            Self::Renamed => 'R',
+            Self::Copied => 'C',
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Added => "new file:",
+            Self::Modified => "modified:",
+            Self::Deleted => "deleted:",
+            Self::TypeChanged => "typechange:",
+            Self::Renamed => "renamed:",
+            Self::Copied => "copied:",
+        }
+    }
+
+    // Same path, other content. A different kind of object (file -> symlink) is a typechange
+    pub fn between(
+        old_mode: u32,
+        old_hash: &[u8; 20],
+        new_mode: u32,
+        new_hash: &[u8; 20],
+    ) -> Option<Self> {
+        if old_mode & 0o170000 != new_mode & 0o170000 {
+            Some(Self::TypeChanged)
+        } else if old_mode != new_mode || old_hash != new_hash {
+            Some(Self::Modified)
+        } else {
+            None
+        }
+    }
+}
+
+// An unmerged path, named after the stages it has: base (1), ours (2) and theirs (3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl Conflict {
+    pub fn from_stages(stages: &[IndexEntry]) -> Self {
+        let has = |stage| stages.iter().any(|entry| entry.stage() == stage);
+        match (has(1), has(2), has(3)) {
+            (true, false, false) => Self::BothDeleted,
+            (false, true, false) => Self::AddedByUs,
+            (true, true, false) => Self::DeletedByThem,
+            (false, false, true) => Self::AddedByThem,
+            (true, false, true) => Self::DeletedByUs,
+            (false, true, true) => Self::BothAdded,
+            _ => Self::BothModified,
+        }
+    }
+
+    // Letters used by status --short
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BothDeleted => "DD",
+            Self::AddedByUs => "AU",
+            Self::DeletedByThem => "UD",
+            Self::AddedByThem => "UA",
+            Self::DeletedByUs => "DU",
+            Self::BothAdded => "AA",
+            Self::BothModified => "UU",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::BothDeleted => "both deleted:",
+            Self::AddedByUs => "added by us:",
+            Self::DeletedByThem => "deleted by them:",
+            Self::AddedByThem => "added by them:",
+            Self::DeletedByUs => "deleted by us:",
+            Self::BothAdded => "both added:",
+            Self::BothModified => "both modified:",
+        }
+    }
+}
+
+pub struct Status {
+    // None when HEAD is detached
+    pub branch: Option<String>,
+    pub head: Option<String>,
+    // HEAD -> index
+    pub staged: BTreeMap<String, Change>,
+    // Old path of the files staged as renamed
+    pub renamed: BTreeMap<String, String>,
+    // Paths left with conflicts by a merge
+    pub unmerged: BTreeMap<String, Conflict>,
+    // A merge is waiting to be committed (MERGE_HEAD exists)
+    pub merging: bool,
+    // Index -> working tree
+    pub unstaged: BTreeMap<String, Change>,
+    // Folders with no tracked file are listed once, with a trailing /
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.unmerged.is_empty()
+    }
+
+    // old -> new for a renamed file
+    fn display_path(&self, path: &str) -> String {
+        match self.renamed.get(path) {
+            Some(old) => format!("{old} -> {path}"),
+            None => path.to_string(),
+        }
+    }
+
+    // status --short and --porcelain=v1. XY <path>, X is the index and Y the working tree
+    pub fn short(&self) -> String {
+        let paths: BTreeSet<&String> = self.staged.keys().chain(self.unstaged.keys()).collect();
+        let mut output = String::new();
+        for (path, conflict) in &self.unmerged {
+            output.push_str(&format!("{} {path}\n", conflict.code()));
+        }
+        for path in paths {
+            let letter = |changes: &BTreeMap<String, Change>| {
+                changes.get(path).map_or(' ', |change| change.letter())
+            };
+            output.push_str(&format!(
+                "{}{} {}\n",
+                letter(&self.staged),
+                letter(&self.unstaged),
+                self.display_path(path)
+            ));
+        }
+        for path in &self.untracked {
+            output.push_str(&format!("?? {path}\n"));
+        }
+        output
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.branch, &self.head) {
+            (Some(branch), _) => writeln!(f, "On branch {branch}")?,
+            (None, Some(head)) => writeln!(f, "HEAD detached at {}", &head[..7])?,
+            (None, None) => writeln!(f, "Not currently on any branch.")?,
+        }
+        if self.head.is_none() {
+            writeln!(f, "\nNo commits yet")?;
+        }
+        match (self.merging, self.unmerged.is_empty()) {
+            (true, false) => writeln!(f, "\nYou have unmerged paths.")?,
+            (true, true) => writeln!(f, "\nAll conflicts fixed but you are still merging.")?,
+            _ => {}
+        }
+        if !self.staged.is_empty() {
+            writeln!(f, "\nChanges to be committed:")?;
+            for (path, change) in &self.staged {
+                let path = match *change {
+                    Change::Renamed => self.display_path(path),
+                    _ => path.clone(),
+                };
+                writeln!(f, "\t{:<12}{path}", change.label())?;
+            }
+        }
+        if !self.unmerged.is_empty() {
+            writeln!(f, "\nUnmerged paths:")?;
+            for (path, conflict) in &self.unmerged {
+                writeln!(f, "\t{:<17}{path}", conflict.label())?;
+            }
+        }
+        if !self.unstaged.is_empty() {
+            writeln!(f, "\nChanges not staged for commit:")?;
+            for (path, change) in &self.unstaged {
+                writeln!(f, "\t{:<12}{path}", change.label())?;
+            }
+        }
+        if !self.untracked.is_empty() {
+            writeln!(f, "\nUntracked files:")?;
+            for path in &self.untracked {
+                writeln!(f, "\t{path}")?;
+            }
+        }
+        if self.is_clean() {
+            match (self.untracked.is_empty(), self.head.is_some()) {
+                (true, true) => writeln!(f, "\nnothing to commit, working tree clean")?,
+                (true, false) => writeln!(f, "\nnothing to commit")?,
+                (false, _) => writeln!(f, "\nnothing added to commit but untracked files present")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Compares the tree of HEAD with the index, and the index with the working tree
+// Files whose stat data did not change are not hashed again. Entries that had to be
+// hashed but did not change get their stat data refreshed in the index
+pub fn status() -> std::io::Result<Status> {
+    let root = RepoRust::get_root().absolute_path.clone();
+    let head_ref = std::fs::read_to_string(root.join(BASE_DIR).join("HEAD"))?;
+    let branch = head_ref
+        .trim()
+        .strip_prefix("ref: ")
+        .map(|name| revision::shorten_ref(name).to_string());
+    let head = refs::read_ref("HEAD")?;
+
+    let mut index = match Index::read_index() {
+        Ok(index) => index,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Index::default(),
+        Err(e) => return Err(e),
+    };
+
+    let head_files = match &head {
+        Some(commit) => Tree::flatten(&revision::resolve_as(commit, ObjectType::Tree)?)?,
+        None => BTreeMap::new(),
+    };
+    let mut staged = BTreeMap::new();
+    for (path, entry) in &index.entries {
+        let change = match head_files.get(path) {
+            Some(head_entry) => {
+                let head_mode = u32::from_str_radix(&head_entry.mode, 8).unwrap_or_default();
+                Change::between(head_mode, &head_entry.hash, entry.mode, &entry.sha1)
+            }
+            None => Some(Change::Added),
+        };
+        if let Some(change) = change {
+            staged.insert(path.clone(), change);
+        }
+    }
+    for path in head_files.keys() {
+        if !index.entries.contains_key(path) && !index.conflicts.contains_key(path) {
+            staged.insert(path.clone(), Change::Deleted);
+        }
+    }
+    // A deleted file and an added one with similar content are a rename
+    let added_or_deleted = staged
+        .iter()
+        .filter(|(_, change)| matches!(change, Change::Added | Change::Deleted))
+        .map(|(path, _)| FileChange {
+            path: path.clone(),
+            old: head_files.get(path).map(Side::from_tree_entry),
+            new: index.entries.get(path).map(Side::from_index_entry),
+            worktree: false,
+            source: None,
+        })
+        .collect();
+    let mut renamed = BTreeMap::new();
+    for change in diff::detect_renames(added_or_deleted, diff::DEFAULT_SIMILARITY, false)? {
+        if let Some(source) = change.source {
+            staged.remove(&source.path);
+            staged.insert(change.path.clone(), Change::Renamed);
+            renamed.insert(change.path, source.path);
+        }
+    }
+
+    let index_mtime = index_mtime();
+    let mut unstaged = BTreeMap::new();
+    let mut refreshed = false;
+    for (path, entry) in index.entries.iter_mut() {
+        match worktree_change(&root.join(path), entry, index_mtime)? {
+            WorktreeState::Changed(change) => {
+                unstaged.insert(path.clone(), change);
+            }
+            WorktreeState::Refreshed(new_entry) => {
+                *entry = new_entry;
+                refreshed = true;
+            }
+            WorktreeState::Unchanged => {}
+        }
+    }
+    if refreshed {
+        Index::from_entries(index.entries.clone())
+            .with_conflicts(index.conflicts.clone())
+            .write_index_to_file()?;
+    }
+    let unmerged = index
+        .conflicts
+        .iter()
+        .map(|(path, stages)| (path.clone(), Conflict::from_stages(stages)))
+        .collect();
+
+    let mut untracked = Vec::new();
+    let matcher = Index::ignore_matcher();
+    untracked_files(&root, &root, &index, &matcher, &mut untracked)?;
+    untracked.sort();
+
+    Ok(Status {
+        branch,
+        head,
+        staged,
+        renamed,
+        unmerged,
+        merging: merge::merge_head()?.is_some(),
+        unstaged,
+        untracked,
+    })
+}
+
+// Modification time of the index file, used to detect racily clean entries
+pub fn index_mtime() -> Option<(i64, i64)> {
+    let root = &RepoRust::get_root().absolute_path;
+    std::fs::metadata(root.join(BASE_DIR).join("index"))
+        .map(|m| (m.mtime(), m.mtime_nsec()))
+        .ok()
+}
+
+pub enum WorktreeState {
+    Unchanged,
+    Changed(Change),
+    // Same content, new stat data
+    Refreshed(IndexEntry),
+}
+
+pub fn worktree_change(
+    path: &Path,
+    entry: &IndexEntry,
+    index_mtime: Option<(i64, i64)>,
+) -> std::io::Result<WorktreeState> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(WorktreeState::Changed(Change::Deleted));
+    };
+
+    // Submodules only change when their HEAD moves. Not cloned ones are an empty folder
+    if entry.mode == MODE_GITLINK {
+        if !metadata.is_dir() {
+            return Ok(WorktreeState::Changed(Change::TypeChanged));
+        }
+        if !path.join(".git").exists() {
+            return Ok(WorktreeState::Unchanged);
+        }
+        return Ok(match Index::index_entry_from_gitlink(path) {
+            Ok(current) if current.sha1 == entry.sha1 => WorktreeState::Unchanged,
+            _ => WorktreeState::Changed(Change::Modified),
+        });
+    }
+    if metadata.is_dir() {
+        return Ok(WorktreeState::Changed(Change::Deleted));
+    }
+
+    let mode = Index::mode_from_metadata(&metadata);
+    if mode != entry.mode {
+        let change = Change::between(entry.mode, &entry.sha1, mode, &entry.sha1);
+        return Ok(WorktreeState::Changed(change.unwrap_or(Change::Modified)));
+    }
+    // Racy git: a file changed in the same instant the index was written keeps
+    // the stat data of the index entry. Entries not older than the index are hashed
+    let racy = index_mtime
+        .is_none_or(|index_mtime| (entry.mtime as i64, entry.mtime_nanos as i64) >= index_mtime);
+    if entry.stat_matches(&metadata) && !racy {
+        return Ok(WorktreeState::Unchanged);
+    }
+    let content = Index::file_content(path, &metadata)?;
+    if Index::sha1_entry(&content) != entry.sha1 {
+        return Ok(WorktreeState::Changed(Change::Modified));
+    }
+    if entry.stat_matches(&metadata) {
+        return Ok(WorktreeState::Unchanged);
+    }
+    Ok(WorktreeState::Refreshed(Index::index_entry_from_file(
+        path,
+    )?))
+}
+
+// Files of dir that are not in the index and not ignored
+fn untracked_files(
+    root: &Path,
+    dir: &Path,
+    index: &Index,
+    matcher: &Gitignore,
+    untracked: &mut Vec<String>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == root.join(BASE_DIR) {
+            continue;
+        }
+        let metadata = path.symlink_metadata()?;
+        if matcher.matched(&path, metadata.is_dir()).is_ignore() {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root)
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let tracked =
+            index.entries.contains_key(&relative) || index.conflicts.contains_key(&relative);
+        if !metadata.is_dir() {
+            if !tracked {
+                untracked.push(relative);
+            }
+            continue;
+        }
+        // A tracked submodule
+        if tracked {
+            continue;
+        }
+        let prefix = format!("{relative}/");
+        let has_tracked = index
+            .entries
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(key, _)| key.starts_with(&prefix))
+            || index
+                .conflicts
+                .range(prefix.clone()..)
+                .next()
+                .is_some_and(|(key, _)| key.starts_with(&prefix));
+        if has_tracked {
+            untracked_files(root, &path, index, matcher, untracked)?;
+        } else if path.join(".git").exists() || has_files(&path, matcher)? {
+            untracked.push(prefix);
+        }
+    }
+    Ok(())
+}
+
+// Empty folders, or with only ignored files, are not shown
+fn has_files(dir: &Path, matcher: &Gitignore) -> std::io::Result<bool> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_dir = path.symlink_metadata()?.is_dir();
+        if matcher.matched(&path, is_dir).is_ignore() {
+            continue;
+        }
+        if !is_dir || has_files(&path, matcher)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
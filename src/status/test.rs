@@ -0,0 +1,271 @@
+use std::{
+    fs::FileTimes,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    git_rust::{BASE_DIR, RepoRust},
+    index::Index,
+    status::{self, Change},
+    test_common::{run_test, run_test_matches},
+};
+
+fn add(path: &str) {
+    let args = run_test_matches(vec!["", "add", path]);
+    RepoRust::add(&args).unwrap();
+}
+
+fn commit(message: &str) {
+    let args = run_test_matches(vec!["", "commit", "-m", message]);
+    RepoRust::commit(&args).unwrap();
+}
+
+fn set_mtime(path: &std::path::Path, secs: u64) {
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    let time = UNIX_EPOCH + Duration::from_secs(secs);
+    file.set_times(FileTimes::new().set_modified(time)).unwrap();
+}
+
+#[test]
+fn test_status_staged_unstaged_and_untracked() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::write(path.join("kept.txt"), "kept\n").unwrap();
+        std::fs::write(path.join("edited.txt"), "edited\n").unwrap();
+        std::fs::write(path.join("removed.txt"), "removed\n").unwrap();
+        let status = status::status().unwrap();
+        assert_eq!(status.branch.as_deref(), Some("master"));
+        assert!(status.head.is_none());
+        assert!(status.is_clean());
+        assert_eq!(status.untracked.len(), 3);
+        assert!(status.to_string().contains("No commits yet"));
+
+        add("kept.txt");
+        add("edited.txt");
+        add("removed.txt");
+        commit("first");
+        let status = status::status().unwrap();
+        assert!(status.head.is_some());
+        assert!(status.is_clean());
+        assert!(status.untracked.is_empty());
+        assert!(
+            status
+                .to_string()
+                .ends_with("nothing to commit, working tree clean\n")
+        );
+
+        // Staged then edited again, staged only, deleted and new
+        std::fs::write(path.join("edited.txt"), "edited twice\n").unwrap();
+        add("edited.txt");
+        std::fs::write(path.join("edited.txt"), "edited three times\n").unwrap();
+        std::fs::write(path.join("new.txt"), "new\n").unwrap();
+        add("new.txt");
+        std::fs::remove_file(path.join("removed.txt")).unwrap();
+        std::fs::write(path.join("untracked.txt"), "untracked\n").unwrap();
+
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.get("edited.txt"), Some(&Change::Modified));
+        assert_eq!(status.staged.get("new.txt"), Some(&Change::Added));
+        assert_eq!(status.unstaged.get("edited.txt"), Some(&Change::Modified));
+        assert_eq!(status.unstaged.get("removed.txt"), Some(&Change::Deleted));
+        assert!(!status.staged.contains_key("kept.txt"));
+        assert!(!status.unstaged.contains_key("kept.txt"));
+        assert_eq!(
+            status.short(),
+            "MM edited.txt\nA  new.txt\n D removed.txt\n?? untracked.txt\n"
+        );
+
+        let args = run_test_matches(vec!["", "status", "--porcelain"]);
+        assert!(RepoRust::status(&args).is_ok());
+        let args = run_test_matches(vec!["", "status", "-s"]);
+        assert!(RepoRust::status(&args).is_ok());
+        let args = run_test_matches(vec!["", "status"]);
+        assert!(RepoRust::status(&args).is_ok());
+    });
+}
+
+#[test]
+fn test_status_racy_and_refreshed_entries() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        let file = path.join("file.txt");
+        let index_file = path.join(BASE_DIR).join("index");
+
+        std::fs::write(&file, "aaaa\n").unwrap();
+        add("file.txt");
+        let old_sha1 = Index::read_index().unwrap().entries["file.txt"].sha1;
+
+        // Same size, changed in the same second the index was written. The entry
+        // has the stat data of the new content but the hash of the old one
+        std::fs::write(&file, "bbbb\n").unwrap();
+        set_mtime(&file, 1_700_000_000);
+        let metadata = file.symlink_metadata().unwrap();
+        let mut index = Index::read_index().unwrap();
+        let entry = Index::index_entry(&file, &metadata, 0o100644, old_sha1).unwrap();
+        index.entries.insert("file.txt".to_string(), entry);
+        Index::from_entries(index.entries)
+            .write_index_to_file()
+            .unwrap();
+        set_mtime(&index_file, 1_700_000_000);
+
+        let status = status::status().unwrap();
+        assert_eq!(status.unstaged.get("file.txt"), Some(&Change::Modified));
+
+        // Once the index is newer than the entry its stat data is trusted
+        set_mtime(&index_file, 1_700_000_100);
+        let status = status::status().unwrap();
+        assert!(status.unstaged.is_empty());
+
+        // Touched but not changed. The entry gets the new stat data
+        add("file.txt");
+        set_mtime(&file, 1_700_000_200);
+        let status = status::status().unwrap();
+        assert!(status.unstaged.is_empty());
+        let entry = &Index::read_index().unwrap().entries["file.txt"];
+        assert_eq!(entry.mtime, 1_700_000_200);
+    });
+}
+
+#[test]
+fn test_status_untracked_folders_and_ignored_files() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::create_dir_all(path.join("src/new")).unwrap();
+        std::fs::create_dir_all(path.join("empty")).unwrap();
+        std::fs::create_dir_all(path.join("build")).unwrap();
+        std::fs::create_dir_all(path.join("logs")).unwrap();
+        std::fs::write(path.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(path.join("src/lib.rs"), "\n").unwrap();
+        std::fs::write(path.join("src/new/mod.rs"), "\n").unwrap();
+        std::fs::write(path.join("build/out.o"), "\n").unwrap();
+        std::fs::write(path.join("logs/run.log"), "\n").unwrap();
+        std::fs::write(path.join("debug.log"), "\n").unwrap();
+        std::fs::write(path.join(".gitrust_ignore"), "build/\n*.log\n").unwrap();
+        add("src/main.rs");
+
+        let status = status::status().unwrap();
+        assert_eq!(
+            status.untracked,
+            vec![".gitrust_ignore", "src/lib.rs", "src/new/"]
+        );
+    });
+}
+
+#[test]
+fn test_add_update_all_and_commit_all() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::create_dir(path.join("dir")).unwrap();
+        for file in ["kept.txt", "edited.txt", "removed.txt", "dir/inner.txt"] {
+            std::fs::write(path.join(file), file).unwrap();
+        }
+        add(".");
+        commit("first");
+
+        std::fs::write(path.join("edited.txt"), "edited\n").unwrap();
+        std::fs::remove_file(path.join("removed.txt")).unwrap();
+        std::fs::remove_file(path.join("dir/inner.txt")).unwrap();
+        std::fs::write(path.join("new.txt"), "new\n").unwrap();
+
+        // -u with a path only looks at the tracked files under it
+        let args = run_test_matches(vec!["", "add", "-u", "dir"]);
+        RepoRust::add(&args).unwrap();
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.get("dir/inner.txt"), Some(&Change::Deleted));
+        assert_eq!(status.unstaged.get("removed.txt"), Some(&Change::Deleted));
+
+        // -u never adds new files
+        let args = run_test_matches(vec!["", "add", "-u"]);
+        RepoRust::add(&args).unwrap();
+        let status = status::status().unwrap();
+        assert_eq!(
+            status.short(),
+            "D  dir/inner.txt\nM  edited.txt\nD  removed.txt\n?? new.txt\n"
+        );
+
+        // -A does
+        let args = run_test_matches(vec!["", "add", "-A"]);
+        RepoRust::add(&args).unwrap();
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.get("new.txt"), Some(&Change::Added));
+        assert!(status.unstaged.is_empty() && status.untracked.is_empty());
+    });
+}
+
+#[test]
+fn test_commit_all_stages_tracked_files() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::write(path.join("edited.txt"), "edited\n").unwrap();
+        std::fs::write(path.join("removed.txt"), "removed\n").unwrap();
+        add(".");
+        commit("first");
+
+        std::fs::write(path.join("edited.txt"), "edited twice\n").unwrap();
+        std::fs::remove_file(path.join("removed.txt")).unwrap();
+        std::fs::write(path.join("new.txt"), "new\n").unwrap();
+        let args = run_test_matches(vec!["", "commit", "-a", "-m", "second"]);
+        RepoRust::commit(&args).unwrap();
+
+        let status = status::status().unwrap();
+        assert!(status.is_clean());
+        assert_eq!(status.short(), "?? new.txt\n");
+        let index = Index::read_index().unwrap();
+        assert_eq!(index.entries.keys().collect::<Vec<_>>(), vec!["edited.txt"]);
+    });
+}
+
+#[test]
+fn test_status_staged_renames() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::write(path.join("old.txt"), "1\n2\n3\n4\n").unwrap();
+        std::fs::write(path.join("gone.txt"), "gone\n").unwrap();
+        add(".");
+        commit("first");
+
+        let args = run_test_matches(vec!["", "mv", "old.txt", "new.txt"]);
+        RepoRust::mv(&args).unwrap();
+        std::fs::write(path.join("new.txt"), "1\n2\n3\n4\n5\n").unwrap();
+        std::fs::remove_file(path.join("gone.txt")).unwrap();
+        std::fs::write(path.join("added.txt"), "something else\n").unwrap();
+        let args = run_test_matches(vec!["", "add", "-A"]);
+        RepoRust::add(&args).unwrap();
+        std::fs::write(path.join("new.txt"), "changed\n").unwrap();
+
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.get("new.txt"), Some(&Change::Renamed));
+        assert!(!status.staged.contains_key("old.txt"));
+        assert_eq!(status.renamed["new.txt"], "old.txt");
+        assert_eq!(
+            status.short(),
+            "A  added.txt\nD  gone.txt\nRM old.txt -> new.txt\n"
+        );
+        assert!(
+            status
+                .to_string()
+                .contains("\trenamed:    old.txt -> new.txt\n")
+        );
+    });
+}
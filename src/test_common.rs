@@ -107,7 +107,26 @@ fn add_mock(args: Vec<&str>) -> ArgMatches {
     let matches = command!().subcommand(
         Command::new("add")
             .about("Update the index using the content found in the working tree.")
-            .arg(Arg::new("path").required(true).value_name("path")),
+            .arg(
+                Arg::new("path")
+                    .required_unless_present_any(["update", "all"])
+                    .value_name("path"),
+            )
+            .arg(
+                Arg::new("update")
+                    .short('u')
+                    .long("update")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("all")
+                    .help("Stage modified and deleted tracked files. New files are not added"),
+            )
+            .arg(
+                Arg::new("all")
+                    .short('A')
+                    .long("all")
+                    .action(ArgAction::SetTrue)
+                    .help("Stage modified, deleted and new files"),
+            ),
     );
     let mut matches = matches.get_matches_from(args);
     let (_, arg) = matches.remove_subcommand().unwrap();
@@ -181,15 +200,439 @@ fn commit_mock(args: Vec<&str>) -> ArgMatches {
     arg
 }
 
+fn tag_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("tag")
+            .about("Create, list or delete a tag")
+            .arg(Arg::new("name").value_name("TAGNAME"))
+            .arg(Arg::new("object").value_name("OBJECT"))
+            .arg(Arg::new("annotate").short('a').action(ArgAction::SetTrue))
+            .arg(Arg::new("message").short('m').value_name("MESSAGE"))
+            .arg(Arg::new("force").short('f').action(ArgAction::SetTrue))
+            .arg(Arg::new("delete").short('d').action(ArgAction::SetTrue))
+            .arg(Arg::new("list").short('l').action(ArgAction::SetTrue)),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn rev_parse_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("rev-parse")
+            .arg(Arg::new("rev").value_name("REV").num_args(1..))
+            .arg(Arg::new("verify").long("verify").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("short")
+                    .long("short")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("7")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("abbrev-ref")
+                    .long("abbrev-ref")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn status_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("status")
+            .arg(
+                Arg::new("short")
+                    .short('s')
+                    .long("short")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("porcelain")
+                    .long("porcelain")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("v1")
+                    .value_parser(["v1"]),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn diff_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("diff")
+            .arg(Arg::new("args").num_args(0..))
+            .arg(Arg::new("paths").num_args(0..).last(true))
+            .arg(
+                Arg::new("cached")
+                    .long("cached")
+                    .visible_alias("staged")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("unified")
+                    .short('U')
+                    .long("unified")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("find-renames")
+                    .short('M')
+                    .long("find-renames")
+                    .num_args(0..=1)
+                    .default_missing_value(""),
+            )
+            .arg(
+                Arg::new("find-copies")
+                    .short('C')
+                    .long("find-copies")
+                    .num_args(0..=1)
+                    .default_missing_value(""),
+            )
+            .arg(Arg::new("stat").long("stat").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("name-only")
+                    .long("name-only")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("name-status")
+                    .long("name-status")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("raw").long("raw").action(ArgAction::SetTrue)),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn log_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("log")
+            .arg(Arg::new("rev").num_args(1..))
+            .arg(
+                Arg::new("oneline")
+                    .long("oneline")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("format").long("format").visible_alias("pretty"))
+            .arg(
+                Arg::new("max-count")
+                    .short('n')
+                    .long("max-count")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+            .arg(Arg::new("since").long("since").visible_alias("after"))
+            .arg(Arg::new("until").long("until").visible_alias("before"))
+            .arg(Arg::new("author").long("author"))
+            .arg(Arg::new("grep").long("grep"))
+            .arg(Arg::new("graph").long("graph").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("decorate")
+                    .long("decorate")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("paths").num_args(0..).last(true))
+            .arg(Arg::new("follow").long("follow").action(ArgAction::SetTrue)),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn branch_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("branch")
+            .arg(Arg::new("args").num_args(0..))
+            .arg(
+                Arg::new("delete")
+                    .short('d')
+                    .long("delete")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("force-delete")
+                    .short('D')
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("move")
+                    .short('m')
+                    .long("move")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("force-move").short('M').action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("force")
+                    .short('f')
+                    .long("force")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("remotes")
+                    .short('r')
+                    .long("remotes")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("all")
+                    .short('a')
+                    .long("all")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("verbose")
+                    .short('v')
+                    .long("verbose")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("set-upstream-to")
+                    .short('u')
+                    .long("set-upstream-to"),
+            )
+            .arg(
+                Arg::new("unset-upstream")
+                    .long("unset-upstream")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn switch_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("switch")
+            .arg(Arg::new("target"))
+            .arg(Arg::new("create").short('c').long("create"))
+            .arg(Arg::new("force-create").short('C').long("force-create"))
+            .arg(Arg::new("detach").long("detach").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("force")
+                    .short('f')
+                    .long("force")
+                    .visible_alias("discard-changes")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn checkout_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("checkout")
+            .arg(Arg::new("args").num_args(0..))
+            .arg(Arg::new("paths").num_args(0..).last(true))
+            .arg(Arg::new("create").short('b'))
+            .arg(Arg::new("force-create").short('B'))
+            .arg(Arg::new("detach").long("detach").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("force")
+                    .short('f')
+                    .long("force")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn pack_refs_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("pack-refs")
+            .arg(Arg::new("all").long("all").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("no-prune")
+                    .long("no-prune")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn rm_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("rm")
+            .arg(Arg::new("pathspec").num_args(1..).required(true))
+            .arg(Arg::new("cached").long("cached").action(ArgAction::SetTrue))
+            .arg(Arg::new("recursive").short('r').action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("force")
+                    .short('f')
+                    .long("force")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn mv_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("mv")
+            .arg(Arg::new("paths").num_args(2..).required(true))
+            .arg(
+                Arg::new("force")
+                    .short('f')
+                    .long("force")
+                    .action(ArgAction::SetTrue),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn reset_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("reset")
+            .arg(Arg::new("args").num_args(0..))
+            .arg(Arg::new("paths").num_args(0..).last(true))
+            .arg(
+                Arg::new("soft")
+                    .long("soft")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["mixed", "hard"]),
+            )
+            .arg(
+                Arg::new("mixed")
+                    .long("mixed")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("hard"),
+            )
+            .arg(Arg::new("hard").long("hard").action(ArgAction::SetTrue)),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn merge_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("merge")
+            .arg(Arg::new("rev"))
+            .arg(
+                Arg::new("ff-only")
+                    .long("ff-only")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("no-ff").long("no-ff").action(ArgAction::SetTrue))
+            .arg(Arg::new("message").short('m'))
+            .arg(
+                Arg::new("allow-unrelated-histories")
+                    .long("allow-unrelated-histories")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("continue")
+                    .long("continue")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("abort").long("abort").action(ArgAction::SetTrue)),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
+fn reflog_mock(args: Vec<&str>) -> ArgMatches {
+    let matches = command!().subcommand(
+        Command::new("reflog")
+            .args_conflicts_with_subcommands(true)
+            .arg(Arg::new("ref"))
+            .subcommand(Command::new("show").arg(Arg::new("ref")))
+            .subcommand(
+                Command::new("expire")
+                    .arg(Arg::new("expire").long("expire"))
+                    .arg(Arg::new("expire-unreachable").long("expire-unreachable"))
+                    .arg(Arg::new("all").long("all").action(ArgAction::SetTrue))
+                    .arg(Arg::new("refs").num_args(0..)),
+            )
+            .subcommand(
+                Command::new("delete").arg(Arg::new("entries").num_args(1..).required(true)),
+            ),
+    );
+    let mut matches = matches.get_matches_from(args);
+    let (_, arg) = matches.remove_subcommand().unwrap();
+    arg
+}
+
 pub fn run_test_matches(args: Vec<&str>) -> ArgMatches {
     match args[1] {
-        "cat-file" => return cat_file_mock(args),
-        "hash-object" => return hash_object_mock(args),
-        "ls-tree" => return ls_tree_mock(args),
-        "add" => return add_mock(args),
-        "write-tree" => return write_tree_mock(args),
-        "commit-tree" => return commit_tree_mock(args),
-        "commit" => return commit_mock(args),
+        "cat-file" => cat_file_mock(args),
+        "hash-object" => hash_object_mock(args),
+        "ls-tree" => ls_tree_mock(args),
+        "add" => add_mock(args),
+        "write-tree" => write_tree_mock(args),
+        "commit-tree" => commit_tree_mock(args),
+        "commit" => commit_mock(args),
+        "tag" => tag_mock(args),
+        "rev-parse" => rev_parse_mock(args),
+        "status" => status_mock(args),
+        "diff" => diff_mock(args),
+        "log" => log_mock(args),
+        "branch" => branch_mock(args),
+        "switch" => switch_mock(args),
+        "checkout" => checkout_mock(args),
+        "pack-refs" => pack_refs_mock(args),
+        "reset" => reset_mock(args),
+        "merge" => merge_mock(args),
+        "rm" => rm_mock(args),
+        "mv" => mv_mock(args),
+        "reflog" => reflog_mock(args),
         _ => panic!("Wrong test command!"),
     }
 }
+
+// Creates a git repo with git2 in path and commits the files
+// Returns the repo and the id of the commit
+pub fn git2_commit_files(path: &Path, files: &[(&str, &str)]) -> (git2::Repository, git2::Oid) {
+    let repo =
+        git2::Repository::open(path).unwrap_or_else(|_| git2::Repository::init(path).unwrap());
+    for (name, content) in files {
+        let file_path = path.join(name);
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        std::fs::write(file_path, content).unwrap();
+    }
+    let mut index = repo.index().unwrap();
+    for (name, _) in files {
+        index.add_path(Path::new(name)).unwrap();
+    }
+    index.write().unwrap();
+    let tree_id = index.write_tree().unwrap();
+    let commit_id = {
+        let tree = repo.find_tree(tree_id).unwrap();
+        let sig = git2::Signature::now("A U Thor", "author@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, "Test commit", &tree, &parents)
+            .unwrap()
+    };
+    (repo, commit_id)
+}
+
+// Builds a packfile with everything reachable from commit, history included
+pub fn git2_pack_commit(repo: &git2::Repository, commit: git2::Oid) -> Vec<u8> {
+    let mut builder = repo.packbuilder().unwrap();
+    let mut walk = repo.revwalk().unwrap();
+    walk.push(commit).unwrap();
+    builder.insert_walk(&mut walk).unwrap();
+    let mut buf = git2::Buf::new();
+    builder.write_buf(&mut buf).unwrap();
+    buf.to_vec()
+}
@@ -0,0 +1,608 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+};
+
+use crate::{
+    git_rust::RepoRust,
+    index::{Index, IndexEntry},
+    objects::{
+        self, ObjectType,
+        blob::Blob,
+        tree::{MODE_EXECUTABLE, MODE_FILE, MODE_GITLINK, MODE_SYMLINK, Tree},
+    },
+    reflog, refs, revision,
+    status::{self, WorktreeState},
+};
+
+#[cfg(test)]
+mod test;
+
+// Mode and blob hash of a file, in a tree or in the index
+pub type FileState = (u32, [u8; 20]);
+
+pub fn tree_files(tree_hash: Option<&str>) -> std::io::Result<BTreeMap<String, FileState>> {
+    let Some(tree_hash) = tree_hash else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(Tree::flatten(tree_hash)?
+        .into_iter()
+        .map(|(path, entry)| {
+            let mode = u32::from_str_radix(&entry.mode, 8).unwrap_or(MODE_FILE);
+            (path, (mode, entry.hash))
+        })
+        .collect())
+}
+
+fn read_index() -> std::io::Result<Index> {
+    match Index::read_index() {
+        Ok(index) => Ok(index),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Index::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn conflict_error(message: &str, paths: &[String], hint: &str) -> std::io::Error {
+    let paths: String = paths.iter().map(|path| format!("\t{path}\n")).collect();
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{message}\n{paths}{hint}\nAborting"),
+    )
+}
+
+// Writes the content of a blob (or an empty folder for a submodule) to the
+// working tree and returns its index entry with fresh stat data
+fn write_file(path: &str, (mode, hash): FileState) -> std::io::Result<IndexEntry> {
+    let file_path = RepoRust::get_root().absolute_path.join(path);
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Replaced instead of overwritten, so the mode of the old file is not kept
+    if let Ok(metadata) = file_path.symlink_metadata()
+        && !metadata.is_dir()
+    {
+        std::fs::remove_file(&file_path)?;
+    }
+    // Submodules are not checked out. Same as git, they are an empty folder
+    if mode == MODE_GITLINK {
+        std::fs::create_dir_all(&file_path)?;
+        let metadata = file_path.symlink_metadata()?;
+        return Index::index_entry(&file_path, &metadata, MODE_GITLINK, hash);
+    }
+    let object = objects::read_object(&hex::encode(hash))?;
+    let content = Blob::decode_object(&object)?;
+    if mode == MODE_SYMLINK {
+        let target = std::ffi::OsStr::from_bytes(&content);
+        std::os::unix::fs::symlink(target, &file_path)?;
+    } else {
+        std::fs::write(&file_path, content)?;
+        if mode == MODE_EXECUTABLE {
+            std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Index::index_entry(&file_path, &file_path.symlink_metadata()?, mode, hash)
+}
+
+// Removes a file (or the empty folder of a submodule) and the folders left empty
+fn remove_file(path: &str) -> std::io::Result<()> {
+    let root = &RepoRust::get_root().absolute_path;
+    let file_path = root.join(path);
+    match file_path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => {
+            // A submodule with files is left in place
+            if std::fs::remove_dir(&file_path).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(_) => std::fs::remove_file(&file_path)?,
+        Err(_) => return Ok(()),
+    }
+    refs::remove_empty_dirs(&file_path, root);
+    Ok(())
+}
+
+// True if the file in the working tree has the content of the index entry
+fn is_clean(path: &str, entry: &IndexEntry, index_mtime: Option<(i64, i64)>) -> bool {
+    let file_path = RepoRust::get_root().absolute_path.join(path);
+    matches!(
+        status::worktree_change(&file_path, entry, index_mtime),
+        Ok(WorktreeState::Unchanged | WorktreeState::Refreshed(_))
+    )
+}
+
+// True if an untracked file in the working tree already has the content of state
+fn has_content(path: &str, (mode, hash): FileState) -> bool {
+    let file_path = RepoRust::get_root().absolute_path.join(path);
+    let Ok(metadata) = file_path.symlink_metadata() else {
+        return true;
+    };
+    if metadata.is_dir() {
+        return mode == MODE_GITLINK;
+    }
+    Index::mode_from_metadata(&metadata) == mode
+        && Index::file_content(&file_path, &metadata)
+            .is_ok_and(|content| Index::sha1_entry(&content) == hash)
+}
+
+// Two-tree checkout: moves the index and the working tree from old_tree (the tree
+// of HEAD, None before the first commit) to new_tree
+// Only the paths that differ between the two trees are touched, so local changes
+// to other paths are kept. Without force, local changes and untracked files that
+// would be overwritten abort the checkout before anything is written
+pub fn switch_tree(old_tree: Option<&str>, new_tree: &str, force: bool) -> std::io::Result<()> {
+    let old = tree_files(old_tree)?;
+    let new = tree_files(Some(new_tree))?;
+    let mut index = read_index()?;
+    let index_mtime = status::index_mtime();
+    if !force && !index.conflicts.is_empty() {
+        let paths: Vec<String> = index.conflicts.keys().cloned().collect();
+        return Err(conflict_error(
+            "The following paths are unmerged:",
+            &paths,
+            "You need to resolve your current index first.",
+        ));
+    }
+
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut overwritten = Vec::new();
+    let mut untracked = Vec::new();
+    let mut updates: Vec<(String, Option<FileState>)> = Vec::new();
+    for path in paths {
+        let (old, new) = (old.get(path).copied(), new.get(path).copied());
+        let entry = index.entries.get(path);
+        let staged = entry.map(|entry| (entry.mode, entry.sha1));
+        if force {
+            let clean = entry.is_some_and(|entry| is_clean(path, entry, index_mtime));
+            if staged != new || !clean {
+                updates.push((path.clone(), new));
+            }
+            continue;
+        }
+        // Same in both trees, or already staged as in the new tree: kept as is
+        if old == new || staged == new {
+            continue;
+        }
+        match entry {
+            None if old.is_none() => {
+                if let Some(new) = new
+                    && !has_content(path, new)
+                {
+                    untracked.push(path.clone());
+                }
+            }
+            Some(entry) if staged == old && is_clean(path, entry, index_mtime) => {}
+            _ => overwritten.push(path.clone()),
+        }
+        updates.push((path.clone(), new));
+    }
+    if !overwritten.is_empty() {
+        return Err(conflict_error(
+            "Your local changes to the following files would be overwritten by checkout:",
+            &overwritten,
+            "Please commit your changes before you switch branches.",
+        ));
+    }
+    if !untracked.is_empty() {
+        return Err(conflict_error(
+            "The following untracked working tree files would be overwritten by checkout:",
+            &untracked,
+            "Please move or remove them before you switch branches.",
+        ));
+    }
+
+    // Removed first, a folder may replace a removed file
+    for (path, _) in updates.iter().filter(|(_, new)| new.is_none()) {
+        remove_file(path)?;
+        index.entries.remove(path);
+    }
+    for (path, new) in updates {
+        if let Some(new) = new {
+            let entry = write_file(&path, new)?;
+            index.entries.insert(path, entry);
+        }
+    }
+    Index::from_entries(index.entries).write_index_to_file()
+}
+
+// Moves HEAD to commit, with the index and the working tree. HEAD points to
+// branch (a full ref name) if given, otherwise it is detached at commit
+pub fn switch_head(commit: &str, branch: Option<&str>, force: bool) -> std::io::Result<()> {
+    let old_commit = refs::read_ref("HEAD")?;
+    let old_tree = match &old_commit {
+        Some(head) => Some(revision::resolve_as(head, ObjectType::Tree)?),
+        None => None,
+    };
+    let new_tree = revision::resolve_as(commit, ObjectType::Tree)?;
+    switch_tree(old_tree.as_deref(), &new_tree, force)?;
+
+    let from = match refs::head_branch()? {
+        Some(name) => revision::shorten_ref(&name).to_string(),
+        None => old_commit.clone().unwrap_or_default(),
+    };
+    let to = branch.map(revision::shorten_ref).unwrap_or(commit);
+    let message = format!("checkout: moving from {from} to {to}");
+    match branch {
+        Some(branch) => {
+            refs::set_head_branch(branch)?;
+            reflog::append("HEAD", old_commit.as_deref(), commit, &message)
+        }
+        None => refs::set_head_detached(commit, &message),
+    }
+}
+
+// A path matches a pathspec if it is the same file or inside that folder
+// "." matches every file
+pub fn matches_pathspec(path: &str, pathspec: &str) -> bool {
+    let pathspec = pathspec.trim_end_matches('/');
+    pathspec == "."
+        || path == pathspec
+        || path
+            .strip_prefix(pathspec)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+// checkout [<tree-ish>] -- <paths>
+// Overwrites the files matching the paths with their content in tree (the index
+// entries are updated too), or in the index when no tree is given
+pub fn checkout_paths(tree: Option<&str>, pathspecs: &[String]) -> std::io::Result<()> {
+    let mut index = read_index()?;
+    let files = match tree {
+        Some(tree) => tree_files(Some(tree))?,
+        None => index
+            .entries
+            .iter()
+            .map(|(path, entry)| (path.clone(), (entry.mode, entry.sha1)))
+            .collect(),
+    };
+    for pathspec in pathspecs {
+        if !files.keys().any(|path| matches_pathspec(path, pathspec)) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("pathspec '{pathspec}' did not match any file(s) known to git"),
+            ));
+        }
+    }
+    for (path, state) in files {
+        if pathspecs
+            .iter()
+            .any(|pathspec| matches_pathspec(&path, pathspec))
+        {
+            let entry = write_file(&path, state)?;
+            index.entries.insert(path, entry);
+        }
+    }
+    Index::from_entries(index.entries)
+        .with_conflicts(index.conflicts)
+        .write_index_to_file()
+}
+
+// Index entry of a file whose working tree copy may have other content
+// Without stat data, status always compares the content
+pub fn unstat_entry(path: &str, (mode, hash): FileState) -> IndexEntry {
+    IndexEntry {
+        ctime: 0,
+        ctime_nanos: 0,
+        mtime: 0,
+        mtime_nanos: 0,
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        sha1: hash,
+        flags: path.len().min(0xFFF) as u16,
+        path: path.as_bytes().to_vec(),
+    }
+}
+
+// The entry of a path reset to state, without touching the working tree
+// Fresh stat data only when the file in the working tree has that content
+fn reset_entry(path: &str, state: FileState, old: Option<&IndexEntry>) -> IndexEntry {
+    if let Some(old) = old
+        && (old.mode, old.sha1) == state
+    {
+        return old.clone();
+    }
+    let file_path = RepoRust::get_root().absolute_path.join(path);
+    if let Ok(metadata) = file_path.symlink_metadata()
+        && !metadata.is_dir()
+        && has_content(path, state)
+        && let Ok(entry) = Index::index_entry(&file_path, &metadata, state.0, state.1)
+    {
+        return entry;
+    }
+    unstat_entry(path, state)
+}
+
+// reset --mixed: the index gets the content of tree (empty when None). The working
+// tree is not changed. With pathspecs, only the matching paths are reset
+pub fn reset_index(tree: Option<&str>, pathspecs: &[String]) -> std::io::Result<()> {
+    let files = tree_files(tree)?;
+    let mut index = read_index()?;
+    let matches = |path: &str| {
+        pathspecs.is_empty()
+            || pathspecs
+                .iter()
+                .any(|pathspec| matches_pathspec(path, pathspec))
+    };
+    let paths: BTreeSet<String> = files
+        .keys()
+        .chain(index.entries.keys())
+        .filter(|path| matches(path))
+        .cloned()
+        .collect();
+    for path in paths {
+        match files.get(&path) {
+            Some(&state) => {
+                let entry = reset_entry(&path, state, index.entries.get(&path));
+                index.entries.insert(path, entry);
+            }
+            None => {
+                index.entries.remove(&path);
+            }
+        }
+    }
+    // The unmerged paths that are reset are resolved
+    index.conflicts.retain(|path, _| !matches(path));
+    Index::from_entries(index.entries)
+        .with_conflicts(index.conflicts)
+        .write_index_to_file()
+}
+
+// reset --hard: the index and the working tree get the content of tree. Local
+// changes are lost and tracked files that are not in tree are deleted
+pub fn reset_hard(tree: Option<&str>) -> std::io::Result<()> {
+    let files = tree_files(tree)?;
+    let mut index = read_index()?;
+    let index_mtime = status::index_mtime();
+    // Unmerged paths are dropped too, with their files that are not in tree
+    let removed: Vec<String> = index
+        .entries
+        .keys()
+        .chain(index.conflicts.keys())
+        .filter(|path| !files.contains_key(*path))
+        .cloned()
+        .collect();
+    // Removed first, a folder may replace a removed file
+    for path in removed {
+        remove_file(&path)?;
+        index.entries.remove(&path);
+    }
+    for (path, state) in files {
+        if let Some(entry) = index.entries.get(&path)
+            && (entry.mode, entry.sha1) == state
+            && is_clean(&path, entry, index_mtime)
+        {
+            continue;
+        }
+        let entry = write_file(&path, state)?;
+        index.entries.insert(path, entry);
+    }
+    Index::from_entries(index.entries).write_index_to_file()
+}
+
+// rm [--cached] [-r] [-f] <pathspec>...
+// Removes the matching index entries, and their files unless cached. Without force,
+// staged content that is not committed, or local changes, are never lost
+// Returns the removed paths
+pub fn remove_paths(
+    pathspecs: &[String],
+    cached: bool,
+    recursive: bool,
+    force: bool,
+) -> std::io::Result<Vec<String>> {
+    let mut index = read_index()?;
+    let index_mtime = status::index_mtime();
+    let head = match refs::read_ref("HEAD")? {
+        Some(head) => Some(revision::resolve_as(&head, ObjectType::Tree)?),
+        None => None,
+    };
+    let head = tree_files(head.as_deref())?;
+
+    let mut paths = BTreeSet::new();
+    for pathspec in pathspecs {
+        // Unmerged paths can be removed too, which resolves them
+        let matched: Vec<&String> = index
+            .entries
+            .keys()
+            .chain(index.conflicts.keys())
+            .filter(|path| matches_pathspec(path, pathspec))
+            .collect();
+        if matched.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("pathspec '{pathspec}' did not match any files"),
+            ));
+        }
+        if !recursive && matched.iter().any(|path| *path != pathspec) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("not removing '{pathspec}' recursively without -r"),
+            ));
+        }
+        paths.extend(matched.into_iter().cloned());
+    }
+
+    if !force {
+        let mut staged_and_local = Vec::new();
+        let mut staged = Vec::new();
+        let mut local = Vec::new();
+        for path in &paths {
+            // The versions of an unmerged path are all in the commits merged
+            let Some(entry) = index.entries.get(path) else {
+                continue;
+            };
+            let file_path = RepoRust::get_root().absolute_path.join(path);
+            let is_staged = head.get(path) != Some(&(entry.mode, entry.sha1));
+            // A file already deleted has nothing to lose
+            let is_local =
+                file_path.symlink_metadata().is_ok() && !is_clean(path, entry, index_mtime);
+            match (is_staged, is_local) {
+                (true, true) => staged_and_local.push(path.clone()),
+                (true, false) if !cached => staged.push(path.clone()),
+                (false, true) if !cached => local.push(path.clone()),
+                _ => {}
+            }
+        }
+        let errors = [
+            (
+                staged_and_local,
+                "has staged content different from both the file and the HEAD",
+                "(use -f to force removal)",
+            ),
+            (
+                staged,
+                "has changes staged in the index",
+                "(use --cached to keep the file, or -f to force removal)",
+            ),
+            (
+                local,
+                "has local modifications",
+                "(use --cached to keep the file, or -f to force removal)",
+            ),
+        ];
+        for (paths, problem, hint) in errors {
+            if !paths.is_empty() {
+                let files: String = paths.iter().map(|path| format!("\n    {path}")).collect();
+                let subject = match paths.len() {
+                    1 => format!("the following file {problem}:"),
+                    _ => format!(
+                        "the following files {}:",
+                        problem.replacen("has", "have", 1)
+                    ),
+                };
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{subject}{files}\n{hint}"),
+                ));
+            }
+        }
+    }
+
+    for path in &paths {
+        index.entries.remove(path);
+        index.conflicts.remove(path);
+        if !cached {
+            remove_file(path)?;
+        }
+    }
+    Index::from_entries(index.entries)
+        .with_conflicts(index.conflicts)
+        .write_index_to_file()?;
+    Ok(paths.into_iter().collect())
+}
+
+// mv [-f] <source>... <destination>
+// Renames files (or folders) in the working tree and their index entries. With
+// several sources, or an existing folder as destination, they are moved into it
+// Returns the (source, destination) of each file
+pub fn move_paths(
+    sources: &[String],
+    destination: &str,
+    force: bool,
+) -> std::io::Result<Vec<(String, String)>> {
+    let root = RepoRust::get_root().absolute_path.clone();
+    let mut index = read_index()?;
+    let destination = destination.trim_end_matches('/');
+    let into_folder = root.join(destination).is_dir();
+    let error = |message: String, source: &str, destination: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{message}, source={source}, destination={destination}"),
+        )
+    };
+    if sources.len() > 1 && !into_folder {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("destination '{destination}' is not a directory"),
+        ));
+    }
+
+    // Every move is checked before any file is touched
+    let mut moves: Vec<(String, String)> = Vec::new();
+    let mut renames: Vec<(String, String)> = Vec::new();
+    for source in sources {
+        let source = source.trim_end_matches('/');
+        // The top of the worktree is not a path that can be moved
+        if source.is_empty() || source == "." {
+            return Err(error("bad source".to_string(), source, destination));
+        }
+        let target = match into_folder {
+            true => {
+                let name = source.rsplit('/').next().unwrap_or(source);
+                format!("{destination}/{name}")
+            }
+            false => destination.to_string(),
+        };
+        if renames.iter().any(|(_, other)| *other == target) {
+            return Err(error(
+                "multiple sources for the same target".to_string(),
+                source,
+                &target,
+            ));
+        }
+        let source_path = root.join(source);
+        if source_path.symlink_metadata().is_err() {
+            return Err(error("bad source".to_string(), source, &target));
+        }
+        if source == target || target.starts_with(&format!("{source}/")) {
+            return Err(error(
+                "can not move directory into itself".to_string(),
+                source,
+                &target,
+            ));
+        }
+        let files: Vec<String> = index
+            .entries
+            .keys()
+            .filter(|path| matches_pathspec(path, source))
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            return Err(error(
+                "not under version control".to_string(),
+                source,
+                &target,
+            ));
+        }
+        let target_path = root.join(&target);
+        if !target_path.parent().is_some_and(|parent| parent.is_dir()) {
+            return Err(error(
+                "destination directory does not exist".to_string(),
+                source,
+                &target,
+            ));
+        }
+        if let Ok(metadata) = target_path.symlink_metadata() {
+            // Only a file can be overwritten, with -f
+            if metadata.is_dir() || !force || source_path.is_dir() {
+                return Err(error("destination exists".to_string(), source, &target));
+            }
+        }
+        for file in files {
+            let moved = format!("{target}{}", &file[source.len()..]);
+            moves.push((file, moved));
+        }
+        renames.push((source.to_string(), target));
+    }
+
+    for (source, target) in &renames {
+        std::fs::rename(root.join(source), root.join(target))?;
+    }
+    for (source, target) in &moves {
+        let mut entry = index.entries.remove(source).unwrap();
+        entry.path = target.as_bytes().to_vec();
+        entry.flags = (entry.flags & !0xFFF) | target.len().min(0xFFF) as u16;
+        index.entries.insert(target.clone(), entry);
+    }
+    Index::from_entries(index.entries)
+        .with_conflicts(index.conflicts)
+        .write_index_to_file()?;
+    Ok(moves)
+}
+
+// Writes every file of a tree to an empty working tree, and a new index
+pub fn checkout_tree(tree_hash: &str) -> std::io::Result<()> {
+    switch_tree(None, tree_hash, false)
+}
@@ -0,0 +1,621 @@
+use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+use crate::{
+    branch,
+    config::Config,
+    git_rust::{BASE_DIR, RepoRust},
+    index::Index,
+    log,
+    objects::{ObjectType, tree::Tree},
+    refs, revision, status,
+    test_common::{run_test, run_test_matches},
+    worktree,
+};
+
+fn add(path: &str) {
+    let args = run_test_matches(vec!["", "add", path]);
+    RepoRust::add(&args).unwrap();
+}
+
+fn commit(message: &str) {
+    let args = run_test_matches(vec!["", "commit", "-m", message]);
+    RepoRust::commit(&args).unwrap();
+}
+
+// Deletes a file from the index and the working tree
+fn remove(root: &std::path::Path, path: &str) {
+    let mut index = Index::read_index().unwrap();
+    index.entries.remove(path);
+    Index::from_entries(index.entries)
+        .write_index_to_file()
+        .unwrap();
+    std::fs::remove_file(root.join(path)).unwrap();
+}
+
+fn switch(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "switch"], args].concat();
+    RepoRust::switch(&run_test_matches(args))
+}
+
+fn checkout(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "checkout"], args].concat();
+    RepoRust::checkout(&run_test_matches(args))
+}
+
+fn index_tree() -> String {
+    hex::encode(Tree::encode_object().unwrap().1)
+}
+
+fn tree_of(rev: &str) -> String {
+    revision::resolve_as(rev, ObjectType::Tree).unwrap()
+}
+
+fn assert_clean() {
+    let status = status::status().unwrap();
+    assert!(status.is_clean(), "{}", status.short());
+    assert!(status.untracked.is_empty(), "{}", status.short());
+}
+
+#[test]
+fn test_switch_updates_index_and_worktree() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        std::fs::create_dir_all(path.join("dir")).unwrap();
+        std::fs::write(path.join("dir/b.txt"), "b\n").unwrap();
+        std::fs::write(path.join("run.sh"), "echo\n").unwrap();
+        std::fs::set_permissions(path.join("run.sh"), PermissionsExt::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("a.txt", path.join("link")).unwrap();
+        for file in ["a.txt", "dir/b.txt", "run.sh", "link"] {
+            add(file);
+        }
+        commit("first");
+
+        branch::create("feature", "HEAD", false).unwrap();
+        switch(&["feature"]).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/feature");
+        std::fs::write(path.join("a.txt"), "a on feature\n").unwrap();
+        remove(&path, "dir/b.txt");
+        std::fs::create_dir_all(path.join("new")).unwrap();
+        std::fs::write(path.join("new/c.txt"), "c\n").unwrap();
+        std::fs::set_permissions(path.join("run.sh"), PermissionsExt::from_mode(0o644)).unwrap();
+        for file in ["a.txt", "new/c.txt", "run.sh"] {
+            add(file);
+        }
+        commit("second");
+
+        switch(&["master"]).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/master");
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a\n");
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/b.txt")).unwrap(),
+            "b\n"
+        );
+        assert!(!path.join("new").exists());
+        let mode = std::fs::metadata(path.join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+        assert_eq!(
+            std::fs::read_link(path.join("link")).unwrap(),
+            PathBuf::from("a.txt")
+        );
+        assert_eq!(index_tree(), tree_of("master"));
+        assert_clean();
+
+        switch(&["feature"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("new/c.txt")).unwrap(),
+            "c\n"
+        );
+        assert!(!path.join("dir").exists());
+        let mode = std::fs::metadata(path.join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0);
+        assert_eq!(index_tree(), tree_of("feature"));
+        assert_clean();
+
+        // Already on the branch
+        switch(&["feature"]).unwrap();
+        assert!(switch(&["missing"]).is_err());
+    });
+}
+
+#[test]
+fn test_switch_keeps_local_changes() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        std::fs::write(path.join("b.txt"), "b\n").unwrap();
+        add("a.txt");
+        add("b.txt");
+        commit("first");
+        switch(&["-c", "feature"]).unwrap();
+        std::fs::write(path.join("a.txt"), "a on feature\n").unwrap();
+        std::fs::write(path.join("c.txt"), "c\n").unwrap();
+        add("a.txt");
+        add("c.txt");
+        commit("second");
+        switch(&["master"]).unwrap();
+        assert!(!path.join("c.txt").exists());
+
+        // A changed file would be overwritten. Nothing is touched
+        std::fs::write(path.join("a.txt"), "local\n").unwrap();
+        let error = switch(&["feature"]).unwrap_err().to_string();
+        assert!(
+            error.contains("would be overwritten by checkout:\n\ta.txt\n"),
+            "{error}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "local\n"
+        );
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/master");
+
+        // Also when the change is staged
+        add("a.txt");
+        assert!(switch(&["feature"]).is_err());
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        add("a.txt");
+
+        // An untracked file with other content would be overwritten
+        std::fs::write(path.join("c.txt"), "untracked\n").unwrap();
+        let error = switch(&["feature"]).unwrap_err().to_string();
+        assert!(error.contains("untracked working tree files"), "{error}");
+        std::fs::write(path.join("c.txt"), "c\n").unwrap();
+
+        // Changes to files that are the same in both branches are kept
+        std::fs::write(path.join("b.txt"), "b changed\n").unwrap();
+        switch(&["feature"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("b.txt")).unwrap(),
+            "b changed\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "a on feature\n"
+        );
+        let status = status::status().unwrap();
+        assert_eq!(status.short(), " M b.txt\n");
+
+        // Force discards them
+        std::fs::write(path.join("a.txt"), "local\n").unwrap();
+        switch(&["-f", "master"]).unwrap();
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a\n");
+        assert_eq!(std::fs::read_to_string(path.join("b.txt")).unwrap(), "b\n");
+        assert!(!path.join("c.txt").exists());
+        assert_clean();
+    });
+}
+
+#[test]
+fn test_checkout_paths_and_detached_head() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // Before the first commit, only HEAD changes
+        switch(&["-c", "main"]).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/main");
+
+        std::fs::create_dir_all(path.join("dir")).unwrap();
+        std::fs::write(path.join("dir/a.txt"), "one\n").unwrap();
+        add("dir/a.txt");
+        commit("first");
+        let first = revision::resolve("HEAD").unwrap();
+        std::fs::write(path.join("dir/a.txt"), "two\n").unwrap();
+        add("dir/a.txt");
+        commit("second");
+
+        // From the index
+        std::fs::write(path.join("dir/a.txt"), "local\n").unwrap();
+        checkout(&["--", "dir/a.txt"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/a.txt")).unwrap(),
+            "two\n"
+        );
+        assert_clean();
+
+        // From a commit, staged as well
+        checkout(&["HEAD~1", "--", "dir"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/a.txt")).unwrap(),
+            "one\n"
+        );
+        assert_eq!(index_tree(), tree_of(&first));
+        assert!(checkout(&["HEAD", "missing.txt"]).is_err());
+        checkout(&["HEAD", "."]).unwrap();
+        assert_clean();
+
+        // Detached HEAD
+        checkout(&[&first[..8]]).unwrap();
+        assert_eq!(refs::head_branch().unwrap(), None);
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(first.clone()));
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/a.txt")).unwrap(),
+            "one\n"
+        );
+        switch(&["main"]).unwrap();
+        assert!(switch(&["HEAD~1"]).is_err());
+        switch(&["--detach", "main"]).unwrap();
+        assert_eq!(refs::head_branch().unwrap(), None);
+
+        // New branches
+        checkout(&["-b", "topic", "main~1"]).unwrap();
+        assert_eq!(refs::head_branch().unwrap().unwrap(), "refs/heads/topic");
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(first.clone()));
+        assert!(checkout(&["-b", "topic"]).is_err());
+        checkout(&["-B", "topic", "main"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/a.txt")).unwrap(),
+            "two\n"
+        );
+
+        // A branch that would lose local changes is not created
+        std::fs::write(path.join("dir/a.txt"), "local\n").unwrap();
+        assert!(switch(&["-c", "lost", &first]).is_err());
+        assert_eq!(refs::read_ref("refs/heads/lost").unwrap(), None);
+
+        // A remote-tracking branch of the same name is tracked
+        refs::write_ref("refs/remotes/origin/remote-only", &first).unwrap();
+        std::fs::write(path.join("dir/a.txt"), "two\n").unwrap();
+        switch(&["remote-only"]).unwrap();
+        assert_eq!(
+            refs::head_branch().unwrap().unwrap(),
+            "refs/heads/remote-only"
+        );
+        let config = Config::read().unwrap();
+        assert_eq!(config.get("branch.remote-only.remote"), Some("origin"));
+
+        // Clone writes a tree to an empty working tree
+        let tree = tree_of("main");
+        std::fs::remove_dir_all(path.join("dir")).unwrap();
+        std::fs::remove_file(path.join(BASE_DIR).join("index")).unwrap();
+        worktree::checkout_tree(&tree).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("dir/a.txt")).unwrap(),
+            "two\n"
+        );
+    });
+}
+
+#[test]
+fn test_commits_on_detached_head() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::write(path.join("a.txt"), "one\n").unwrap();
+        add("a.txt");
+        commit("first");
+        let first = revision::resolve("HEAD").unwrap();
+        switch(&["--detach", "master"]).unwrap();
+        assert!(log::orphaned(&first).unwrap().is_empty());
+
+        // Commits move HEAD, not the branch
+        std::fs::write(path.join("a.txt"), "two\n").unwrap();
+        add("a.txt");
+        commit("second");
+        std::fs::write(path.join("a.txt"), "three\n").unwrap();
+        add("a.txt");
+        commit("third");
+        let third = refs::read_ref("HEAD").unwrap().unwrap();
+        assert_eq!(refs::head_branch().unwrap(), None);
+        assert_eq!(
+            refs::read_ref("refs/heads/master").unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(revision::resolve("HEAD~2").unwrap(), first);
+        let status = status::status().unwrap();
+        assert!(
+            status
+                .to_string()
+                .starts_with(&format!("HEAD detached at {}\n", &third[..7]))
+        );
+
+        // Switching away leaves them behind
+        switch(&["master"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("a.txt")).unwrap(),
+            "one\n"
+        );
+        let orphaned: Vec<String> = log::orphaned(&third)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.hash)
+            .collect();
+        assert_eq!(orphaned.len(), 2);
+        assert_eq!(orphaned[0], third);
+        branch::create("rescued", &third, false).unwrap();
+        assert!(log::orphaned(&third).unwrap().is_empty());
+
+        // Checking out a tag detaches HEAD
+        refs::write_ref("refs/tags/v1", &first).unwrap();
+        checkout(&["v1"]).unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(first));
+        assert_eq!(refs::head_branch().unwrap(), None);
+    });
+}
+
+fn reset(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "reset"], args].concat();
+    RepoRust::reset(&run_test_matches(args))
+}
+
+#[test]
+fn test_reset_soft_mixed_and_hard() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        std::fs::write(path.join("a.txt"), "a1\n").unwrap();
+        add("a.txt");
+        commit("first");
+        let first = refs::read_ref("HEAD").unwrap().unwrap();
+        std::fs::write(path.join("a.txt"), "a2\n").unwrap();
+        std::fs::write(path.join("b.txt"), "b\n").unwrap();
+        add(".");
+        commit("second");
+        let second = refs::read_ref("HEAD").unwrap().unwrap();
+
+        // --soft: only the branch moves, the changes of second are staged
+        reset(&["--soft", "HEAD~1"]).unwrap();
+        assert_eq!(
+            refs::read_ref("refs/heads/master").unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(refs::read_ref("ORIG_HEAD").unwrap(), Some(second.clone()));
+        assert_eq!(index_tree(), tree_of(&second));
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.len(), 2);
+        assert!(status.unstaged.is_empty());
+
+        // --mixed: the index follows, the working tree keeps the changes
+        reset(&["ORIG_HEAD"]).unwrap();
+        reset(&["--mixed", "HEAD~1"]).unwrap();
+        assert_eq!(index_tree(), tree_of(&first));
+        let status = status::status().unwrap();
+        assert!(status.staged.is_empty());
+        assert_eq!(status.unstaged.len(), 1);
+        assert_eq!(status.untracked, vec!["b.txt"]);
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a2\n");
+
+        // --hard: local changes and tracked files that are not in the target are lost
+        reset(&["--hard", &second]).unwrap();
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(second.clone()));
+        std::fs::write(path.join("a.txt"), "local\n").unwrap();
+        std::fs::write(path.join("c.txt"), "c\n").unwrap();
+        add("c.txt");
+        std::fs::write(path.join("untracked.txt"), "u\n").unwrap();
+        reset(&["--hard"]).unwrap();
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a2\n");
+        assert!(!path.join("c.txt").exists());
+        assert!(path.join("untracked.txt").exists());
+        reset(&["--hard", "HEAD~1"]).unwrap();
+        assert!(!path.join("b.txt").exists());
+        std::fs::remove_file(path.join("untracked.txt")).unwrap();
+        assert_clean();
+
+        // Every move is in the reflog
+        let messages: Vec<String> = crate::reflog::read("refs/heads/master")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        assert_eq!(
+            messages[2..],
+            [
+                "reset: moving to HEAD~1",
+                "reset: moving to ORIG_HEAD",
+                "reset: moving to HEAD~1",
+                &format!("reset: moving to {second}"),
+                "reset: moving to HEAD",
+                "reset: moving to HEAD~1",
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_reset_paths() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+
+        // Before the first commit, the paths leave the index
+        std::fs::create_dir_all(path.join("dir")).unwrap();
+        std::fs::write(path.join("a.txt"), "a1\n").unwrap();
+        std::fs::write(path.join("dir/b.txt"), "b1\n").unwrap();
+        add(".");
+        reset(&["a.txt"]).unwrap();
+        let entries = Index::read_index().unwrap().entries;
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["dir/b.txt"]);
+        add(".");
+        commit("first");
+        let first = refs::read_ref("HEAD").unwrap().unwrap();
+
+        std::fs::write(path.join("a.txt"), "a2\n").unwrap();
+        std::fs::write(path.join("dir/b.txt"), "b2\n").unwrap();
+        std::fs::write(path.join("dir/new.txt"), "new\n").unwrap();
+        add(".");
+        reset(&["--", "dir"]).unwrap();
+        let status = status::status().unwrap();
+        assert_eq!(status.staged.keys().collect::<Vec<_>>(), vec!["a.txt"]);
+        assert_eq!(
+            status.unstaged.keys().collect::<Vec<_>>(),
+            vec!["dir/b.txt"]
+        );
+        assert_eq!(status.untracked, vec!["dir/new.txt"]);
+        // The branch does not move and the files keep their content
+        assert_eq!(refs::read_ref("HEAD").unwrap(), Some(first));
+        assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a2\n");
+
+        reset(&["HEAD", "a.txt"]).unwrap();
+        assert!(status::status().unwrap().staged.is_empty());
+        assert!(reset(&["--hard", "HEAD", "--", "a.txt"]).is_err());
+        assert!(reset(&["--soft", "--", "a.txt"]).is_err());
+    });
+}
+
+fn rm(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "rm"], args].concat();
+    RepoRust::rm(&run_test_matches(args))
+}
+
+fn mv(args: &[&str]) -> std::io::Result<()> {
+    let args = [&["", "mv"], args].concat();
+    RepoRust::mv(&run_test_matches(args))
+}
+
+fn index_paths() -> Vec<String> {
+    Index::read_index().unwrap().entries.into_keys().collect()
+}
+
+#[test]
+fn test_rm_removes_entries_and_files() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::create_dir_all(path.join("dir/sub")).unwrap();
+        for file in ["a.txt", "b.txt", "c.txt", "dir/d.txt", "dir/sub/e.txt"] {
+            std::fs::write(path.join(file), file).unwrap();
+        }
+        add(".");
+        commit("first");
+
+        rm(&["a.txt"]).unwrap();
+        assert!(!path.join("a.txt").exists());
+        rm(&["--cached", "b.txt"]).unwrap();
+        assert!(path.join("b.txt").exists());
+        assert!(rm(&["dir"]).is_err());
+        assert!(rm(&["missing.txt"]).is_err());
+        rm(&["-r", "dir"]).unwrap();
+        assert!(!path.join("dir").exists());
+        assert_eq!(index_paths(), vec!["c.txt"]);
+        // Committed as deleted
+        commit("second");
+        let files = Tree::flatten(&tree_of("HEAD")).unwrap();
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["c.txt"]);
+
+        // Local changes
+        std::fs::write(path.join("c.txt"), "local\n").unwrap();
+        let e = rm(&["c.txt"]).unwrap_err();
+        assert!(e.to_string().contains("has local modifications"), "{e}");
+        rm(&["--cached", "c.txt"]).unwrap();
+        assert!(path.join("c.txt").exists());
+
+        // Staged content that is in no commit
+        add("c.txt");
+        let e = rm(&["c.txt"]).unwrap_err();
+        assert!(
+            e.to_string().contains("has changes staged in the index"),
+            "{e}"
+        );
+        std::fs::write(path.join("c.txt"), "other\n").unwrap();
+        let e = rm(&["--cached", "c.txt"]).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("different from both the file and the HEAD")
+        );
+        rm(&["-f", "c.txt"]).unwrap();
+        assert!(!path.join("c.txt").exists());
+        assert!(index_paths().is_empty());
+    });
+}
+
+#[test]
+fn test_mv_renames_entries_and_files() {
+    run_test(|setup| {
+        let setup = setup.lock().unwrap().take().unwrap().dir;
+        let path = PathBuf::from(&setup.test_dir);
+        RepoRust::new_repo(path.to_str().unwrap()).unwrap();
+        RepoRust::init().unwrap();
+        std::fs::create_dir_all(path.join("dir")).unwrap();
+        for file in ["a.txt", "b.txt", "dir/c.txt"] {
+            std::fs::write(path.join(file), file).unwrap();
+        }
+        add(".");
+        commit("first");
+        let tree = tree_of("HEAD");
+
+        mv(&["a.txt", "renamed.txt"]).unwrap();
+        assert!(!path.join("a.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("renamed.txt")).unwrap(),
+            "a.txt"
+        );
+        // Into an existing folder, and a folder with its files
+        mv(&["renamed.txt", "b.txt", "dir"]).unwrap();
+        mv(&["dir", "moved"]).unwrap();
+        assert_eq!(
+            index_paths(),
+            vec!["moved/b.txt", "moved/c.txt", "moved/renamed.txt"]
+        );
+        assert!(path.join("moved/c.txt").is_file());
+        // The index keeps the same blobs
+        let status = status::status().unwrap();
+        assert!(status.unstaged.is_empty());
+        assert!(status.untracked.is_empty());
+        assert_ne!(index_tree(), tree);
+
+        std::fs::write(path.join("untracked.txt"), "u\n").unwrap();
+        assert!(mv(&["untracked.txt", "other.txt"]).is_err());
+        assert!(mv(&["missing.txt", "other.txt"]).is_err());
+        assert!(mv(&["moved/b.txt", "nowhere/b.txt"]).is_err());
+        assert!(mv(&["moved", "moved/inner"]).is_err());
+        // An existing file is only overwritten with -f
+        assert!(mv(&["moved/b.txt", "untracked.txt"]).is_err());
+        mv(&["-f", "moved/b.txt", "untracked.txt"]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path.join("untracked.txt")).unwrap(),
+            "b.txt"
+        );
+        assert_eq!(
+            index_paths(),
+            vec!["moved/c.txt", "moved/renamed.txt", "untracked.txt"]
+        );
+
+        // Two sources with the same name, and the top of the worktree, are refused
+        // before anything moves
+        std::fs::create_dir_all(path.join("other")).unwrap();
+        std::fs::create_dir_all(path.join("target")).unwrap();
+        std::fs::write(path.join("other/c.txt"), "other c\n").unwrap();
+        add("other/c.txt");
+        let e = mv(&["moved/c.txt", "other/c.txt", "target"]).unwrap_err();
+        assert!(e.to_string().contains("multiple sources"), "{e}");
+        assert!(mv(&[".", "target"]).is_err());
+        assert!(mv(&["./", "target"]).is_err());
+        assert!(path.join("moved/c.txt").is_file());
+        assert!(path.join("other/c.txt").is_file());
+        assert_eq!(
+            index_paths(),
+            vec![
+                "moved/c.txt",
+                "moved/renamed.txt",
+                "other/c.txt",
+                "untracked.txt"
+            ]
+        );
+    });
+}